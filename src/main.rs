@@ -1,10 +1,11 @@
 // src/main.rs
 // Main entry point for the Borf interpreter and REPL
 
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use std::path::Path;
+use std::time::{Duration, Instant};
 
-use borf_lib::repl::interpreter::{Evaluator, EvaluatorError, Result};
+use borf_lib::repl::interpreter::{compile_source, expand_definitions, max_threads, read_back, Capabilities, Evaluator, EvaluatorError, Grant, Parser as BorfParser, Result, Strategy, Value};
 use borf_lib::repl::repl::Repl;
 
 #[derive(Parser)]
@@ -12,6 +13,7 @@ use borf_lib::repl::repl::Repl;
 #[command(author = "Borf Team")]
 #[command(version = "0.1.0")]
 #[command(about = "Borf programming language", long_about = None)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
@@ -19,6 +21,115 @@ struct Cli {
     /// File to execute
     #[arg(short, long)]
     file: Option<String>,
+
+    /// Check word calls against their declared stack effects at runtime
+    #[arg(long, global = true)]
+    checked: bool,
+
+    /// Run quotations with named parameters through their STACKER translation
+    #[arg(long, global = true)]
+    stacker: bool,
+
+    /// Run a file or expression with both quotation strategies and report any divergence
+    #[arg(long, global = true)]
+    differential: bool,
+
+    /// Evaluate a file or expression by walking its syntax tree or by interaction net reduction
+    #[arg(long, global = true, value_enum, default_value_t = Backend::Tree)]
+    backend: Backend,
+
+    /// Threads to reduce nets on with --backend=inet (default 1, or every core with --bench; at most every core)
+    #[arg(long, global = true)]
+    threads: Option<usize>,
+
+    /// With --backend=inet, reduce on one thread and on --threads threads and report interactions per second
+    #[arg(long, global = true)]
+    bench: bool,
+
+    /// Allow reading files, or only these paths (comma separated)
+    #[arg(long, global = true, value_name = "PATHS", num_args = 0..=1, require_equals = true, value_delimiter = ',')]
+    allow_read: Option<Vec<String>>,
+
+    /// Allow writing files, or only these paths (comma separated)
+    #[arg(long, global = true, value_name = "PATHS", num_args = 0..=1, require_equals = true, value_delimiter = ',')]
+    allow_write: Option<Vec<String>>,
+
+    /// Allow reading environment variables, or only these ones
+    #[arg(long, global = true, value_name = "NAMES", num_args = 0..=1, require_equals = true, value_delimiter = ',')]
+    allow_env: Option<Vec<String>>,
+
+    /// Allow running subprocesses, or only these programs
+    #[arg(long, global = true, value_name = "PROGRAMS", num_args = 0..=1, require_equals = true, value_delimiter = ',')]
+    allow_run: Option<Vec<String>>,
+
+    /// Allow network connections, or only to these hosts (host or host:port)
+    #[arg(long, global = true, value_name = "HOSTS", num_args = 0..=1, require_equals = true, value_delimiter = ',')]
+    allow_net: Option<Vec<String>>,
+
+    /// Allow all file, environment, process and network access
+    #[arg(long, global = true)]
+    allow_all: bool,
+
+    /// Script to run, as in `borf script.borf a b c` or from a `#!/usr/bin/env borf` line
+    script: Option<String>,
+
+    /// Arguments passed to the script, available with `args`
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    args: Vec<String>,
+}
+
+/// How a program is evaluated
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Backend {
+    /// The tree-walking evaluator
+    Tree,
+    /// Compile the pure fragment to interaction combinators and reduce the net
+    Inet,
+}
+
+impl Cli {
+    fn strategy(&self) -> Strategy {
+        if self.stacker { Strategy::Stacker } else { Strategy::Named }
+    }
+
+    // The capabilities granted by --allow-* flags, or `default` when none is given
+    fn capabilities(&self, default: Capabilities) -> Capabilities {
+        if self.allow_all {
+            return Capabilities::all();
+        }
+        let flags = [&self.allow_read, &self.allow_write, &self.allow_env, &self.allow_run, &self.allow_net];
+        if flags.iter().all(|flag| flag.is_none()) {
+            return default;
+        }
+
+        let mut capabilities = Capabilities::none();
+        match &self.allow_read {
+            Some(paths) if paths.is_empty() => capabilities.read = Grant::All,
+            Some(paths) => paths.iter().for_each(|path| capabilities.allow_read(path)),
+            None => {}
+        }
+        match &self.allow_write {
+            Some(paths) if paths.is_empty() => capabilities.write = Grant::All,
+            Some(paths) => paths.iter().for_each(|path| capabilities.allow_write(path)),
+            None => {}
+        }
+        match &self.allow_env {
+            Some(names) if names.is_empty() => capabilities.env = Grant::All,
+            Some(names) => names.iter().for_each(|name| capabilities.allow_env(name)),
+            None => {}
+        }
+        match &self.allow_run {
+            Some(programs) if programs.is_empty() => capabilities.subprocess = Grant::All,
+            Some(programs) => programs.iter().for_each(|program| capabilities.allow_subprocess(program)),
+            None => {}
+        }
+        match &self.allow_net {
+            Some(hosts) if hosts.is_empty() => capabilities.net = Grant::All,
+            Some(hosts) => hosts.iter().for_each(|host| capabilities.allow_net(host)),
+            None => {}
+        }
+        capabilities
+    }
 }
 
 #[derive(Subcommand)]
@@ -40,6 +151,12 @@ enum Commands {
         regular: bool,
     },
 
+    /// Print the point-free stack code of each definition in a file
+    Expand {
+        /// Borf file to expand
+        file: String,
+    },
+
     /// Run metacircular evaluator tests
     Test,
 
@@ -141,6 +258,93 @@ fn run_metacircular_repl() -> Result<()> {
     }
 }
 
+// The regular evaluator, initialized and set up by the command-line flags; I/O words
+// get only the capabilities the --allow-* flags grant
+fn make_evaluator(cli: &Cli) -> Result<Evaluator> {
+    let mut evaluator = Evaluator::new();
+    evaluator.initialize()?;
+    evaluator.checked = cli.checked;
+    evaluator.strategy = cli.strategy();
+    evaluator.capabilities = cli.capabilities(Capabilities::none());
+    Ok(evaluator)
+}
+
+// Run a script with its arguments. The process exits with the status given to `exit`,
+// 0 when the script finishes, or 1 on an uncaught error.
+fn run_script(cli: &Cli, script: &str) -> Result<()> {
+    let mut evaluator = make_evaluator(cli)?;
+    evaluator.args = cli.args.clone();
+
+    if let Err(err) = evaluator.eval_file(script) {
+        exit_with_error(err);
+    }
+    Ok(())
+}
+
+// The program to run for a flag that takes a whole program: an eval expression, a file or a script
+fn program_source(cli: &Cli, flag: &str) -> Result<String> {
+    match (&cli.command, cli.file.as_ref().or(cli.script.as_ref())) {
+        (Some(Commands::Eval { expression, .. }), _) => Ok(expression.clone()),
+        (None, Some(file)) => Ok(std::fs::read_to_string(file)?),
+        _ => {
+            eprintln!("Error: {} needs a file or an expression to evaluate", flag);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Reduce a program's net, on several threads if asked, and print the stack it leaves
+fn run_inet(source: &str, threads: usize) -> Result<()> {
+    let (mut net, system) = compile_source(source)?;
+    if threads > 1 {
+        net.normalize_parallel(&system, threads)?;
+    } else {
+        net.normalize(&system)?;
+    }
+    print_stack(&read_back(&net)?);
+    Ok(())
+}
+
+// Time both reducers on a program's net, checking that they reach the same normal form
+fn bench_inet(source: &str, threads: usize) -> Result<()> {
+    let (net, system) = compile_source(source)?;
+    let mut sequential = net.clone();
+    let start = Instant::now();
+    let stats = sequential.normalize(&system)?;
+    report_rate("normalize", stats.interactions, start.elapsed());
+
+    let mut parallel = net;
+    let start = Instant::now();
+    let stats = parallel.normalize_parallel(&system, threads)?;
+    let label = format!("normalize_parallel on {} thread{}", threads, if threads == 1 { "" } else { "s" });
+    report_rate(&label, stats.interactions, start.elapsed());
+
+    if parallel.canonical() != sequential.canonical() {
+        return Err(EvaluatorError::EvalError("The parallel reducer reached a different net from the sequential one".to_string()));
+    }
+    print_stack(&read_back(&parallel)?);
+    Ok(())
+}
+
+fn report_rate(label: &str, interactions: usize, elapsed: Duration) {
+    let rate = interactions as f64 / elapsed.as_secs_f64().max(1e-9);
+    println!("{}: {} interactions in {:.3}s, {:.0} interactions/s", label, interactions, elapsed.as_secs_f64(), rate);
+}
+
+fn print_stack(stack: &[Value]) {
+    let items: Vec<String> = stack.iter().map(|value| value.to_string()).collect();
+    println!("{}", items.join(" "));
+}
+
+// Exit with the status of an `exit`, or report an uncaught error and exit with 1
+fn exit_with_error(err: EvaluatorError) -> ! {
+    if let EvaluatorError::Exit(status) = err {
+        std::process::exit(status);
+    }
+    eprintln!("Error: {}", err);
+    std::process::exit(1);
+}
+
 // This function is kept for reference, but we now use the
 // run_metacircular_repl function with the simplified initialization
 // to handle both the simple and complex implementations
@@ -225,6 +429,30 @@ fn evaluate_with_metacircular(expression: &str) -> Result<()> {
 fn main() -> Result<()> {
     let cli = Cli::parse();
 
+    if cli.differential {
+        let source = program_source(&cli, "--differential")?;
+        let report = Evaluator::differential(&source, &cli.capabilities(Capabilities::none()));
+        println!("{}", report);
+        if report.diverged() {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if cli.backend == Backend::Inet {
+        let source = program_source(&cli, "--backend=inet")?;
+        // normalize_parallel runs no more threads than there are cores
+        let result = if cli.bench {
+            bench_inet(&source, cli.threads.unwrap_or(max_threads()).clamp(1, max_threads()))
+        } else {
+            run_inet(&source, cli.threads.unwrap_or(1).clamp(1, max_threads()))
+        };
+        if let Err(err) = result {
+            exit_with_error(err);
+        }
+        return Ok(());
+    }
+
     // Special case for test commands
     match &cli.command {
         Some(Commands::BasicTest) => {
@@ -351,6 +579,9 @@ fn main() -> Result<()> {
             if *regular {
                 // Start the regular REPL
                 let mut repl = Repl::new()?;
+                repl.set_checked(cli.checked);
+                repl.set_capabilities(cli.capabilities(Capabilities::all()));
+                repl.set_strategy(cli.strategy());
                 repl.run()?;
             } else {
                 // Run the metacircular REPL by default
@@ -363,8 +594,7 @@ fn main() -> Result<()> {
         }) => {
             if *regular {
                 // Evaluate a single expression with the regular evaluator
-                let mut evaluator = Evaluator::new();
-                evaluator.initialize()?;
+                let mut evaluator = make_evaluator(&cli)?;
 
                 match evaluator.eval(expression) {
                     Ok(result) => {
@@ -372,16 +602,24 @@ fn main() -> Result<()> {
                             println!("{}", result);
                         }
                     }
-                    Err(err) => {
-                        eprintln!("Error: {}", err);
-                        std::process::exit(1);
-                    }
+                    Err(err) => exit_with_error(err),
                 }
             } else {
                 // Evaluate using the metacircular evaluator by default
                 evaluate_with_metacircular(expression)?;
             }
         }
+        Some(Commands::Expand { file }) => {
+            let content = std::fs::read_to_string(file)?;
+            let program = BorfParser::new(&content).parse()
+                .map_err(|e| EvaluatorError::ParseError(e.to_string()))?;
+            for (name, expanded) in expand_definitions(&program) {
+                match expanded {
+                    Ok(quotation) => println!("{} : {}", quotation, name),
+                    Err(err) => eprintln!("{}: not translatable: {}", name, err),
+                }
+            }
+        }
         Some(Commands::Test) => {
             println!("Testing metacircular evaluator capabilities");
             println!("===========================================");
@@ -448,7 +686,9 @@ fn main() -> Result<()> {
         }
         None => {
             // Check if a file was provided
-            if let Some(file) = &cli.file {
+            if let Some(script) = &cli.script {
+                run_script(&cli, script)?;
+            } else if let Some(file) = &cli.file {
                 // Execute the file
                 let path = Path::new(file);
                 if !path.exists() {
@@ -512,8 +752,7 @@ fn main() -> Result<()> {
                                     eprintln!("Falling back to regular evaluator...");
 
                                     // As a last resort, use the regular evaluator
-                                    let mut regular_eval = Evaluator::new();
-                                    regular_eval.initialize()?;
+                                    let mut regular_eval = make_evaluator(&cli)?;
 
                                     match regular_eval.eval_file(path) {
                                         Ok(result) => {
@@ -521,10 +760,7 @@ fn main() -> Result<()> {
                                                 println!("{}", result);
                                             }
                                         }
-                                        Err(err) => {
-                                            eprintln!("Error: {}", err);
-                                            std::process::exit(1);
-                                        }
+                                        Err(err) => exit_with_error(err),
                                     }
                                 }
                             }
@@ -534,8 +770,7 @@ fn main() -> Result<()> {
                             eprintln!("Falling back to regular evaluator...");
 
                             // As a last resort, use the regular evaluator
-                            let mut regular_eval = Evaluator::new();
-                            regular_eval.initialize()?;
+                            let mut regular_eval = make_evaluator(&cli)?;
 
                             match regular_eval.eval_file(path) {
                                 Ok(result) => {
@@ -543,10 +778,7 @@ fn main() -> Result<()> {
                                         println!("{}", result);
                                     }
                                 }
-                                Err(err) => {
-                                    eprintln!("Error: {}", err);
-                                    std::process::exit(1);
-                                }
+                                Err(err) => exit_with_error(err),
                             }
                         }
                     }
@@ -554,8 +786,7 @@ fn main() -> Result<()> {
                     // If Borf-in-Borf doesn't exist, fall back to regular evaluator
                     eprintln!("Warning: Metacircular evaluator not found. Using regular evaluator instead.");
 
-                    let mut regular_eval = Evaluator::new();
-                    regular_eval.initialize()?;
+                    let mut regular_eval = make_evaluator(&cli)?;
 
                     match regular_eval.eval_file(path) {
                         Ok(result) => {
@@ -563,10 +794,7 @@ fn main() -> Result<()> {
                                 println!("{}", result);
                             }
                         }
-                        Err(err) => {
-                            eprintln!("Error: {}", err);
-                            std::process::exit(1);
-                        }
+                        Err(err) => exit_with_error(err),
                     }
                 }
             } else {
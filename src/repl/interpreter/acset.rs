@@ -0,0 +1,379 @@
+// src/repl/interpreter/acset.rs
+// Attributed C-sets, after Catlab.jl. A schema names objects, homs between objects
+// and attributes from objects to attribute types. An ACSet numbers the parts of each
+// object 0..n and stores every hom and attribute as a column indexed by part; each
+// hom also keeps its inverse, from target part to source parts, so `incident` is a
+// lookup instead of a scan.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use crate::repl::interpreter::types::{EvaluatorError, Result, Value};
+
+/// A hom `name: dom -> codom` between objects, or an attribute from an object to an attribute type
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hom {
+    pub name: String,
+    pub dom: String,
+    pub codom: String,
+}
+
+/// The shape of an ACSet
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Schema {
+    pub objects: Vec<String>,
+    pub homs: Vec<Hom>,
+    pub attr_types: Vec<String>,
+    pub attrs: Vec<Hom>,
+}
+
+fn acset_error(message: String) -> EvaluatorError {
+    EvaluatorError::EvalError(message)
+}
+
+impl Schema {
+    /// A schema, checking that names are distinct and every hom and attribute
+    /// runs between declared objects and attribute types
+    pub fn new(objects: Vec<String>, homs: Vec<Hom>, attr_types: Vec<String>, attrs: Vec<Hom>) -> Result<Self> {
+        let mut names: Vec<&str> = Vec::new();
+        let all_names = objects.iter().chain(&attr_types).chain(homs.iter().chain(&attrs).map(|hom| &hom.name));
+        for name in all_names {
+            if names.contains(&name.as_str()) {
+                return Err(acset_error(format!("Schema declares '{}' twice", name)));
+            }
+            names.push(name);
+        }
+        for hom in &homs {
+            if !objects.contains(&hom.dom) || !objects.contains(&hom.codom) {
+                return Err(acset_error(format!(
+                    "Hom {}: {} -> {} must run between objects of the schema", hom.name, hom.dom, hom.codom
+                )));
+            }
+        }
+        for attr in &attrs {
+            if !objects.contains(&attr.dom) || !attr_types.contains(&attr.codom) {
+                return Err(acset_error(format!(
+                    "Attribute {}: {} -> {} must run from an object to an attribute type", attr.name, attr.dom, attr.codom
+                )));
+            }
+        }
+        Ok(Schema { objects, homs, attr_types, attrs })
+    }
+
+    /// A schema from Borf values: a list of objects, a list of `(name dom codom)` homs,
+    /// a list of attribute types and a list of `(name dom type)` attributes
+    pub fn from_values(objects: Value, homs: Value, attr_types: Value, attrs: Value) -> Result<Self> {
+        Schema::new(
+            names(objects, "objects")?,
+            arrows(homs, "homs")?,
+            names(attr_types, "attribute types")?,
+            arrows(attrs, "attributes")?,
+        )
+    }
+
+    pub fn has_object(&self, name: &str) -> bool {
+        self.objects.iter().any(|object| object == name)
+    }
+
+    pub fn hom(&self, name: &str) -> Option<&Hom> {
+        self.homs.iter().find(|hom| hom.name == name)
+    }
+
+    pub fn attr(&self, name: &str) -> Option<&Hom> {
+        self.attrs.iter().find(|attr| attr.name == name)
+    }
+
+    /// The homs into an object
+    pub fn homs_into<'a>(&'a self, object: &'a str) -> impl Iterator<Item = &'a Hom> + 'a {
+        self.homs.iter().filter(move |hom| hom.codom == object)
+    }
+
+    /// The homs out of an object
+    pub fn homs_from<'a>(&'a self, object: &'a str) -> impl Iterator<Item = &'a Hom> + 'a {
+        self.homs.iter().filter(move |hom| hom.dom == object)
+    }
+
+    pub fn attrs_from<'a>(&'a self, object: &'a str) -> impl Iterator<Item = &'a Hom> + 'a {
+        self.attrs.iter().filter(move |attr| attr.dom == object)
+    }
+}
+
+// A list of strings
+fn names(value: Value, what: &str) -> Result<Vec<String>> {
+    match value {
+        Value::List(items) => items.into_iter()
+            .map(|item| match item {
+                Value::String(name) => Ok(name),
+                other => Err(EvaluatorError::TypeError(format!("Schema {} must be strings, got {}", what, other))),
+            })
+            .collect(),
+        other => Err(EvaluatorError::TypeError(format!("Schema {} must be a list, got {}", what, other))),
+    }
+}
+
+// A list of (name dom codom) triples
+fn arrows(value: Value, what: &str) -> Result<Vec<Hom>> {
+    let triples = match value {
+        Value::List(items) => items,
+        other => return Err(EvaluatorError::TypeError(format!("Schema {} must be a list, got {}", what, other))),
+    };
+    triples.into_iter()
+        .map(|triple| {
+            let parts = names(triple.clone(), what).ok().filter(|parts| parts.len() == 3);
+            match parts.as_deref() {
+                Some([name, dom, codom]) => Ok(Hom { name: name.clone(), dom: dom.clone(), codom: codom.clone() }),
+                _ => Err(EvaluatorError::TypeError(format!(
+                    "Schema {} must be (name dom codom) triples, got {}", what, triple
+                ))),
+            }
+        })
+        .collect()
+}
+
+// Written as Borf would: Schema(V, E; src: E -> V, tgt: E -> V; Name; name: V -> Name)
+impl fmt::Display for Schema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let arrows = |homs: &[Hom]| homs.iter()
+            .map(|hom| format!("{}: {} -> {}", hom.name, hom.dom, hom.codom))
+            .collect::<Vec<_>>()
+            .join(", ");
+        write!(f, "Schema({}; {}", self.objects.join(", "), arrows(&self.homs))?;
+        if !self.attr_types.is_empty() {
+            write!(f, "; {}; {}", self.attr_types.join(", "), arrows(&self.attrs))?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// An attributed C-set: the parts of each object, with their subparts and attribute values
+#[derive(Debug, Clone, PartialEq)]
+pub struct ACSet {
+    pub schema: Rc<Schema>,
+    // Number of parts of each object
+    parts: HashMap<String, usize>,
+    // Each hom's target by source part, None until it is set
+    homs: HashMap<String, Vec<Option<usize>>>,
+    // Each hom's sources by target part, in ascending order
+    index: HashMap<String, Vec<Vec<usize>>>,
+    // Each attribute's value by part, None until it is set
+    attrs: HashMap<String, Vec<Option<Value>>>,
+}
+
+impl ACSet {
+    /// An ACSet with no parts
+    pub fn new(schema: Rc<Schema>) -> Self {
+        ACSet {
+            parts: schema.objects.iter().map(|object| (object.clone(), 0)).collect(),
+            homs: schema.homs.iter().map(|hom| (hom.name.clone(), Vec::new())).collect(),
+            index: schema.homs.iter().map(|hom| (hom.name.clone(), Vec::new())).collect(),
+            attrs: schema.attrs.iter().map(|attr| (attr.name.clone(), Vec::new())).collect(),
+            schema,
+        }
+    }
+
+    pub fn nparts(&self, object: &str) -> Result<usize> {
+        self.parts.get(object).copied()
+            .ok_or_else(|| acset_error(format!("'{}' is not an object of the schema", object)))
+    }
+
+    fn check_part(&self, object: &str, part: usize) -> Result<()> {
+        if part < self.nparts(object)? {
+            Ok(())
+        } else {
+            Err(acset_error(format!("{} has no part {}", object, part)))
+        }
+    }
+
+    fn hom(&self, name: &str) -> Result<Hom> {
+        self.schema.hom(name).cloned()
+            .ok_or_else(|| acset_error(format!("'{}' is not a hom of the schema", name)))
+    }
+
+    fn attr(&self, name: &str) -> Result<Hom> {
+        self.schema.attr(name).cloned()
+            .ok_or_else(|| acset_error(format!("'{}' is not an attribute of the schema", name)))
+    }
+
+    /// Add a part with no subparts or attribute values set
+    pub fn add_part(&mut self, object: &str) -> Result<usize> {
+        Ok(self.add_parts(object, 1)?.start)
+    }
+
+    pub fn add_parts(&mut self, object: &str, count: usize) -> Result<std::ops::Range<usize>> {
+        let start = self.nparts(object)?;
+        let end = start + count;
+        self.parts.insert(object.to_string(), end);
+        let schema = Rc::clone(&self.schema);
+        for hom in schema.homs_from(object) {
+            self.homs.get_mut(&hom.name).unwrap().resize(end, None);
+        }
+        for hom in schema.homs_into(object) {
+            self.index.get_mut(&hom.name).unwrap().resize(end, Vec::new());
+        }
+        for attr in schema.attrs_from(object) {
+            self.attrs.get_mut(&attr.name).unwrap().resize(end, None);
+        }
+        Ok(start..end)
+    }
+
+    /// The target of a part under a hom, if it has been set
+    pub fn subpart(&self, part: usize, hom: &str) -> Result<Option<usize>> {
+        let hom = self.hom(hom)?;
+        self.check_part(&hom.dom, part)?;
+        Ok(self.homs[&hom.name][part])
+    }
+
+    pub fn set_subpart(&mut self, part: usize, hom: &str, target: usize) -> Result<()> {
+        let hom = self.hom(hom)?;
+        self.check_part(&hom.dom, part)?;
+        self.check_part(&hom.codom, target)?;
+        let index = self.index.get_mut(&hom.name).unwrap();
+        if let Some(old) = self.homs[&hom.name][part] {
+            unindex(&mut index[old], part);
+        }
+        reindex(&mut index[target], part);
+        self.homs.get_mut(&hom.name).unwrap()[part] = Some(target);
+        Ok(())
+    }
+
+    /// The parts a hom sends to a part, in ascending order
+    pub fn incident(&self, part: usize, hom: &str) -> Result<&[usize]> {
+        let hom = self.hom(hom)?;
+        self.check_part(&hom.codom, part)?;
+        Ok(&self.index[&hom.name][part])
+    }
+
+    /// The value of an attribute on a part, if it has been set
+    pub fn get_attr(&self, part: usize, attr: &str) -> Result<Option<&Value>> {
+        let attr = self.attr(attr)?;
+        self.check_part(&attr.dom, part)?;
+        Ok(self.attrs[&attr.name][part].as_ref())
+    }
+
+    /// Set an attribute, checking the value against the attribute type when it is Num, Int, String or Symbol
+    pub fn set_attr(&mut self, part: usize, attr: &str, value: Value) -> Result<()> {
+        let attr = self.attr(attr)?;
+        self.check_part(&attr.dom, part)?;
+        let fits = match attr.codom.as_str() {
+            "Num" | "Int" => matches!(value, Value::Number(_)),
+            "String" => matches!(value, Value::String(_)),
+            "Symbol" => matches!(value, Value::Symbol(_)),
+            _ => true,
+        };
+        if !fits {
+            return Err(EvaluatorError::TypeError(format!(
+                "Attribute {} takes a {}, got {}", attr.name, attr.codom, value
+            )));
+        }
+        self.attrs.get_mut(&attr.name).unwrap()[part] = Some(value);
+        Ok(())
+    }
+
+    /// The parts whose attribute has a value, in ascending order
+    pub fn incident_attr(&self, value: &Value, attr: &str) -> Result<Vec<usize>> {
+        let attr = self.attr(attr)?;
+        Ok(self.attrs[&attr.name].iter()
+            .enumerate()
+            .filter(|(_, set)| set.as_ref() == Some(value))
+            .map(|(part, _)| part)
+            .collect())
+    }
+
+    /// Remove a part. Parts that referred to it keep no subpart there, and the last
+    /// part of the object takes its number, as in Catlab.
+    pub fn rem_part(&mut self, object: &str, part: usize) -> Result<()> {
+        self.check_part(object, part)?;
+        let last = self.nparts(object)? - 1;
+        let schema = Rc::clone(&self.schema);
+
+        for hom in schema.homs_into(object) {
+            // Sources of the removed part lose their subpart; those of the last part follow it
+            let sources = std::mem::take(&mut self.index.get_mut(&hom.name).unwrap()[part]);
+            let column = self.homs.get_mut(&hom.name).unwrap();
+            for source in sources {
+                column[source] = None;
+            }
+            let index = self.index.get_mut(&hom.name).unwrap();
+            index.swap_remove(part);
+            if part != last {
+                for &source in &index[part] {
+                    column[source] = Some(part);
+                }
+            }
+        }
+        for hom in schema.homs_from(object) {
+            // The removed part leaves the index; the last part is indexed under its new number
+            let column = self.homs.get_mut(&hom.name).unwrap();
+            let index = self.index.get_mut(&hom.name).unwrap();
+            if let Some(target) = column[part] {
+                unindex(&mut index[target], part);
+            }
+            column.swap_remove(part);
+            if part != last {
+                if let Some(target) = column[part] {
+                    unindex(&mut index[target], last);
+                    reindex(&mut index[target], part);
+                }
+            }
+        }
+        for attr in schema.attrs_from(object) {
+            self.attrs.get_mut(&attr.name).unwrap().swap_remove(part);
+        }
+        self.parts.insert(object.to_string(), last);
+        Ok(())
+    }
+
+    /// Remove a part and, recursively, every part that refers to it
+    pub fn cascading_rem_part(&mut self, object: &str, part: usize) -> Result<()> {
+        self.check_part(object, part)?;
+        let mut doomed: HashMap<String, Vec<usize>> = HashMap::new();
+        let mut pending = vec![(object.to_string(), part)];
+        while let Some((object, part)) = pending.pop() {
+            let parts = doomed.entry(object.clone()).or_default();
+            if parts.contains(&part) {
+                continue;
+            }
+            parts.push(part);
+            for hom in self.schema.homs_into(&object) {
+                pending.extend(self.index[&hom.name][part].iter().map(|&source| (hom.dom.clone(), source)));
+            }
+        }
+        // Removing a part renumbers only the last part of its object, so removing
+        // each object's parts from the highest down leaves the others' numbers intact
+        for (object, mut parts) in doomed {
+            parts.sort_unstable_by(|a, b| b.cmp(a));
+            for part in parts {
+                self.rem_part(&object, part)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Add a source to a sorted index entry
+fn reindex(sources: &mut Vec<usize>, source: usize) {
+    if let Err(position) = sources.binary_search(&source) {
+        sources.insert(position, source);
+    }
+}
+
+fn unindex(sources: &mut Vec<usize>, source: usize) {
+    if let Ok(position) = sources.binary_search(&source) {
+        sources.remove(position);
+    }
+}
+
+// The number of parts of each object: ACSet(V: 3, E: 2)
+impl fmt::Display for ACSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let counts: Vec<String> = self.schema.objects.iter()
+            .map(|object| format!("{}: {}", object, self.parts[object]))
+            .collect();
+        write!(f, "ACSet({})", counts.join(", "))
+    }
+}
+
+/// Part numbers as a Borf list
+pub fn parts_value(parts: impl IntoIterator<Item = usize>) -> Value {
+    Value::List(parts.into_iter().map(|part| Value::Number(part as i32)).collect())
+}
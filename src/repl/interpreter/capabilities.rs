@@ -0,0 +1,174 @@
+// src/repl/interpreter/capabilities.rs
+// What a Borf program may do outside the interpreter. Every word that reads or
+// writes files, looks at the environment, starts processes or uses the network
+// asks the evaluator's Capabilities first; a denial is a PermissionDenied error,
+// which `try` can catch like any other error.
+
+use std::path::{Component, Path, PathBuf};
+use crate::repl::interpreter::types::{EvaluatorError, Result};
+
+/// One kind of access: none of it, only the listed entries, or all of it
+#[derive(Debug, Clone, PartialEq)]
+pub enum Grant<T> {
+    Denied,
+    Only(Vec<T>),
+    All,
+}
+
+impl<T> Default for Grant<T> {
+    fn default() -> Self {
+        Grant::Denied
+    }
+}
+
+impl<T> Grant<T> {
+    // Add an entry, unless everything is granted already
+    fn add(&mut self, entry: T) {
+        match self {
+            Grant::Denied => *self = Grant::Only(vec![entry]),
+            Grant::Only(entries) => entries.push(entry),
+            Grant::All => {}
+        }
+    }
+
+    fn allows(&self, matches: impl Fn(&T) -> bool) -> bool {
+        match self {
+            Grant::Denied => false,
+            Grant::Only(entries) => entries.iter().any(matches),
+            Grant::All => true,
+        }
+    }
+}
+
+/// The capabilities of an evaluator. The default grants nothing.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Capabilities {
+    pub read: Grant<PathBuf>,      // files and directories that may be read
+    pub write: Grant<PathBuf>,     // files and directories that may be written
+    pub env: Grant<String>,        // environment variables that may be read
+    pub subprocess: Grant<String>, // programs that may be run
+    pub net: Grant<String>,        // hosts, or host:port, that may be connected to
+}
+
+impl Capabilities {
+    /// No access outside the interpreter
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    /// Every kind of access, as in the REPL
+    pub fn all() -> Self {
+        Capabilities {
+            read: Grant::All,
+            write: Grant::All,
+            env: Grant::All,
+            subprocess: Grant::All,
+            net: Grant::All,
+        }
+    }
+
+    /// Allow reading a file, or anything below a directory
+    pub fn allow_read(&mut self, path: impl AsRef<Path>) {
+        self.read.add(resolve(path.as_ref()));
+    }
+
+    /// Allow writing a file, or anything below a directory
+    pub fn allow_write(&mut self, path: impl AsRef<Path>) {
+        self.write.add(resolve(path.as_ref()));
+    }
+
+    pub fn allow_env(&mut self, name: &str) {
+        self.env.add(name.to_string());
+    }
+
+    pub fn allow_subprocess(&mut self, program: &str) {
+        self.subprocess.add(program.to_string());
+    }
+
+    /// Allow connecting to a host on any port, or to one port with "host:port"
+    pub fn allow_net(&mut self, host: &str) {
+        self.net.add(host.to_string());
+    }
+
+    pub fn check_read(&self, path: &str) -> Result<()> {
+        let resolved = resolve(Path::new(path));
+        if self.read.allows(|granted| resolved.starts_with(granted)) {
+            Ok(())
+        } else {
+            Err(denied(format!("read access to '{}'", path), "--allow-read"))
+        }
+    }
+
+    pub fn check_write(&self, path: &str) -> Result<()> {
+        let resolved = resolve(Path::new(path));
+        if self.write.allows(|granted| resolved.starts_with(granted)) {
+            Ok(())
+        } else {
+            Err(denied(format!("write access to '{}'", path), "--allow-write"))
+        }
+    }
+
+    pub fn check_env(&self, name: &str) -> Result<()> {
+        if self.env.allows(|granted| granted == name) {
+            Ok(())
+        } else {
+            Err(denied(format!("access to environment variable '{}'", name), "--allow-env"))
+        }
+    }
+
+    pub fn check_subprocess(&self, program: &str) -> Result<()> {
+        if self.subprocess.allows(|granted| granted == program) {
+            Ok(())
+        } else {
+            Err(denied(format!("running '{}'", program), "--allow-run"))
+        }
+    }
+
+    pub fn check_net(&self, host: &str, port: u16) -> Result<()> {
+        let address = format!("{}:{}", host, port);
+        if self.net.allows(|granted| *granted == host || *granted == address) {
+            Ok(())
+        } else {
+            Err(denied(format!("network access to '{}'", address), "--allow-net"))
+        }
+    }
+}
+
+fn denied(what: String, flag: &str) -> EvaluatorError {
+    EvaluatorError::PermissionDenied(format!("{} is not granted (see {})", what, flag))
+}
+
+// An absolute path without `.` or `..`, with symlinks followed as far as the path
+// exists, so neither can lead outside a granted directory
+fn resolve(path: &Path) -> PathBuf {
+    let absolute = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir().unwrap_or_default().join(path)
+    };
+    let mut normal = PathBuf::new();
+    for component in absolute.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normal.pop();
+            }
+            other => normal.push(other),
+        }
+    }
+
+    let mut existing = normal;
+    let mut missing = Vec::new();
+    while !existing.exists() {
+        match existing.file_name() {
+            Some(name) => {
+                missing.push(name.to_os_string());
+                existing.pop();
+            }
+            None => break,
+        }
+    }
+    let mut resolved = existing.canonicalize().unwrap_or(existing);
+    resolved.extend(missing.iter().rev());
+    resolved
+}
@@ -0,0 +1,827 @@
+// src/repl/interpreter/combinators.rs
+// Running the pure fragment of Borf on interaction nets. Numbers, add and mul, the stack
+// shuffles, quotations, call and if compile to Lafont's symmetric interaction
+// combinators: the constructor γ serves as both lambda and application, the duplicator
+// δ copies and the eraser ε discards. A quotation becomes a lambda for each value it
+// takes from the stack, returning a Church tuple when it leaves more than one, and
+// calling it applies it to those values. Numbers are Church numerals, and `c [t] [f] if`
+// turns c into a Church boolean and applies that to the two branches, so zero selects f
+// and neither branch is copied.
+//
+// Each δ carries a label, as in Lamping's abstract algorithm: duplicators with different
+// labels copy each other rather than annihilating. Without the algorithm's oracle, the
+// copies of a copied quotation can meet under one label when they are called and read
+// back wrongly, so code that copies a quotation which is already a copy is refused.
+// Numbers are only applied by add, mul and if, and their copies may be copied again.
+// Code in this fragment cannot recurse, and the results are checked against the tree
+// walker on a corpus of programs.
+
+use std::collections::HashMap;
+use crate::repl::interpreter::inet::{Cell, InteractionRule, Net, Port, ReductionStats, RewriteSystem, INTERFACE, PRINCIPAL};
+use crate::repl::interpreter::parser::parse;
+use crate::repl::interpreter::stack_effects::get_word_effect;
+use crate::repl::interpreter::types::{EvaluatorError, Expr, Param, Result, Value};
+
+/// The constructor: a lambda at its principal port, or an application at its second auxiliary port
+pub const CONSTRUCTOR: &str = "γ";
+
+pub const ERASER: &str = "ε";
+
+// The most wires readback follows for one value before deciding the net loops
+const READBACK_LIMIT: usize = 10_000_000;
+
+// How deep a term is shown in a readback error
+const SHOW_DEPTH: usize = 16;
+
+const SHUFFLES: [&str; 8] = ["dup", "drop", "swap", "rot", "over", "tuck", "2dup", "nip"];
+
+/// The duplicator with a label
+pub fn duplicator(label: usize) -> String {
+    format!("δ{}", label)
+}
+
+fn compile_error(message: String) -> EvaluatorError {
+    EvaluatorError::EvalError(message)
+}
+
+fn binary(agent: &str) -> Cell {
+    Cell { agent: agent.to_string(), arity: 2 }
+}
+
+fn eraser() -> Cell {
+    Cell { agent: ERASER.to_string(), arity: 0 }
+}
+
+/// The rules of the combinators, with a duplicator for each label below `labels`
+pub fn combinator_rules(labels: usize) -> Result<RewriteSystem> {
+    let mut agents = vec![CONSTRUCTOR.to_string()];
+    agents.extend((0..labels).map(duplicator));
+    let mut rules = Vec::new();
+    for (i, a) in agents.iter().enumerate() {
+        rules.push(annihilate(a)?);
+        for b in &agents[i + 1..] {
+            rules.push(commute(a, b)?);
+        }
+        let mut erased = Net::new(2);
+        for k in 0..2 {
+            let cell = erased.add_cell(&eraser());
+            erased.connect((cell, PRINCIPAL), (INTERFACE, k))?;
+        }
+        rules.push(InteractionRule { left: eraser(), right: binary(a), rhs: erased });
+    }
+    rules.push(InteractionRule { left: eraser(), right: eraser(), rhs: Net::new(0) });
+    RewriteSystem::new(rules)
+}
+
+// Two cells of one agent: each auxiliary port is joined to its counterpart
+fn annihilate(agent: &str) -> Result<InteractionRule> {
+    let mut rhs = Net::new(4);
+    rhs.connect((INTERFACE, 0), (INTERFACE, 2))?;
+    rhs.connect((INTERFACE, 1), (INTERFACE, 3))?;
+    Ok(InteractionRule { left: binary(agent), right: binary(agent), rhs })
+}
+
+// Cells of different agents copy each other: a copy of b goes on each of a's auxiliary
+// ports and a copy of a on each of b's, wired so that copy i of a uses copies i of b
+fn commute(a: &str, b: &str) -> Result<InteractionRule> {
+    let mut rhs = Net::new(4);
+    let copies_of_b = [rhs.add_cell(&binary(b)), rhs.add_cell(&binary(b))];
+    let copies_of_a = [rhs.add_cell(&binary(a)), rhs.add_cell(&binary(a))];
+    for (i, &copy_of_a) in copies_of_a.iter().enumerate() {
+        rhs.connect((copies_of_b[i], PRINCIPAL), (INTERFACE, i))?;
+        rhs.connect((copy_of_a, PRINCIPAL), (INTERFACE, i + 2))?;
+        for (j, &copy_of_b) in copies_of_b.iter().enumerate() {
+            rhs.connect((copy_of_a, j + 1), (copy_of_b, i + 1))?;
+        }
+    }
+    Ok(InteractionRule { left: binary(a), right: binary(b), rhs })
+}
+
+/// Compile a program to a net with a free port for each value it leaves on the stack,
+/// bottom first, and the rules for the duplicators it uses
+pub fn compile_to_net(program: &Expr) -> Result<(Net, RewriteSystem)> {
+    let mut compiler = Compiler {
+        net: Net::new(0),
+        labels: 0,
+        words: HashMap::new(),
+        inlining: Vec::new(),
+        frames: vec![Frame::default()],
+    };
+    compiler.expr(program)?;
+    let frame = compiler.frames.pop().expect("the program's frame");
+    if let Some(position) = frame.stack.iter().position(|entry| entry.shape.is_some()) {
+        return Err(compile_error(format!(
+            "Value {} on the stack is a quotation; only numbers are read back from a net", position
+        )));
+    }
+    for entry in frame.stack {
+        let free = compiler.net.add_free_port();
+        compiler.wire(entry.port, free)?;
+    }
+    Ok((compiler.net, combinator_rules(compiler.labels)?))
+}
+
+/// Parse a program and compile it to a net, with the rules to reduce it by
+pub fn compile_source(source: &str) -> Result<(Net, RewriteSystem)> {
+    let program = parse(source).map_err(|e| EvaluatorError::ParseError(e.to_string()))?;
+    compile_to_net(&program)
+}
+
+/// Compile a program, reduce its net and read back the stack it leaves
+pub fn eval_inet(source: &str) -> Result<(Vec<Value>, ReductionStats)> {
+    let (mut net, system) = compile_source(source)?;
+    let stats = net.normalize(&system)?;
+    Ok((read_back(&net)?, stats))
+}
+
+// What the compiler knows of a quotation: how many values it takes, how many of those
+// are bound to named parameters without knowing what they are, and what it leaves
+#[derive(Debug, Clone, PartialEq)]
+struct Shape {
+    inputs: usize,
+    params: usize,
+    outputs: Vec<Option<Shape>>,
+    // Whether it copies each value it takes, the top one first
+    copies: Vec<bool>,
+}
+
+// A value on the stack at compile time: the port it comes out of, its shape when it is a
+// known quotation, and whether it is one of several copies of a value
+#[derive(Debug, Clone)]
+struct Entry {
+    port: Port,
+    shape: Option<Shape>,
+    copy: bool,
+}
+
+// The code of the program, a quotation or a branch being compiled
+#[derive(Default)]
+struct Frame {
+    stack: Vec<Entry>,
+    // The lambdas of the values taken from below the frame's stack, the top one first
+    taken: Vec<usize>,
+    // The shapes of the values below, top first, when the frame runs where the stack is known
+    below: Vec<Option<Shape>>,
+    // Named parameters, with a port for each use left, and whether those are copies
+    scope: HashMap<String, (Vec<Port>, Option<Shape>, bool)>,
+    // The lambdas of the values taken from below that the code copies
+    copied: Vec<usize>,
+    params: usize,
+    // Words are compiled without the parameters of the code that calls them
+    sealed: bool,
+}
+
+struct Compiler {
+    net: Net,
+    labels: usize,
+    // Definitions, compiled where they are used
+    words: HashMap<String, Expr>,
+    // The words being compiled, innermost last
+    inlining: Vec<String>,
+    frames: Vec<Frame>,
+}
+
+impl Compiler {
+    fn frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("compiling inside a frame")
+    }
+
+    fn cell(&mut self, agent: &str, arity: usize) -> usize {
+        self.net.add_cell(&Cell { agent: agent.to_string(), arity })
+    }
+
+    fn wire(&mut self, a: Port, b: Port) -> Result<()> {
+        self.net.connect(a, b)
+    }
+
+    fn lambda(&mut self) -> usize {
+        self.cell(CONSTRUCTOR, 2)
+    }
+
+    fn apply(&mut self, function: Port, argument: Port) -> Result<Port> {
+        let application = self.cell(CONSTRUCTOR, 2);
+        self.wire(function, (application, PRINCIPAL))?;
+        self.wire(argument, (application, 1))?;
+        Ok((application, 2))
+    }
+
+    fn erase(&mut self, port: Port) -> Result<()> {
+        let eraser = self.cell(ERASER, 0);
+        self.wire(port, (eraser, PRINCIPAL))
+    }
+
+    // A port for each use of a value, copying it with a tree of duplicators of one label
+    fn share(&mut self, port: Port, uses: usize) -> Result<Vec<Port>> {
+        match uses {
+            0 => {
+                self.erase(port)?;
+                Ok(Vec::new())
+            },
+            1 => Ok(vec![port]),
+            _ => {
+                let agent = duplicator(self.labels);
+                self.labels += 1;
+                let (mut ports, mut rest) = (Vec::new(), port);
+                for _ in 1..uses {
+                    let copy = self.cell(&agent, 2);
+                    self.wire(rest, (copy, PRINCIPAL))?;
+                    ports.push((copy, 1));
+                    rest = (copy, 2);
+                }
+                ports.push(rest);
+                Ok(ports)
+            },
+        }
+    }
+
+    // Share a value between its uses, noting that the code copies it
+    fn copy(&mut self, entry: &Entry, uses: usize, word: &str) -> Result<Vec<Port>> {
+        if uses > 1 {
+            self.copying(entry, word)?;
+        }
+        self.share(entry.port, uses)
+    }
+
+    // Refuse to copy a copied quotation again, and remember the values from below the stack that are copied
+    fn copying(&mut self, entry: &Entry, word: &str) -> Result<()> {
+        if entry.copy && entry.shape.is_some() {
+            return Err(compile_error(format!(
+                "Cannot compile '{}' copying a quotation that is already a copy: nested duplication \
+                 can read back wrongly on interaction combinators", word
+            )));
+        }
+        let frame = self.frame();
+        if let Some(&lambda) = frame.taken.iter().find(|&&lambda| entry.port == (lambda, 1)) {
+            frame.copied.push(lambda);
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, port: Port, shape: Option<Shape>, copy: bool) {
+        self.frame().stack.push(Entry { port, shape, copy });
+    }
+
+    fn pop(&mut self, word: &str) -> Result<Entry> {
+        if let Some(entry) = self.frame().stack.pop() {
+            return Ok(entry);
+        }
+        if self.frames.len() == 1 {
+            return Err(compile_error(format!("'{}' needs more values than the stack holds", word)));
+        }
+        Ok(self.take())
+    }
+
+    // Take the next value from below the frame's stack as a lambda's variable
+    fn take(&mut self) -> Entry {
+        let lambda = self.lambda();
+        let frame = self.frame();
+        let shape = frame.below.get(frame.taken.len()).cloned().flatten();
+        frame.taken.push(lambda);
+        Entry { port: (lambda, 1), shape, copy: false }
+    }
+
+    // The shapes of the values the next code finds on the stack, top first
+    fn context(&self) -> Vec<Option<Shape>> {
+        let frame = self.frames.last().expect("compiling inside a frame");
+        frame.stack.iter().rev()
+            .map(|entry| entry.shape.clone())
+            .chain(frame.below.iter().skip(frame.taken.len()).cloned())
+            .collect()
+    }
+
+    fn sequence(&mut self, exprs: &[Expr]) -> Result<()> {
+        let mut index = 0;
+        while index < exprs.len() {
+            match (&exprs[index], exprs.get(index + 1), exprs.get(index + 2)) {
+                // `name ( a -- b )` declares a word's stack effect
+                (Expr::Symbol(_), Some(Expr::StackEffect(_)), _) => index += 2,
+                // A literal depth before pick or roll says which value to move
+                (Expr::Number(depth), Some(Expr::Symbol(word)), _) if *depth >= 0 && (word == "pick" || word == "roll") => {
+                    self.reach(*depth as usize, word == "roll")?;
+                    index += 2;
+                },
+                // Literal branches of if are compiled knowing the stack they run on
+                (then_branch, Some(else_branch), Some(Expr::Symbol(word))) if word == "if" => {
+                    match (quotation(then_branch), quotation(else_branch)) {
+                        (Some(then_branch), Some(else_branch)) => {
+                            self.branches(then_branch, else_branch)?;
+                            index += 3;
+                        },
+                        _ => {
+                            self.expr(&exprs[index])?;
+                            index += 1;
+                        },
+                    }
+                },
+                (expr, _, _) => {
+                    self.expr(expr)?;
+                    index += 1;
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Result<()> {
+        match expr {
+            Expr::Number(n) if *n >= 0 => self.numeral(*n as usize)?,
+            Expr::Number(n) => return Err(compile_error(format!(
+                "Cannot compile {}: numbers are Church numerals, which are never negative", n
+            ))),
+            Expr::Quotation(params, body) | Expr::TypedQuotation(params, body, _) => {
+                self.open(params, body, Vec::new(), false)?;
+                let inputs = self.frame().taken.len();
+                let entry = self.close(inputs)?;
+                self.frame().stack.push(entry);
+            },
+            Expr::Symbol(word) => self.word(word)?,
+            Expr::If(condition, then_branch, else_branch) => {
+                // A Nil condition means the condition is already on the stack
+                if !matches!(condition.as_ref(), Expr::Nil) {
+                    self.expr(condition)?;
+                }
+                self.branches((&[], body(then_branch)), (&[], body(else_branch)))?;
+            },
+            Expr::Sequence(exprs) => self.sequence(exprs)?,
+            Expr::Assignment(value, name) if self.frames.len() == 1 => {
+                self.words.insert(name.clone(), value.as_ref().clone());
+            },
+            Expr::StackEffect(_) | Expr::EffectAnnotation(_) => {},
+            other => return Err(compile_error(format!("Cannot compile {} to interaction combinators", other))),
+        }
+        Ok(())
+    }
+
+    // λf.λx. f (f (... x)), with n uses of f
+    fn numeral(&mut self, n: usize) -> Result<()> {
+        let (f, x) = (self.lambda(), self.lambda());
+        let mut result = (x, 1);
+        for copy in self.share((f, 1), n)? {
+            result = self.apply(copy, result)?;
+        }
+        self.wire(result, (x, 2))?;
+        self.wire((x, PRINCIPAL), (f, 2))?;
+        self.push((f, PRINCIPAL), None, false);
+        Ok(())
+    }
+
+    fn word(&mut self, word: &str) -> Result<()> {
+        // Parameters come first, then definitions, then built-in words
+        if let Some((port, shape, copy)) = self.parameter(word)? {
+            self.push(port, shape, copy);
+            return Ok(());
+        }
+        if let Some(definition) = self.words.get(word).cloned() {
+            return self.inline(word, &definition);
+        }
+        match word {
+            "add" | "mul" => self.arithmetic(word),
+            "call" => {
+                let quotation = self.pop(word)?;
+                self.call(quotation, word)
+            },
+            "if" => {
+                let (else_branch, then_branch) = (self.pop(word)?, self.pop(word)?);
+                let condition = self.pop(word)?;
+                self.select(condition, then_branch, else_branch)
+            },
+            _ if SHUFFLES.contains(&word) => self.shuffle(word),
+            "pick" | "roll" => Err(compile_error(format!("Cannot compile {} without a literal depth before it", word))),
+            _ => Err(compile_error(format!(
+                "Cannot compile '{}' to interaction combinators: only numbers, add, mul, stack shuffles, \
+                 quotations, call, if and definitions of these are supported", word
+            ))),
+        }
+    }
+
+    // The next port of a parameter in scope, its shape and whether it is a copy
+    fn parameter(&mut self, name: &str) -> Result<Option<(Port, Option<Shape>, bool)>> {
+        for frame in self.frames.iter_mut().rev() {
+            if let Some((ports, shape, copy)) = frame.scope.get_mut(name) {
+                let port = ports.pop()
+                    .ok_or_else(|| compile_error(format!("Parameter {} is used more often than counted", name)))?;
+                return Ok(Some((port, shape.clone(), *copy)));
+            }
+            if frame.sealed {
+                break;
+            }
+        }
+        Ok(None)
+    }
+
+    // Compile a word's definition where it is called, knowing the stack it runs on
+    fn inline(&mut self, word: &str, definition: &Expr) -> Result<()> {
+        match definition {
+            Expr::Quotation(params, body) | Expr::TypedQuotation(params, body, _) => {
+                if self.inlining.iter().any(|name| name == word) {
+                    return Err(compile_error(format!("Cannot compile {}: recursive words have no net in this fragment", word)));
+                }
+                self.inlining.push(word.to_string());
+                let below = self.context();
+                self.open(params, body, below, true)?;
+                let inputs = self.frame().taken.len();
+                let quotation = self.close(inputs)?;
+                self.inlining.pop();
+                self.call(quotation, word)
+            },
+            value => self.expr(value),
+        }
+    }
+
+    fn arithmetic(&mut self, word: &str) -> Result<()> {
+        let (b, a) = (self.pop(word)?, self.pop(word)?);
+        if a.shape.is_some() || b.shape.is_some() {
+            return Err(compile_error(format!("Cannot compile {} of a quotation", word)));
+        }
+        let f = self.lambda();
+        if word == "add" {
+            // λf.λx. a f (b f x)
+            let x = self.lambda();
+            let copies = self.share((f, 1), 2)?;
+            let a_f = self.apply(a.port, copies[0])?;
+            let b_f = self.apply(b.port, copies[1])?;
+            let b_f_x = self.apply(b_f, (x, 1))?;
+            let sum = self.apply(a_f, b_f_x)?;
+            self.wire(sum, (x, 2))?;
+            self.wire((x, PRINCIPAL), (f, 2))?;
+        } else {
+            // λf. a (b f)
+            let b_f = self.apply(b.port, (f, 1))?;
+            let product = self.apply(a.port, b_f)?;
+            self.wire(product, (f, 2))?;
+        }
+        self.push((f, PRINCIPAL), None, false);
+        Ok(())
+    }
+
+    fn shuffle(&mut self, word: &str) -> Result<()> {
+        let effect = get_word_effect(word).expect("shuffle words have effects");
+        let mut taken = Vec::new();
+        for _ in &effect.inputs {
+            taken.push(self.pop(word)?);
+        }
+        taken.reverse();
+        let mut copies = Vec::new();
+        for (input, entry) in effect.inputs.iter().zip(&taken) {
+            let uses = effect.outputs.iter().filter(|output| output.name == input.name).count();
+            let mut ports = self.copy(entry, uses, word)?;
+            ports.reverse();
+            copies.push((ports, entry.copy || uses > 1));
+        }
+        for output in &effect.outputs {
+            let index = effect.inputs.iter().position(|input| input.name == output.name).expect("shuffles only copy inputs");
+            let port = copies[index].0.pop().expect("a copy for each output");
+            self.push(port, taken[index].shape.clone(), copies[index].1);
+        }
+        Ok(())
+    }
+
+    // Copy or move the value at a depth to the top
+    fn reach(&mut self, depth: usize, moved: bool) -> Result<()> {
+        while self.frame().stack.len() <= depth {
+            if self.frames.len() == 1 {
+                let word = if moved { "roll" } else { "pick" };
+                return Err(compile_error(format!("'{} {}' needs more values than the stack holds", depth, word)));
+            }
+            let entry = self.take();
+            self.frame().stack.insert(0, entry);
+        }
+        let position = self.frame().stack.len() - 1 - depth;
+        if moved {
+            let entry = self.frame().stack.remove(position);
+            self.frame().stack.push(entry);
+        } else {
+            let entry = self.frame().stack[position].clone();
+            let copies = self.copy(&entry, 2, "pick")?;
+            self.frame().stack[position].port = copies[0];
+            self.frame().stack[position].copy = true;
+            self.push(copies[1], entry.shape, true);
+        }
+        Ok(())
+    }
+
+    // Start compiling a quotation's code, binding its named parameters
+    fn open(&mut self, params: &[Param], body: &[Expr], below: Vec<Option<Shape>>, sealed: bool) -> Result<()> {
+        let known = !below.is_empty();
+        self.frames.push(Frame { below, sealed, ..Frame::default() });
+        let mut args = Vec::new();
+        for _ in params {
+            args.push(self.pop("a parameter")?);
+        }
+        args.reverse();
+        for (param, arg) in params.iter().zip(args) {
+            let count = body.iter().map(|expr| uses(&param.name, expr)).sum();
+            let ports = self.copy(&arg, count, &param.name)?;
+            // A later parameter of the same name hides an earlier one
+            let bound = (ports, arg.shape, arg.copy || count > 1);
+            if let Some((unused, _, _)) = self.frame().scope.insert(param.name.clone(), bound) {
+                for port in unused {
+                    self.erase(port)?;
+                }
+            }
+        }
+        if !known {
+            self.frame().params = params.len();
+        }
+        self.sequence(body)
+    }
+
+    // Finish a quotation as a lambda for each of at least `inputs` values. Values it does
+    // not touch are taken from further down and left under its results.
+    fn close(&mut self, inputs: usize) -> Result<Entry> {
+        while self.frame().taken.len() < inputs {
+            let entry = self.take();
+            self.frame().stack.insert(0, entry);
+        }
+        let frame = self.frames.pop().expect("a frame to close");
+        for (unused, _, _) in frame.scope.into_values() {
+            for port in unused {
+                self.erase(port)?;
+            }
+        }
+
+        let outputs: Vec<Option<Shape>> = frame.stack.iter().map(|entry| entry.shape.clone()).collect();
+        let mut result = match frame.stack.len() {
+            1 => frame.stack[0].port,
+            // λs. s v1 ... vn, which is λs. s when there is nothing to return
+            _ => {
+                let tuple = self.lambda();
+                let mut applied = (tuple, 1);
+                for entry in &frame.stack {
+                    applied = self.apply(applied, entry.port)?;
+                }
+                self.wire(applied, (tuple, 2))?;
+                (tuple, PRINCIPAL)
+            },
+        };
+        for &lambda in &frame.taken {
+            self.wire(result, (lambda, 2))?;
+            result = (lambda, PRINCIPAL);
+        }
+        let copies = frame.taken.iter().map(|lambda| frame.copied.contains(lambda)).collect();
+        let shape = Shape { inputs: frame.taken.len(), params: frame.params, outputs, copies };
+        Ok(Entry { port: result, shape: Some(shape), copy: false })
+    }
+
+    // Apply a quotation to the values it takes and push what it leaves
+    fn call(&mut self, quotation: Entry, word: &str) -> Result<()> {
+        let shape = quotation.shape.ok_or_else(|| compile_error(format!(
+            "Cannot compile '{}' on a value that is not known to be a quotation when compiling", word
+        )))?;
+        let mut args = Vec::new();
+        for copied in &shape.copies {
+            let arg = self.pop(word)?;
+            if *copied {
+                self.copying(&arg, word)?;
+            }
+            args.push(arg);
+        }
+        args.reverse();
+        if args[shape.inputs - shape.params..].iter().any(|arg| arg.shape.is_some()) {
+            return Err(compile_error(format!(
+                "Cannot compile '{}' passing a quotation to a named parameter of a quotation value; define the quotation as a word instead", word
+            )));
+        }
+        let mut result = quotation.port;
+        for arg in args {
+            result = self.apply(result, arg.port)?;
+        }
+        match shape.outputs.len() {
+            0 => self.erase(result)?,
+            1 => self.push(result, shape.outputs[0].clone(), false),
+            count => {
+                let copies = self.share(result, count)?;
+                for (i, copy) in copies.into_iter().enumerate() {
+                    let projection = self.projection(i, count)?;
+                    let value = self.apply(copy, projection)?;
+                    self.push(value, shape.outputs[i].clone(), true);
+                }
+            },
+        }
+        Ok(())
+    }
+
+    // λx1 ... λxn. xi, for taking a value out of a tuple
+    fn projection(&mut self, i: usize, count: usize) -> Result<Port> {
+        let lambdas: Vec<usize> = (0..count).map(|_| self.lambda()).collect();
+        self.wire((lambdas[i], 1), (lambdas[count - 1], 2))?;
+        for (j, &lambda) in lambdas.iter().enumerate() {
+            if j != i {
+                self.erase((lambda, 1))?;
+            }
+            if j > 0 {
+                self.wire((lambda, PRINCIPAL), (lambdas[j - 1], 2))?;
+            }
+        }
+        Ok((lambdas[0], PRINCIPAL))
+    }
+
+    // `c [t] [f] if` with literal branches, each compiled on the stack under the condition
+    fn branches(&mut self, then_branch: (&[Param], &[Expr]), else_branch: (&[Param], &[Expr])) -> Result<()> {
+        let condition = self.pop("if")?;
+        let below = self.context();
+        let mut compiled = Vec::new();
+        for (params, body) in [then_branch, else_branch] {
+            self.open(params, body, below.clone(), false)?;
+            let inputs = self.frame().taken.len();
+            compiled.push(self.close(inputs)?);
+        }
+        let else_branch = compiled.pop().expect("two branches");
+        let then_branch = compiled.pop().expect("two branches");
+        self.select(condition, then_branch, else_branch)
+    }
+
+    // Call one of two quotations, chosen at reduction time by a Church numeral:
+    // c (λ_. λt.λf. t) (λt.λf. f) is true when c is positive and false when it is zero
+    fn select(&mut self, condition: Entry, then_branch: Entry, else_branch: Entry) -> Result<()> {
+        if condition.shape.is_some() {
+            return Err(compile_error("Cannot compile if with a quotation as its condition".to_string()));
+        }
+        let (then_shape, else_shape) = match (&then_branch.shape, &else_branch.shape) {
+            (Some(then_shape), Some(else_shape)) => (then_shape.clone(), else_shape.clone()),
+            _ => return Err(compile_error("Cannot compile if without two quotations known when compiling".to_string())),
+        };
+        let depth = |shape: &Shape| shape.outputs.len() as isize - shape.inputs as isize;
+        if depth(&then_shape) != depth(&else_shape) {
+            return Err(compile_error(format!(
+                "Cannot compile if whose branches change the stack depth differently ({} and {})",
+                depth(&then_shape), depth(&else_shape)
+            )));
+        }
+        let inputs = then_shape.inputs.max(else_shape.inputs);
+        let then_branch = self.pad(then_branch, inputs)?;
+        let else_branch = self.pad(else_branch, inputs)?;
+        let (then_shape, else_shape) = (then_branch.shape.clone().unwrap(), else_branch.shape.clone().unwrap());
+
+        let positive = self.lambda();
+        self.erase((positive, 1))?;
+        let yes = self.boolean(true)?;
+        self.wire(yes, (positive, 2))?;
+        let no = self.boolean(false)?;
+        let condition = self.apply(condition.port, (positive, PRINCIPAL))?;
+        let condition = self.apply(condition, no)?;
+        let chosen = self.apply(condition, then_branch.port)?;
+        let chosen = self.apply(chosen, else_branch.port)?;
+        let outputs = then_shape.outputs.iter()
+            .zip(&else_shape.outputs)
+            .map(|(a, b)| if a == b { a.clone() } else { None })
+            .collect();
+        let copies = then_shape.copies.iter().zip(&else_shape.copies).map(|(a, b)| *a || *b).collect();
+        let shape = Shape { inputs, params: then_shape.params.max(else_shape.params), outputs, copies };
+        self.call(Entry { port: chosen, shape: Some(shape), copy: false }, "if")
+    }
+
+    // λt.λf. t or λt.λf. f
+    fn boolean(&mut self, value: bool) -> Result<Port> {
+        let (t, f) = (self.lambda(), self.lambda());
+        let (kept, dropped) = if value { (t, f) } else { (f, t) };
+        self.wire((kept, 1), (f, 2))?;
+        self.erase((dropped, 1))?;
+        self.wire((f, PRINCIPAL), (t, 2))?;
+        Ok((t, PRINCIPAL))
+    }
+
+    // A quotation taking at least `inputs` values, leaving the extra ones under its results
+    fn pad(&mut self, quotation: Entry, inputs: usize) -> Result<Entry> {
+        let shape = quotation.shape.clone().expect("a known quotation");
+        if shape.inputs >= inputs {
+            return Ok(quotation);
+        }
+        let below = self.context();
+        self.frames.push(Frame { below, sealed: true, ..Frame::default() });
+        self.call(quotation, "if")?;
+        self.close(inputs)
+    }
+}
+
+// The quotation literal an expression is, as its parameters and body
+fn quotation(expr: &Expr) -> Option<(&[Param], &[Expr])> {
+    match expr {
+        Expr::Quotation(params, body) | Expr::TypedQuotation(params, body, _) => Some((params, body)),
+        _ => None,
+    }
+}
+
+fn body(expr: &Expr) -> &[Expr] {
+    match expr {
+        Expr::Sequence(exprs) => exprs,
+        other => std::slice::from_ref(other),
+    }
+}
+
+// How often code names a parameter, not counting quotations with a parameter of the same name
+fn uses(name: &str, expr: &Expr) -> usize {
+    match expr {
+        Expr::Symbol(word) if word == name => 1,
+        Expr::Quotation(params, body) | Expr::TypedQuotation(params, body, _) => {
+            if params.iter().any(|param| param.name == name) {
+                0
+            } else {
+                body.iter().map(|expr| uses(name, expr)).sum()
+            }
+        },
+        Expr::If(condition, then_branch, else_branch) => {
+            uses(name, condition) + uses(name, then_branch) + uses(name, else_branch)
+        },
+        Expr::Sequence(exprs) => exprs.iter().map(|expr| uses(name, expr)).sum(),
+        _ => 0,
+    }
+}
+
+// Which copy each path through a labelled duplicator is in, innermost last
+type Paths = HashMap<String, Vec<usize>>;
+
+fn readback_error(message: String) -> EvaluatorError {
+    EvaluatorError::EvalError(message)
+}
+
+/// Read back the numbers at the free ports of a net in normal form, bottom of the stack first
+pub fn read_back(net: &Net) -> Result<Vec<Value>> {
+    (0..net.free_ports()).map(|free| read_number(net, free)).collect()
+}
+
+// A Church numeral is λf.λx. f (f (... x))
+fn read_number(net: &Net, free: usize) -> Result<Value> {
+    let not_a_number = || readback_error(format!(
+        "Value {} on the stack reads back as {}, which is not a number", free, show(net, (INTERFACE, free))
+    ));
+    let mut paths = Paths::new();
+    let f = match follow(net, (INTERFACE, free), &mut paths)? {
+        (lambda, PRINCIPAL) => lambda,
+        _ => return Err(not_a_number()),
+    };
+    let x = match follow(net, (f, 2), &mut paths)? {
+        (lambda, PRINCIPAL) => lambda,
+        _ => return Err(not_a_number()),
+    };
+    let (mut count, mut at) = (0, (x, 2));
+    loop {
+        match follow(net, at, &mut paths)? {
+            (lambda, 1) if lambda == x => return Ok(Value::Number(count)),
+            (application, 2) => {
+                if follow(net, (application, PRINCIPAL), &mut paths.clone())? != (f, 1) {
+                    return Err(not_a_number());
+                }
+                count += 1;
+                at = (application, 1);
+            },
+            _ => return Err(not_a_number()),
+        }
+    }
+}
+
+// Follow the wire from a port, through duplicators, to the constructor port it reaches.
+// Reaching a duplicator at an auxiliary port means reading that copy of what is behind
+// its principal port; reaching one at its principal port means it joins copies made by
+// a duplicator of its label further out, and the path says which copy to take.
+fn follow(net: &Net, from: Port, paths: &mut Paths) -> Result<Port> {
+    let mut port = from;
+    for _ in 0..READBACK_LIMIT {
+        let (cell, slot) = net.target(port)
+            .ok_or_else(|| readback_error(format!("Cannot read back: port {:?} is not wired", port)))?;
+        match net.agent(cell) {
+            Some(CONSTRUCTOR) => return Ok((cell, slot)),
+            Some(agent) if agent.starts_with('δ') => {
+                let path = paths.entry(agent.to_string()).or_default();
+                if slot == PRINCIPAL {
+                    let copy = path.pop().ok_or_else(|| readback_error(format!(
+                        "Cannot read back: a {} joins copies that were never made", agent
+                    )))?;
+                    port = (cell, copy);
+                } else {
+                    path.push(slot);
+                    port = (cell, PRINCIPAL);
+                }
+            },
+            Some(agent) => return Err(readback_error(format!("Cannot read back a value ending at {}", agent))),
+            None => return Err(readback_error("Cannot read back a wire between two free ports".to_string())),
+        }
+    }
+    Err(readback_error(format!("Readback followed {} wires without reaching a value", READBACK_LIMIT)))
+}
+
+// The lambda term at a port, for error messages: λa.λb.(a b)
+fn show(net: &Net, from: Port) -> String {
+    show_term(net, from, &mut Paths::new(), &mut Vec::new(), 0)
+}
+
+fn show_term(net: &Net, from: Port, paths: &mut Paths, lambdas: &mut Vec<usize>, depth: usize) -> String {
+    if depth == SHOW_DEPTH {
+        return "…".to_string();
+    }
+    let name = |n: usize| if n < 26 { ((b'a' + n as u8) as char).to_string() } else { format!("v{}", n) };
+    match follow(net, from, paths) {
+        Ok((lambda, PRINCIPAL)) => {
+            lambdas.push(lambda);
+            let variable = name(lambdas.len() - 1);
+            format!("λ{}.{}", variable, show_term(net, (lambda, 2), paths, lambdas, depth + 1))
+        },
+        Ok((lambda, 1)) => lambdas.iter().position(|&bound| bound == lambda).map(name).unwrap_or_else(|| "?".to_string()),
+        Ok((application, _)) => format!(
+            "({} {})",
+            show_term(net, (application, PRINCIPAL), &mut paths.clone(), lambdas, depth + 1),
+            show_term(net, (application, 1), &mut paths.clone(), lambdas, depth + 1)
+        ),
+        Err(_) => "?".to_string(),
+    }
+}
@@ -0,0 +1,181 @@
+// src/repl/interpreter/dataflow.rs
+// The dataflow of stack code as a wiring diagram. Each word becomes a box with a
+// port for every item of its stack effect, and each literal a box with a single
+// output; wires follow the values as they move through stack positions. Shuffle
+// words such as dup, drop and swap get no box of their own: they only change
+// which port a later wire starts at, so dup shows up as two wires from one port
+// and drop as a port with none. Values taken from below the code's own stack
+// become the diagram's inputs and whatever is left on it becomes its outputs.
+
+use std::collections::HashMap;
+use crate::repl::interpreter::stack_effects::{StackEffect, StackItem, get_word_effect};
+use crate::repl::interpreter::stack_types::format_type;
+use crate::repl::interpreter::types::{EvaluatorError, Expr, Result};
+use crate::repl::interpreter::wiring::{WiringDiagram, OUTER_BOX};
+
+// Words that only rearrange the stack, drawn as wires rather than boxes
+const SHUFFLES: [&str; 8] = ["dup", "drop", "swap", "rot", "over", "tuck", "2dup", "nip"];
+
+/// The wiring diagram of a quotation body without named parameters,
+/// consulting `known` for the effects of user-defined words
+pub fn quotation_to_diagram(body: &[Expr], known: &HashMap<String, StackEffect>) -> Result<WiringDiagram> {
+    let mut dataflow = Dataflow { known, diagram: WiringDiagram::default(), stack: Vec::new(), taken: 0, wires: Vec::new() };
+    dataflow.sequence(body)?;
+    dataflow.finish()
+}
+
+// Where a value on the modelled stack comes from
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Source {
+    // The nth value taken from below the code's stack, counting from the top
+    Input(usize),
+    Port(usize, usize),
+}
+
+struct Dataflow<'a> {
+    known: &'a HashMap<String, StackEffect>,
+    diagram: WiringDiagram,
+    stack: Vec<Source>,
+    taken: usize,
+    // Wires are added once the number of inputs is known
+    wires: Vec<(Source, (usize, usize))>,
+}
+
+impl Dataflow<'_> {
+    fn pop(&mut self) -> Source {
+        self.stack.pop().unwrap_or_else(|| {
+            self.taken += 1;
+            Source::Input(self.taken - 1)
+        })
+    }
+
+    fn sequence(&mut self, body: &[Expr]) -> Result<()> {
+        let mut exprs = body.iter().peekable();
+        while let Some(expr) = exprs.next() {
+            // A literal depth before pick or roll says which value to move
+            if let (Expr::Number(depth), Some(Expr::Symbol(word))) = (expr, exprs.peek()) {
+                if *depth >= 0 && (word == "pick" || word == "roll") {
+                    self.reach(*depth as usize, word == "roll");
+                    exprs.next();
+                    continue;
+                }
+            }
+            self.expr(expr)?;
+        }
+        Ok(())
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Result<()> {
+        match expr {
+            Expr::Number(_) => self.literal(expr, "Int"),
+            Expr::String(_) => self.literal(expr, "String"),
+            Expr::Boolean(_) => self.literal(expr, "Bool"),
+            Expr::Nil => self.literal(expr, "Nil"),
+            Expr::Literal(_) => self.literal(expr, "Any"),
+            Expr::Quotation(params, _) | Expr::TypedQuotation(params, _, _) if params.is_empty() => {
+                self.literal(expr, "Quotation")
+            },
+            Expr::Symbol(word) if SHUFFLES.contains(&word.as_str()) => self.shuffle(word),
+            Expr::Symbol(word) => self.word(word)?,
+            Expr::Pipeline(left, right) => {
+                self.expr(left)?;
+                self.expr(right)?;
+            },
+            other => return Err(EvaluatorError::EvalError(format!(
+                "Cannot draw {} in a wiring diagram; expand quotations with parameters first", other
+            ))),
+        }
+        Ok(())
+    }
+
+    fn literal(&mut self, expr: &Expr, port_type: &str) {
+        let number = self.diagram.add_box(&expr.to_string(), Vec::new(), vec![port_type.to_string()]);
+        self.stack.push(Source::Port(number, 0));
+    }
+
+    // Copy or move the value at a depth to the top
+    fn reach(&mut self, depth: usize, moved: bool) {
+        while self.stack.len() <= depth {
+            self.stack.insert(0, Source::Input(self.taken));
+            self.taken += 1;
+        }
+        let position = self.stack.len() - 1 - depth;
+        let source = if moved { self.stack.remove(position) } else { self.stack[position] };
+        self.stack.push(source);
+    }
+
+    fn shuffle(&mut self, word: &str) {
+        let effect = get_word_effect(word).expect("shuffle words have effects");
+        let mut taken: Vec<Source> = effect.inputs.iter().map(|_| self.pop()).collect();
+        taken.reverse();
+        for output in &effect.outputs {
+            let index = effect.inputs.iter().position(|input| input.name == output.name).expect("shuffles only copy inputs");
+            self.stack.push(taken[index]);
+        }
+    }
+
+    fn word(&mut self, word: &str) -> Result<()> {
+        let effect = self.known.get(word).cloned()
+            .or_else(|| get_word_effect(word))
+            .ok_or_else(|| EvaluatorError::EvalError(format!(
+                "Cannot draw '{}': it has no known stack effect", word
+            )))?;
+        if effect.is_row_polymorphic() {
+            return Err(EvaluatorError::EvalError(format!(
+                "Cannot draw '{}': its effect {} depends on its quotation arguments", word, effect
+            )));
+        }
+        let inputs = effect.inputs.iter().map(port_type).collect();
+        let outputs: Vec<String> = effect.outputs.iter().map(port_type).collect();
+        let number = self.diagram.add_box(word, inputs, outputs.clone());
+        let mut taken: Vec<Source> = effect.inputs.iter().map(|_| self.pop()).collect();
+        taken.reverse();
+        for (port, source) in taken.into_iter().enumerate() {
+            self.wires.push((source, (number, port)));
+        }
+        self.stack.extend((0..outputs.len()).map(|port| Source::Port(number, port)));
+        Ok(())
+    }
+
+    // Number the inputs, deepest first, give them the types of the ports they feed and add the wires
+    fn finish(mut self) -> Result<WiringDiagram> {
+        let count = self.taken;
+        let input = |n: usize| count - 1 - n;
+        let mut inputs = vec!["Any".to_string(); count];
+        for (source, (number, port)) in &self.wires {
+            if let Source::Input(n) = *source {
+                if inputs[input(n)] == "Any" {
+                    inputs[input(n)] = self.diagram.get_box(*number)?.inputs[*port].clone();
+                }
+            }
+        }
+        let mut outputs = Vec::new();
+        for (port, source) in std::mem::take(&mut self.stack).into_iter().enumerate() {
+            outputs.push(match source {
+                Source::Input(n) => inputs[input(n)].clone(),
+                Source::Port(number, output) => self.diagram.get_box(number)?.outputs[output].clone(),
+            });
+            self.wires.push((source, (OUTER_BOX, port)));
+        }
+
+        self.diagram.inputs = inputs;
+        self.diagram.outputs = outputs;
+        for (source, target) in std::mem::take(&mut self.wires) {
+            let source = match source {
+                Source::Input(n) => (OUTER_BOX, input(n)),
+                Source::Port(number, port) => (number, port),
+            };
+            self.diagram.add_wire(source, target)?;
+        }
+        Ok(self.diagram)
+    }
+}
+
+// A port's type from a stack effect item; untyped items take anything
+fn port_type(item: &StackItem) -> String {
+    match (&item.effect, &item.item_type) {
+        (Some(_), _) => "Quotation".to_string(),
+        (None, Some(item_type)) => format_type(item_type),
+        (None, None) => "Any".to_string(),
+    }
+}
@@ -3,7 +3,8 @@
 
 use std::collections::{HashMap, HashSet};
 use std::fmt;
-use crate::repl::interpreter::types::{EvaluatorError, Result, Value};
+use std::rc::Rc;
+use crate::repl::interpreter::types::{EvaluatorError, Expr, Result, Value};
 
 // Represent different types of effects
 #[derive(Debug, Clone, PartialEq)]
@@ -27,12 +28,51 @@ impl fmt::Display for EffectType {
     }
 }
 
+// Where a resource was created, for leak reports
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Origin {
+    pub location: String,      // The code that created it, e.g. "line 1, column 14 in open_log"
+    pub call_stack: Vec<String>, // The calls leading there, innermost first
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.location.is_empty() {
+            write!(f, "created at an unknown location")?;
+        } else {
+            write!(f, "created at {}", self.location)?;
+        }
+        for call in &self.call_stack {
+            write!(f, "\n    {}", call)?;
+        }
+        Ok(())
+    }
+}
+
+// Releases what a resource holds when it is consumed: a Borf quotation ( value -- ),
+// run by the evaluator, or a Rust callback given the resource's inner value
+#[derive(Clone)]
+pub enum Finalizer {
+    Quotation(Value),
+    Native(Rc<dyn Fn(&Value) -> Result<()>>),
+}
+
+impl fmt::Debug for Finalizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Finalizer::Quotation(quotation) => write!(f, "Quotation({})", quotation),
+            Finalizer::Native(_) => write!(f, "Native"),
+        }
+    }
+}
+
 // Define a resource that can be tracked
 #[derive(Debug, Clone)]
 pub struct Resource {
     id: usize,            // Unique identifier for the resource
     resource_type: String, // Type of the resource (e.g., "file", "socket")
     consumed: bool,       // Whether the resource has been consumed
+    origin: Origin,       // Where the resource was created
 }
 
 impl Resource {
@@ -41,9 +81,14 @@ impl Resource {
             id,
             resource_type: resource_type.to_string(),
             consumed: false,
+            origin: Origin::default(),
         }
     }
     
+    pub fn origin(&self) -> &Origin {
+        &self.origin
+    }
+    
     pub fn mark_consumed(&mut self) -> Result<()> {
         if self.consumed {
             return Err(EvaluatorError::EvalError(
@@ -69,6 +114,17 @@ pub struct ResourceManager {
     resources: HashMap<usize, Resource>, // Map from resource ID to Resource
     next_id: usize,                     // Next resource ID to assign
     current_regions: Vec<HashSet<usize>>, // Stack of regions for borrowed resources
+    finalizers: HashMap<String, Finalizer>, // Finalizer for each resource type that has one
+    consumed_log: Vec<usize>,           // IDs of consumed resources, in the order they were consumed
+    used_log: Vec<usize>,               // IDs of resources used or borrowed, in the order they were
+}
+
+// A point in the life of a ResourceManager to compare later states against
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResourceMark {
+    next_id: usize,
+    consumed: usize,
+    used: usize,
 }
 
 impl ResourceManager {
@@ -77,18 +133,83 @@ impl ResourceManager {
             resources: HashMap::new(),
             next_id: 0,
             current_regions: Vec::new(),
+            finalizers: HashMap::new(),
+            consumed_log: Vec::new(),
+            used_log: Vec::new(),
         }
     }
     
     // Create a new resource and return its ID
     pub fn create_resource(&mut self, resource_type: &str) -> usize {
+        self.create_resource_at(resource_type, Origin::default())
+    }
+    
+    // Create a new resource, remembering where it was created
+    pub fn create_resource_at(&mut self, resource_type: &str, origin: Origin) -> usize {
         let id = self.next_id;
         self.next_id += 1;
         
-        self.resources.insert(id, Resource::new(id, resource_type));
+        let mut resource = Resource::new(id, resource_type);
+        resource.origin = origin;
+        self.resources.insert(id, resource);
         id
     }
     
+    // The ID the next resource will get; resources created from here on have IDs at least this
+    pub fn next_id(&self) -> usize {
+        self.next_id
+    }
+    
+    // Mark the current state, to see later which resources were created, consumed and used since
+    pub fn mark(&self) -> ResourceMark {
+        ResourceMark { next_id: self.next_id, consumed: self.consumed_log.len(), used: self.used_log.len() }
+    }
+    
+    // The effects observed since a mark: a Creates or Consumes for each resource type
+    // created or consumed, a Transfers for each type of the `moved` resources (older ones
+    // that were handed over rather than consumed), a Uses for each type of the other older
+    // resources used and kept, or Pure if there were none
+    pub fn effects_since(&self, mark: ResourceMark, moved: &[usize]) -> Vec<EffectType> {
+        let consumed = &self.consumed_log[mark.consumed..];
+        let mut effects = Vec::new();
+        let mut note = |id: &usize, effect: fn(String) -> EffectType| {
+            if let Some(resource) = self.resources.get(id) {
+                let effect = effect(resource.resource_type().to_string());
+                if !effects.contains(&effect) {
+                    effects.push(effect);
+                }
+            }
+        };
+        for id in mark.next_id..self.next_id {
+            note(&id, EffectType::Creates);
+        }
+        for id in consumed {
+            note(id, EffectType::Consumes);
+        }
+        for id in moved.iter().filter(|id| **id < mark.next_id && !consumed.contains(id)) {
+            note(id, EffectType::Transfers);
+        }
+        for id in self.used_log[mark.used..].iter()
+            .filter(|id| **id < mark.next_id && !consumed.contains(id) && !moved.contains(id))
+        {
+            note(id, EffectType::Uses);
+        }
+        if effects.is_empty() {
+            effects.push(EffectType::Pure);
+        }
+        effects
+    }
+    
+    // Register the finalizer run when a resource of the given type is consumed
+    pub fn register_finalizer(&mut self, resource_type: &str, finalizer: Finalizer) {
+        self.finalizers.insert(resource_type.to_string(), finalizer);
+    }
+    
+    // The finalizer of a resource type, if it has one
+    pub fn finalizer(&self, resource_type: &str) -> Option<&Finalizer> {
+        self.finalizers.get(resource_type)
+    }
+    
     // Mark a resource as consumed
     pub fn consume_resource(&mut self, id: usize) -> Result<()> {
         if let Some(resource) = self.resources.get_mut(&id) {
@@ -101,12 +222,20 @@ impl ResourceManager {
             }
             
             resource.mark_consumed()?;
+            self.consumed_log.push(id);
             Ok(())
         } else {
             Err(EvaluatorError::EvalError(format!("Resource with ID {} not found", id)))
         }
     }
     
+    // Check that a resource is live before an operation on it, noting the use
+    pub fn use_resource(&mut self, id: usize) -> Result<()> {
+        self.check_resource(id)?;
+        self.used_log.push(id);
+        Ok(())
+    }
+    
     // Check if a resource exists and is not consumed
     pub fn check_resource(&self, id: usize) -> Result<()> {
         if let Some(resource) = self.resources.get(&id) {
@@ -137,19 +266,32 @@ impl ResourceManager {
         self.current_regions.push(HashSet::new());
     }
     
-    // End the current borrowing region
-    pub fn end_region(&mut self) -> Result<()> {
-        if let Some(_) = self.current_regions.pop() {
-            Ok(())
-        } else {
-            Err(EvaluatorError::EvalError("No active borrowing region".to_string()))
+    // End the current borrowing region. `live` holds every value that outlives the region,
+    // described by where it is ("on the stack", "in binding 'x'"); none may hold a borrow from it.
+    pub fn end_region(&mut self, live: &[(String, Value)]) -> Result<()> {
+        let region = match self.current_regions.pop() {
+            Some(region) => region,
+            None => return Err(EvaluatorError::EvalError("No active borrowing region".to_string())),
+        };
+        
+        // A resource also borrowed by an enclosing region may still be referred to
+        let ending: HashSet<usize> = region.into_iter().filter(|id| !self.is_borrowed(*id)).collect();
+        for (place, value) in live {
+            if let Some((id, container)) = find_borrowed(value, &ending) {
+                let resource_type = self.resource_type(id).unwrap_or_default();
+                return Err(EvaluatorError::EvalError(format!(
+                    "Borrowed resource {} (type {}) escapes its region {}{}",
+                    id, resource_type, place, container
+                )));
+            }
         }
+        Ok(())
     }
     
     // Borrow a resource in the current region
     pub fn borrow_resource(&mut self, id: usize) -> Result<()> {
-        // Check if the resource exists and is not consumed
-        self.check_resource(id)?;
+        // Check if the resource exists and is not consumed; lending it out is a use
+        self.use_resource(id)?;
         
         // Add to the current region
         if let Some(region) = self.current_regions.last_mut() {
@@ -172,18 +314,23 @@ impl ResourceManager {
     
     // Check for resource leaks at the end of evaluation
     pub fn check_for_leaks(&self) -> Result<()> {
-        let mut leaked = Vec::new();
-        
-        for (id, resource) in &self.resources {
-            if !resource.is_consumed() {
-                leaked.push(format!("{} (type {})", id, resource.resource_type()));
-            }
-        }
+        self.check_for_leaks_since(0)
+    }
+    
+    // Check for leaks among the resources created since `first_id`, e.g. by one file
+    pub fn check_for_leaks_since(&self, first_id: usize) -> Result<()> {
+        let mut leaked: Vec<&Resource> = self.resources.values()
+            .filter(|resource| resource.id >= first_id && !resource.is_consumed())
+            .collect();
+        leaked.sort_by_key(|resource| resource.id);
         
         if !leaked.is_empty() {
+            let report: Vec<String> = leaked.iter()
+                .map(|resource| format!("  {} (type {}) {}", resource.id, resource.resource_type(), resource.origin()))
+                .collect();
             Err(EvaluatorError::EvalError(
-                format!("Resource leak detected: {} resources not consumed: {}", 
-                        leaked.len(), leaked.join(", "))
+                format!("Resource leak detected: {} resources not consumed:\n{}", 
+                        leaked.len(), report.join("\n"))
             ))
         } else {
             Ok(())
@@ -235,6 +382,92 @@ pub fn parse_effect(effect_str: &str) -> Result<EffectType> {
     }
 }
 
+// Find a borrow of one of `ids` in a value, along with the container it was found in
+fn find_borrowed(value: &Value, ids: &HashSet<usize>) -> Option<(usize, String)> {
+    let inside = |kind: &str, mut values: Vec<&Value>| {
+        values.drain(..).find_map(|value| find_borrowed(value, ids))
+            .map(|(id, _)| (id, format!(" inside a {}", kind)))
+    };
+    match value {
+        Value::BorrowedResource(id, _) if ids.contains(id) => Some((*id, String::new())),
+        Value::List(items) => inside("list", items.iter().collect()),
+        Value::Variant(_, items) => inside("variant", items.iter().collect()),
+        Value::Map(fields) | Value::Module(_, fields) => inside("map", fields.values().collect()),
+        Value::Pipeline(left, right) => inside("pipeline", vec![left.as_ref(), right.as_ref()]),
+        Value::Quoted(inner) | Value::Quasiquoted(inner) | Value::Resource(_, inner)
+        | Value::BorrowedResource(_, inner) | Value::Optional(Some(inner)) => {
+            find_borrowed(inner, ids)
+        },
+        Value::Quotation(_, body, closure) | Value::TypedQuotation(_, body, _, closure) => {
+            let captured = closure.as_ref().map(|env| env.local_bindings()).unwrap_or_default();
+            // Values curried into stack code are part of its body
+            let mut values = literals(body);
+            values.extend(captured.iter().map(|(_, value)| value));
+            inside("quotation's closure", values)
+        },
+        _ => None,
+    }
+}
+
+// The values embedded in code, including in nested quotations
+fn literals(body: &[Expr]) -> Vec<&Value> {
+    body.iter()
+        .flat_map(|expr| match expr {
+            Expr::Literal(value) => vec![value.as_ref()],
+            Expr::Quotation(_, body) | Expr::TypedQuotation(_, body, _) | Expr::Sequence(body) => literals(body),
+            _ => Vec::new(),
+        })
+        .collect()
+}
+
+// Check the effects observed while a word ran against its annotations. Resource types are
+// compared without case, so `!creates[File]` covers resources of type "file".
+pub fn verify_effects(word: &str, declared: &[EffectType], observed: &[EffectType]) -> Result<()> {
+    let same = |a: &EffectType, b: &EffectType| match (a, b) {
+        (EffectType::Creates(a), EffectType::Creates(b))
+        | (EffectType::Consumes(a), EffectType::Consumes(b))
+        | (EffectType::Uses(a), EffectType::Uses(b))
+        | (EffectType::Transfers(a), EffectType::Transfers(b)) => a.eq_ignore_ascii_case(b),
+        _ => false,
+    };
+    let declares = |effect: &EffectType| declared.iter().any(|d| same(d, effect));
+    let observes = |effect: &EffectType| observed.iter().any(|o| same(effect, o));
+    let annotations: Vec<String> = declared.iter().map(|e| e.to_string()).collect();
+    let annotations = annotations.join(" ");
+    
+    // Everything the word did must be declared ...
+    for effect in observed {
+        let action = match effect {
+            EffectType::Creates(resource_type) => format!("created a {} resource", resource_type),
+            EffectType::Consumes(resource_type) => format!("consumed a {} resource", resource_type),
+            EffectType::Uses(resource_type) => format!("used a {} resource", resource_type),
+            EffectType::Transfers(resource_type) => format!("transferred a {} resource", resource_type),
+            EffectType::Pure => continue,
+        };
+        if !declares(effect) {
+            return Err(EvaluatorError::EvalError(format!(
+                "word {} declared {} but {}", word, annotations, action
+            )));
+        }
+    }
+    // ... and everything it declares must have happened
+    for effect in declared {
+        let action = match effect {
+            EffectType::Creates(resource_type) => format!("did not create a {} resource", resource_type),
+            EffectType::Consumes(resource_type) => format!("did not consume a {} resource", resource_type),
+            EffectType::Uses(resource_type) => format!("did not use a {} resource", resource_type),
+            EffectType::Transfers(resource_type) => format!("did not transfer a {} resource", resource_type),
+            EffectType::Pure => continue,
+        };
+        if !observes(effect) {
+            return Err(EvaluatorError::EvalError(format!(
+                "word {} declared {} but {}", word, annotations, action
+            )));
+        }
+    }
+    Ok(())
+}
+
 // Extension to Value for resource handling
 pub trait ResourceValue {
     fn get_resource_id(&self) -> Option<usize>;
@@ -245,7 +478,7 @@ pub trait ResourceValue {
 impl ResourceValue for Value {
     fn get_resource_id(&self) -> Option<usize> {
         match self {
-            Value::Resource(id, _) => Some(*id),
+            Value::Resource(id, _) | Value::BorrowedResource(id, _) => Some(*id),
             _ => None,
         }
     }
@@ -268,26 +501,33 @@ pub fn tag_as_resource(value: Value, resource_type: &str, manager: &mut Resource
     value.with_resource_id(id)
 }
 
-pub fn use_resource(value: &Value, manager: &ResourceManager) -> Result<()> {
+pub fn use_resource(value: &Value, manager: &mut ResourceManager) -> Result<()> {
     if let Some(id) = value.get_resource_id() {
-        manager.check_resource(id)?;
-        Ok(())
+        manager.use_resource(id)
     } else {
         Err(EvaluatorError::EvalError("Expected a resource value".to_string()))
     }
 }
 
+// Consume a resource and return its inner value. A Rust finalizer for its type runs here;
+// a quotation finalizer is left to the evaluator, which can run Borf code.
 pub fn consume_resource(value: &Value, manager: &mut ResourceManager) -> Result<Value> {
     if let Some(id) = value.get_resource_id() {
         manager.consume_resource(id)?;
         
         // Return the inner value
-        if let Value::Resource(_, inner) = value {
-            Ok(*inner.clone())
+        let inner = if let Value::Resource(_, inner) = value {
+            *inner.clone()
         } else {
             // This shouldn't happen due to the check above
-            Ok(Value::Nil)
+            Value::Nil
+        };
+        
+        let resource_type = manager.resource_type(id)?;
+        if let Some(Finalizer::Native(finalize)) = manager.finalizer(&resource_type) {
+            finalize(&inner)?;
         }
+        Ok(inner)
     } else {
         Err(EvaluatorError::EvalError("Expected a resource value".to_string()))
     }
@@ -298,7 +538,7 @@ pub fn borrow_resource(value: &Value, manager: &mut ResourceManager) -> Result<V
         manager.borrow_resource(id)?;
         
         // Return a reference to the resource
-        if let Value::Resource(_, inner) = value {
+        if let Value::Resource(_, inner) | Value::BorrowedResource(_, inner) = value {
             Ok(Value::BorrowedResource(id, inner.clone()))
         } else {
             // This shouldn't happen due to the check above
@@ -35,4 +35,25 @@ impl Env {
     pub fn set(&mut self, name: &str, value: Value) {
         self.bindings.insert(name.to_string(), value);
     }
-}
\ No newline at end of file
+
+    // Whether the nearest binding of a name is in the global scope, rather than a
+    // parameter or captured value of an enclosing quotation
+    pub fn is_global(&self, name: &str) -> bool {
+        match &self.parent {
+            _ if self.bindings.contains_key(name) => self.parent.is_none(),
+            Some(parent) => parent.is_global(name),
+            None => false,
+        }
+    }
+
+    // Bindings made below the global scope, such as the parameters of enclosing quotations.
+    // Inner scopes come last so their bindings win when applied in order.
+    pub fn local_bindings(&self) -> Vec<(String, Value)> {
+        let mut bindings = match &self.parent {
+            Some(parent) => parent.local_bindings(),
+            None => return Vec::new(),
+        };
+        bindings.extend(self.bindings.iter().map(|(name, value)| (name.clone(), value.clone())));
+        bindings
+    }
+}
@@ -2,19 +2,168 @@
 // This module provides the evaluator for the Borf interpreter
 
 use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt::Write as _;
 use std::fs;
+use std::hash::Hasher;
+use std::io::{BufRead, BufReader, Read, Write};
 use std::path::{Path, PathBuf};
-use crate::repl::interpreter::types::{Env, EvaluatorError, Expr, Param, Pattern, Result, Type, Value};
+use std::rc::Rc;
+use crate::repl::interpreter::types::{Env, EvaluatorError, Expr, Param, Pattern, Result, SourceMap, Span, Type, Value};
 use crate::repl::interpreter::parser::Parser;
-use crate::repl::interpreter::effects::{ResourceManager, ResourceValue, EffectType, 
-                                        tag_as_resource, use_resource, consume_resource, borrow_resource};
+use crate::repl::interpreter::effects::{ResourceManager, ResourceValue, EffectType, Finalizer, Origin,
+                                        use_resource, consume_resource, borrow_resource, verify_effects};
+use crate::repl::interpreter::stack_types::{infer_quotation, format_type};
+use crate::repl::interpreter::stack_effects::{StackEffect, get_word_effect, translate_quotation_with};
+use crate::repl::interpreter::stack_check::check_program;
+use crate::repl::interpreter::linearity::{check_linearity, free_symbols};
+use crate::repl::interpreter::io::{FileTable, FILE_RESOURCE, file_value};
+use crate::repl::interpreter::handles::read_line;
+use crate::repl::interpreter::capabilities::Capabilities;
+use crate::repl::interpreter::process::{ProcessTable, PROCESS_RESOURCE, PIPE_RESOURCE, process_value, pipe_value};
+use crate::repl::interpreter::net::{NetTable, Listener, Socket, LISTENER_RESOURCE, SOCKET_RESOURCE, net_value};
+use crate::repl::interpreter::acset::{ACSet, Schema, parts_value};
+use crate::repl::interpreter::query::{homomorphisms, homomorphism_value, query};
+use crate::repl::interpreter::migration::{SchemaFunctor, delta_migrate, sigma_migrate};
+use crate::repl::interpreter::wiring::{WiringDiagram, port_types};
+use crate::repl::interpreter::dataflow::quotation_to_diagram;
+use crate::repl::interpreter::inet::{Cell, InteractionRule, Net, RewriteSystem};
+use crate::repl::interpreter::optimizer::Optimizer;
+
+/// How quotations with named parameters are applied
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// Bind the parameters in a new environment
+    Named,
+    /// Run the STACKER translation of the quotation as plain stack code
+    Stacker,
+}
+
+/// Final stacks of one program run with each strategy, or the error it stopped with
+#[derive(Debug, Clone, PartialEq)]
+pub struct Differential {
+    pub named: std::result::Result<Vec<Value>, String>,
+    pub stacker: std::result::Result<Vec<Value>, String>,
+    /// Quotations the STACKER run could not translate and applied by binding their parameters
+    pub fallbacks: Vec<String>,
+}
+
+impl Differential {
+    pub fn diverged(&self) -> bool {
+        self.named != self.stacker
+    }
+}
+
+impl std::fmt::Display for Differential {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn outcome(result: &std::result::Result<Vec<Value>, String>) -> String {
+            match result {
+                Ok(stack) => {
+                    let items: Vec<String> = stack.iter().map(|v| v.to_string()).collect();
+                    format!("[{}]", items.join(" "))
+                },
+                Err(error) => format!("error: {}", error),
+            }
+        }
+        if self.diverged() {
+            writeln!(f, "Strategies diverge")?;
+            writeln!(f, "  named:   {}", outcome(&self.named))?;
+            write!(f, "  stacker: {}", outcome(&self.stacker))?;
+        } else {
+            write!(f, "Strategies agree: {}", outcome(&self.named))?;
+        }
+        for quotation in &self.fallbacks {
+            write!(f, "\n  stacker bound the parameters of {}", quotation)?;
+        }
+        Ok(())
+    }
+}
+
+// A STACKER translation, with the quotation it was made from to tell fingerprint collisions apart
+struct Translation {
+    params: Vec<Param>,
+    body: Vec<Expr>,
+    code: Vec<Expr>,
+}
+
+// Hashes formatted text without building the string
+struct Fingerprint(DefaultHasher);
+
+impl std::fmt::Write for Fingerprint {
+    fn write_str(&mut self, text: &str) -> std::fmt::Result {
+        self.0.write(text.as_bytes());
+        Ok(())
+    }
+}
+
+fn fingerprint(params: &[Param], body: &[Expr]) -> u64 {
+    let mut hasher = Fingerprint(DefaultHasher::new());
+    let _ = write!(hasher, "{:?}{:?}", params, body);
+    hasher.0.finish()
+}
 
 // Evaluator with resource tracking
 pub struct Evaluator {
     pub env: Env,
     pub stack: Vec<Value>,
     pub prelude_path: PathBuf,
+    // Stack effects of user-defined words, inferred or declared when a file is loaded
+    pub word_effects: HashMap<String, StackEffect>,
+    // Stack effects declared for words, enforced at runtime in checked mode
+    pub declared_effects: HashMap<String, StackEffect>,
+    // Whether word calls are checked against their declared stack effects
+    pub checked: bool,
+    // How quotations with named parameters are applied
+    pub strategy: Strategy,
+    // STACKER translations by quotation fingerprint and the effects generation they were made in
+    translations: HashMap<(u64, u64), Translation>,
+    // Bumped whenever the known stack effects or the optimizer's rules change
+    effects_generation: u64,
+    // Quotations the STACKER strategy could not translate, in the order first met
+    fallbacks: Vec<String>,
+    // Peephole rules applied to translations and by the optimize word
+    pub optimizer: Optimizer,
+    // A declaration waiting for the definition that follows it
+    pending_effect: Option<StackEffect>,
+    // Resource effects declared for words (`!creates[File]`), verified in checked mode
+    pub annotated_effects: HashMap<String, Vec<EffectType>>,
+    // Resource effects seen while each word ran
+    pub observed_effects: HashMap<String, Vec<EffectType>>,
+    // Annotations waiting for the definition that follows them
+    pending_annotations: Vec<EffectType>,
+    // Lowest stack depth reached during the innermost checked word call
+    low_water: Option<usize>,
+    // Names bound during each open borrowing region, innermost last
+    region_bindings: Vec<Vec<String>>,
+    // What is being run, for leak reports: the file or "<input>"
+    source: String,
+    // Words being called, innermost last, with where each was called from
+    frames: Vec<(String, String)>,
+    // Where the expression the innermost sequence is evaluating starts
+    position: Option<Span>,
+    // Where the expressions in the body of each word defined in source start
+    word_spans: HashMap<String, Rc<[Span]>>,
+    // Body spans of the definitions in the program being run, until each is evaluated
+    parsed_spans: HashMap<String, Vec<Span>>,
+    // Spans for the body of the word about to run
+    next_spans: Option<Rc<[Span]>>,
     resource_manager: ResourceManager,
+    // OS handles of the files open as File resources
+    files: FileTable,
+    // Child processes and pipes open as Process and Pipe resources
+    processes: ProcessTable,
+    // Sockets open as Listener and Socket resources
+    net: NetTable,
+    // What I/O words may access; nothing unless the embedder grants it
+    pub capabilities: Capabilities,
+    // Command-line arguments of a script, and where read_stdin_line and read_stdin read from
+    pub args: Vec<String>,
+    pub input: Box<dyn BufRead>,
+}
+
+// Whether a condition selects the true branch of if: anything but zero and nil does
+fn is_truthy(value: &Value) -> bool {
+    !matches!(value, Value::Number(0) | Value::Nil | Value::Nothing)
 }
 
 impl Evaluator {
@@ -23,7 +172,33 @@ impl Evaluator {
             env: Env::new(),
             stack: Vec::new(),
             prelude_path: PathBuf::from("src/prelude"),
+            word_effects: HashMap::new(),
+            declared_effects: HashMap::new(),
+            checked: false,
+            strategy: Strategy::Named,
+            translations: HashMap::new(),
+            effects_generation: 0,
+            fallbacks: Vec::new(),
+            optimizer: Optimizer::new(),
+            pending_effect: None,
+            annotated_effects: HashMap::new(),
+            observed_effects: HashMap::new(),
+            pending_annotations: Vec::new(),
+            low_water: None,
+            region_bindings: Vec::new(),
+            source: "<input>".to_string(),
+            frames: Vec::new(),
+            position: None,
+            word_spans: HashMap::new(),
+            parsed_spans: HashMap::new(),
+            next_spans: None,
             resource_manager: ResourceManager::new(),
+            files: FileTable::new(),
+            processes: ProcessTable::new(),
+            net: NetTable::new(),
+            capabilities: Capabilities::none(),
+            args: Vec::new(),
+            input: Box::new(BufReader::new(std::io::stdin())),
         }
     }
 
@@ -32,35 +207,225 @@ impl Evaluator {
             env: Env::new(),
             stack: Vec::new(),
             prelude_path: prelude_path.as_ref().to_path_buf(),
+            word_effects: HashMap::new(),
+            declared_effects: HashMap::new(),
+            checked: false,
+            strategy: Strategy::Named,
+            translations: HashMap::new(),
+            effects_generation: 0,
+            fallbacks: Vec::new(),
+            optimizer: Optimizer::new(),
+            pending_effect: None,
+            annotated_effects: HashMap::new(),
+            observed_effects: HashMap::new(),
+            pending_annotations: Vec::new(),
+            low_water: None,
+            region_bindings: Vec::new(),
+            source: "<input>".to_string(),
+            frames: Vec::new(),
+            position: None,
+            word_spans: HashMap::new(),
+            parsed_spans: HashMap::new(),
+            next_spans: None,
             resource_manager: ResourceManager::new(),
+            files: FileTable::new(),
+            processes: ProcessTable::new(),
+            net: NetTable::new(),
+            capabilities: Capabilities::none(),
+            args: Vec::new(),
+            input: Box::new(BufReader::new(std::io::stdin())),
         }
     }
     
     // Resource management functions
     
-    // Create a new resource
+    // Create a new resource, remembering where it was created
     fn create_resource(&mut self, resource_type: &str, value: Value) -> Value {
-        tag_as_resource(value, resource_type, &mut self.resource_manager)
+        let origin = Origin {
+            location: self.here(),
+            call_stack: self.frames.iter().rev()
+                .map(|(word, called_at)| format!("{} called at {}", word, called_at))
+                .collect(),
+        };
+        let id = self.resource_manager.create_resource_at(resource_type, origin);
+        value.with_resource_id(id)
+    }
+    
+    // The expression being evaluated and the word or source it belongs to
+    fn here(&self) -> String {
+        let context = self.frames.last().map_or(&self.source, |(word, _)| word);
+        match self.position {
+            Some(span) => format!("{} in {}", span, context),
+            None => context.clone(),
+        }
     }
     
     // Use a resource (check if it's valid)
-    fn use_resource(&self, value: &Value) -> Result<()> {
-        use_resource(value, &self.resource_manager)
+    fn use_resource(&mut self, value: &Value) -> Result<()> {
+        use_resource(value, &mut self.resource_manager)
     }
     
-    // Consume a resource
+    // Consume a resource, running the finalizer of its type
     fn consume_resource(&mut self, value: &Value) -> Result<Value> {
-        consume_resource(value, &mut self.resource_manager)
+        let inner = consume_resource(value, &mut self.resource_manager)?;
+        let resource_type = value.get_resource_id()
+            .and_then(|id| self.resource_manager.resource_type(id).ok())
+            .unwrap_or_default();
+        if let Some(Finalizer::Quotation(quotation)) = self.resource_manager.finalizer(&resource_type).cloned() {
+            // The finalizer takes a copy of the inner value: ( value -- )
+            self.stack.push(inner.clone());
+            match quotation {
+                Value::Quotation(params, body, closure) | Value::TypedQuotation(params, body, _, closure) => {
+                    self.apply_quotation(&params, &body, closure.as_deref())?;
+                },
+                _ => return Err(EvaluatorError::TypeError("A finalizer must be a quotation".to_string())),
+            }
+        }
+        Ok(inner)
+    }
+    
+    // Register the finalizer run when a resource of the given type is consumed
+    pub fn register_finalizer(&mut self, resource_type: &str, finalizer: Finalizer) {
+        self.resource_manager.register_finalizer(resource_type, finalizer);
+    }
+    
+    // Open a file as a File resource
+    fn open_file(&mut self, path: &str, mode: &str) -> Result<Value> {
+        if mode == "r" {
+            self.capabilities.check_read(path)?;
+        } else {
+            self.capabilities.check_write(path)?;
+        }
+        let file = FileTable::open(path, mode)?;
+        let id = self.create_resource(FILE_RESOURCE, Value::Nil).get_resource_id().unwrap_or_default();
+        self.files.insert(id, file);
+        Ok(Value::Resource(id, Box::new(file_value(path, mode, id))))
+    }
+    
+    // The ID and type of the resource on top of the stack, which stays there,
+    // checking it is live and one of the given types
+    fn top_resource(&mut self, word: &str, types: &[&str]) -> Result<(usize, String)> {
+        let expected = types.join(" or ");
+        let value = self.stack.last().cloned()
+            .ok_or_else(|| EvaluatorError::EvalError(format!("{} requires a {} on the stack", word, expected)))?;
+        self.use_resource(&value)?;
+        let id = value.get_resource_id().unwrap_or_default();
+        let resource_type = self.resource_manager.resource_type(id)?;
+        if !types.contains(&resource_type.as_str()) {
+            return Err(EvaluatorError::TypeError(format!("{} requires a {}, got {}", word, expected, value)));
+        }
+        Ok((id, resource_type))
+    }
+    
+    // The handle of the open file on top of the stack
+    fn top_file(&mut self, word: &str) -> Result<usize> {
+        self.top_resource(word, &[FILE_RESOURCE]).map(|(id, _)| id)
+    }
+    
+    // Track a listening socket as a Listener resource
+    fn listener_resource(&mut self, listener: Listener, address: &str) -> Value {
+        let id = self.create_resource(LISTENER_RESOURCE, Value::Nil).get_resource_id().unwrap_or_default();
+        self.net.insert_listener(id, listener);
+        Value::Resource(id, Box::new(net_value(address, id)))
+    }
+    
+    // Track a connection as a Socket resource
+    fn socket_resource(&mut self, socket: Socket, address: &str) -> Value {
+        let id = self.create_resource(SOCKET_RESOURCE, Value::Nil).get_resource_id().unwrap_or_default();
+        self.net.insert_socket(id, socket);
+        Value::Resource(id, Box::new(net_value(address, id)))
+    }
+    
+    // Pop a port number argument of a word
+    fn pop_port(&mut self, word: &str) -> Result<u16> {
+        match self.stack.pop() {
+            Some(Value::Number(port)) if (0..=65535).contains(&port) => Ok(port as u16),
+            Some(other) => Err(EvaluatorError::TypeError(format!("{} requires a port number, got {}", word, other))),
+            None => Err(EvaluatorError::EvalError(format!("{} requires a port number on the stack", word))),
+        }
+    }
+    
+    // Pop a list of string arguments of a word
+    fn pop_strings(&mut self, word: &str) -> Result<Vec<String>> {
+        match self.stack.pop() {
+            Some(Value::List(items)) => items.into_iter()
+                .map(|item| match item {
+                    Value::String(s) => Ok(s),
+                    other => Err(EvaluatorError::TypeError(format!("{} requires a list of strings, got {}", word, other))),
+                })
+                .collect(),
+            Some(other) => Err(EvaluatorError::TypeError(format!("{} requires a list of strings, got {}", word, other))),
+            None => Err(EvaluatorError::EvalError(format!("{} requires a list on the stack", word))),
+        }
+    }
+    
+    // Pop a string argument of a word
+    fn pop_string(&mut self, word: &str) -> Result<String> {
+        match self.stack.pop() {
+            Some(Value::String(s)) => Ok(s),
+            Some(other) => Err(EvaluatorError::TypeError(format!("{} requires a string, got {}", word, other))),
+            None => Err(EvaluatorError::EvalError(format!("{} requires a string on the stack", word))),
+        }
+    }
+    
+    // Pop a part number, or a count of parts, argument of a word
+    fn pop_part(&mut self, word: &str) -> Result<usize> {
+        match self.stack.pop() {
+            Some(Value::Number(n)) if n >= 0 => Ok(n as usize),
+            Some(other) => Err(EvaluatorError::TypeError(format!("{} requires a part number, got {}", word, other))),
+            None => Err(EvaluatorError::EvalError(format!("{} requires a part number on the stack", word))),
+        }
+    }
+    
+    // Pop the ACSet a word works on
+    fn pop_acset(&mut self, word: &str) -> Result<Box<ACSet>> {
+        match self.stack.pop() {
+            Some(Value::ACSet(acset)) => Ok(acset),
+            Some(other) => Err(EvaluatorError::TypeError(format!("{} requires an ACSet, got {}", word, other))),
+            None => Err(EvaluatorError::EvalError(format!("{} requires an ACSet on the stack", word))),
+        }
+    }
+    
+    // Pop the wiring diagram a word works on
+    fn pop_diagram(&mut self, word: &str) -> Result<Box<WiringDiagram>> {
+        match self.stack.pop() {
+            Some(Value::WiringDiagram(diagram)) => Ok(diagram),
+            Some(other) => Err(EvaluatorError::TypeError(format!("{} requires a wiring diagram, got {}", word, other))),
+            None => Err(EvaluatorError::EvalError(format!("{} requires a wiring diagram on the stack", word))),
+        }
+    }
+    
+    // Pop the interaction net a word works on
+    fn pop_net(&mut self, word: &str) -> Result<Box<Net>> {
+        match self.stack.pop() {
+            Some(Value::Net(net)) => Ok(net),
+            Some(other) => Err(EvaluatorError::TypeError(format!("{} requires an interaction net, got {}", word, other))),
+            None => Err(EvaluatorError::EvalError(format!("{} requires an interaction net on the stack", word))),
+        }
     }
     
     // Start a borrowing region
     fn start_borrowing_region(&mut self) {
         self.resource_manager.start_region();
+        self.region_bindings.push(Vec::new());
     }
     
-    // End a borrowing region
+    // End a borrowing region; its borrows must not survive on the stack or in a binding
+    // made while it was open, the only ones that can hold them
     fn end_borrowing_region(&mut self) -> Result<()> {
-        self.resource_manager.end_region()
+        let mut names = self.region_bindings.pop().unwrap_or_default();
+        names.sort();
+        names.dedup();
+        let mut live: Vec<(String, Value)> = self.stack.iter()
+            .map(|value| ("on the stack".to_string(), value.clone()))
+            .collect();
+        live.extend(names.iter()
+            .filter_map(|name| self.env.get(name).map(|value| (format!("in binding '{}'", name), value))));
+        // Bindings made in an inner region were also made in the regions around it
+        if let Some(outer) = self.region_bindings.last_mut() {
+            outer.extend(names);
+        }
+        self.resource_manager.end_region(&live)
     }
     
     // Borrow a resource
@@ -68,8 +433,8 @@ impl Evaluator {
         borrow_resource(value, &mut self.resource_manager)
     }
     
-    // Check for resource leaks
-    fn check_for_resource_leaks(&self) -> Result<()> {
+    // Check for resources that were created but never consumed
+    pub fn check_for_resource_leaks(&self) -> Result<()> {
         self.resource_manager.check_for_leaks()
     }
 
@@ -89,48 +454,266 @@ impl Evaluator {
         self.env.set("over", Value::Symbol("over".to_string()));
         self.env.set("tuck", Value::Symbol("tuck".to_string()));
         self.env.set("pick", Value::Symbol("pick".to_string()));
+        self.env.set("roll", Value::Symbol("roll".to_string()));
+        self.env.set("nip", Value::Symbol("nip".to_string()));
+        self.env.set("2dup", Value::Symbol("2dup".to_string()));
         
         // Add data structures and control operations
         self.env.set("list", Value::Symbol("list".to_string()));
         self.env.set("map", Value::Symbol("map".to_string()));
         self.env.set("if", Value::Symbol("if".to_string()));
         self.env.set("eq", Value::Symbol("eq".to_string()));
+        self.env.set("call", Value::Symbol("call".to_string()));
+        self.env.set("curry", Value::Symbol("curry".to_string()));
         
         // Add metaprogramming operations
         self.env.set("eval", Value::Symbol("eval".to_string()));
+        self.env.set("expand", Value::Symbol("expand".to_string()));
+        self.env.set("optimize", Value::Symbol("optimize".to_string()));
+        self.env.set("rule", Value::Symbol("rule".to_string()));
         self.env.set("quote", Value::Symbol("quote".to_string()));
         self.env.set("unquote", Value::Symbol("unquote".to_string()));
         self.env.set("quasiquote", Value::Symbol("quasiquote".to_string()));
         
         // Add resource management operations
         self.env.set("create_resource", Value::Symbol("create_resource".to_string()));
+        self.env.set("register_finalizer", Value::Symbol("register_finalizer".to_string()));
         self.env.set("consume_resource", Value::Symbol("consume_resource".to_string()));
         self.env.set("borrow", Value::Symbol("borrow".to_string()));
         self.env.set("is_resource", Value::Symbol("is_resource".to_string()));
         self.env.set("resource_type", Value::Symbol("resource_type".to_string()));
         self.env.set("with_borrowed", Value::Symbol("with_borrowed".to_string()));
+        self.env.set("unwrap", Value::Symbol("unwrap".to_string()));
+        self.env.set("try", Value::Symbol("try".to_string()));
+        
+        // Add process operations; a Process is waited for or killed, consuming a Pipe closes it
+        self.resource_manager.register_finalizer(PIPE_RESOURCE, self.processes.pipe_finalizer());
+        for word in ["run", "spawn", "wait", "kill", "stdin_pipe", "stdout_pipe", "stderr_pipe", "close_pipe"] {
+            self.env.set(word, Value::Symbol(word.to_string()));
+        }
+        
+        // Add network operations; consuming a Listener or Socket closes it
+        self.resource_manager.register_finalizer(LISTENER_RESOURCE, self.net.listener_finalizer());
+        self.resource_manager.register_finalizer(SOCKET_RESOURCE, self.net.socket_finalizer());
+        for word in ["tcp_listen", "tcp_connect", "unix_listen", "unix_connect", "accept", "local_port", "send", "recv", "close"] {
+            self.env.set(word, Value::Symbol(word.to_string()));
+        }
+        
+        // Add ACSet operations, named as in catlab.borf
+        for word in ["schema", "empty_acset", "add_part", "add_parts", "nparts", "parts", "set_subpart", "subpart",
+                     "incident", "set_prop", "get_prop", "rem_part", "cascading_rem_part",
+                     "homomorphisms", "monic_homomorphisms", "query",
+                     "schema_functor", "delta_migrate", "sigma_migrate"] {
+            self.env.set(word, Value::Symbol(word.to_string()));
+        }
+        
+        // Add wiring diagram operations, named as in catlab.borf
+        for word in ["empty_diagram", "add_box", "add_wire", "compose", "tensor", "substitute", "to_dot", "quotation_to_diagram"] {
+            self.env.set(word, Value::Symbol(word.to_string()));
+        }
+        
+        // Add interaction net operations, named as in catlab.borf
+        for word in ["create_cell", "empty_net", "add_cell", "connect", "active_pairs", "create_rewrite_system", "normalize", "normalize_parallel"] {
+            self.env.set(word, Value::Symbol(word.to_string()));
+        }
+        
+        // Add script operations
+        for word in ["args", "getenv", "read_stdin_line", "read_stdin", "exit"] {
+            self.env.set(word, Value::Symbol(word.to_string()));
+        }
+        
+        // Add file operations; consuming a File resource closes its handle
+        self.resource_manager.register_finalizer(FILE_RESOURCE, self.files.finalizer());
+        for word in ["open_file", "close_file", "read_all", "read_line", "write", "seek", "with_file"] {
+            self.env.set(word, Value::Symbol(word.to_string()));
+        }
         
         // Add stack inspection and debugging
         self.env.set(".s", Value::Symbol(".s".to_string()));
         self.env.set("depth", Value::Symbol("depth".to_string()));
         self.env.set(".resources", Value::Symbol(".resources".to_string()));
+        self.env.set(".effects", Value::Symbol(".effects".to_string()));
         
         Ok(())
     }
     
     // Evaluate a Borf program
     pub fn eval(&mut self, input: &str) -> Result<Value> {
+        let mut parser = Parser::new(input);
+        match parser.parse_with_spans() {
+            Ok((expr, spans)) => {
+                // Programs that only work on the stack show the top of the stack
+                match self.eval_program(&expr, spans)? {
+                    Some(value) => Ok(value),
+                    None => Ok(self.stack.last().cloned().unwrap_or(Value::Nil)),
+                }
+            },
+            Err(e) => Err(EvaluatorError::ParseError(e.to_string())),
+        }
+    }
+    
+    // Infer the type of a Borf expression without evaluating it
+    pub fn type_of(&self, input: &str) -> Result<String> {
         let mut parser = Parser::new(input);
         match parser.parse() {
-            Ok(expr) => self.eval_expr(&expr).map(|opt_val| opt_val.unwrap_or(Value::Nil)),
-            Err(e) => Err(e),
+            Ok(expr) => {
+                let typ = self.infer_type(&expr)?;
+                self.type_to_string(&typ)
+            },
+            Err(e) => Err(EvaluatorError::ParseError(e.to_string())),
         }
     }
     
+    // Show the stack translation of a quotation, or of the word with the given name
+    pub fn expand(&self, input: &str) -> Result<String> {
+        let mut parser = Parser::new(input);
+        let expr = parser.parse()
+            .map_err(|e| EvaluatorError::ParseError(e.to_string()))?;
+        let expr = match expr {
+            Expr::Sequence(mut exprs) if exprs.len() == 1 => exprs.remove(0),
+            expr => expr,
+        };
+        
+        let (params, body) = match expr {
+            Expr::Quotation(params, body) => (params, body),
+            Expr::Symbol(name) => match self.env.get(&name) {
+                Some(Value::Quotation(params, body, _)) => (params, body),
+                _ => return Err(EvaluatorError::EvalError(format!("'{}' is not a quotation", name))),
+            },
+            _ => return Err(EvaluatorError::EvalError(
+                "expand expects a quotation or the name of a word".to_string()
+            )),
+        };
+        
+        let translated = translate_quotation_with(&params, &body, &self.known_effects())
+            .map_err(|e| EvaluatorError::EvalError(e.to_string()))?;
+        Ok(Expr::Quotation(Vec::new(), self.optimizer.optimize(&translated)).to_string())
+    }
+    
+    // Show a quotation of stack code, or the word with the given name, after optimization
+    pub fn optimize(&self, input: &str) -> Result<String> {
+        let mut parser = Parser::new(input);
+        let expr = parser.parse()
+            .map_err(|e| EvaluatorError::ParseError(e.to_string()))?;
+        let expr = match expr {
+            Expr::Sequence(mut exprs) if exprs.len() == 1 => exprs.remove(0),
+            expr => expr,
+        };
+        
+        let body = match expr {
+            Expr::Quotation(params, body) if params.is_empty() => body,
+            Expr::Symbol(name) => match self.env.get(&name) {
+                Some(Value::Quotation(params, body, _)) if params.is_empty() => body,
+                _ => return Err(EvaluatorError::EvalError(format!("'{}' is not a quotation of stack code", name))),
+            },
+            _ => return Err(EvaluatorError::EvalError(
+                "optimize expects a quotation without named parameters or the name of a word".to_string()
+            )),
+        };
+        Ok(Expr::Quotation(Vec::new(), self.optimizer.optimize(&body)).to_string())
+    }
+    
+    // Show the dataflow of a quotation, or the word with the given name, as Graphviz DOT
+    pub fn dot(&self, input: &str) -> Result<String> {
+        let mut parser = Parser::new(input);
+        let expr = parser.parse()
+            .map_err(|e| EvaluatorError::ParseError(e.to_string()))?;
+        let expr = match expr {
+            Expr::Sequence(mut exprs) if exprs.len() == 1 => exprs.remove(0),
+            expr => expr,
+        };
+        
+        let (params, body) = match expr {
+            Expr::Quotation(params, body) => (params, body),
+            Expr::Symbol(name) => match self.env.get(&name) {
+                Some(Value::Quotation(params, body, _)) => (params, body),
+                _ => return Err(EvaluatorError::EvalError(format!("'{}' is not a quotation", name))),
+            },
+            _ => return Err(EvaluatorError::EvalError(
+                "dot expects a quotation or the name of a word".to_string()
+            )),
+        };
+        Ok(self.dataflow(&params, &body)?.to_dot())
+    }
+    
+    // The wiring diagram of a quotation's STACKER translation
+    fn dataflow(&self, params: &[Param], body: &[Expr]) -> Result<WiringDiagram> {
+        let known = self.known_effects();
+        let code = if params.is_empty() {
+            body.to_vec()
+        } else {
+            translate_quotation_with(params, body, &known)
+                .map_err(|e| EvaluatorError::EvalError(e.to_string()))?
+        };
+        quotation_to_diagram(&self.optimizer.optimize(&code), &known)
+    }
+    
+    // Stack effects of user words, inferred or declared, for translating calls to them
+    fn known_effects(&self) -> HashMap<String, StackEffect> {
+        let mut known = self.word_effects.clone();
+        known.extend(self.declared_effects.iter().map(|(name, effect)| (name.clone(), effect.clone())));
+        known
+    }
+    
     // Evaluate a Borf file
     pub fn eval_file<P: AsRef<Path>>(&mut self, file_path: P) -> Result<Value> {
         let content = fs::read_to_string(file_path.as_ref())?;
-        self.eval(&content)
+        
+        // Every resource the file creates must be consumed by the time it finishes
+        let first_resource = self.resource_manager.next_id();
+        let outer_source = std::mem::replace(&mut self.source, file_path.as_ref().display().to_string());
+        let result = self.eval_source(&content);
+        self.source = outer_source;
+        let value = result?;
+        self.resource_manager.check_for_leaks_since(first_resource)?;
+        Ok(value)
+    }
+    
+    // Evaluate the contents of a Borf file, checking stack effects before running it
+    pub fn eval_source(&mut self, content: &str) -> Result<Value> {
+        let mut parser = Parser::new(content);
+        let (program, spans) = parser.parse_with_spans()
+            .map_err(|e| EvaluatorError::ParseError(e.to_string()))?;
+        
+        // Verify declared stack effects and infer the rest before running anything
+        let effects = check_program(&program)
+            .map_err(|e| EvaluatorError::TypeError(e.to_string()))?;
+        
+        // Every linear value must be used exactly once on every path
+        let mut known = self.known_effects();
+        known.extend(effects.clone());
+        let errors = check_linearity(&program, &known);
+        if !errors.is_empty() {
+            let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            return Err(EvaluatorError::TypeError(messages.join("\n")));
+        }
+        if !effects.is_empty() {
+            self.word_effects.extend(effects);
+            self.effects_changed();
+        }
+        
+        self.eval_program(&program, spans).map(|opt_val| opt_val.unwrap_or(Value::Nil))
+    }
+    
+    // Evaluate a parsed program, placing each expression at the span the parser found it at
+    fn eval_program(&mut self, program: &Expr, spans: SourceMap) -> Result<Option<Value>> {
+        let outer_spans = std::mem::replace(&mut self.parsed_spans, spans.words);
+        let outer_position = self.position;
+        let result = match (program, spans.program.as_slice()) {
+            (Expr::Sequence(_), program_spans) => {
+                self.next_spans = Some(program_spans.into());
+                self.eval_expr(program)
+            },
+            (_, [span]) => {
+                self.position = Some(*span);
+                self.eval_expr(program)
+            },
+            _ => self.eval_expr(program),
+        };
+        self.position = outer_position;
+        self.next_spans = None;
+        self.parsed_spans = outer_spans;
+        result
     }
     
     // Evaluate an expression with type checking
@@ -138,14 +721,26 @@ impl Evaluator {
         match expr {
             Expr::Number(n) => Ok(Some(Value::Number(*n))),
             Expr::String(s) => Ok(Some(Value::String(s.clone()))),
+            Expr::Literal(value) => Ok(Some(value.as_ref().clone())),
             Expr::Symbol(s) => {
                 // Look up symbol in environment
-                if let Some(value) = self.env.get(s) {
-                    Ok(Some(value))
-                } else {
-                    // Try to execute as operation
-                    self.execute_operation(s)?;
-                    Ok(None)
+                match self.env.get(s) {
+                    // Words defined as quotations and built-in operations are called; a
+                    // quotation bound to a parameter is a value like any other
+                    Some(Value::Quotation(..)) | Some(Value::TypedQuotation(..)) if self.env.is_global(s) => {
+                        self.call_word(s)?;
+                        Ok(None)
+                    },
+                    Some(Value::Symbol(op)) if op == *s => {
+                        self.call_word(s)?;
+                        Ok(None)
+                    },
+                    Some(value) => Ok(Some(value)),
+                    None => {
+                        // Try to execute as operation
+                        self.call_word(s)?;
+                        Ok(None)
+                    }
                 }
             },
             Expr::Quotation(params, body) => {
@@ -229,10 +824,41 @@ impl Evaluator {
                 
                 // Bind the value in the environment
                 self.env.set(name, value.clone());
+                match self.parsed_spans.remove(name) {
+                    Some(spans) => { self.word_spans.insert(name.clone(), spans.into()); },
+                    None => { self.word_spans.remove(name); },
+                }
+                if let Some(names) = self.region_bindings.last_mut() {
+                    names.push(name.clone());
+                }
+                
+                // A stack effect written just before a definition belongs to it
+                if let Some(effect) = self.pending_effect.take() {
+                    self.declared_effects.insert(name.clone(), effect);
+                    self.effects_changed();
+                }
+                if !self.pending_annotations.is_empty() {
+                    let annotations = std::mem::take(&mut self.pending_annotations);
+                    self.annotated_effects.insert(name.clone(), annotations);
+                }
                 
                 // Return the value
                 Ok(Some(value))
             },
+            Expr::Sequence(exprs) => {
+                self.eval_sequence(exprs)?;
+                Ok(None)
+            },
+            Expr::StackEffect(effect) => {
+                // Attach the declaration to the next definition
+                self.pending_effect = Some(effect.clone());
+                Ok(None)
+            },
+            Expr::EffectAnnotation(effect) => {
+                // Annotations also belong to the next definition
+                self.pending_annotations.push(effect.clone());
+                Ok(None)
+            },
             Expr::Match(expr, patterns) => {
                 // Evaluate the expression to match against
                 let value = self.eval_expr(expr)?
@@ -311,11 +937,308 @@ impl Evaluator {
                     _ => Err(EvaluatorError::TypeError("Cannot unquote non-quoted type".to_string())),
                 }
             },
+            Expr::Tuple(items) => {
+                // The values the elements leave on the stack, as a list: ("-c" "ls")
+                let depth = self.stack.len();
+                self.eval_sequence(items)?;
+                Ok(Some(Value::List(self.stack.split_off(depth))))
+            },
+            Expr::If(condition, then_branch, else_branch) => {
+                // A Nil condition means the condition is already on the stack
+                if !matches!(condition.as_ref(), Expr::Nil) {
+                    if let Some(value) = self.eval_expr(condition)? {
+                        self.stack.push(value);
+                    }
+                }
+                let condition = self.stack.pop()
+                    .ok_or_else(|| EvaluatorError::EvalError("if requires a condition on the stack".to_string()))?;
+                let branch = if is_truthy(&condition) { then_branch } else { else_branch };
+                self.eval_expr(branch)
+            },
             // TODO: Implement other expression types
             _ => Err(EvaluatorError::EvalError(format!("Unsupported expression type: {:?}", expr))),
         }
     }
     
+    // Evaluate expressions in order, leaving the values they produce on the stack
+    fn eval_sequence(&mut self, exprs: &[Expr]) -> Result<()> {
+        let spans = self.next_spans.take().filter(|spans| spans.len() == exprs.len());
+        let mut index = 0;
+        while index < exprs.len() {
+            // `name ( a b -- c )` declares the stack effect of a word
+            if let (Expr::Symbol(name), Some(Expr::StackEffect(effect))) = (&exprs[index], exprs.get(index + 1)) {
+                self.declared_effects.insert(name.clone(), effect.clone());
+                self.effects_changed();
+                index += 2;
+                
+                // followed by any resource effect annotations: `!creates[File]`
+                let mut annotations = Vec::new();
+                while let Some(Expr::EffectAnnotation(effect)) = exprs.get(index) {
+                    annotations.push(effect.clone());
+                    index += 1;
+                }
+                if !annotations.is_empty() {
+                    self.annotated_effects.insert(name.clone(), annotations);
+                }
+                continue;
+            }
+            
+            let expr = &exprs[index];
+            let outer_position = self.position;
+            if let Some(spans) = &spans {
+                self.position = Some(spans[index]);
+            }
+            let value = self.eval_expr(expr);
+            self.position = outer_position;
+            let value = value?;
+            
+            // Definitions bind a name rather than leaving a value behind
+            if let Some(value) = value {
+                if !matches!(expr, Expr::Assignment(..)) {
+                    self.stack.push(value);
+                }
+            }
+            index += 1;
+        }
+        Ok(())
+    }
+    
+    // Call a word, recording the call for leak reports
+    fn call_word(&mut self, name: &str) -> Result<()> {
+        // Only words defined in Borf appear in the call stack
+        if !matches!(self.env.get(name), Some(Value::Quotation(..)) | Some(Value::TypedQuotation(..))) {
+            return self.call_checked(name);
+        }
+        let called_at = self.here();
+        self.frames.push((name.to_string(), called_at));
+        let held = self.held_resources();
+        let mark = self.resource_manager.mark();
+        self.next_spans = self.word_spans.get(name).cloned();
+        let result = self.call_checked(name);
+        self.next_spans = None;
+        self.frames.pop();
+        result?;
+        
+        // Compare what the word did to resources with its annotations. A resource that was
+        // on the stack before the call and is not afterwards has been handed over, unless consumed.
+        let still_held = self.held_resources();
+        let moved: Vec<usize> = held.into_iter().filter(|id| !still_held.contains(id)).collect();
+        let observed = self.resource_manager.effects_since(mark, &moved);
+        self.observe_effects(name, &observed);
+        if self.checked {
+            if let Some(declared) = self.annotated_effects.get(name) {
+                verify_effects(name, declared, &observed)?;
+            }
+        }
+        Ok(())
+    }
+    
+    // The resources owned directly by values on the stack
+    fn held_resources(&self) -> Vec<usize> {
+        self.stack.iter()
+            .filter_map(|value| match value {
+                Value::Resource(id, _) => Some(*id),
+                _ => None,
+            })
+            .collect()
+    }
+    
+    // Add effects seen in one call of a word to those seen before
+    fn observe_effects(&mut self, name: &str, observed: &[EffectType]) {
+        let seen = self.observed_effects.entry(name.to_string()).or_default();
+        for effect in observed {
+            if !seen.contains(effect) {
+                seen.push(effect.clone());
+            }
+        }
+        // A word is only pure if no call did anything to resources
+        if seen.len() > 1 {
+            seen.retain(|effect| *effect != EffectType::Pure);
+        }
+    }
+    
+    // The effects report as a table of words with declared / observed effects
+    pub fn format_effects(&self) -> String {
+        let report = self.effects_report();
+        if report.is_empty() {
+            return "No words with resource effects yet".to_string();
+        }
+        let list = |effects: &[EffectType]| {
+            if effects.is_empty() {
+                "-".to_string()
+            } else {
+                effects.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(" ")
+            }
+        };
+        let mut result = "Word effects (declared / observed):".to_string();
+        for (name, declared, observed) in report {
+            result.push_str(&format!("\n  {:15} {} / {}", name, list(&declared), list(&observed)));
+        }
+        result
+    }
+    
+    // Each annotated or observed word with its declared and observed resource effects
+    pub fn effects_report(&self) -> Vec<(String, Vec<EffectType>, Vec<EffectType>)> {
+        let mut names: Vec<&String> = self.annotated_effects.keys().chain(self.observed_effects.keys()).collect();
+        names.sort();
+        names.dedup();
+        names.into_iter()
+            .map(|name| (
+                name.clone(),
+                self.annotated_effects.get(name).cloned().unwrap_or_default(),
+                self.observed_effects.get(name).cloned().unwrap_or_default(),
+            ))
+            .collect()
+    }
+    
+    // Run a word, checking it against its declared stack effect in checked mode
+    fn call_checked(&mut self, name: &str) -> Result<()> {
+        let before = self.stack.len();
+        
+        // Note how deep this call reaches for any checked word that is running it
+        let effect = self.declared_effects.get(name).cloned().or_else(|| get_word_effect(name));
+        if let Some(effect) = &effect {
+            self.note_depth(before.saturating_sub(effect.inputs.len()));
+        }
+        
+        let declared = if self.checked { self.declared_effects.get(name).cloned() } else { None };
+        let Some(declared) = declared else {
+            self.run_word(name)?;
+            self.note_depth(self.stack.len());
+            return Ok(());
+        };
+        
+        // Track the lowest depth reached while the word runs
+        let outer_low = self.low_water.replace(before);
+        let result = self.run_word(name);
+        let after = self.stack.len();
+        let low = self.low_water.take().unwrap_or(before).min(after);
+        self.low_water = outer_low.map(|outer| outer.min(low));
+        result?;
+        
+        // Row-polymorphic effects do not fix how many items are taken
+        if declared.is_row_polymorphic() {
+            return Ok(());
+        }
+        
+        let consumed = before - low;
+        let produced = after - low;
+        if consumed != declared.inputs.len() || produced != declared.outputs.len() {
+            return Err(EvaluatorError::EvalError(format!(
+                "word {} declared {} but consumed {} / produced {}",
+                name, declared, consumed, produced
+            )));
+        }
+        Ok(())
+    }
+    
+    // Lower the low-water mark of the running checked word
+    fn note_depth(&mut self, depth: usize) {
+        if let Some(low) = self.low_water.as_mut() {
+            *low = (*low).min(depth);
+        }
+    }
+    
+    // Run a word: apply its quotation or execute the built-in operation
+    fn run_word(&mut self, name: &str) -> Result<()> {
+        match self.env.get(name) {
+            Some(Value::Quotation(params, body, closure)) | Some(Value::TypedQuotation(params, body, _, closure)) => {
+                self.apply_quotation(&params, &body, closure.as_deref())
+            },
+            _ => self.execute_operation(name),
+        }
+    }
+    
+    // Apply a quotation to the stack, binding its parameters from the top items
+    fn apply_quotation(&mut self, params: &[Param], body: &[Expr], closure: Option<&Env>) -> Result<()> {
+        // Spans belong to the body as written, not to its translation
+        let spans = self.next_spans.take();
+        if self.strategy == Strategy::Stacker && !params.is_empty() {
+            if let Some(code) = self.translation(params, body, closure) {
+                return self.eval_sequence(&code);
+            }
+            let quotation = Expr::Quotation(params.to_vec(), body.to_vec()).to_string();
+            if !self.fallbacks.contains(&quotation) {
+                self.fallbacks.push(quotation);
+            }
+        }
+        
+        if self.stack.len() < params.len() {
+            return Err(EvaluatorError::EvalError(format!(
+                "Quotation expects {} values but the stack has {}",
+                params.len(), self.stack.len()
+            )));
+        }
+        
+        // Words are looked up in the calling environment so definitions can refer to later ones
+        let args = self.stack.split_off(self.stack.len() - params.len());
+        self.note_depth(self.stack.len());
+        let mut scope = Env::with_parent(&self.env);
+        // Parameters of the quotations it was created in are captured lexically
+        if let Some(closure) = closure {
+            for (name, value) in closure.local_bindings() {
+                scope.set(&name, value);
+            }
+        }
+        for (param, arg) in params.iter().zip(args) {
+            scope.set(&param.name, arg);
+        }
+        
+        let saved = std::mem::replace(&mut self.env, scope);
+        self.next_spans = spans;
+        let result = self.eval_sequence(body);
+        self.env = saved;
+        result
+    }
+    
+    // The cached STACKER translation of a quotation. Bodies that cannot be translated,
+    // such as ones calling words without a known stack effect or naming values captured
+    // in their closure, keep binding their parameters; they are tried again on every call.
+    fn translation(&mut self, params: &[Param], body: &[Expr], closure: Option<&Env>) -> Option<Vec<Expr>> {
+        if let Some(closure) = closure {
+            let captured: Vec<String> = closure.local_bindings().into_iter().map(|(name, _)| name).collect();
+            if free_symbols(params, body).iter().any(|name| captured.contains(name)) {
+                return None;
+            }
+        }
+        
+        let key = (fingerprint(params, body), self.effects_generation);
+        if let Some(cached) = self.translations.get(&key) {
+            if cached.params == params && cached.body == body {
+                return Some(cached.code.clone());
+            }
+        }
+        let code = translate_quotation_with(params, body, &self.known_effects()).ok()
+            .map(|code: Vec<Expr>| self.optimizer.optimize(&code))?;
+        self.translations.insert(key, Translation { params: params.to_vec(), body: body.to_vec(), code: code.clone() });
+        Some(code)
+    }
+    
+    // Translations made with other stack effects or rules may no longer be right
+    fn effects_changed(&mut self) {
+        self.effects_generation += 1;
+        let generation = self.effects_generation;
+        self.translations.retain(|&(_, made_in), _| made_in == generation);
+    }
+    
+    // Run a program once with each strategy, on fresh evaluators with the given
+    // capabilities, and compare the final stacks
+    pub fn differential(source: &str, capabilities: &Capabilities) -> Differential {
+        let run = |strategy: Strategy| {
+            let mut evaluator = Evaluator::new();
+            evaluator.strategy = strategy;
+            evaluator.capabilities = capabilities.clone();
+            let stack = evaluator.initialize()
+                .and_then(|_| evaluator.eval_source(source))
+                .map(|_| std::mem::take(&mut evaluator.stack))
+                .map_err(|e| e.to_string());
+            (stack, evaluator.fallbacks)
+        };
+        let (named, _) = run(Strategy::Named);
+        let (stacker, fallbacks) = run(Strategy::Stacker);
+        Differential { named, stacker, fallbacks }
+    }
+    
     // Execute a built-in operation
     fn execute_operation(&mut self, operation: &str) -> Result<()> {
         match operation {
@@ -507,6 +1430,38 @@ impl Evaluator {
                     return Err(EvaluatorError::EvalError("tuck requires two values on the stack".to_string()));
                 }
             },
+            "nip" => {
+                // Remove the second item
+                if self.stack.len() >= 2 {
+                    let top = self.stack.pop().unwrap();
+                    self.stack.pop();
+                    self.stack.push(top);
+                } else {
+                    return Err(EvaluatorError::EvalError("nip requires two values on the stack".to_string()));
+                }
+            },
+            "2dup" => {
+                // Copy the top two items
+                if self.stack.len() >= 2 {
+                    let pair = self.stack[self.stack.len() - 2..].to_vec();
+                    self.stack.extend(pair);
+                } else {
+                    return Err(EvaluatorError::EvalError("2dup requires two values on the stack".to_string()));
+                }
+            },
+            "roll" => {
+                // Move the nth item to the top
+                match self.stack.pop() {
+                    Some(Value::Number(n)) if n >= 0 && (n as usize) < self.stack.len() => {
+                        let item = self.stack.remove(self.stack.len() - 1 - n as usize);
+                        self.stack.push(item);
+                    },
+                    Some(Value::Number(n)) => {
+                        return Err(EvaluatorError::EvalError(format!("Invalid roll depth: {}", n)));
+                    },
+                    _ => return Err(EvaluatorError::EvalError("roll requires a number on the stack".to_string())),
+                }
+            },
             "pick" => {
                 // Copy the nth item to the top
                 if self.stack.len() >= 2 {
@@ -540,6 +1495,23 @@ impl Evaluator {
                     return Err(EvaluatorError::EvalError("create_resource requires a value and a resource type".to_string()));
                 }
             },
+            "register_finalizer" => {
+                // ( quot type -- ) run quot on the inner value of each resource of the type when it is consumed
+                if self.stack.len() >= 2 {
+                    let resource_type = match self.stack.pop().unwrap() {
+                        Value::String(s) => s,
+                        _ => return Err(EvaluatorError::EvalError("register_finalizer requires a string resource type".to_string())),
+                    };
+                    match self.stack.pop().unwrap() {
+                        quotation @ (Value::Quotation(..) | Value::TypedQuotation(..)) => {
+                            self.register_finalizer(&resource_type, Finalizer::Quotation(quotation));
+                        },
+                        _ => return Err(EvaluatorError::TypeError("register_finalizer requires a quotation".to_string())),
+                    }
+                } else {
+                    return Err(EvaluatorError::EvalError("register_finalizer requires a quotation and a resource type".to_string()));
+                }
+            },
             "consume_resource" => {
                 // Pop a resource and consume it
                 if let Some(value) = self.stack.pop() {
@@ -602,10 +1574,11 @@ impl Evaluator {
                     
                     // Check that we got a quotation and a resource
                     match quotation {
-                        Value::Quotation(params, body, env) => {
-                            if params.len() != 1 {
+                        Value::Quotation(params, body, closure) | Value::TypedQuotation(params, body, _, closure) => {
+                            // Stack code without parameters finds the borrowed resource on the stack
+                            if params.len() > 1 {
                                 return Err(EvaluatorError::EvalError(
-                                    "with_borrowed requires a quotation with exactly one parameter".to_string()
+                                    "with_borrowed requires a quotation with at most one parameter".to_string()
                                 ));
                             }
                             
@@ -615,20 +1588,18 @@ impl Evaluator {
                                 ));
                             }
                             
-                            // Start a borrowing region
+                            // Run the quotation on a borrowed reference inside its own region
                             self.start_borrowing_region();
+                            let result = self.borrow_resource(&resource).and_then(|borrowed| {
+                                self.stack.push(borrowed);
+                                self.apply_quotation(&params, &body, closure.as_deref())
+                            });
                             
-                            // Create a borrowed resource
-                            let borrowed = self.borrow_resource(&resource)?;
-                            
-                            // Push the borrowed resource
-                            self.stack.push(borrowed);
-                            
-                            // Evaluate the quotation
-                            // TODO: Implement proper quotation application
-                            
-                            // End the borrowing region
-                            self.end_borrowing_region()?;
+                            // The region ends and the owner gets the resource back even when the quotation fails
+                            let ended = self.end_borrowing_region();
+                            self.stack.push(resource);
+                            result?;
+                            ended?;
                         },
                         _ => return Err(EvaluatorError::EvalError(
                             "with_borrowed requires a quotation as the first argument".to_string()
@@ -641,6 +1612,663 @@ impl Evaluator {
                 }
             },
             
+            "call" => {
+                // Apply the quotation on top of the stack
+                match self.stack.pop() {
+                    Some(Value::Quotation(params, body, closure)) | Some(Value::TypedQuotation(params, body, _, closure)) => {
+                        self.apply_quotation(&params, &body, closure.as_deref())?;
+                    },
+                    Some(_) => return Err(EvaluatorError::TypeError("call requires a quotation".to_string())),
+                    None => return Err(EvaluatorError::EvalError("call requires a quotation on the stack".to_string())),
+                }
+            },
+            "if" => {
+                // ( cond t f -- ... ): zero and nil select f
+                let (f, t) = (self.stack.pop(), self.stack.pop());
+                let condition = self.stack.pop()
+                    .ok_or_else(|| EvaluatorError::EvalError("if requires a condition and two quotations".to_string()))?;
+                match if is_truthy(&condition) { t } else { f } {
+                    Some(Value::Quotation(params, body, closure)) | Some(Value::TypedQuotation(params, body, _, closure)) => {
+                        self.apply_quotation(&params, &body, closure.as_deref())?;
+                    },
+                    _ => return Err(EvaluatorError::TypeError("if requires two quotations".to_string())),
+                }
+            },
+            "curry" => {
+                // ( x quot -- quot' ): x becomes the quotation's last parameter or first word
+                let quotation = self.stack.pop();
+                let value = self.stack.pop()
+                    .ok_or_else(|| EvaluatorError::EvalError("curry requires a value and a quotation".to_string()))?;
+                match quotation {
+                    Some(Value::Quotation(mut params, mut body, closure)) => {
+                        match params.pop() {
+                            Some(param) => {
+                                let mut scope = match closure {
+                                    Some(env) => Env::with_parent(&env),
+                                    None => Env::with_parent(&Env::new()),
+                                };
+                                scope.set(&param.name, value);
+                                self.stack.push(Value::Quotation(params, body, Some(Box::new(scope))));
+                            },
+                            None => {
+                                body.insert(0, Expr::Literal(Box::new(value)));
+                                self.stack.push(Value::Quotation(params, body, closure));
+                            },
+                        }
+                    },
+                    _ => return Err(EvaluatorError::TypeError("curry requires a quotation".to_string())),
+                }
+            },
+            
+            "optimize" => {
+                // Apply the peephole rules to a quotation of stack code
+                match self.stack.pop() {
+                    Some(Value::Quotation(params, body, env)) if params.is_empty() => {
+                        let optimized = self.optimizer.optimize(&body);
+                        self.stack.push(Value::Quotation(params, optimized, env));
+                    },
+                    Some(Value::Quotation(..)) => return Err(EvaluatorError::TypeError(
+                        "optimize works on stack code; expand quotations with named parameters first".to_string()
+                    )),
+                    Some(_) => return Err(EvaluatorError::TypeError("optimize requires a quotation".to_string())),
+                    None => return Err(EvaluatorError::EvalError("optimize requires a quotation on the stack".to_string())),
+                }
+            },
+            "rule" => {
+                // ( pattern replacement -- ) teach the optimizer a rewrite
+                let replacement = self.stack.pop();
+                let pattern = self.stack.pop();
+                match (pattern, replacement) {
+                    (Some(Value::Quotation(p, pattern, _)), Some(Value::Quotation(r, replacement, _)))
+                        if p.is_empty() && r.is_empty() =>
+                    {
+                        let known = self.known_effects();
+                        self.optimizer.add_rule(pattern, replacement, &known)?;
+                        self.effects_changed();
+                    },
+                    _ => return Err(EvaluatorError::TypeError(
+                        "rule requires two quotations of stack code: a pattern and its replacement".to_string()
+                    )),
+                }
+            },
+            
+            "expand" => {
+                // Replace a quotation with its translation to stack operations
+                match self.stack.pop() {
+                    Some(Value::Quotation(params, body, env)) => {
+                        let translated = translate_quotation_with(&params, &body, &self.known_effects())
+                            .map_err(|e| EvaluatorError::EvalError(e.to_string()))?;
+                        let translated = self.optimizer.optimize(&translated);
+                        self.stack.push(Value::Quotation(Vec::new(), translated, env));
+                    },
+                    Some(_) => return Err(EvaluatorError::TypeError("expand requires a quotation".to_string())),
+                    None => return Err(EvaluatorError::EvalError("expand requires a quotation on the stack".to_string())),
+                }
+            },
+            
+            // Process operations
+            "run" => {
+                // ( program args -- result ) run to completion: { stdout, stderr, status }
+                let args = self.pop_strings("run")?;
+                let program = self.pop_string("run")?;
+                self.capabilities.check_subprocess(&program)?;
+                let result = ProcessTable::run(&program, &args)?;
+                self.stack.push(result);
+            },
+            "spawn" => {
+                // ( program args -- !Process ) start a process with piped standard streams
+                let args = self.pop_strings("spawn")?;
+                let program = self.pop_string("spawn")?;
+                self.capabilities.check_subprocess(&program)?;
+                let child = ProcessTable::spawn(&program, &args)?;
+                let pid = child.id();
+                let id = self.create_resource(PROCESS_RESOURCE, Value::Nil).get_resource_id().unwrap_or_default();
+                self.processes.insert(id, child);
+                self.stack.push(Value::Resource(id, Box::new(process_value(&program, pid, id))));
+            },
+            "wait" => {
+                // ( !Process -- status ) wait for the process to exit, consuming it
+                let (id, _) = self.top_resource("wait", &[PROCESS_RESOURCE])?;
+                let status = self.processes.wait(id)?;
+                let process = self.stack.pop().unwrap();
+                self.consume_resource(&process)?;
+                self.stack.push(Value::Number(status));
+            },
+            "kill" => {
+                // ( !Process -- ) kill the process, consuming it
+                let (id, _) = self.top_resource("kill", &[PROCESS_RESOURCE])?;
+                self.processes.kill(id)?;
+                let process = self.stack.pop().unwrap();
+                self.consume_resource(&process)?;
+            },
+            "stdin_pipe" | "stdout_pipe" | "stderr_pipe" => {
+                // ( !Process -- !Process !Pipe ) take a pipe to one of the process's streams
+                let (process, _) = self.top_resource(operation, &[PROCESS_RESOURCE])?;
+                let stream = operation.trim_end_matches("_pipe");
+                let pipe = self.processes.take_pipe(process, stream)?;
+                let id = self.create_resource(PIPE_RESOURCE, Value::Nil).get_resource_id().unwrap_or_default();
+                self.processes.insert_pipe(id, pipe);
+                self.stack.push(Value::Resource(id, Box::new(pipe_value(stream, process, id))));
+            },
+            "close_pipe" => {
+                // ( !Pipe -- ) consuming the resource closes the pipe
+                self.top_resource("close_pipe", &[PIPE_RESOURCE])?;
+                let pipe = self.stack.pop().unwrap();
+                self.consume_resource(&pipe)?;
+            },
+            
+            // Network operations
+            "tcp_listen" => {
+                // ( host port -- !Listener ) port 0 picks a free port, see local_port
+                let port = self.pop_port("tcp_listen")?;
+                let host = self.pop_string("tcp_listen")?;
+                self.capabilities.check_net(&host, port)?;
+                let listener = NetTable::tcp_listen(&host, port)?;
+                let listener = self.listener_resource(listener, &format!("{}:{}", host, port));
+                self.stack.push(listener);
+            },
+            "tcp_connect" => {
+                // ( host port -- !Socket )
+                let port = self.pop_port("tcp_connect")?;
+                let host = self.pop_string("tcp_connect")?;
+                self.capabilities.check_net(&host, port)?;
+                let socket = NetTable::tcp_connect(&host, port)?;
+                let socket = self.socket_resource(socket, &format!("{}:{}", host, port));
+                self.stack.push(socket);
+            },
+            "unix_listen" | "unix_connect" => {
+                // ( path -- !Listener ), ( path -- !Socket ) Unix domain sockets, which need write access to the path
+                let path = self.pop_string(operation)?;
+                self.capabilities.check_write(&path)?;
+                let resource = if operation == "unix_listen" {
+                    let listener = NetTable::unix_listen(&path)?;
+                    self.listener_resource(listener, &path)
+                } else {
+                    let socket = NetTable::unix_connect(&path)?;
+                    self.socket_resource(socket, &path)
+                };
+                self.stack.push(resource);
+            },
+            "accept" => {
+                // ( !Listener -- !Listener !Socket ) wait for the next connection
+                let (id, _) = self.top_resource("accept", &[LISTENER_RESOURCE])?;
+                let (socket, peer) = self.net.accept(id)?;
+                let socket = self.socket_resource(socket, &peer);
+                self.stack.push(socket);
+            },
+            "local_port" => {
+                // ( !Listener -- !Listener port ) the port a TCP listener is bound to
+                let (id, _) = self.top_resource("local_port", &[LISTENER_RESOURCE])?;
+                let port = self.net.local_port(id)?;
+                self.stack.push(Value::Number(port as i32));
+            },
+            "send" => {
+                // ( !Socket text -- !Socket )
+                let text = self.pop_string("send")?;
+                let (id, _) = self.top_resource("send", &[SOCKET_RESOURCE])?;
+                self.net.send(id, &text)?;
+            },
+            "recv" => {
+                // ( !Socket -- !Socket ?text ) wait for data; Nothing once the peer has closed
+                let (id, _) = self.top_resource("recv", &[SOCKET_RESOURCE])?;
+                let text = self.net.recv(id)?;
+                self.stack.push(Value::Optional(text.map(|text| Box::new(Value::String(text)))));
+            },
+            "close" => {
+                // ( handle -- ) close a file, pipe, listener or socket by consuming it
+                self.top_resource("close", &[FILE_RESOURCE, PIPE_RESOURCE, LISTENER_RESOURCE, SOCKET_RESOURCE])?;
+                let handle = self.stack.pop().unwrap();
+                self.consume_resource(&handle)?;
+            },
+            
+            // ACSet operations; an ACSet is a value, given back by each word that uses it
+            "schema" => {
+                // ( objects homs attr_types attrs -- schema ) homs and attrs are (name dom codom) triples
+                if self.stack.len() < 4 {
+                    return Err(EvaluatorError::EvalError("schema requires four lists on the stack".to_string()));
+                }
+                let lists = self.stack.split_off(self.stack.len() - 4);
+                let [objects, homs, attr_types, attrs]: [Value; 4] = lists.try_into().unwrap();
+                let schema = Schema::from_values(objects, homs, attr_types, attrs)?;
+                self.stack.push(Value::Schema(std::rc::Rc::new(schema)));
+            },
+            "empty_acset" => {
+                // ( schema -- acset )
+                match self.stack.pop() {
+                    Some(Value::Schema(schema)) => self.stack.push(Value::ACSet(Box::new(ACSet::new(schema)))),
+                    _ => return Err(EvaluatorError::TypeError("empty_acset requires a schema".to_string())),
+                }
+            },
+            "add_part" | "add_parts" | "nparts" | "parts" => {
+                // ( acset ob -- acset part ), ( acset ob n -- acset parts ),
+                // ( acset ob -- acset n ), ( acset ob -- acset parts )
+                let count = if operation == "add_parts" { self.pop_part(operation)? } else { 0 };
+                let object = self.pop_string(operation)?;
+                let mut acset = self.pop_acset(operation)?;
+                let result = match operation {
+                    "add_part" => Value::Number(acset.add_part(&object)? as i32),
+                    "add_parts" => parts_value(acset.add_parts(&object, count)?),
+                    "nparts" => Value::Number(acset.nparts(&object)? as i32),
+                    _ => parts_value(0..acset.nparts(&object)?),
+                };
+                self.stack.push(Value::ACSet(acset));
+                self.stack.push(result);
+            },
+            "set_subpart" => {
+                // ( acset part hom target -- acset )
+                let target = self.pop_part("set_subpart")?;
+                let hom = self.pop_string("set_subpart")?;
+                let part = self.pop_part("set_subpart")?;
+                let mut acset = self.pop_acset("set_subpart")?;
+                acset.set_subpart(part, &hom, target)?;
+                self.stack.push(Value::ACSet(acset));
+            },
+            "subpart" | "incident" => {
+                // ( acset part hom -- acset ?target ), ( acset part hom -- acset sources )
+                let hom = self.pop_string(operation)?;
+                let part = self.pop_part(operation)?;
+                let acset = self.pop_acset(operation)?;
+                let result = if operation == "subpart" {
+                    let target = acset.subpart(part, &hom)?;
+                    Value::Optional(target.map(|target| Box::new(Value::Number(target as i32))))
+                } else {
+                    parts_value(acset.incident(part, &hom)?.iter().copied())
+                };
+                self.stack.push(Value::ACSet(acset));
+                self.stack.push(result);
+            },
+            "set_prop" => {
+                // ( acset part attr value -- acset )
+                let value = self.stack.pop()
+                    .ok_or_else(|| EvaluatorError::EvalError("set_prop requires a value on the stack".to_string()))?;
+                let attr = self.pop_string("set_prop")?;
+                let part = self.pop_part("set_prop")?;
+                let mut acset = self.pop_acset("set_prop")?;
+                acset.set_attr(part, &attr, value)?;
+                self.stack.push(Value::ACSet(acset));
+            },
+            "get_prop" => {
+                // ( acset part attr -- acset ?value ) Nothing if the attribute has not been set
+                let attr = self.pop_string("get_prop")?;
+                let part = self.pop_part("get_prop")?;
+                let acset = self.pop_acset("get_prop")?;
+                let value = acset.get_attr(part, &attr)?.cloned();
+                self.stack.push(Value::ACSet(acset));
+                self.stack.push(Value::Optional(value.map(Box::new)));
+            },
+            "rem_part" | "cascading_rem_part" => {
+                // ( acset ob part -- acset ) the last part of the object takes the removed part's number
+                let part = self.pop_part(operation)?;
+                let object = self.pop_string(operation)?;
+                let mut acset = self.pop_acset(operation)?;
+                if operation == "rem_part" {
+                    acset.rem_part(&object, part)?;
+                } else {
+                    acset.cascading_rem_part(&object, part)?;
+                }
+                self.stack.push(Value::ACSet(acset));
+            },
+            "homomorphisms" | "monic_homomorphisms" => {
+                // ( pattern acset -- acset homs ) each hom maps every object to the images of its parts
+                let acset = self.pop_acset(operation)?;
+                let pattern = self.pop_acset(operation)?;
+                let found = homomorphisms(&pattern, &acset, operation == "monic_homomorphisms")?;
+                self.stack.push(Value::ACSet(acset));
+                self.stack.push(Value::List(found.iter().map(homomorphism_value).collect()));
+            },
+            "query" => {
+                // ( acset variables atoms -- acset answers ) each answer maps the variables to parts
+                let atoms = self.stack.pop()
+                    .ok_or_else(|| EvaluatorError::EvalError("query requires a list of atoms on the stack".to_string()))?;
+                let variables = self.stack.pop()
+                    .ok_or_else(|| EvaluatorError::EvalError("query requires a list of variables on the stack".to_string()))?;
+                let acset = self.pop_acset("query")?;
+                let answers = query(&acset, variables, atoms)?;
+                self.stack.push(Value::ACSet(acset));
+                self.stack.push(answers);
+            },
+            "schema_functor" => {
+                // ( dom codom objects homs attrs -- functor ) images of objects, homs and attributes;
+                // anything not listed goes to the one of the same name
+                if self.stack.len() < 5 {
+                    return Err(EvaluatorError::EvalError("schema_functor requires two schemas and three lists on the stack".to_string()));
+                }
+                let arguments = self.stack.split_off(self.stack.len() - 5);
+                let [dom, codom, objects, homs, attrs]: [Value; 5] = arguments.try_into().unwrap();
+                let functor = match (dom, codom) {
+                    (Value::Schema(dom), Value::Schema(codom)) => SchemaFunctor::from_values(dom, codom, objects, homs, attrs)?,
+                    _ => return Err(EvaluatorError::TypeError("schema_functor requires a domain and a codomain schema".to_string())),
+                };
+                self.stack.push(Value::SchemaFunctor(std::rc::Rc::new(functor)));
+            },
+            "delta_migrate" | "sigma_migrate" => {
+                // ( acset functor -- acset ) Δ pulls an instance of the codomain back to the domain,
+                // Σ pushes an instance of the domain forward to the codomain
+                let functor = match self.stack.pop() {
+                    Some(Value::SchemaFunctor(functor)) => functor,
+                    _ => return Err(EvaluatorError::TypeError(format!("{} requires a schema functor", operation))),
+                };
+                let acset = self.pop_acset(operation)?;
+                let migrated = if operation == "delta_migrate" {
+                    delta_migrate(&functor, &acset)?
+                } else {
+                    sigma_migrate(&functor, &acset)?
+                };
+                self.stack.push(Value::ACSet(Box::new(migrated)));
+            },
+            
+            // Wiring diagram operations; box 0 is the diagram's own boundary
+            "empty_diagram" => {
+                // ( inputs outputs -- diagram ) lists of port type names
+                let outputs = self.stack.pop().ok_or_else(|| EvaluatorError::EvalError("empty_diagram requires two lists of port types".to_string()))?;
+                let inputs = self.stack.pop().ok_or_else(|| EvaluatorError::EvalError("empty_diagram requires two lists of port types".to_string()))?;
+                let diagram = WiringDiagram::new(port_types(inputs)?, port_types(outputs)?);
+                self.stack.push(Value::WiringDiagram(Box::new(diagram)));
+            },
+            "add_box" => {
+                // ( diagram name inputs outputs -- diagram box ) boxes are numbered from 1
+                let outputs = self.stack.pop().ok_or_else(|| EvaluatorError::EvalError("add_box requires a list of output port types".to_string()))?;
+                let inputs = self.stack.pop().ok_or_else(|| EvaluatorError::EvalError("add_box requires a list of input port types".to_string()))?;
+                let name = self.pop_string("add_box")?;
+                let mut diagram = self.pop_diagram("add_box")?;
+                let number = diagram.add_box(&name, port_types(inputs)?, port_types(outputs)?);
+                self.stack.push(Value::WiringDiagram(diagram));
+                self.stack.push(Value::Number(number as i32));
+            },
+            "add_wire" => {
+                // ( diagram src_box src_port tgt_box tgt_port -- diagram ) from an output to an input of the same type
+                let target_port = self.pop_part("add_wire")?;
+                let target_box = self.pop_part("add_wire")?;
+                let source_port = self.pop_part("add_wire")?;
+                let source_box = self.pop_part("add_wire")?;
+                let mut diagram = self.pop_diagram("add_wire")?;
+                diagram.add_wire((source_box, source_port), (target_box, target_port))?;
+                self.stack.push(Value::WiringDiagram(diagram));
+            },
+            "compose" | "tensor" => {
+                // ( d1 d2 -- d ) d1's outputs feed d2's inputs, or d1 beside d2
+                let second = self.pop_diagram(operation)?;
+                let first = self.pop_diagram(operation)?;
+                let combined = if operation == "compose" { first.compose(&second)? } else { first.tensor(&second) };
+                self.stack.push(Value::WiringDiagram(Box::new(combined)));
+            },
+            "substitute" => {
+                // ( diagram box inner -- diagram ) replace a box by a diagram with the same interface
+                let inner = self.pop_diagram("substitute")?;
+                let number = self.pop_part("substitute")?;
+                let diagram = self.pop_diagram("substitute")?;
+                let substituted = diagram.substitute(number, &inner)?;
+                self.stack.push(Value::WiringDiagram(Box::new(substituted)));
+            },
+            "to_dot" => {
+                // ( diagram -- dot ) Graphviz source for the diagram
+                let diagram = self.pop_diagram("to_dot")?;
+                self.stack.push(Value::String(diagram.to_dot()));
+            },
+            "quotation_to_diagram" => {
+                // ( quot -- diagram ) a box per word, with wires following stack positions
+                let diagram = match self.stack.pop() {
+                    Some(Value::Quotation(params, body, _)) | Some(Value::TypedQuotation(params, body, _, _)) => {
+                        self.dataflow(&params, &body)?
+                    },
+                    Some(other) => return Err(EvaluatorError::TypeError(format!("quotation_to_diagram requires a quotation, got {}", other))),
+                    None => return Err(EvaluatorError::EvalError("quotation_to_diagram requires a quotation on the stack".to_string())),
+                };
+                self.stack.push(Value::WiringDiagram(Box::new(diagram)));
+            },
+            
+            // Interaction net operations; cell 0 is the net's free ports and port 0 a cell's principal port
+            "create_cell" => {
+                // ( agent arity -- cell )
+                let arity = self.pop_part("create_cell")?;
+                let agent = self.pop_string("create_cell")?;
+                self.stack.push(Value::Cell(Cell { agent, arity }));
+            },
+            "empty_net" => {
+                // ( free -- net ) a net with this many free ports
+                let free = self.pop_part("empty_net")?;
+                self.stack.push(Value::Net(Box::new(Net::new(free))));
+            },
+            "add_cell" => {
+                // ( net cell -- net id ) cells are numbered from 1
+                let cell = match self.stack.pop() {
+                    Some(Value::Cell(cell)) => cell,
+                    Some(other) => return Err(EvaluatorError::TypeError(format!("add_cell requires a cell, got {}", other))),
+                    None => return Err(EvaluatorError::EvalError("add_cell requires a cell on the stack".to_string())),
+                };
+                let mut net = self.pop_net("add_cell")?;
+                let number = net.add_cell(&cell);
+                self.stack.push(Value::Net(net));
+                self.stack.push(Value::Number(number as i32));
+            },
+            "connect" => {
+                // ( net cell1 port1 cell2 port2 -- net )
+                let port2 = self.pop_part("connect")?;
+                let cell2 = self.pop_part("connect")?;
+                let port1 = self.pop_part("connect")?;
+                let cell1 = self.pop_part("connect")?;
+                let mut net = self.pop_net("connect")?;
+                net.connect((cell1, port1), (cell2, port2))?;
+                self.stack.push(Value::Net(net));
+            },
+            "active_pairs" => {
+                // ( net -- net pairs ) cells wired principal port to principal port
+                let net = self.pop_net("active_pairs")?;
+                let pairs = net.active_pairs().into_iter()
+                    .map(|(a, b)| Value::List(vec![Value::Number(a as i32), Value::Number(b as i32)]))
+                    .collect();
+                self.stack.push(Value::Net(net));
+                self.stack.push(Value::List(pairs));
+            },
+            "create_rewrite_system" => {
+                // ( rules -- system ) each rule a (cell1 cell2 net) list
+                let rules = match self.stack.pop() {
+                    Some(Value::List(rules)) => rules,
+                    Some(other) => return Err(EvaluatorError::TypeError(format!("create_rewrite_system requires a list of rules, got {}", other))),
+                    None => return Err(EvaluatorError::EvalError("create_rewrite_system requires a list of rules on the stack".to_string())),
+                };
+                let rules = rules.into_iter()
+                    .map(|rule| match rule {
+                        Value::List(parts) => match <[Value; 3]>::try_from(parts) {
+                            Ok([Value::Cell(left), Value::Cell(right), Value::Net(rhs)]) => Ok(InteractionRule { left, right, rhs: *rhs }),
+                            Ok(parts) => Err(EvaluatorError::TypeError(format!("Rules must be (cell cell net), got {}", Value::List(parts.to_vec())))),
+                            Err(parts) => Err(EvaluatorError::TypeError(format!("Rules must be (cell cell net), got {}", Value::List(parts)))),
+                        },
+                        other => Err(EvaluatorError::TypeError(format!("Rules must be (cell cell net) lists, got {}", other))),
+                    })
+                    .collect::<Result<Vec<InteractionRule>>>()?;
+                self.stack.push(Value::RewriteSystem(std::rc::Rc::new(RewriteSystem::new(rules)?)));
+            },
+            "normalize" => {
+                // ( net system -- net stats ) interact until no active pair has a rule
+                let system = match self.stack.pop() {
+                    Some(Value::RewriteSystem(system)) => system,
+                    Some(other) => return Err(EvaluatorError::TypeError(format!("normalize requires a rewrite system, got {}", other))),
+                    None => return Err(EvaluatorError::EvalError("normalize requires a rewrite system on the stack".to_string())),
+                };
+                let mut net = self.pop_net("normalize")?;
+                let stats = net.normalize(&system)?;
+                self.stack.push(Value::Net(net));
+                self.stack.push(stats.to_value());
+            },
+            "normalize_parallel" => {
+                // ( net system threads -- net stats ) normalize, rewriting each round's active pairs on that many threads
+                let threads = match self.stack.pop() {
+                    Some(Value::Number(n)) if n > 0 => n as usize,
+                    Some(other) => return Err(EvaluatorError::TypeError(format!("normalize_parallel requires a positive number of threads, got {}", other))),
+                    None => return Err(EvaluatorError::EvalError("normalize_parallel requires a number of threads on the stack".to_string())),
+                };
+                let system = match self.stack.pop() {
+                    Some(Value::RewriteSystem(system)) => system,
+                    Some(other) => return Err(EvaluatorError::TypeError(format!("normalize_parallel requires a rewrite system, got {}", other))),
+                    None => return Err(EvaluatorError::EvalError("normalize_parallel requires a rewrite system on the stack".to_string())),
+                };
+                let mut net = self.pop_net("normalize_parallel")?;
+                let stats = net.normalize_parallel(&system, threads)?;
+                self.stack.push(Value::Net(net));
+                self.stack.push(stats.to_value());
+            },
+            
+            // Script operations
+            "args" => {
+                // ( -- args ) the script's command-line arguments as a list of strings
+                let args = self.args.iter().map(|arg| Value::String(arg.clone())).collect();
+                self.stack.push(Value::List(args));
+            },
+            "getenv" => {
+                // ( name -- ?value ) Nothing if the variable is not set
+                let name = self.pop_string("getenv")?;
+                self.capabilities.check_env(&name)?;
+                let value = std::env::var(&name).ok().map(|value| Box::new(Value::String(value)));
+                self.stack.push(Value::Optional(value));
+            },
+            "read_stdin_line" => {
+                // ( -- ?line ) a line of standard input, Nothing at the end of it
+                let line = read_line(&mut self.input)?;
+                self.stack.push(Value::Optional(line.map(|line| Box::new(Value::String(line)))));
+            },
+            "read_stdin" => {
+                // ( -- text ) the rest of standard input
+                let mut text = String::new();
+                self.input.read_to_string(&mut text)?;
+                self.stack.push(Value::String(text));
+            },
+            "exit" => {
+                // ( status -- ) stop the program; `try` does not catch this
+                let status = match self.stack.pop() {
+                    Some(Value::Number(status)) => status,
+                    _ => return Err(EvaluatorError::EvalError("exit requires a status number".to_string())),
+                };
+                std::io::stdout().flush()?;
+                return Err(EvaluatorError::Exit(status));
+            },
+            "try" => {
+                // ( ..a quot handler -- ..b ) run quot; if it fails, restore the stack,
+                // push the error, e.g. PermissionDenied("..."), and run handler. An exit is not caught.
+                let mut quotations = Vec::new();
+                for _ in 0..2 {
+                    match self.stack.pop() {
+                        Some(Value::Quotation(params, body, closure)) | Some(Value::TypedQuotation(params, body, _, closure)) => {
+                            quotations.push((params, body, closure));
+                        },
+                        _ => return Err(EvaluatorError::TypeError("try requires a quotation and a handler quotation".to_string())),
+                    }
+                }
+                let (params, body, closure) = quotations.pop().unwrap();
+                let (handler_params, handler_body, handler_closure) = quotations.pop().unwrap();
+                let saved = self.stack.clone();
+                match self.apply_quotation(&params, &body, closure.as_deref()) {
+                    Ok(()) => {},
+                    Err(err @ EvaluatorError::Exit(_)) => return Err(err),
+                    Err(err) => {
+                        self.stack = saved;
+                        self.stack.push(err.to_value());
+                        self.apply_quotation(&handler_params, &handler_body, handler_closure.as_deref())?;
+                    },
+                }
+            },
+            "unwrap" => {
+                // ( ?a -- a ) take the value out of an optional
+                match self.stack.pop() {
+                    Some(Value::Optional(Some(value))) => self.stack.push(*value),
+                    Some(Value::Optional(None)) | Some(Value::Nothing) => {
+                        return Err(EvaluatorError::EvalError("unwrap of Nothing".to_string()));
+                    },
+                    Some(other) => return Err(EvaluatorError::TypeError(format!("unwrap requires an optional value, got {}", other))),
+                    None => return Err(EvaluatorError::EvalError("unwrap requires a value on the stack".to_string())),
+                }
+            },
+            
+            // File operations
+            "open_file" => {
+                // ( path mode -- ?!File ) Nothing if the OS cannot open the file; an unknown
+                // mode or a path the capabilities do not allow is an error
+                let mode = self.pop_string("open_file")?;
+                let path = self.pop_string("open_file")?;
+                let file = match self.open_file(&path, &mode) {
+                    Ok(file) => Some(Box::new(file)),
+                    Err(EvaluatorError::FileError(_)) => None,
+                    Err(err) => return Err(err),
+                };
+                self.stack.push(Value::Optional(file));
+            },
+            "close_file" => {
+                // ( !File -- ) consuming the resource closes the handle
+                self.top_file("close_file")?;
+                let file = self.stack.pop().unwrap();
+                self.consume_resource(&file)?;
+            },
+            "read_all" => {
+                // ( !File -- !File text ), or from a process's output or a connection
+                // until it is closed: ( !Pipe -- !Pipe text ), ( !Socket -- !Socket text )
+                let text = match self.top_resource("read_all", &[FILE_RESOURCE, PIPE_RESOURCE, SOCKET_RESOURCE])? {
+                    (id, resource_type) if resource_type == FILE_RESOURCE => self.files.read_all(id)?,
+                    (id, resource_type) if resource_type == PIPE_RESOURCE => self.processes.read_all(id)?,
+                    (id, _) => self.net.read_all(id)?,
+                };
+                self.stack.push(Value::String(text));
+            },
+            "read_line" => {
+                // ( !File -- !File ?line ) from a file, pipe or socket; Nothing at the end of the input
+                let line = match self.top_resource("read_line", &[FILE_RESOURCE, PIPE_RESOURCE, SOCKET_RESOURCE])? {
+                    (id, resource_type) if resource_type == FILE_RESOURCE => self.files.read_line(id)?,
+                    (id, resource_type) if resource_type == PIPE_RESOURCE => self.processes.read_line(id)?,
+                    (id, _) => self.net.read_line(id)?,
+                };
+                self.stack.push(Value::Optional(line.map(|line| Box::new(Value::String(line)))));
+            },
+            "write" => {
+                // ( !File text -- !File ), or to a process's input or a connection:
+                // ( !Pipe text -- !Pipe ), ( !Socket text -- !Socket )
+                let text = self.pop_string("write")?;
+                match self.top_resource("write", &[FILE_RESOURCE, PIPE_RESOURCE, SOCKET_RESOURCE])? {
+                    (id, resource_type) if resource_type == FILE_RESOURCE => self.files.write(id, &text)?,
+                    (id, resource_type) if resource_type == PIPE_RESOURCE => self.processes.write(id, &text)?,
+                    (id, _) => self.net.send(id, &text)?,
+                }
+            },
+            "seek" => {
+                // ( !File offset -- !File ) move to a byte offset from the start
+                let offset = match self.stack.pop() {
+                    Some(Value::Number(n)) if n >= 0 => n as u64,
+                    _ => return Err(EvaluatorError::EvalError("seek requires a non-negative offset".to_string())),
+                };
+                let id = self.top_file("seek")?;
+                self.files.seek(id, offset)?;
+            },
+            "with_file" => {
+                // ( path mode quot -- ... ) run quot ( file -- ... file ) on the opened file,
+                // then close it, also when the quotation fails
+                let (params, body, closure) = match self.stack.pop() {
+                    Some(Value::Quotation(params, body, closure)) | Some(Value::TypedQuotation(params, body, _, closure)) => {
+                        (params, body, closure)
+                    },
+                    _ => return Err(EvaluatorError::TypeError("with_file requires a quotation".to_string())),
+                };
+                let mode = self.pop_string("with_file")?;
+                let path = self.pop_string("with_file")?;
+                let file = self.open_file(&path, &mode)?;
+                let id = file.get_resource_id().unwrap_or_default();
+                self.stack.push(file.clone());
+                let result = self.apply_quotation(&params, &body, closure.as_deref());
+                
+                // The quotation gives the file back on top of the stack
+                let returned = result.is_ok()
+                    && self.stack.last().and_then(|value| value.get_resource_id()) == Some(id);
+                if returned {
+                    self.stack.pop();
+                }
+                if self.resource_manager.check_resource(id).is_ok() {
+                    self.consume_resource(&file)?;
+                }
+                result?;
+                if !returned {
+                    return Err(EvaluatorError::EvalError(
+                        "with_file requires its quotation to leave the file on top of the stack".to_string()
+                    ));
+                }
+            },
+            
             // Stack inspection
             ".s" => {
                 // Print the current stack
@@ -657,6 +2285,10 @@ impl Evaluator {
                 // Print information about resources
                 println!("{}", self.resource_manager.stats());
             },
+            ".effects" => {
+                // Print each word's declared and observed resource effects
+                println!("{}", self.format_effects());
+            },
             // TODO: Implement other operations
             _ => return Err(EvaluatorError::EvalError(format!("Unknown operation: {}", operation))),
         }
@@ -683,14 +2315,11 @@ impl Evaluator {
                     )))
                 }
             },
-            Expr::Quotation(params, _) => {
-                // Create a function type based on parameter types and inferred return type
-                let param_types = params.iter()
-                    .map(|p| p.type_annotation.clone().unwrap_or(Type::Simple("Any".to_string())))
-                    .collect();
-                
-                // We can't reliably infer return type without evaluating, so use Any
-                Ok(Type::Function(param_types, Box::new(Type::Simple("Any".to_string()))))
+            Expr::Quotation(params, body) => {
+                // Compose the stack types of the body's words without evaluating it
+                let lookup = |name: &str| self.env.get(name);
+                let signature = infer_quotation(params, body, &lookup)?;
+                Ok(signature.to_type())
             },
             Expr::TypedQuotation(params, _, return_type) => {
                 // Use the explicitly provided return type
@@ -798,14 +2427,18 @@ impl Evaluator {
             Value::Number(_) => Ok(Type::Simple("Num".to_string())),
             Value::String(_) => Ok(Type::Simple("String".to_string())),
             Value::Symbol(_) => Ok(Type::Simple("Symbol".to_string())),
-            Value::Quotation(params, _, _) => {
-                // Create function type from parameters
-                let param_types = params.iter()
-                    .map(|p| p.type_annotation.clone().unwrap_or(Type::Simple("Any".to_string())))
-                    .collect();
-                
-                // Return type is unknown without evaluation
-                Ok(Type::Function(param_types, Box::new(Type::Simple("Any".to_string()))))
+            Value::Quotation(params, body, _) => {
+                // Infer the stack type of the body, falling back to an untyped function
+                let lookup = |name: &str| self.env.get(name);
+                match infer_quotation(params, body, &lookup) {
+                    Ok(signature) => Ok(signature.to_type()),
+                    Err(_) => {
+                        let param_types = params.iter()
+                            .map(|p| p.type_annotation.clone().unwrap_or(Type::Simple("Any".to_string())))
+                            .collect();
+                        Ok(Type::Function(param_types, Box::new(Type::Simple("Any".to_string()))))
+                    }
+                }
             },
             Value::TypedQuotation(params, _, return_type, _) => {
                 // Use the explicitly provided return type
@@ -851,6 +2484,13 @@ impl Evaluator {
                 Ok(Type::Linear(Box::new(inner_type)))
             },
             Value::Nothing => Ok(Type::Simple("Nothing".to_string())),
+            Value::Schema(_) => Ok(Type::Simple("Schema".to_string())),
+            Value::ACSet(_) => Ok(Type::Simple("ACSet".to_string())),
+            Value::SchemaFunctor(_) => Ok(Type::Simple("SchemaFunctor".to_string())),
+            Value::WiringDiagram(_) => Ok(Type::Simple("WiringDiagram".to_string())),
+            Value::Cell(_) => Ok(Type::Simple("Cell".to_string())),
+            Value::Net(_) => Ok(Type::Simple("Net".to_string())),
+            Value::RewriteSystem(_) => Ok(Type::Simple("RewriteSystem".to_string())),
             Value::Nil => Ok(Type::Simple("Nil".to_string())),
             _ => Ok(Type::Simple("Any".to_string()))
         }
@@ -984,6 +2624,7 @@ impl Evaluator {
                 }
                 Ok(format!("{{ {} }}", variant_strings.join(" | ")))
             },
+            Type::StackEffect(_, _) => Ok(format_type(typ)),
         }
     }
     
@@ -1166,6 +2807,20 @@ impl Evaluator {
                 
                 Ok(Type::Function(processed_params, Box::new(processed_return)))
             },
+            Type::StackEffect(inputs, outputs) => {
+                // Process the stack items on both sides recursively
+                let mut processed_inputs = Vec::new();
+                for input in inputs {
+                    processed_inputs.push(self.process_type_quasiquote(input)?);
+                }
+                
+                let mut processed_outputs = Vec::new();
+                for output in outputs {
+                    processed_outputs.push(self.process_type_quasiquote(output)?);
+                }
+                
+                Ok(Type::StackEffect(processed_inputs, processed_outputs))
+            },
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,28 @@
+// src/repl/interpreter/handles.rs
+// What the tables of OS handles behind resources have in common: files, pipes and sockets
+// are named by a handle in the resource's inner value and read a line at a time.
+
+use std::io::BufRead;
+use crate::repl::interpreter::types::Value;
+
+/// The handle in a resource's inner value: `{ ..., handle: Int }`
+pub fn handle(value: &Value) -> Option<usize> {
+    match value {
+        Value::Map(fields) => match fields.get("handle") {
+            Some(Value::Number(handle)) if *handle >= 0 => Some(*handle as usize),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The next line without its line ending, or None at the end of the input
+pub fn read_line(reader: &mut dyn BufRead) -> std::io::Result<Option<String>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    let trimmed = line.trim_end_matches(['\n', '\r']).len();
+    line.truncate(trimmed);
+    Ok(Some(line))
+}
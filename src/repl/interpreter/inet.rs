@@ -0,0 +1,1026 @@
+// src/repl/interpreter/inet.rs
+// Lafont interaction nets. A cell is an agent with one principal port (port 0) and
+// some auxiliary ports (1 to its arity); each port is wired to at most one other.
+// Two cells whose principal ports are wired together form an active pair, and an
+// interaction rule for their agents replaces the pair by a net whose free ports are
+// the first agent's auxiliary ports followed by the second's. As with wiring
+// diagrams, cells are numbered from 1 and cell 0 stands for the net's own free ports.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::ops::Range;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering::Relaxed};
+use std::sync::{Condvar, Mutex, PoisonError, RwLock};
+use std::thread;
+use crate::repl::interpreter::types::{EvaluatorError, Result, Value};
+
+/// The cell number of the net's free ports
+pub const INTERFACE: usize = 0;
+
+/// The port number of every cell's principal port
+pub const PRINCIPAL: usize = 0;
+
+/// The most interactions normalize performs before giving up: nets need not have a normal form
+pub const INTERACTION_LIMIT: usize = 10_000_000;
+
+/// Rounds with fewer active pairs than this are rewritten by normalize_parallel on one thread
+pub const PARALLEL_ROUND: usize = 256;
+
+/// A (cell, port) pair
+pub type Port = (usize, usize);
+
+/// A kind of cell: an agent name and the number of auxiliary ports
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cell {
+    pub agent: String,
+    pub arity: usize,
+}
+
+impl fmt::Display for Cell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Cell({}/{})", self.agent, self.arity)
+    }
+}
+
+// A cell in a net: its agent's index in the net's agent table and what each port is wired to
+#[derive(Debug, Clone, PartialEq)]
+struct NetCell {
+    agent: usize,
+    ports: Vec<Option<Port>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Net {
+    // Agent names, in order of first use
+    agents: Vec<String>,
+    cells: Vec<Option<NetCell>>,
+    // Numbers of removed cells, for reuse
+    vacant: Vec<usize>,
+    free: Vec<Option<Port>>,
+    // Pairs that may be active, to be checked before they interact
+    pending: Vec<(usize, usize)>,
+}
+
+fn net_error(message: String) -> EvaluatorError {
+    EvaluatorError::TypeError(message)
+}
+
+// The index of an agent name in a net's agent table, adding it if it is new
+fn intern(agents: &mut Vec<String>, agent: &str) -> usize {
+    match agents.iter().position(|known| known == agent) {
+        Some(index) => index,
+        None => {
+            agents.push(agent.to_string());
+            agents.len() - 1
+        },
+    }
+}
+
+impl Net {
+    /// A net with the given number of free ports and no cells
+    pub fn new(free: usize) -> Self {
+        Net { free: vec![None; free], ..Net::default() }
+    }
+
+    /// Add a cell with every port unwired and return its number
+    pub fn add_cell(&mut self, cell: &Cell) -> usize {
+        let agent = self.intern(&cell.agent);
+        self.insert(agent, cell.arity)
+    }
+
+    fn intern(&mut self, agent: &str) -> usize {
+        intern(&mut self.agents, agent)
+    }
+
+    fn insert(&mut self, agent: usize, arity: usize) -> usize {
+        let cell = NetCell { agent, ports: vec![None; arity + 1] };
+        match self.vacant.pop() {
+            Some(number) => {
+                self.cells[number - 1] = Some(cell);
+                number
+            },
+            None => {
+                self.cells.push(Some(cell));
+                self.cells.len()
+            },
+        }
+    }
+
+    fn remove(&mut self, number: usize) {
+        self.cells[number - 1] = None;
+        self.vacant.push(number);
+    }
+
+    fn cell(&self, number: usize) -> Option<&NetCell> {
+        match number {
+            INTERFACE => None,
+            _ => self.cells.get(number - 1).and_then(|cell| cell.as_ref()),
+        }
+    }
+
+    /// The agent of a cell
+    pub fn agent(&self, number: usize) -> Option<&str> {
+        self.cell(number).map(|cell| self.agents[cell.agent].as_str())
+    }
+
+    /// The numbers of the cells in the net, in ascending order
+    pub fn cell_numbers(&self) -> Vec<usize> {
+        (1..=self.cells.len()).filter(|&number| self.cell(number).is_some()).collect()
+    }
+
+    pub fn free_ports(&self) -> usize {
+        self.free.len()
+    }
+
+    /// Add an unwired free port and return it
+    pub fn add_free_port(&mut self) -> Port {
+        self.free.push(None);
+        (INTERFACE, self.free.len() - 1)
+    }
+
+    fn slot(&self, (number, port): Port) -> Option<&Option<Port>> {
+        match number {
+            INTERFACE => self.free.get(port),
+            _ => self.cell(number).and_then(|cell| cell.ports.get(port)),
+        }
+    }
+
+    fn slot_mut(&mut self, (number, port): Port) -> &mut Option<Port> {
+        match number {
+            INTERFACE => &mut self.free[port],
+            _ => &mut self.cells[number - 1].as_mut().expect("wired ports belong to live cells").ports[port],
+        }
+    }
+
+    /// The port a port is wired to
+    pub fn target(&self, port: Port) -> Option<Port> {
+        self.slot(port).copied().flatten()
+    }
+
+    /// Wire two unwired ports together
+    pub fn connect(&mut self, a: Port, b: Port) -> Result<()> {
+        for port in [a, b] {
+            match self.slot(port) {
+                None => return Err(net_error(format!("The net has no port {:?}", port))),
+                Some(Some(other)) => return Err(net_error(format!("Port {:?} is already wired to {:?}", port, other))),
+                Some(None) => {},
+            }
+        }
+        if a == b {
+            return Err(net_error(format!("Cannot wire port {:?} to itself", a)));
+        }
+        self.link(a, b);
+        Ok(())
+    }
+
+    // Wire two ports, noting the pair if both are principal
+    fn link(&mut self, a: Port, b: Port) {
+        *self.slot_mut(a) = Some(b);
+        *self.slot_mut(b) = Some(a);
+        if a.1 == PRINCIPAL && b.1 == PRINCIPAL && a.0 != INTERFACE && b.0 != INTERFACE {
+            self.pending.push((a.0, b.0));
+        }
+    }
+
+    fn is_active(&self, a: usize, b: usize) -> bool {
+        self.cell(a).is_some() && self.target((a, PRINCIPAL)) == Some((b, PRINCIPAL))
+    }
+
+    /// Every active pair, each once, the lower numbered cell first
+    pub fn active_pairs(&self) -> Vec<(usize, usize)> {
+        self.cell_numbers().into_iter()
+            .filter_map(|number| match self.target((number, PRINCIPAL)) {
+                Some((other, PRINCIPAL)) if other != INTERFACE && number < other => Some((number, other)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Rewrite active pairs until none has a rule, returning what was done
+    pub fn normalize(&mut self, system: &RewriteSystem) -> Result<ReductionStats> {
+        let mut stats = ReductionStats::default();
+        let mut agents: HashMap<(usize, usize), Option<(usize, bool)>> = HashMap::new();
+        let mut stuck = Vec::new();
+        self.pending = self.active_pairs();
+        self.pending.reverse();
+        while let Some((a, b)) = self.pending.pop() {
+            if !self.is_active(a, b) {
+                continue;
+            }
+            let key = (self.cell(a).unwrap().agent, self.cell(b).unwrap().agent);
+            let found = match agents.get(&key) {
+                Some(found) => *found,
+                None => {
+                    let found = system.find(&self.agents[key.0], &self.agents[key.1]);
+                    agents.insert(key, found);
+                    found
+                },
+            };
+            match found {
+                Some((index, flipped)) => {
+                    if stats.interactions == INTERACTION_LIMIT {
+                        return Err(EvaluatorError::EvalError(format!(
+                            "normalize stopped after {} interactions; the net may have no normal form", INTERACTION_LIMIT
+                        )));
+                    }
+                    let rule = &system.rules[index];
+                    let (left, right) = if flipped { (b, a) } else { (a, b) };
+                    self.interact(left, right, rule);
+                    stats.interactions += 1;
+                    *stats.rules.entry(rule.name()).or_insert(0) += 1;
+                    stats.peak_cells = stats.peak_cells.max(self.cells.len() - self.vacant.len());
+                },
+                None => stuck.push((a, b)),
+            }
+        }
+        stats.stuck = stuck.len();
+        stats.cells = self.cells.len() - self.vacant.len();
+        stats.peak_cells = stats.peak_cells.max(stats.cells);
+        Ok(stats)
+    }
+
+    /// Rewrite active pairs until none has a rule, as normalize does, but in rounds: each
+    /// round rewrites every active pair at once, shared out among the given number of
+    /// threads, at most max_threads(). Rounds are laid out before any thread starts on them,
+    /// so the resulting net, numbering included, is the same whatever the number of threads,
+    /// and it is the net normalize gives up to the numbering of its cells (see canonical).
+    /// If a thread fails the reduction stops with an error and the net is left as it was.
+    pub fn normalize_parallel(&mut self, system: &RewriteSystem, threads: usize) -> Result<ReductionStats> {
+        let threads = threads.clamp(1, max_threads());
+        let arity = self.cells.iter().flatten()
+            .map(|cell| cell.ports.len() - 1)
+            .fold(system.arity, usize::max);
+        let shared = Shared {
+            wiring: RwLock::new(Wiring::new(self, arity + 1)),
+            round: RwLock::default(),
+            made: Mutex::default(),
+            barrier: Gate::new(threads),
+            done: AtomicBool::new(false),
+            failure: Mutex::default(),
+        };
+        let mut schedule = Schedule {
+            system,
+            agents: self.agents.clone(),
+            rules: HashMap::new(),
+            vacant: self.vacant.clone(),
+            pending: self.active_pairs(),
+            stats: ReductionStats::default(),
+        };
+        let result = thread::scope(|scope| {
+            for worker in 1..threads {
+                let shared = &shared;
+                scope.spawn(move || {
+                    // A worker that stops early has failed, and the main thread reports why
+                    if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| shared.work(worker, threads))) {
+                        shared.fail(payload);
+                    }
+                });
+            }
+            let result = panic::catch_unwind(AssertUnwindSafe(|| schedule.run(&shared, threads)))
+                .unwrap_or_else(|payload| Err(shared.fail(payload)));
+            shared.done.store(true, Relaxed);
+            if threads > 1 {
+                let _ = shared.wait();
+            }
+            result
+        });
+        // A thread that panicked may have left the wiring half rewritten
+        if shared.failure.into_inner().unwrap_or_else(PoisonError::into_inner).is_some() {
+            return result;
+        }
+        self.agents = schedule.agents;
+        self.restore(shared.wiring.into_inner().unwrap_or_else(PoisonError::into_inner), schedule.vacant);
+        result
+    }
+
+    // Take back the cells and wires of a net rewritten in a Wiring
+    fn restore(&mut self, wiring: Wiring, vacant: Vec<usize>) {
+        self.cells = (1..=wiring.agents.len())
+            .map(|number| match wiring.agents[number - 1].load(Relaxed) {
+                VACANT => None,
+                agent => {
+                    let ports = (0..=wiring.arities[number - 1].load(Relaxed))
+                        .map(|port| wiring.target(wiring.slot((number, port))).map(|slot| wiring.port(slot)))
+                        .collect();
+                    Some(NetCell { agent, ports })
+                },
+            })
+            .collect();
+        self.free = (0..wiring.free).map(|slot| wiring.target(slot).map(|slot| wiring.port(slot))).collect();
+        self.vacant = vacant;
+        self.pending.clear();
+    }
+
+    /// The same net with its cells renumbered in the order they are reached from its free
+    /// ports, so that nets differing only in how their cells are numbered compare equal.
+    /// Cells out of reach of the free ports follow, in their old order.
+    pub fn canonical(&self) -> Net {
+        let mut order = Vec::new();
+        let mut seen = vec![false; self.cells.len() + 1];
+        let mut queue: VecDeque<usize> = VecDeque::new();
+        let roots = self.free.iter().flatten().map(|&(number, _)| number).chain(self.cell_numbers());
+        for root in roots {
+            if root == INTERFACE || seen[root] {
+                continue;
+            }
+            seen[root] = true;
+            queue.push_back(root);
+            while let Some(number) = queue.pop_front() {
+                order.push(number);
+                for &(other, _) in self.cell(number).unwrap().ports.iter().flatten() {
+                    if other != INTERFACE && !seen[other] {
+                        seen[other] = true;
+                        queue.push_back(other);
+                    }
+                }
+            }
+        }
+
+        let mut renumbered = vec![INTERFACE; self.cells.len() + 1];
+        let mut net = Net::new(self.free.len());
+        for &number in &order {
+            let cell = self.cell(number).unwrap();
+            let agent = net.intern(&self.agents[cell.agent]);
+            renumbered[number] = net.insert(agent, cell.ports.len() - 1);
+        }
+        let map = |target: &Option<Port>| target.map(|(number, port)| (renumbered[number], port));
+        net.free = self.free.iter().map(map).collect();
+        for &number in &order {
+            let ports = self.cell(number).unwrap().ports.iter().map(map).collect();
+            net.cells[renumbered[number] - 1].as_mut().unwrap().ports = ports;
+        }
+        net
+    }
+
+    // Replace an active pair by the rule's net. The pair's auxiliary ports become
+    // pass-through points: each wire into one continues along the rule's wire from the
+    // matching free port, which may lead into another of the pair's auxiliary ports.
+    fn interact(&mut self, left: usize, right: usize, rule: &InteractionRule) {
+        let split = rule.left.arity;
+        let aux = |k: usize| if k < split { (left, k + 1) } else { (right, k - split + 1) };
+        let which = |(number, port): Port| match number {
+            n if n == left && port != PRINCIPAL => Some(port - 1),
+            n if n == right && port != PRINCIPAL => Some(split + port - 1),
+            _ => None,
+        };
+        let count = split + rule.right.arity;
+        let outside: Vec<Option<Port>> = (0..count).map(|k| self.target(aux(k))).collect();
+
+        // Copy the rule's cells in, wiring them among themselves
+        let rhs = &rule.rhs;
+        let mut numbers = vec![INTERFACE; rhs.cells.len() + 1];
+        for number in rhs.cell_numbers() {
+            let cell = rhs.cell(number).unwrap();
+            let agent = self.intern(&rhs.agents[cell.agent]);
+            numbers[number] = self.insert(agent, cell.ports.len() - 1);
+        }
+        let mut inside: Vec<Option<Endpoint>> = vec![None; count];
+        for number in rhs.cell_numbers() {
+            for (port, target) in rhs.cell(number).unwrap().ports.iter().enumerate() {
+                match *target {
+                    Some((INTERFACE, k)) => inside[k] = Some(Endpoint::Port((numbers[number], port))),
+                    Some(other) if (number, port) < other => self.link((numbers[number], port), (numbers[other.0], other.1)),
+                    _ => {},
+                }
+            }
+        }
+        for (k, target) in rhs.free.iter().enumerate() {
+            if let Some((INTERFACE, j)) = *target {
+                inside[k] = Some(Endpoint::Aux(j));
+            }
+        }
+        self.remove(left);
+        self.remove(right);
+
+        // Follow each path of pass-through points from an end outside them to its other end
+        let mut visited = vec![false; count];
+        for start in 0..count {
+            let ends = [
+                outside[start].filter(|&port| which(port).is_none()).map(|port| (port, true)),
+                match inside[start] {
+                    Some(Endpoint::Port(port)) => Some((port, false)),
+                    _ => None,
+                },
+            ];
+            for (from, came_from_outside) in ends.into_iter().flatten() {
+                if visited[start] {
+                    continue;
+                }
+                let (mut k, mut outward) = (start, came_from_outside);
+                let end = loop {
+                    visited[k] = true;
+                    let next = if outward {
+                        inside[k].map(|endpoint| match endpoint {
+                            Endpoint::Aux(j) => Step::Through(j),
+                            Endpoint::Port(port) => Step::End(port),
+                        })
+                    } else {
+                        outside[k].map(|port| match which(port) {
+                            Some(j) => Step::Through(j),
+                            None => Step::End(port),
+                        })
+                    };
+                    match next {
+                        Some(Step::Through(j)) => {
+                            k = j;
+                            outward = !outward;
+                        },
+                        Some(Step::End(port)) => break Some(port),
+                        None => break None,
+                    }
+                };
+                match end {
+                    Some(end) => self.link(from, end),
+                    None if came_from_outside => *self.slot_mut(from) = None,
+                    None => {},
+                }
+            }
+        }
+    }
+}
+
+// Where one of a rule's free ports leads: into the rule's net, or straight to another free port
+#[derive(Debug, Clone, Copy)]
+enum Endpoint {
+    Port(Port),
+    Aux(usize),
+}
+
+// Where a wire from one of the pair's auxiliary ports leads next
+enum Step {
+    Through(usize),
+    End(Port),
+}
+
+// Marks a cell number not in use in a Wiring
+const VACANT: usize = usize::MAX;
+
+// A net laid out for rewriting on several threads at once. Every cell gets the same number
+// of port slots, so a port is a single index: the free ports come first, then each cell's
+// ports. A slot holds the index of the slot it is wired to plus one, or 0 when unwired.
+// Within a phase of a round threads write disjoint slots, and the barriers between phases
+// order their writes, so relaxed atomics are enough.
+struct Wiring {
+    free: usize,
+    stride: usize,
+    agents: Vec<AtomicUsize>,
+    arities: Vec<AtomicUsize>,
+    // For a cell rewritten this round, its job's number in the round plus one
+    owners: Vec<AtomicUsize>,
+    slots: Vec<AtomicUsize>,
+}
+
+fn atomics(count: usize) -> Vec<AtomicUsize> {
+    (0..count).map(|_| AtomicUsize::new(0)).collect()
+}
+
+impl Wiring {
+    fn new(net: &Net, stride: usize) -> Self {
+        let wiring = Wiring {
+            free: net.free.len(),
+            stride,
+            agents: atomics(net.cells.len()),
+            arities: atomics(net.cells.len()),
+            owners: atomics(net.cells.len()),
+            slots: atomics(net.free.len() + net.cells.len() * stride),
+        };
+        for (slot, target) in net.free.iter().enumerate() {
+            wiring.wire(slot, target.map(|port| wiring.slot(port)));
+        }
+        for (index, cell) in net.cells.iter().enumerate() {
+            wiring.agents[index].store(cell.as_ref().map_or(VACANT, |cell| cell.agent), Relaxed);
+            for (port, target) in cell.iter().flat_map(|cell| cell.ports.iter().enumerate()) {
+                wiring.arities[index].store(port, Relaxed);
+                wiring.wire(wiring.slot((index + 1, port)), target.map(|port| wiring.slot(port)));
+            }
+        }
+        wiring
+    }
+
+    fn slot(&self, (number, port): Port) -> usize {
+        match number {
+            INTERFACE => port,
+            _ => self.free + (number - 1) * self.stride + port,
+        }
+    }
+
+    fn port(&self, slot: usize) -> Port {
+        match slot.checked_sub(self.free) {
+            None => (INTERFACE, slot),
+            Some(offset) => (offset / self.stride + 1, offset % self.stride),
+        }
+    }
+
+    fn target(&self, slot: usize) -> Option<usize> {
+        self.slots[slot].load(Relaxed).checked_sub(1)
+    }
+
+    fn wire(&self, slot: usize, target: Option<usize>) {
+        self.slots[slot].store(target.map_or(0, |target| target + 1), Relaxed);
+    }
+
+    // The job rewriting the cell a slot belongs to, if one is
+    fn owner(&self, slot: usize) -> Option<usize> {
+        match self.port(slot) {
+            (INTERFACE, _) => None,
+            (number, _) => self.owners[number - 1].load(Relaxed).checked_sub(1),
+        }
+    }
+
+    // The cells of two slots wired together, if both are principal ports
+    fn active(&self, a: usize, b: usize) -> Option<(usize, usize)> {
+        match (self.port(a), self.port(b)) {
+            ((a, PRINCIPAL), (b, PRINCIPAL)) if a != INTERFACE && b != INTERFACE => Some((a.min(b), a.max(b))),
+            _ => None,
+        }
+    }
+
+    // Add an unused cell number
+    fn grow(&mut self) -> usize {
+        self.agents.push(AtomicUsize::new(VACANT));
+        self.arities.push(AtomicUsize::new(0));
+        self.owners.push(AtomicUsize::new(0));
+        self.slots.extend(atomics(self.stride));
+        self.agents.len()
+    }
+}
+
+// A rule's net ready to copy into a Wiring: the agent and arity of each of its cells, the
+// wires between them by position, and where each of its free ports leads
+struct Template {
+    rule: usize,
+    split: usize,
+    cells: Vec<(usize, usize)>,
+    links: Vec<(Port, Port)>,
+    inside: Vec<Endpoint>,
+}
+
+impl Template {
+    fn new(system: &RewriteSystem, index: usize, agents: &mut Vec<String>) -> Self {
+        let rule = &system.rules[index];
+        let rhs = &rule.rhs;
+        let numbers = rhs.cell_numbers();
+        let position = |number: usize| numbers.iter().position(|&other| other == number).unwrap();
+        let cells = numbers.iter()
+            .map(|&number| {
+                let cell = rhs.cell(number).unwrap();
+                (intern(agents, &rhs.agents[cell.agent]), cell.ports.len() - 1)
+            })
+            .collect();
+        let mut links = Vec::new();
+        for &number in &numbers {
+            for (port, target) in rhs.cell(number).unwrap().ports.iter().enumerate() {
+                match *target {
+                    Some(other) if other.0 != INTERFACE && (number, port) < other => {
+                        links.push(((position(number), port), (position(other.0), other.1)));
+                    },
+                    _ => {},
+                }
+            }
+        }
+        let inside = rhs.free.iter()
+            .map(|target| match target.expect("rules wire every free port") {
+                (INTERFACE, j) => Endpoint::Aux(j),
+                (number, port) => Endpoint::Port((position(number), port)),
+            })
+            .collect();
+        Template { rule: index, split: rule.left.arity, cells, links, inside }
+    }
+}
+
+// An active pair rewritten this round, its cells in the order of its rule's agents
+struct Job {
+    left: usize,
+    right: usize,
+    template: usize,
+    // Where the numbers of its new cells and the ends of its free ports start in the round
+    cells: usize,
+    inside: usize,
+}
+
+#[derive(Default)]
+struct Round {
+    // The rules used so far, made ready as they are first needed
+    templates: Vec<Template>,
+    jobs: Vec<Job>,
+    cells: Vec<usize>,
+    // Where each free port of a job's rule leads: a slot times two, or another of its free ports times two plus one
+    inside: Vec<AtomicUsize>,
+}
+
+/// The most threads normalize_parallel reduces on: as many as the machine can run at once
+pub fn max_threads() -> usize {
+    thread::available_parallelism().map_or(1, |threads| threads.get())
+}
+
+// A barrier that a failing thread can break, sending every thread waiting at it on its way
+struct Gate {
+    // Threads arrived at the current crossing, crossings so far, and whether it is broken
+    state: Mutex<(usize, usize, bool)>,
+    crossed: Condvar,
+    threads: usize,
+}
+
+impl Gate {
+    fn new(threads: usize) -> Self {
+        Gate { state: Mutex::new((0, 0, false)), crossed: Condvar::new(), threads }
+    }
+
+    // Wait for every thread to arrive; false if the gate is broken before they all do
+    fn wait(&self) -> bool {
+        // Nothing panics while holding the state, so a poisoned lock still holds good state
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if state.2 {
+            return false;
+        }
+        state.0 += 1;
+        if state.0 == self.threads {
+            state.0 = 0;
+            state.1 += 1;
+            self.crossed.notify_all();
+            return true;
+        }
+        let crossing = state.1;
+        while state.1 == crossing && !state.2 {
+            state = self.crossed.wait(state).unwrap_or_else(PoisonError::into_inner);
+        }
+        !state.2
+    }
+
+    fn break_open(&self) {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner).2 = true;
+        self.crossed.notify_all();
+    }
+}
+
+// What the threads of a parallel reduction share
+struct Shared {
+    wiring: RwLock<Wiring>,
+    round: RwLock<Round>,
+    // Active pairs made this round
+    made: Mutex<Vec<(usize, usize)>>,
+    barrier: Gate,
+    done: AtomicBool,
+    // Why the first thread to fail did, if one has
+    failure: Mutex<Option<String>>,
+}
+
+// The slot of a job's pair that its rule's free port k stands for
+fn aux(wiring: &Wiring, round: &Round, job: &Job, k: usize) -> usize {
+    let split = round.templates[job.template].split;
+    wiring.slot(if k < split { (job.left, k + 1) } else { (job.right, k - split + 1) })
+}
+
+// The jobs one of a number of threads takes from a round
+fn share(jobs: usize, worker: usize, threads: usize) -> Range<usize> {
+    jobs * worker / threads..jobs * (worker + 1) / threads
+}
+
+impl Shared {
+    // Rewrite a share of each round until the reduction is done or another thread fails
+    fn work(&self, worker: usize, threads: usize) -> Result<()> {
+        loop {
+            self.wait()?;
+            if self.done.load(Relaxed) {
+                return Ok(());
+            }
+            let jobs = self.round.read().unwrap().jobs.len();
+            self.rewrite(share(jobs, worker, threads));
+            self.wait()?;
+            self.join(share(jobs, worker, threads));
+            self.wait()?;
+        }
+    }
+
+    // Wait at the barrier for the other threads, or for the failure of one of them
+    fn wait(&self) -> Result<()> {
+        if self.barrier.wait() {
+            return Ok(());
+        }
+        let failure = self.failure.lock().unwrap_or_else(PoisonError::into_inner);
+        Err(EvaluatorError::EvalError(format!(
+            "normalize_parallel stopped: a thread failed: {}", failure.as_deref().unwrap_or("unknown error")
+        )))
+    }
+
+    // Record why a thread panicked and release the others from the barrier
+    fn fail(&self, payload: Box<dyn std::any::Any + Send>) -> EvaluatorError {
+        let message = payload.downcast_ref::<&str>().map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown error".to_string());
+        self.failure.lock().unwrap_or_else(PoisonError::into_inner).get_or_insert(message);
+        self.barrier.break_open();
+        self.wait().unwrap_err()
+    }
+
+    // Copy each job's rule net into its new cells and note where its free ports lead
+    fn rewrite(&self, jobs: Range<usize>) {
+        let wiring = self.wiring.read().unwrap();
+        let round = self.round.read().unwrap();
+        let mut made = Vec::new();
+        for job in &round.jobs[jobs] {
+            let template = &round.templates[job.template];
+            let numbers = &round.cells[job.cells..job.cells + template.cells.len()];
+            for (&number, &(agent, arity)) in numbers.iter().zip(&template.cells) {
+                wiring.agents[number - 1].store(agent, Relaxed);
+                wiring.arities[number - 1].store(arity, Relaxed);
+                wiring.owners[number - 1].store(0, Relaxed);
+                for port in 0..wiring.stride {
+                    wiring.wire(wiring.slot((number, port)), None);
+                }
+            }
+            for &((a, p), (b, q)) in &template.links {
+                let (a, b) = (wiring.slot((numbers[a], p)), wiring.slot((numbers[b], q)));
+                wiring.wire(a, Some(b));
+                wiring.wire(b, Some(a));
+                made.extend(wiring.active(a, b));
+            }
+            for (k, endpoint) in template.inside.iter().enumerate() {
+                let code = match *endpoint {
+                    Endpoint::Port((position, port)) => 2 * wiring.slot((numbers[position], port)),
+                    Endpoint::Aux(j) => 2 * j + 1,
+                };
+                round.inside[job.inside + k].store(code, Relaxed);
+            }
+        }
+        self.made.lock().unwrap().extend(made);
+    }
+
+    // Wire the two ends of every path through the jobs' free ports together. Each end is
+    // a port outside the round's pairs or a port of a new cell, and each is written by
+    // whoever walks the path from it, so no two threads write the same slot.
+    fn join(&self, jobs: Range<usize>) {
+        let wiring = self.wiring.read().unwrap();
+        let round = self.round.read().unwrap();
+        let mut made = Vec::new();
+        let mut end = |start: usize, end: Option<usize>| {
+            wiring.wire(start, end);
+            if let Some(end) = end.filter(|&end| start < end) {
+                made.extend(wiring.active(start, end));
+            }
+        };
+        for index in jobs {
+            let job = &round.jobs[index];
+            for k in 0..round.templates[job.template].inside.len() {
+                match wiring.target(aux(&wiring, &round, job, k)) {
+                    Some(outside) if wiring.owner(outside).is_none() => {
+                        end(outside, self.walk(&wiring, &round, index, k, true));
+                    },
+                    _ => {},
+                }
+                let code = round.inside[job.inside + k].load(Relaxed);
+                if code & 1 == 0 {
+                    end(code / 2, self.walk(&wiring, &round, index, k, false));
+                }
+            }
+        }
+        self.made.lock().unwrap().extend(made);
+    }
+
+    // Follow a path from a job's free port k, into its rule's net or out of its pair,
+    // through any free ports met on the way, to the slot at its other end
+    fn walk(&self, wiring: &Wiring, round: &Round, mut index: usize, mut k: usize, mut inward: bool) -> Option<usize> {
+        loop {
+            let job = &round.jobs[index];
+            if inward {
+                let code = round.inside[job.inside + k].load(Relaxed);
+                if code & 1 == 0 {
+                    return Some(code / 2);
+                }
+                k = code / 2;
+                inward = false;
+            } else {
+                let outside = wiring.target(aux(wiring, round, job, k))?;
+                index = match wiring.owner(outside) {
+                    Some(owner) => owner,
+                    None => return Some(outside),
+                };
+                let other = &round.jobs[index];
+                let (number, port) = wiring.port(outside);
+                k = if number == other.left { port - 1 } else { round.templates[other.template].split + port - 1 };
+                inward = true;
+            }
+        }
+    }
+}
+
+// What only the thread running a parallel reduction touches: which pairs come next and
+// which cell numbers are free
+struct Schedule<'a> {
+    system: &'a RewriteSystem,
+    agents: Vec<String>,
+    // The template for each pair of agents met, and whether its rule is written the other way round
+    rules: HashMap<(usize, usize), Option<(usize, bool)>>,
+    vacant: Vec<usize>,
+    pending: Vec<(usize, usize)>,
+    stats: ReductionStats,
+}
+
+impl Schedule<'_> {
+    fn run(&mut self, shared: &Shared, threads: usize) -> Result<ReductionStats> {
+        loop {
+            let jobs = self.prepare(shared)?;
+            if jobs == 0 {
+                break;
+            }
+            if threads > 1 && jobs >= PARALLEL_ROUND {
+                shared.wait()?;
+                shared.rewrite(share(jobs, 0, threads));
+                shared.wait()?;
+                shared.join(share(jobs, 0, threads));
+                shared.wait()?;
+            } else {
+                shared.rewrite(0..jobs);
+                shared.join(0..jobs);
+            }
+            self.finish(shared);
+        }
+        let wiring = shared.wiring.read().unwrap();
+        self.stats.cells = wiring.agents.len() - self.vacant.len();
+        self.stats.peak_cells = self.stats.peak_cells.max(self.stats.cells);
+        Ok(self.stats.clone())
+    }
+
+    // Lay out a round for the pending pairs that have rules, returning how many it has
+    fn prepare(&mut self, shared: &Shared) -> Result<usize> {
+        let mut wiring = shared.wiring.write().unwrap();
+        let mut round = shared.round.write().unwrap();
+        round.jobs.clear();
+        round.cells.clear();
+        let system = self.system;
+        let mut inside = 0;
+        for (a, b) in std::mem::take(&mut self.pending) {
+            let key = (wiring.agents[a - 1].load(Relaxed), wiring.agents[b - 1].load(Relaxed));
+            let found = match self.rules.get(&key) {
+                Some(found) => *found,
+                None => {
+                    let found = match system.find(&self.agents[key.0], &self.agents[key.1]) {
+                        Some((rule, flipped)) => {
+                            round.templates.push(Template::new(system, rule, &mut self.agents));
+                            Some((round.templates.len() - 1, flipped))
+                        },
+                        None => None,
+                    };
+                    self.rules.insert(key, found);
+                    found
+                },
+            };
+            let (template, flipped) = match found {
+                Some(found) => found,
+                None => {
+                    self.stats.stuck += 1;
+                    continue;
+                },
+            };
+            if self.stats.interactions == INTERACTION_LIMIT {
+                return Err(EvaluatorError::EvalError(format!(
+                    "normalize stopped after {} interactions; the net may have no normal form", INTERACTION_LIMIT
+                )));
+            }
+            let (left, right) = if flipped { (b, a) } else { (a, b) };
+            let index = round.jobs.len();
+            wiring.owners[left - 1].store(index + 1, Relaxed);
+            wiring.owners[right - 1].store(index + 1, Relaxed);
+            let cells = round.cells.len();
+            for _ in 0..round.templates[template].cells.len() {
+                let number = self.vacant.pop().unwrap_or_else(|| wiring.grow());
+                round.cells.push(number);
+            }
+            round.jobs.push(Job { left, right, template, cells, inside });
+            inside += round.templates[template].inside.len();
+            self.stats.interactions += 1;
+            let rule = &system.rules[round.templates[template].rule];
+            *self.stats.rules.entry(rule.name()).or_insert(0) += 1;
+        }
+        if round.inside.len() < inside {
+            round.inside = atomics(inside);
+        }
+        self.stats.peak_cells = self.stats.peak_cells.max(wiring.agents.len() - self.vacant.len());
+        Ok(round.jobs.len())
+    }
+
+    // Remove the round's pairs and take the pairs it made, in order, for the next round
+    fn finish(&mut self, shared: &Shared) {
+        let wiring = shared.wiring.read().unwrap();
+        for job in &shared.round.read().unwrap().jobs {
+            for number in [job.left, job.right] {
+                wiring.agents[number - 1].store(VACANT, Relaxed);
+                self.vacant.push(number);
+            }
+        }
+        self.pending = std::mem::take(&mut *shared.made.lock().unwrap());
+        self.pending.sort_unstable();
+    }
+}
+
+// The agents and how many of each: Net(Z: 1, S: 2; 1 free port)
+impl fmt::Display for Net {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut counts = vec![0; self.agents.len()];
+        for cell in self.cells.iter().flatten() {
+            counts[cell.agent] += 1;
+        }
+        let agents: Vec<String> = self.agents.iter()
+            .zip(counts)
+            .filter(|(_, count)| *count > 0)
+            .map(|(agent, count)| format!("{}: {}", agent, count))
+            .collect();
+        let plural = if self.free.len() == 1 { "" } else { "s" };
+        if agents.is_empty() {
+            write!(f, "Net({} free port{})", self.free.len(), plural)
+        } else {
+            write!(f, "Net({}; {} free port{})", agents.join(", "), self.free.len(), plural)
+        }
+    }
+}
+
+/// An interaction rule: the net an active pair of these agents becomes
+#[derive(Debug, Clone, PartialEq)]
+pub struct InteractionRule {
+    pub left: Cell,
+    pub right: Cell,
+    pub rhs: Net,
+}
+
+impl InteractionRule {
+    fn name(&self) -> String {
+        format!("{} >< {}", self.left.agent, self.right.agent)
+    }
+}
+
+/// A set of interaction rules, at most one for each pair of agents
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RewriteSystem {
+    pub rules: Vec<InteractionRule>,
+    // The rule for each ordered pair of agents, and whether it is written the other way round
+    index: HashMap<(String, String), (usize, bool)>,
+    // The most auxiliary ports of any cell in the rules
+    arity: usize,
+}
+
+impl RewriteSystem {
+    /// A system of rules, checking that each rule's net has the free ports its agents need
+    pub fn new(rules: Vec<InteractionRule>) -> Result<Self> {
+        let mut system = RewriteSystem::default();
+        for rule in rules {
+            let needed = rule.left.arity + rule.right.arity;
+            if rule.rhs.free.len() != needed {
+                return Err(net_error(format!(
+                    "The rule for {} needs a net with {} free ports, not {}", rule.name(), needed, rule.rhs.free.len()
+                )));
+            }
+            if let Some(k) = rule.rhs.free.iter().position(|target| target.is_none()) {
+                return Err(net_error(format!("Free port {} of the rule for {} is not wired", k, rule.name())));
+            }
+            if system.find(&rule.left.agent, &rule.right.agent).is_some() {
+                return Err(net_error(format!("There is already a rule for {}", rule.name())));
+            }
+            let cells = rule.rhs.cells.iter().flatten().map(|cell| cell.ports.len() - 1);
+            system.arity = cells.fold(system.arity.max(rule.left.arity).max(rule.right.arity), usize::max);
+            let (left, right) = (rule.left.agent.clone(), rule.right.agent.clone());
+            system.index.insert((right.clone(), left.clone()), (system.rules.len(), true));
+            system.index.insert((left, right), (system.rules.len(), false));
+            system.rules.push(rule);
+        }
+        Ok(system)
+    }
+
+    // The rule for a pair of agents, and whether it is written the other way round
+    fn find(&self, a: &str, b: &str) -> Option<(usize, bool)> {
+        self.index.get(&(a.to_string(), b.to_string())).copied()
+    }
+}
+
+impl fmt::Display for RewriteSystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<String> = self.rules.iter().map(|rule| rule.name()).collect();
+        write!(f, "RewriteSystem({})", names.join(", "))
+    }
+}
+
+/// What normalize did
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ReductionStats {
+    pub interactions: usize,
+    // Interactions by rule, named "A >< B"
+    pub rules: HashMap<String, usize>,
+    // Active pairs left because no rule applies
+    pub stuck: usize,
+    pub cells: usize,
+    pub peak_cells: usize,
+}
+
+impl ReductionStats {
+    pub fn to_value(&self) -> Value {
+        let rules = self.rules.iter().map(|(name, count)| (name.clone(), Value::Number(*count as i32))).collect();
+        let mut stats: HashMap<String, Value> = [
+            ("interactions", self.interactions),
+            ("stuck", self.stuck),
+            ("cells", self.cells),
+            ("peak_cells", self.peak_cells),
+        ].iter().map(|(name, count)| (name.to_string(), Value::Number(*count as i32))).collect();
+        stats.insert("rules".to_string(), Value::Map(rules));
+        Value::Map(stats)
+    }
+}
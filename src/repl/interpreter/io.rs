@@ -0,0 +1,118 @@
+// src/repl/interpreter/io.rs
+// Files opened by Borf programs. Each open file is a linear resource of type File:
+// the value on the stack is a Value::Resource and the handle lives in a FileTable.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::rc::Rc;
+use crate::repl::interpreter::effects::Finalizer;
+use crate::repl::interpreter::handles::{handle, read_line};
+use crate::repl::interpreter::types::{EvaluatorError, Result, Value};
+
+/// The resource type of open files
+pub const FILE_RESOURCE: &str = "File";
+
+/// Open OS file handles by resource ID. Clones share the same table, so the
+/// finalizer registered for File resources can close what the evaluator opened.
+#[derive(Debug, Clone, Default)]
+pub struct FileTable {
+    files: Rc<RefCell<HashMap<usize, BufReader<File>>>>,
+}
+
+// An I/O failure as a FileError, naming the file
+fn io_error(action: &str, path: &str, err: std::io::Error) -> EvaluatorError {
+    EvaluatorError::FileError(std::io::Error::new(err.kind(), format!("Cannot {} '{}': {}", action, path, err)))
+}
+
+impl FileTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open a file in mode "r" (read), "w" (write, truncating) or "a" (append). The OS
+    /// refusing is a FileError; an unknown mode is an EvalError.
+    pub fn open(path: &str, mode: &str) -> Result<File> {
+        let mut options = OpenOptions::new();
+        match mode {
+            "r" => options.read(true),
+            "w" => options.write(true).create(true).truncate(true),
+            "a" => options.append(true).create(true),
+            _ => return Err(EvaluatorError::EvalError(format!(
+                "Unknown file mode '{}': expected \"r\", \"w\" or \"a\"", mode
+            ))),
+        };
+        options.open(path).map_err(|err| io_error("open", path, err))
+    }
+
+    pub fn insert(&self, id: usize, file: File) {
+        self.files.borrow_mut().insert(id, BufReader::new(file));
+    }
+
+    pub fn is_open(&self, id: usize) -> bool {
+        self.files.borrow().contains_key(&id)
+    }
+
+    fn with_file<T>(&self, id: usize, action: impl FnOnce(&mut BufReader<File>) -> std::io::Result<T>) -> Result<T> {
+        let mut files = self.files.borrow_mut();
+        let file = files.get_mut(&id)
+            .ok_or_else(|| EvaluatorError::EvalError(format!("File {} is not open", id)))?;
+        action(file).map_err(|err| EvaluatorError::EvalError(format!("I/O error on file {}: {}", id, err)))
+    }
+
+    /// The rest of the file from the current position
+    pub fn read_all(&self, id: usize) -> Result<String> {
+        self.with_file(id, |file| {
+            let mut text = String::new();
+            file.read_to_string(&mut text)?;
+            Ok(text)
+        })
+    }
+
+    /// The next line without its line ending, or None at the end of the file
+    pub fn read_line(&self, id: usize) -> Result<Option<String>> {
+        self.with_file(id, |file| read_line(file))
+    }
+
+    pub fn write(&self, id: usize, text: &str) -> Result<()> {
+        self.with_file(id, |file| {
+            // Move the OS position back to where reading got to, dropping read-ahead
+            file.seek(SeekFrom::Current(0))?;
+            file.get_mut().write_all(text.as_bytes())
+        })
+    }
+
+    /// Move to a byte offset from the start of the file
+    pub fn seek(&self, id: usize, offset: u64) -> Result<()> {
+        self.with_file(id, |file| file.seek(SeekFrom::Start(offset)).map(|_| ()))
+    }
+
+    /// Flush and close a file; closing one that is not open does nothing
+    pub fn close(&self, id: usize) -> Result<()> {
+        let file = self.files.borrow_mut().remove(&id);
+        match file {
+            Some(mut file) => file.get_mut().flush()
+                .map_err(|err| EvaluatorError::EvalError(format!("I/O error closing file {}: {}", id, err))),
+            None => Ok(()),
+        }
+    }
+
+    /// The finalizer for File resources: closes the handle named by the resource's inner value
+    pub fn finalizer(&self) -> Finalizer {
+        let table = self.clone();
+        Finalizer::Native(Rc::new(move |value: &Value| match handle(value) {
+            Some(id) => table.close(id),
+            None => Err(EvaluatorError::EvalError(format!("Not a file handle: {}", value))),
+        }))
+    }
+}
+
+/// The inner value of a File resource: `{ path: String, mode: String, handle: Int }`
+pub fn file_value(path: &str, mode: &str, id: usize) -> Value {
+    let mut fields = HashMap::new();
+    fields.insert("path".to_string(), Value::String(path.to_string()));
+    fields.insert("mode".to_string(), Value::String(mode.to_string()));
+    fields.insert("handle".to_string(), Value::Number(id as i32));
+    Value::Map(fields)
+}
@@ -0,0 +1,465 @@
+// src/repl/interpreter/linearity.rs
+// Static check that every value of linear (!) type is used exactly once on every path
+
+use std::collections::HashMap;
+use std::fmt;
+use crate::repl::interpreter::stack_effects::{StackEffect, get_word_effect};
+use crate::repl::interpreter::types::{Expr, Param, Pattern, Type};
+
+/// A linear value that is duplicated, discarded or never used
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinearityError {
+    /// Where the offending use is, or where a use is missing, e.g. "close_twice, word 3 `f`"
+    pub location: String,
+    pub message: String,
+}
+
+impl fmt::Display for LinearityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)
+    }
+}
+
+// Words that throw a stack item away without using it
+const DISCARDING: &[&str] = &["drop", "nip", "2drop"];
+
+/// Whether values of a type must be used exactly once. `?!T` is linear too: the
+/// optional has to be inspected exactly once to get at the value inside.
+pub fn is_linear_type(typ: &Type) -> bool {
+    match typ {
+        Type::Linear(_) => true,
+        Type::Optional(inner) => is_linear_type(inner),
+        _ => false,
+    }
+}
+
+// A named binding: a quotation parameter, captured parameter or match variable
+#[derive(Debug, Clone)]
+struct Binding {
+    name: String,
+    linear: bool,
+    // Where a linear binding was used
+    used_at: Option<String>,
+}
+
+// A stack item; linear ones carry a description of the value for diagnostics
+#[derive(Debug, Clone, PartialEq)]
+enum Slot {
+    Plain,
+    Linear(String),
+}
+
+#[derive(Debug, Clone)]
+struct State {
+    bindings: Vec<Binding>,
+    // The stack, while its shape can be followed
+    stack: Option<Vec<Slot>>,
+}
+
+impl State {
+    fn pop(&mut self) -> Option<Slot> {
+        // Items from below the start of the body are not linear as far as we know
+        self.stack.as_mut().map(|stack| stack.pop().unwrap_or(Slot::Plain))
+    }
+
+    fn push(&mut self, slot: Slot) {
+        if let Some(stack) = self.stack.as_mut() {
+            stack.push(slot);
+        }
+    }
+
+    fn binding(&mut self, name: &str) -> Option<&mut Binding> {
+        self.bindings.iter_mut().rev().find(|binding| binding.name == name)
+    }
+}
+
+struct Checker<'a> {
+    known: &'a HashMap<String, StackEffect>,
+    errors: Vec<LinearityError>,
+}
+
+impl<'a> Checker<'a> {
+    fn error(&mut self, location: &str, message: String) {
+        self.errors.push(LinearityError { location: location.to_string(), message });
+    }
+
+    // Check a quotation body. `inputs` are the stack items it starts with, and
+    // `captured` the linear bindings of enclosing quotations it refers to.
+    fn quotation(
+        &mut self,
+        context: &str,
+        params: &[Param],
+        body: &[Expr],
+        inputs: Vec<Slot>,
+        captured: Vec<Binding>,
+    ) {
+        let mut state = State { bindings: captured, stack: Some(inputs) };
+        let outer = state.bindings.len();
+
+        // Parameters take the top items; a declared linear input makes its parameter linear
+        let mut taken: Vec<Option<Slot>> = params.iter().map(|_| state.pop()).collect();
+        taken.reverse();
+        for (param, slot) in params.iter().zip(taken) {
+            let annotated = param.type_annotation.as_ref().map_or(false, is_linear_type);
+            state.bindings.push(Binding {
+                name: param.name.clone(),
+                linear: annotated || matches!(slot, Some(Slot::Linear(_))),
+                used_at: None,
+            });
+        }
+
+        self.body(context, body, &mut state);
+
+        let end = format!("{}, end of body", context);
+        for (index, binding) in state.bindings.iter().enumerate() {
+            if binding.linear && binding.used_at.is_none() {
+                let kind = if index < outer { "captured linear value" } else { "linear parameter" };
+                self.error(&end, format!("{} '{}' is never used", kind, binding.name));
+            }
+        }
+    }
+
+    fn body(&mut self, context: &str, body: &[Expr], state: &mut State) {
+        for (index, expr) in body.iter().enumerate() {
+            let location = format!("{}, word {} `{}`", context, index + 1, expr);
+            self.expr(&location, expr, state);
+        }
+    }
+
+    fn expr(&mut self, location: &str, expr: &Expr, state: &mut State) {
+        match expr {
+            Expr::Number(_) | Expr::String(_) | Expr::Boolean(_) | Expr::Nil | Expr::Literal(_) => {
+                state.push(Slot::Plain);
+            },
+            Expr::Symbol(name) => self.symbol(location, name, state),
+            Expr::Sequence(exprs) => {
+                for expr in exprs {
+                    self.expr(location, expr, state);
+                }
+            },
+            Expr::Pipeline(left, right) => {
+                self.expr(location, left, state);
+                self.expr(location, right, state);
+            },
+            Expr::Quotation(params, body) | Expr::TypedQuotation(params, body, _) => {
+                self.nested(location, params, body, state);
+            },
+            Expr::If(condition, then_branch, else_branch) => {
+                // A Nil condition means the condition is already on the stack
+                if !matches!(condition.as_ref(), Expr::Nil) {
+                    self.expr(location, condition, state);
+                }
+                state.pop();
+                let names = ["then branch".to_string(), "else branch".to_string()];
+                self.paths(location, state, &names, |checker, index, context, state| {
+                    let arm = if index == 0 { then_branch } else { else_branch };
+                    checker.expr(context, arm, state);
+                });
+            },
+            Expr::Match(subject, cases) => {
+                // A Nil subject means the value to match is already on the stack
+                if !matches!(subject.as_ref(), Expr::Nil) {
+                    self.expr(location, subject, state);
+                }
+                let linear_subject = matches!(state.pop(), Some(Slot::Linear(_)));
+
+                let names: Vec<String> = cases.iter().enumerate()
+                    .map(|(index, (pattern, _))| format!("arm {} `{}`", index + 1, pattern_text(pattern)))
+                    .collect();
+                self.paths(location, state, &names, |checker, index, context, state| {
+                    let (pattern, arm) = &cases[index];
+                    checker.arm(context, pattern, linear_subject, arm, state);
+                });
+            },
+            Expr::Assignment(value, _) => self.expr(location, value, state),
+            Expr::StackEffect(_) | Expr::EffectAnnotation(_) => {},
+            other => {
+                // Check quotations inside other forms, but stop following the stack
+                for inner in nested_exprs(other) {
+                    self.expr(location, inner, state);
+                }
+                state.stack = None;
+            },
+        }
+    }
+
+    fn symbol(&mut self, location: &str, name: &str, state: &mut State) {
+        if let Some(binding) = state.binding(name) {
+            if !binding.linear {
+                state.push(Slot::Plain);
+                return;
+            }
+            match binding.used_at.clone() {
+                Some(first) => self.error(location, format!(
+                    "linear value '{}' is used again (first used at {})", name, first
+                )),
+                None => binding.used_at = Some(location.to_string()),
+            }
+            state.push(Slot::Linear(format!("'{}'", name)));
+            return;
+        }
+
+        if name == "pick" || name == "roll" {
+            // The depth is not followed, so the stack shape is lost
+            state.stack = None;
+            return;
+        }
+
+        let effect = match self.known.get(name).cloned().or_else(|| get_word_effect(name)) {
+            Some(effect) if !effect.is_row_polymorphic() => effect,
+            _ => {
+                state.stack = None;
+                return;
+            },
+        };
+        if state.stack.is_none() {
+            return;
+        }
+
+        let mut inputs: Vec<Slot> = effect.inputs.iter().filter_map(|_| state.pop()).collect();
+        inputs.reverse();
+        for (input, slot) in effect.inputs.iter().zip(&inputs) {
+            if let Slot::Linear(value) = slot {
+                let copies = effect.outputs.iter().filter(|output| output.name == input.name).count();
+                if copies > 1 {
+                    self.error(location, format!("`{}` would duplicate linear value {}", name, value));
+                } else if copies == 0 && DISCARDING.contains(&name) {
+                    self.error(location, format!("`{}` would discard linear value {}", name, value));
+                }
+            }
+        }
+        for output in &effect.outputs {
+            let slot = match effect.inputs.iter().rposition(|input| input.name == output.name) {
+                Some(index) => inputs[index].clone(),
+                None if output.item_type.as_ref().map_or(false, is_linear_type) => {
+                    Slot::Linear(format!("'{}' from `{}`", output.name, name))
+                },
+                None => Slot::Plain,
+            };
+            state.push(slot);
+        }
+    }
+
+    // A quotation literal: the linear values it refers to are moved into it
+    fn nested(&mut self, location: &str, params: &[Param], body: &[Expr], state: &mut State) {
+        let mut captured = Vec::new();
+        for name in free_symbols(params, body) {
+            if let Some(binding) = state.binding(&name) {
+                if !binding.linear {
+                    continue;
+                }
+                match binding.used_at.clone() {
+                    Some(first) => {
+                        let message = format!("linear value '{}' is captured after being used at {}", name, first);
+                        self.error(location, message);
+                    },
+                    None => binding.used_at = Some(location.to_string()),
+                }
+                captured.push(Binding { name, linear: true, used_at: None });
+            }
+        }
+
+        let linear = !captured.is_empty();
+        let names: Vec<String> = captured.iter().map(|binding| format!("'{}'", binding.name)).collect();
+        self.quotation(&format!("{} quotation", location), params, body, Vec::new(), captured);
+        state.push(if linear {
+            Slot::Linear(format!("quotation capturing {}", names.join(", ")))
+        } else {
+            Slot::Plain
+        });
+    }
+
+    // Check alternative paths from the same state; each must use the same linear values
+    fn paths<F>(&mut self, location: &str, state: &mut State, names: &[String], mut check: F)
+    where
+        F: FnMut(&mut Self, usize, &str, &mut State),
+    {
+        if names.is_empty() {
+            return;
+        }
+        let outcomes: Vec<(String, State)> = names.iter().enumerate()
+            .map(|(index, name)| {
+                let mut branch = state.clone();
+                let context = format!("{}, {}", location, name);
+                check(self, index, &context, &mut branch);
+                (name.clone(), branch)
+            })
+            .collect();
+
+        for (index, binding) in state.bindings.iter_mut().enumerate() {
+            if !binding.linear || binding.used_at.is_some() {
+                continue;
+            }
+            let using: Vec<&(String, State)> = outcomes.iter()
+                .filter(|(_, branch)| branch.bindings[index].used_at.is_some())
+                .collect();
+            if using.is_empty() {
+                continue;
+            }
+            for (name, _) in outcomes.iter().filter(|(_, branch)| branch.bindings[index].used_at.is_none()) {
+                self.errors.push(LinearityError {
+                    location: format!("{}, {}", location, name),
+                    message: format!(
+                        "linear value '{}' is not used here but is used in the {}",
+                        binding.name, using[0].0
+                    ),
+                });
+            }
+            binding.used_at = using[0].1.bindings[index].used_at.clone();
+        }
+
+        // The stack stays known only if every path leaves the same linear items behind
+        let first = outcomes[0].1.stack.clone();
+        let agree = outcomes.iter().all(|(_, branch)| branch.stack == first);
+        if !agree {
+            let linear_left = |stack: &Option<Vec<Slot>>| {
+                stack.as_ref().map(|stack| stack.iter().filter(|slot| matches!(slot, Slot::Linear(_))).count())
+            };
+            let counts: Vec<Option<usize>> = outcomes.iter()
+                .map(|(_, branch)| linear_left(&branch.stack))
+                .collect();
+            let known = counts.iter().all(|count| count.is_some());
+            if known && counts.windows(2).any(|pair| pair[0] != pair[1]) {
+                self.error(location, "paths leave different linear values on the stack".to_string());
+            }
+        }
+        state.stack = if agree { first } else { None };
+    }
+
+    // A match arm: variables bound from a linear subject are linear too
+    fn arm(&mut self, context: &str, pattern: &Pattern, linear_subject: bool, arm: &Expr, state: &mut State) {
+        if linear_subject && matches!(pattern, Pattern::Wildcard) {
+            self.error(context, "`_` would discard the linear value being matched".to_string());
+        }
+        let outer = state.bindings.len();
+        bind_pattern(pattern, linear_subject, &mut state.bindings);
+        let body = match arm {
+            Expr::Sequence(exprs) => exprs.clone(),
+            other => vec![other.clone()],
+        };
+        self.body(context, &body, state);
+
+        for binding in state.bindings.drain(outer..).collect::<Vec<_>>() {
+            if binding.linear && binding.used_at.is_none() {
+                self.error(context, format!("linear value '{}' bound by the pattern is never used", binding.name));
+            }
+        }
+    }
+}
+
+fn bind_pattern(pattern: &Pattern, linear: bool, bindings: &mut Vec<Binding>) {
+    match pattern {
+        Pattern::Variable(name) => bindings.push(Binding { name: name.clone(), linear, used_at: None }),
+        Pattern::Linear(inner) => bind_pattern(inner, true, bindings),
+        Pattern::Quote(inner) => bind_pattern(inner, linear, bindings),
+        Pattern::Map(fields) => {
+            for inner in fields.values() {
+                bind_pattern(inner, linear, bindings);
+            }
+        },
+        Pattern::Variant(_, inner) => {
+            for inner in inner {
+                bind_pattern(inner, linear, bindings);
+            }
+        },
+        Pattern::Wildcard | Pattern::Literal(_) | Pattern::TypePattern(_) => {},
+    }
+}
+
+fn pattern_text(pattern: &Pattern) -> String {
+    match pattern {
+        Pattern::Wildcard => "_".to_string(),
+        Pattern::Literal(expr) => expr.to_string(),
+        Pattern::Variable(name) => name.clone(),
+        Pattern::Linear(inner) => format!("!{}", pattern_text(inner)),
+        Pattern::Quote(inner) => format!("'{}", pattern_text(inner)),
+        Pattern::Variant(tag, inner) => {
+            let inner: Vec<String> = inner.iter().map(pattern_text).collect();
+            format!("{}({})", tag, inner.join(", "))
+        },
+        other => format!("{:?}", other),
+    }
+}
+
+// Symbols a quotation refers to that its own parameters do not bind
+pub fn free_symbols(params: &[Param], body: &[Expr]) -> Vec<String> {
+    fn collect(body: &[Expr], bound: &[String], found: &mut Vec<String>) {
+        for expr in body {
+            match expr {
+                Expr::Symbol(name) => {
+                    if !bound.contains(name) && !found.contains(name) {
+                        found.push(name.clone());
+                    }
+                },
+                Expr::Quotation(params, inner) | Expr::TypedQuotation(params, inner, _) => {
+                    let mut bound = bound.to_vec();
+                    bound.extend(params.iter().map(|param| param.name.clone()));
+                    collect(inner, &bound, found);
+                },
+                other => collect(&nested_exprs(other).into_iter().cloned().collect::<Vec<_>>(), bound, found),
+            }
+        }
+    }
+    let bound: Vec<String> = params.iter().map(|param| param.name.clone()).collect();
+    let mut found = Vec::new();
+    collect(body, &bound, &mut found);
+    found
+}
+
+// The expressions directly inside a compound expression
+fn nested_exprs(expr: &Expr) -> Vec<&Expr> {
+    match expr {
+        Expr::Sequence(exprs) | Expr::Tuple(exprs) => exprs.iter().collect(),
+        Expr::Pipeline(a, b) | Expr::Binary(_, a, b) | Expr::Times(a, b) | Expr::While(a, b)
+        | Expr::Map(a, b) | Expr::Filter(a, b) | Expr::BiAt(a, b) => vec![a, b],
+        Expr::If(a, b, c) | Expr::Bi(a, b, c) | Expr::For(a, b, c) | Expr::Fold(a, b, c)
+        | Expr::BiStar(a, b, c) => vec![a, b, c],
+        Expr::Tri(a, b, c, d) => vec![a, b, c, d],
+        Expr::Dip(a) | Expr::Dip2(a) | Expr::Keep(a) | Expr::Loop(a) | Expr::Assignment(a, _) => vec![a],
+        Expr::Match(subject, cases) => {
+            let mut exprs = vec![subject.as_ref()];
+            exprs.extend(cases.iter().map(|(_, arm)| arm));
+            exprs
+        },
+        Expr::Cleave(x, quots) => {
+            let mut exprs = vec![x.as_ref()];
+            exprs.extend(quots.iter());
+            exprs
+        },
+        _ => Vec::new(),
+    }
+}
+
+/// Check every definition, and the code outside definitions, of a program.
+/// `effects` gives the stack effects of words; declared inputs of linear type
+/// (`( f:!File -- )`) make the matching stack items of a definition linear.
+pub fn check_linearity(program: &Expr, effects: &HashMap<String, StackEffect>) -> Vec<LinearityError> {
+    let exprs = match program {
+        Expr::Sequence(exprs) => exprs.as_slice(),
+        other => std::slice::from_ref(other),
+    };
+    let mut checker = Checker { known: effects, errors: Vec::new() };
+
+    let mut top_level = Vec::new();
+    for expr in exprs {
+        match expr {
+            Expr::Assignment(value, name) => match value.as_ref() {
+                Expr::Quotation(params, body) | Expr::TypedQuotation(params, body, _) => {
+                    let inputs = effects.get(name)
+                        .map(|effect| effect.inputs.iter()
+                            .map(|item| match &item.item_type {
+                                Some(typ) if is_linear_type(typ) => Slot::Linear(format!("input '{}'", item.name)),
+                                _ => Slot::Plain,
+                            })
+                            .collect())
+                        .unwrap_or_default();
+                    checker.quotation(name, params, body, inputs, Vec::new());
+                },
+                other => top_level.push(other.clone()),
+            },
+            other => top_level.push(other.clone()),
+        }
+    }
+    checker.quotation("top level", &[], &top_level, Vec::new(), Vec::new());
+    checker.errors
+}
@@ -0,0 +1,383 @@
+// src/repl/interpreter/migration.rs
+// Functorial data migration between ACSet schemas. A schema functor F: C -> D sends
+// each object of C to an object of D, each hom of C to a path of homs in D, and each
+// attribute of C to a path followed by an attribute of D. Δ_F pulls a D-instance back
+// to a C-instance by composing with F; Σ_F pushes a C-instance forward, freely adding
+// the parts D's homs require and identifying the parts F's equations make equal.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use crate::repl::interpreter::acset::{ACSet, Schema};
+use crate::repl::interpreter::types::{EvaluatorError, Result, Value};
+
+/// The most parts Σ migration creates before giving up: a schema with cyclic homs
+/// can need infinitely many
+const SIGMA_LIMIT: usize = 100_000;
+
+/// A functor between schemas
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaFunctor {
+    pub dom: Rc<Schema>,
+    pub codom: Rc<Schema>,
+    pub objects: HashMap<String, String>,
+    // Each hom's path, first hom first; an empty path is an identity
+    pub homs: HashMap<String, Vec<String>>,
+    // Each attribute's path and the attribute at its end
+    pub attrs: HashMap<String, (Vec<String>, String)>,
+}
+
+fn functor_error(message: String) -> EvaluatorError {
+    EvaluatorError::TypeError(message)
+}
+
+impl SchemaFunctor {
+    /// A functor from the listed images, checking that every path runs between the right objects.
+    /// An object, hom or attribute that is not listed goes to the one of the same name.
+    pub fn new(
+        dom: Rc<Schema>,
+        codom: Rc<Schema>,
+        mut objects: HashMap<String, String>,
+        mut homs: HashMap<String, Vec<String>>,
+        mut attrs: HashMap<String, (Vec<String>, String)>,
+    ) -> Result<Self> {
+        let listed = objects.keys().chain(homs.keys()).chain(attrs.keys());
+        for name in listed {
+            if !dom.has_object(name) && dom.hom(name).is_none() && dom.attr(name).is_none() {
+                return Err(functor_error(format!("{} is not in the functor's domain", name)));
+            }
+        }
+        for object in &dom.objects {
+            let image = objects.entry(object.clone()).or_insert_with(|| object.clone());
+            if !codom.has_object(image) {
+                return Err(functor_error(format!("Object {} goes to {}, which is not an object of the codomain", object, image)));
+            }
+        }
+        let end_of = |start: &str, path: &[String], what: &str| -> Result<String> {
+            let mut object = start.to_string();
+            for step in path {
+                match codom.hom(step) {
+                    Some(hom) if hom.dom == object => object = hom.codom.clone(),
+                    Some(hom) => return Err(functor_error(format!(
+                        "The path of {} cannot follow {}: {} -> {} from {}", what, step, hom.dom, hom.codom, object
+                    ))),
+                    None => return Err(functor_error(format!("The path of {} has {}, which is not a hom of the codomain", what, step))),
+                }
+            }
+            Ok(object)
+        };
+        for hom in &dom.homs {
+            let path = homs.entry(hom.name.clone()).or_insert_with(|| vec![hom.name.clone()]);
+            let end = end_of(&objects[&hom.dom], path, &hom.name)?;
+            if end != objects[&hom.codom] {
+                return Err(functor_error(format!(
+                    "Hom {}: {} -> {} goes to a path ending at {}, not {}", hom.name, hom.dom, hom.codom, end, objects[&hom.codom]
+                )));
+            }
+        }
+        let mut attr_types: HashMap<&str, &str> = HashMap::new();
+        for attr in &dom.attrs {
+            let (path, image) = attrs.entry(attr.name.clone()).or_insert_with(|| (Vec::new(), attr.name.clone()));
+            let end = end_of(&objects[&attr.dom], path, &attr.name)?;
+            let target = match codom.attr(image) {
+                Some(target) if target.dom == end => target,
+                _ => return Err(functor_error(format!(
+                    "Attribute {} goes to {}, which is not an attribute of {} in the codomain", attr.name, image, end
+                ))),
+            };
+            // Attribute types go where their attributes go
+            let type_image = attr_types.entry(attr.codom.as_str()).or_insert(target.codom.as_str());
+            if *type_image != target.codom {
+                return Err(functor_error(format!(
+                    "Attribute type {} goes to both {} and {}", attr.codom, type_image, target.codom
+                )));
+            }
+        }
+        Ok(SchemaFunctor { dom, codom, objects, homs, attrs })
+    }
+
+    /// A functor from Borf values: `(ob image)` pairs, `(hom path...)` lists and
+    /// `(attr path... image)` lists
+    pub fn from_values(dom: Rc<Schema>, codom: Rc<Schema>, objects: Value, homs: Value, attrs: Value) -> Result<Self> {
+        let objects = rows(objects, "objects")?.into_iter()
+            .map(|row| match row.as_slice() {
+                [object, image] => Ok((object.clone(), image.clone())),
+                _ => Err(functor_error(format!("Functor objects must be (ob image) pairs, got ({})", row.join(" ")))),
+            })
+            .collect::<Result<_>>()?;
+        let homs = rows(homs, "homs")?.into_iter()
+            .map(|mut row| {
+                let name = row.remove(0);
+                (name, row)
+            })
+            .collect();
+        let attrs = rows(attrs, "attributes")?.into_iter()
+            .map(|mut row| match row.len() {
+                0 | 1 => Err(functor_error(format!("Functor attributes must be (attr path... image) lists, got ({})", row.join(" ")))),
+                _ => {
+                    let name = row.remove(0);
+                    let image = row.pop().unwrap();
+                    Ok((name, (row, image)))
+                },
+            })
+            .collect::<Result<_>>()?;
+        SchemaFunctor::new(dom, codom, objects, homs, attrs)
+    }
+}
+
+// A list of non-empty lists of strings
+fn rows(value: Value, what: &str) -> Result<Vec<Vec<String>>> {
+    let invalid = |value: &Value| functor_error(format!("Functor {} must be a list of lists of names, got {}", what, value));
+    let rows = match value {
+        Value::List(rows) => rows,
+        other => return Err(invalid(&other)),
+    };
+    rows.iter()
+        .map(|row| match row {
+            Value::List(items) if !items.is_empty() => items.iter()
+                .map(|item| match item {
+                    Value::String(name) => Ok(name.clone()),
+                    _ => Err(invalid(row)),
+                })
+                .collect(),
+            _ => Err(invalid(row)),
+        })
+        .collect()
+}
+
+// Objects and their images: Functor(V => V, E => E; src => src, tgt => tgt)
+impl fmt::Display for SchemaFunctor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let objects: Vec<String> = self.dom.objects.iter()
+            .map(|object| format!("{} => {}", object, self.objects[object]))
+            .collect();
+        let homs: Vec<String> = self.dom.homs.iter()
+            .map(|hom| {
+                let path = &self.homs[&hom.name];
+                let image = if path.is_empty() { "id".to_string() } else { path.join(".") };
+                format!("{} => {}", hom.name, image)
+            })
+            .collect();
+        write!(f, "Functor({}; {})", objects.join(", "), homs.join(", "))
+    }
+}
+
+// Follow a path of homs from a part; None if a subpart along the way is unset
+fn follow(acset: &ACSet, part: usize, path: &[String]) -> Result<Option<usize>> {
+    let mut current = part;
+    for hom in path {
+        match acset.subpart(current, hom)? {
+            Some(next) => current = next,
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(current))
+}
+
+/// Δ_F: the C-instance whose parts of c are the parts of F(c) in the D-instance
+pub fn delta_migrate(functor: &SchemaFunctor, acset: &ACSet) -> Result<ACSet> {
+    if *acset.schema != *functor.codom {
+        return Err(functor_error("delta_migrate requires an ACSet on the functor's codomain".to_string()));
+    }
+    let mut migrated = ACSet::new(Rc::clone(&functor.dom));
+    for object in &functor.dom.objects {
+        migrated.add_parts(object, acset.nparts(&functor.objects[object])?)?;
+    }
+    for hom in &functor.dom.homs {
+        for part in 0..migrated.nparts(&hom.dom)? {
+            if let Some(target) = follow(acset, part, &functor.homs[&hom.name])? {
+                migrated.set_subpart(part, &hom.name, target)?;
+            }
+        }
+    }
+    for attr in &functor.dom.attrs {
+        let (path, image) = &functor.attrs[&attr.name];
+        for part in 0..migrated.nparts(&attr.dom)? {
+            let value = match follow(acset, part, path)? {
+                Some(end) => acset.get_attr(end, image)?.cloned(),
+                None => None,
+            };
+            if let Some(value) = value {
+                migrated.set_attr(part, &attr.name, value)?;
+            }
+        }
+    }
+    Ok(migrated)
+}
+
+/// Σ_F: the free D-instance generated by the parts of the C-instance, subject to
+/// F's hom of each part being its image under the hom in C
+pub fn sigma_migrate(functor: &SchemaFunctor, acset: &ACSet) -> Result<ACSet> {
+    if *acset.schema != *functor.dom {
+        return Err(functor_error("sigma_migrate requires an ACSet on the functor's domain".to_string()));
+    }
+    let mut chase = Chase::new(Rc::clone(&functor.codom));
+    let mut generators: HashMap<&str, Vec<usize>> = HashMap::new();
+    for object in &functor.dom.objects {
+        let nodes = (0..acset.nparts(object)?)
+            .map(|_| chase.fresh(&functor.objects[object]))
+            .collect::<Result<_>>()?;
+        generators.insert(object.as_str(), nodes);
+    }
+    for hom in &functor.dom.homs {
+        for (part, &node) in generators[hom.dom.as_str()].iter().enumerate() {
+            if let Some(target) = acset.subpart(part, &hom.name)? {
+                let end = chase.follow(node, &functor.homs[&hom.name])?;
+                chase.union(end, generators[hom.codom.as_str()][target])?;
+            }
+        }
+    }
+    for attr in &functor.dom.attrs {
+        let (path, image) = &functor.attrs[&attr.name];
+        for (part, &node) in generators[attr.dom.as_str()].iter().enumerate() {
+            if let Some(value) = acset.get_attr(part, &attr.name)? {
+                let end = chase.follow(node, path)?;
+                chase.set_attr(end, image, value.clone())?;
+            }
+        }
+    }
+    chase.complete()?;
+    chase.into_acset()
+}
+
+// The parts of a Σ migration being built: nodes merged by union-find, with the
+// homs and attributes of each class kept on its root
+struct Chase {
+    schema: Rc<Schema>,
+    objects: Vec<String>,
+    parent: Vec<usize>,
+    homs: Vec<HashMap<String, usize>>,
+    attrs: Vec<HashMap<String, Value>>,
+}
+
+impl Chase {
+    fn new(schema: Rc<Schema>) -> Self {
+        Chase { schema, objects: Vec::new(), parent: Vec::new(), homs: Vec::new(), attrs: Vec::new() }
+    }
+
+    fn fresh(&mut self, object: &str) -> Result<usize> {
+        if self.parent.len() >= SIGMA_LIMIT {
+            return Err(EvaluatorError::EvalError(format!(
+                "sigma_migrate needs more than {} parts; the codomain's homs may form a cycle", SIGMA_LIMIT
+            )));
+        }
+        let node = self.parent.len();
+        self.objects.push(object.to_string());
+        self.parent.push(node);
+        self.homs.push(HashMap::new());
+        self.attrs.push(HashMap::new());
+        Ok(node)
+    }
+
+    fn find(&mut self, node: usize) -> usize {
+        let mut root = node;
+        while self.parent[root] != root {
+            root = self.parent[root];
+        }
+        let mut current = node;
+        while self.parent[current] != root {
+            let next = self.parent[current];
+            self.parent[current] = root;
+            current = next;
+        }
+        root
+    }
+
+    // The node a hom sends a node to, adding a new one if it has none yet
+    fn step(&mut self, node: usize, hom: &str) -> Result<usize> {
+        let node = self.find(node);
+        if let Some(&target) = self.homs[node].get(hom) {
+            return Ok(self.find(target));
+        }
+        let codom = self.schema.hom(hom).unwrap().codom.clone();
+        let target = self.fresh(&codom)?;
+        self.homs[node].insert(hom.to_string(), target);
+        Ok(target)
+    }
+
+    fn follow(&mut self, node: usize, path: &[String]) -> Result<usize> {
+        let mut current = node;
+        for hom in path {
+            current = self.step(current, hom)?;
+        }
+        Ok(self.find(current))
+    }
+
+    // Identify two nodes, and so everything their homs send them to; the older node is kept
+    fn union(&mut self, a: usize, b: usize) -> Result<()> {
+        let mut pending = vec![(a, b)];
+        while let Some((a, b)) = pending.pop() {
+            let (a, b) = (self.find(a), self.find(b));
+            if a == b {
+                continue;
+            }
+            let (kept, merged) = if a < b { (a, b) } else { (b, a) };
+            self.parent[merged] = kept;
+            for (hom, target) in std::mem::take(&mut self.homs[merged]) {
+                match self.homs[kept].get(&hom) {
+                    Some(&existing) => pending.push((existing, target)),
+                    None => {
+                        self.homs[kept].insert(hom, target);
+                    },
+                }
+            }
+            for (attr, value) in std::mem::take(&mut self.attrs[merged]) {
+                self.set_attr(kept, &attr, value)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn set_attr(&mut self, node: usize, attr: &str, value: Value) -> Result<()> {
+        let node = self.find(node);
+        match self.attrs[node].get(attr) {
+            Some(existing) if *existing != value => Err(EvaluatorError::EvalError(format!(
+                "sigma_migrate identifies parts whose {} differ: {} and {}", attr, existing, value
+            ))),
+            _ => {
+                self.attrs[node].insert(attr.to_string(), value);
+                Ok(())
+            },
+        }
+    }
+
+    // Give every part each hom out of its object, adding parts as needed
+    fn complete(&mut self) -> Result<()> {
+        let schema = Rc::clone(&self.schema);
+        let mut node = 0;
+        while node < self.parent.len() {
+            if self.find(node) == node {
+                let object = self.objects[node].clone();
+                for hom in schema.homs_from(&object) {
+                    self.step(node, &hom.name)?;
+                }
+            }
+            node += 1;
+        }
+        Ok(())
+    }
+
+    // Number each class's root within its object, oldest first
+    fn into_acset(mut self) -> Result<ACSet> {
+        let mut acset = ACSet::new(Rc::clone(&self.schema));
+        let mut numbers: HashMap<usize, usize> = HashMap::new();
+        for node in 0..self.parent.len() {
+            if self.find(node) == node {
+                numbers.insert(node, acset.add_part(&self.objects[node])?);
+            }
+        }
+        for node in 0..self.parent.len() {
+            if self.find(node) != node {
+                continue;
+            }
+            for (hom, target) in self.homs[node].clone() {
+                let target = self.find(target);
+                acset.set_subpart(numbers[&node], &hom, numbers[&target])?;
+            }
+            for (attr, value) in self.attrs[node].clone() {
+                acset.set_attr(numbers[&node], &attr, value)?;
+            }
+        }
+        Ok(acset)
+    }
+}
@@ -2,15 +2,48 @@
 // This module provides the interpreter for the Borf language
 
 mod types;
+mod errors;
 mod env;
 mod parser;
 mod evaluator;
 mod stack_effects;
+mod stack_types;
+mod stack_check;
+mod optimizer;
 mod effects;
+mod linearity;
+mod io;
+mod handles;
+mod capabilities;
+mod process;
+mod net;
+mod acset;
+mod query;
+mod migration;
+mod wiring;
+mod dataflow;
+mod inet;
+mod combinators;
 
 // Re-export the public types
-pub use types::{Env, EvaluatorError, Expr, Param, Pattern, Result, Type, TypeParam, Value};
+pub use types::{Env, EvaluatorError, Expr, Param, Pattern, Result, SourceMap, Span, Type, TypeParam, Value};
+pub use errors::BorfError;
 pub use parser::Parser;
-pub use evaluator::Evaluator;
-pub use stack_effects::{StackEffect, get_word_effect};
-pub use effects::{EffectType, ResourceManager, ResourceValue};
\ No newline at end of file
+pub use evaluator::{Differential, Evaluator, Strategy};
+pub use stack_effects::{StackEffect, StackItem, get_word_effect, parse_stack_effect, translate_quotation, translate_quotation_with, expand_definitions};
+pub use stack_check::{check_program, infer_effect, format_effect};
+pub use optimizer::{Optimizer, Rule, equivalent_code};
+pub use stack_types::{Signature, StackTy, infer_quotation, format_type};
+pub use effects::{EffectType, Finalizer, Origin, ResourceManager, ResourceValue};
+pub use linearity::{LinearityError, check_linearity, is_linear_type};
+pub use io::{FileTable, FILE_RESOURCE};
+pub use capabilities::{Capabilities, Grant};
+pub use process::{ProcessTable, PIPE_RESOURCE, PROCESS_RESOURCE};
+pub use net::{NetTable, LISTENER_RESOURCE, SOCKET_RESOURCE};
+pub use acset::{ACSet, Hom, Schema};
+pub use query::{Homomorphism, homomorphisms, query};
+pub use migration::{SchemaFunctor, delta_migrate, sigma_migrate};
+pub use wiring::{WiringBox, WiringDiagram, Wire, OUTER_BOX};
+pub use dataflow::quotation_to_diagram;
+pub use inet::{Cell, InteractionRule, Net, Port, ReductionStats, RewriteSystem, max_threads, INTERFACE, PARALLEL_ROUND, PRINCIPAL};
+pub use combinators::{combinator_rules, compile_source, compile_to_net, eval_inet, read_back};
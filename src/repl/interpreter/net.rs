@@ -0,0 +1,302 @@
+// src/repl/interpreter/net.rs
+// Network connections made by Borf programs. A listening socket is a linear
+// resource of type Listener and a connection is a linear resource of type Socket;
+// the OS handles live in a NetTable, like files in a FileTable.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Chain, Cursor, Read, Write};
+use std::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::rc::Rc;
+use crate::repl::interpreter::effects::Finalizer;
+use crate::repl::interpreter::handles::{handle, read_line};
+use crate::repl::interpreter::types::{EvaluatorError, Result, Value};
+
+/// The resource type of listening sockets
+pub const LISTENER_RESOURCE: &str = "Listener";
+
+/// The resource type of connected sockets
+pub const SOCKET_RESOURCE: &str = "Socket";
+
+/// A listening socket
+#[derive(Debug)]
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener, String),
+}
+
+/// A connected stream, either end of a TCP or Unix domain socket connection
+#[derive(Debug)]
+pub enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Stream {
+    fn try_clone(&self) -> std::io::Result<Stream> {
+        match self {
+            Stream::Tcp(stream) => stream.try_clone().map(Stream::Tcp),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.try_clone().map(Stream::Unix),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.read(buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.write(buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.flush(),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+/// A connection, buffered for reading
+#[derive(Debug)]
+pub struct Socket {
+    reader: BufReader<Stream>,
+    writer: Stream,
+    // The start of a character split across reads, held back by recv until the rest arrives
+    pending: Vec<u8>,
+}
+
+impl Socket {
+    pub fn new(stream: Stream) -> Result<Self> {
+        let writer = stream.try_clone().map_err(|err| net_error("set up", "connection", err))?;
+        Ok(Socket { reader: BufReader::new(stream), writer, pending: Vec::new() })
+    }
+
+    // What has been read, then the rest of the connection
+    fn unread(&mut self) -> Chain<Cursor<Vec<u8>>, &mut BufReader<Stream>> {
+        Cursor::new(std::mem::take(&mut self.pending)).chain(&mut self.reader)
+    }
+}
+
+// Where an incomplete UTF-8 sequence at the end of the bytes starts, if there is one
+fn incomplete_tail(bytes: &[u8]) -> Option<usize> {
+    // A sequence is at most 4 bytes: a leading byte and up to 3 continuation bytes (10xxxxxx)
+    let start = (bytes.len().saturating_sub(3)..bytes.len()).rev()
+        .find(|&i| bytes[i] & 0xC0 != 0x80)?;
+    match std::str::from_utf8(&bytes[start..]) {
+        Err(err) if err.error_len().is_none() => Some(start),
+        _ => None,
+    }
+}
+
+/// Listeners and sockets by resource ID. Clones share the same tables, so the
+/// finalizers registered for Listener and Socket resources can close what the evaluator opened.
+#[derive(Debug, Clone, Default)]
+pub struct NetTable {
+    listeners: Rc<RefCell<HashMap<usize, Listener>>>,
+    sockets: Rc<RefCell<HashMap<usize, Socket>>>,
+}
+
+fn net_error(action: &str, address: &str, err: std::io::Error) -> EvaluatorError {
+    EvaluatorError::EvalError(format!("Cannot {} '{}': {}", action, address, err))
+}
+
+impl NetTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Listen for TCP connections; port 0 picks a free port
+    pub fn tcp_listen(host: &str, port: u16) -> Result<Listener> {
+        let address = format!("{}:{}", host, port);
+        TcpListener::bind(&address)
+            .map(Listener::Tcp)
+            .map_err(|err| net_error("listen on", &address, err))
+    }
+
+    pub fn tcp_connect(host: &str, port: u16) -> Result<Socket> {
+        let address = format!("{}:{}", host, port);
+        let stream = TcpStream::connect(&address).map_err(|err| net_error("connect to", &address, err))?;
+        Socket::new(Stream::Tcp(stream))
+    }
+
+    /// Listen for connections on a Unix domain socket at a path
+    #[cfg(unix)]
+    pub fn unix_listen(path: &str) -> Result<Listener> {
+        UnixListener::bind(path)
+            .map(|listener| Listener::Unix(listener, path.to_string()))
+            .map_err(|err| net_error("listen on", path, err))
+    }
+
+    #[cfg(unix)]
+    pub fn unix_connect(path: &str) -> Result<Socket> {
+        let stream = UnixStream::connect(path).map_err(|err| net_error("connect to", path, err))?;
+        Socket::new(Stream::Unix(stream))
+    }
+
+    #[cfg(not(unix))]
+    pub fn unix_listen(path: &str) -> Result<Listener> {
+        Err(EvaluatorError::EvalError(format!("Cannot listen on '{}': Unix domain sockets are not supported here", path)))
+    }
+
+    #[cfg(not(unix))]
+    pub fn unix_connect(path: &str) -> Result<Socket> {
+        Err(EvaluatorError::EvalError(format!("Cannot connect to '{}': Unix domain sockets are not supported here", path)))
+    }
+
+    pub fn insert_listener(&self, id: usize, listener: Listener) {
+        self.listeners.borrow_mut().insert(id, listener);
+    }
+
+    pub fn insert_socket(&self, id: usize, socket: Socket) {
+        self.sockets.borrow_mut().insert(id, socket);
+    }
+
+    /// The port a TCP listener is bound to
+    pub fn local_port(&self, id: usize) -> Result<u16> {
+        let listeners = self.listeners.borrow();
+        match listeners.get(&id) {
+            Some(Listener::Tcp(listener)) => listener.local_addr()
+                .map(|address| address.port())
+                .map_err(|err| EvaluatorError::EvalError(format!("I/O error on listener {}: {}", id, err))),
+            #[cfg(unix)]
+            Some(Listener::Unix(..)) => Err(EvaluatorError::EvalError(format!("Listener {} is not a TCP listener", id))),
+            None => Err(EvaluatorError::EvalError(format!("Listener {} is not open", id))),
+        }
+    }
+
+    /// Wait for the next connection and return it with its peer address
+    pub fn accept(&self, id: usize) -> Result<(Socket, String)> {
+        let listeners = self.listeners.borrow();
+        let listener = listeners.get(&id)
+            .ok_or_else(|| EvaluatorError::EvalError(format!("Listener {} is not open", id)))?;
+        let accept_error = |err| EvaluatorError::EvalError(format!("Cannot accept on listener {}: {}", id, err));
+        match listener {
+            Listener::Tcp(listener) => {
+                let (stream, peer) = listener.accept().map_err(accept_error)?;
+                Ok((Socket::new(Stream::Tcp(stream))?, peer.to_string()))
+            },
+            #[cfg(unix)]
+            Listener::Unix(listener, path) => {
+                let (stream, _) = listener.accept().map_err(accept_error)?;
+                Ok((Socket::new(Stream::Unix(stream))?, path.clone()))
+            },
+        }
+    }
+
+    fn with_socket<T>(&self, id: usize, action: impl FnOnce(&mut Socket) -> std::io::Result<T>) -> Result<T> {
+        let mut sockets = self.sockets.borrow_mut();
+        let socket = sockets.get_mut(&id)
+            .ok_or_else(|| EvaluatorError::EvalError(format!("Socket {} is not open", id)))?;
+        action(socket).map_err(|err| EvaluatorError::EvalError(format!("I/O error on socket {}: {}", id, err)))
+    }
+
+    pub fn send(&self, id: usize, text: &str) -> Result<()> {
+        self.with_socket(id, |socket| {
+            socket.writer.write_all(text.as_bytes())?;
+            socket.writer.flush()
+        })
+    }
+
+    /// The data that has arrived, waiting until there is some, or None once the peer has closed.
+    /// A character split across reads is given whole by the next call.
+    pub fn recv(&self, id: usize) -> Result<Option<String>> {
+        self.with_socket(id, |socket| loop {
+            let received = socket.reader.fill_buf()?;
+            if received.is_empty() {
+                // A character the peer never finished is lost, like any other invalid bytes
+                if socket.pending.is_empty() {
+                    return Ok(None);
+                }
+                let text = String::from_utf8_lossy(&socket.pending).into_owned();
+                socket.pending.clear();
+                return Ok(Some(text));
+            }
+            let mut bytes = std::mem::take(&mut socket.pending);
+            bytes.extend_from_slice(received);
+            let length = received.len();
+            socket.reader.consume(length);
+
+            if let Some(start) = incomplete_tail(&bytes) {
+                socket.pending = bytes.split_off(start);
+            }
+            if !bytes.is_empty() {
+                return Ok(Some(String::from_utf8_lossy(&bytes).into_owned()));
+            }
+        })
+    }
+
+    /// The next line without its line ending, or None once the peer has closed
+    pub fn read_line(&self, id: usize) -> Result<Option<String>> {
+        self.with_socket(id, |socket| read_line(&mut socket.unread()))
+    }
+
+    /// Everything until the peer closes the connection
+    pub fn read_all(&self, id: usize) -> Result<String> {
+        self.with_socket(id, |socket| {
+            let mut text = String::new();
+            socket.unread().read_to_string(&mut text)?;
+            Ok(text)
+        })
+    }
+
+    /// Close a listener, removing the path of a Unix domain socket; closing one that is not open does nothing
+    pub fn close_listener(&self, id: usize) -> Result<()> {
+        let listener = self.listeners.borrow_mut().remove(&id);
+        match listener {
+            #[cfg(unix)]
+            Some(Listener::Unix(_, path)) => std::fs::remove_file(&path).map_err(|err| net_error("remove", &path, err)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Close a connection; closing one that is not open does nothing
+    pub fn close_socket(&self, id: usize) -> Result<()> {
+        self.sockets.borrow_mut().remove(&id);
+        Ok(())
+    }
+
+    /// The finalizer for Listener resources
+    pub fn listener_finalizer(&self) -> Finalizer {
+        let table = self.clone();
+        Finalizer::Native(Rc::new(move |value: &Value| match handle(value) {
+            Some(id) => table.close_listener(id),
+            None => Err(EvaluatorError::EvalError(format!("Not a listener handle: {}", value))),
+        }))
+    }
+
+    /// The finalizer for Socket resources
+    pub fn socket_finalizer(&self) -> Finalizer {
+        let table = self.clone();
+        Finalizer::Native(Rc::new(move |value: &Value| match handle(value) {
+            Some(id) => table.close_socket(id),
+            None => Err(EvaluatorError::EvalError(format!("Not a socket handle: {}", value))),
+        }))
+    }
+}
+
+/// The inner value of a Listener or Socket resource: `{ address: String, handle: Int }`,
+/// where the address is where a listener listens or who a socket is connected to
+pub fn net_value(address: &str, id: usize) -> Value {
+    let mut fields = HashMap::new();
+    fields.insert("address".to_string(), Value::String(address.to_string()));
+    fields.insert("handle".to_string(), Value::Number(id as i32));
+    Value::Map(fields)
+}
@@ -0,0 +1,275 @@
+// src/repl/interpreter/optimizer.rs
+// Peephole optimizer for stack code, driven by a table of rewrite rules
+
+use std::collections::HashMap;
+use std::fmt;
+use crate::repl::interpreter::stack_effects::{StackEffect, get_word_effect};
+use crate::repl::interpreter::types::{EvaluatorError, Expr, Result};
+
+/// Rewrites every optimizer starts with. Each one is checked against the stack effects of
+/// its words when an optimizer is made, like rules added later.
+const BUILTIN_RULES: &[(&str, &str)] = &[
+    ("swap swap", ""),
+    ("dup drop", ""),
+    ("over over", "2dup"),
+    ("0 pick", "dup"),
+    ("1 pick", "over"),
+    ("0 roll", ""),
+    ("1 roll", "swap"),
+    ("2 roll", "rot"),
+    ("swap drop", "nip"),
+    ("over drop", ""),
+    ("rot rot rot", ""),
+];
+
+// Words that only compute on the stack; a rule may not remove or reorder any other word,
+// since it could do I/O or use a resource
+const PURE_WORDS: &[&str] = &[
+    "dup", "drop", "swap", "rot", "over", "tuck", "2dup", "nip", "pick", "roll",
+    "+", "add", "-", "sub", "*", "mul", "/", "div", "mod", "sqrt",
+    "and", "or", "not", "==", "eq", "!=", "<", ">", "<=", ">=",
+];
+
+// Words whose two inputs can be given in either order
+const COMMUTATIVE: &[&str] = &["+", "add", "*", "mul", "==", "eq", "!=", "and", "or"];
+
+/// A rewrite of a sequence of stack words into a shorter one with the same effect
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    pub pattern: Vec<Expr>,
+    pub replacement: Vec<Expr>,
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let words = |code: &[Expr]| code.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(" ");
+        write!(f, "[{}] => [{}]", words(&self.pattern), words(&self.replacement))
+    }
+}
+
+/// Rewrites stack code with a table of rules until none applies
+#[derive(Debug, Clone)]
+pub struct Optimizer {
+    rules: Vec<Rule>,
+}
+
+impl Optimizer {
+    pub fn new() -> Self {
+        let mut optimizer = Optimizer { rules: Vec::new() };
+        let commutative = COMMUTATIVE.iter().map(|op| (format!("swap {}", op), op.to_string()));
+        let builtin = BUILTIN_RULES.iter()
+            .map(|(pattern, replacement)| (pattern.to_string(), replacement.to_string()))
+            .chain(commutative);
+        for (pattern, replacement) in builtin {
+            optimizer.add_rule(words(&pattern), words(&replacement), &HashMap::new())
+                .unwrap_or_else(|e| panic!("Built-in rule does not match the word effects: {}", e));
+        }
+        optimizer
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// Add a rule. Both sides may only use pure stack words and must be shown to leave the
+    /// same stack from the effects of those words alone; rules that cannot be proven are rejected.
+    pub fn add_rule(
+        &mut self,
+        pattern: Vec<Expr>,
+        replacement: Vec<Expr>,
+        known: &HashMap<String, StackEffect>,
+    ) -> Result<()> {
+        let rule = Rule { pattern, replacement };
+        if rule.pattern.is_empty() {
+            return Err(EvaluatorError::TypeError("A rule needs a non-empty pattern".to_string()));
+        }
+        // Removing or moving any other word could drop I/O or a use of a resource
+        let impure = rule.pattern.iter().chain(&rule.replacement).find_map(|expr| match expr {
+            Expr::Symbol(word) if !PURE_WORDS.contains(&word.as_str()) => Some(word),
+            _ => None,
+        });
+        if let Some(word) = impure {
+            return Err(EvaluatorError::TypeError(format!(
+                "Rule {} uses '{}', which is not known to be pure", rule, word
+            )));
+        }
+
+        // Every rewrite shrinks the code, so optimization always terminates
+        if rule.replacement.len() >= rule.pattern.len() {
+            return Err(EvaluatorError::TypeError(format!(
+                "Rule {} must replace its pattern with shorter code", rule
+            )));
+        }
+
+        let (lhs, rhs) = match (Simulation::run(&rule.pattern, known), Simulation::run(&rule.replacement, known)) {
+            (Some(lhs), Some(rhs)) => (lhs, rhs),
+            _ => return Err(EvaluatorError::TypeError(format!(
+                "Cannot determine the stack effect of both sides of rule {}", rule
+            ))),
+        };
+        if lhs.depth_change() != rhs.depth_change() {
+            return Err(EvaluatorError::TypeError(format!(
+                "Rule {} changes the stack depth by {} on the left but {} on the right",
+                rule, lhs.depth_change(), rhs.depth_change()
+            )));
+        }
+        if !lhs.equivalent(&rhs) {
+            return Err(EvaluatorError::TypeError(format!(
+                "Rule {} cannot be shown to leave the same stack on both sides", rule
+            )));
+        }
+
+        self.rules.push(rule);
+        Ok(())
+    }
+
+    /// Optimize a sequence of stack words, including quotations without parameters inside it
+    pub fn optimize(&self, code: &[Expr]) -> Vec<Expr> {
+        let mut code: Vec<Expr> = code.iter()
+            .map(|expr| match expr {
+                Expr::Quotation(params, body) if params.is_empty() => {
+                    Expr::Quotation(Vec::new(), self.optimize(body))
+                },
+                other => other.clone(),
+            })
+            .collect();
+
+        loop {
+            let mut optimized = Vec::with_capacity(code.len());
+            let mut changed = false;
+            let mut i = 0;
+            while i < code.len() {
+                // The longest pattern starting here wins
+                let rule = self.rules.iter()
+                    .filter(|rule| code[i..].starts_with(&rule.pattern))
+                    .max_by_key(|rule| rule.pattern.len());
+                match rule {
+                    Some(rule) => {
+                        optimized.extend(rule.replacement.iter().cloned());
+                        i += rule.pattern.len();
+                        changed = true;
+                    },
+                    None => {
+                        optimized.push(code[i].clone());
+                        i += 1;
+                    },
+                }
+            }
+            if !changed {
+                return optimized;
+            }
+            code = optimized;
+        }
+    }
+}
+
+impl Default for Optimizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Split stack code written as words into expressions
+fn words(code: &str) -> Vec<Expr> {
+    code.split_whitespace()
+        .map(|word| match word.parse::<i32>() {
+            Ok(n) => Expr::Number(n),
+            Err(_) => Expr::Symbol(word.to_string()),
+        })
+        .collect()
+}
+
+// A symbolic value: an item that was on the stack before the code ran,
+// a literal, or an output of a word applied to other symbolic values
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Sym {
+    Input(usize),
+    Number(i32),
+    Literal(String),
+    Output(String, Vec<Sym>, usize),
+}
+
+// Symbolic execution of stack code, using the effects of its words
+struct Simulation {
+    stack: Vec<Sym>,
+    // How many items were taken from below the starting stack
+    pulled: usize,
+}
+
+impl Simulation {
+    fn run(code: &[Expr], known: &HashMap<String, StackEffect>) -> Option<Simulation> {
+        let mut sim = Simulation { stack: Vec::new(), pulled: 0 };
+        for expr in code {
+            match expr {
+                Expr::Number(n) => sim.stack.push(Sym::Number(*n)),
+                Expr::Symbol(word) => sim.word(word, known)?,
+                other => sim.stack.push(Sym::Literal(other.to_string())),
+            }
+        }
+        Some(sim)
+    }
+
+    fn pop(&mut self) -> Sym {
+        self.stack.pop().unwrap_or_else(|| {
+            self.pulled += 1;
+            Sym::Input(self.pulled - 1)
+        })
+    }
+
+    fn word(&mut self, word: &str, known: &HashMap<String, StackEffect>) -> Option<()> {
+        if word == "pick" || word == "roll" {
+            let depth = match self.pop() {
+                Sym::Number(n) if n >= 0 => n as usize,
+                _ => return None,
+            };
+            let mut items: Vec<Sym> = (0..=depth).map(|_| self.pop()).collect();
+            items.reverse();
+            let item = if word == "pick" { items[0].clone() } else { items.remove(0) };
+            self.stack.extend(items);
+            self.stack.push(item);
+            return Some(());
+        }
+
+        let effect = known.get(word).cloned().or_else(|| get_word_effect(word))?;
+        if effect.is_row_polymorphic() {
+            return None;
+        }
+        let mut inputs: Vec<Sym> = effect.inputs.iter().map(|_| self.pop()).collect();
+        inputs.reverse();
+        let mut arguments = inputs.clone();
+        if COMMUTATIVE.contains(&word) {
+            arguments.sort();
+        }
+        for (index, output) in effect.outputs.iter().enumerate() {
+            match effect.inputs.iter().rposition(|input| input.name == output.name) {
+                Some(position) => self.stack.push(inputs[position].clone()),
+                None => self.stack.push(Sym::Output(word.to_string(), arguments.clone(), index)),
+            }
+        }
+        Some(())
+    }
+
+    fn depth_change(&self) -> isize {
+        self.stack.len() as isize - self.pulled as isize
+    }
+
+    // The whole stack reached by either simulation, with untouched items filled in
+    fn padded(&self, depth: usize) -> Vec<Sym> {
+        let mut full: Vec<Sym> = (self.pulled..depth).rev().map(Sym::Input).collect();
+        full.extend(self.stack.iter().cloned());
+        full
+    }
+
+    fn equivalent(&self, other: &Simulation) -> bool {
+        let depth = self.pulled.max(other.pulled);
+        self.padded(depth) == other.padded(depth)
+    }
+}
+
+/// Check that code on both sides leaves the same stack, from the effects of its words alone
+pub fn equivalent_code(lhs: &[Expr], rhs: &[Expr], known: &HashMap<String, StackEffect>) -> bool {
+    match (Simulation::run(lhs, known), Simulation::run(rhs, known)) {
+        (Some(lhs), Some(rhs)) => lhs.equivalent(&rhs),
+        _ => false,
+    }
+}
@@ -8,8 +8,9 @@ use pest::pratt_parser::{PrattParser, Assoc, Op};
 use pest_derive::Parser;
 
 use crate::repl::interpreter::errors::{BorfError, BorfSpan, Result};
-use crate::repl::interpreter::types::{Expr, Param, Pattern, Type, Value};
-use crate::repl::interpreter::stack_effects::{StackEffect, parse_stack_effect, translate_quotation};
+use crate::repl::interpreter::types::{Expr, Param, Pattern, SourceMap, Span, Type, Value};
+use crate::repl::interpreter::stack_effects::{StackEffect, parse_item_type, parse_stack_effect};
+use crate::repl::interpreter::effects::parse_effect;
 
 #[derive(Parser)]
 #[grammar = "repl/interpreter/borf.pest"]
@@ -19,7 +20,7 @@ pub struct BorfParser;
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Rule {
     // Top-level rules
-    program, module_decl, import_decl, top_level_expr,
+    program, shebang, module_decl, import_decl, top_level_expr,
     
     // Expression rules
     expr, atom, infix_op,
@@ -47,6 +48,8 @@ pub enum Rule {
     
     // Stack effect
     stack_effect, stack_inputs, stack_outputs, stack_item,
+    row_variable, quotation_item, typed_item, item_type, effect_space,
+    effect_annotation,
     
     // Special rules
     WHITESPACE, COMMENT, EOI,
@@ -72,6 +75,12 @@ impl PestParser {
     }
 
     pub fn parse(&self) -> Result<Expr> {
+        self.parse_with_spans().map(|(expr, _)| expr)
+    }
+
+    /// Parse the program, noting where each top-level expression starts and where each
+    /// expression in the body of a word it defines does
+    pub fn parse_with_spans(&self) -> Result<(Expr, SourceMap)> {
         match BorfParser::parse(Rule::program, &self.source) {
             Ok(mut pairs) => {
                 // Get the program node (should be the first and only top level rule)
@@ -79,11 +88,19 @@ impl PestParser {
                 
                 // Program should contain a list of expressions
                 let mut exprs = Vec::new();
+                let mut spans = SourceMap::default();
                 
                 for pair in program.into_inner() {
                     match pair.as_rule() {
                         Rule::top_level_expr => {
-                            exprs.push(self.parse_expression(pair.into_inner().next().unwrap())?);
+                            spans.program.push(span(&pair));
+                            let inner = pair.into_inner().next().unwrap();
+                            let definition = definition_spans(inner.clone());
+                            let expr = self.parse_expression(inner)?;
+                            if let (Expr::Assignment(_, name), Some(body_spans)) = (&expr, definition) {
+                                spans.words.insert(name.clone(), body_spans);
+                            }
+                            exprs.push(expr);
                         },
                         Rule::module_decl => {
                             // Handle module declaration
@@ -93,6 +110,9 @@ impl PestParser {
                             // Handle import declaration
                             // For now, we just parse it but don't do anything with it
                         },
+                        Rule::shebang => {
+                            // The interpreter line of a script, ignore
+                        },
                         Rule::EOI => {
                             // End of input marker, ignore
                         },
@@ -110,11 +130,11 @@ impl PestParser {
                 // For simplicity, if we have a single expression, return it
                 // Otherwise, create a sequence/block expression
                 if exprs.len() == 1 {
-                    Ok(exprs.remove(0))
+                    Ok((exprs.remove(0), spans))
                 } else {
                     // In a concatenative language, multiple expressions at the top level
                     // are just executed in sequence
-                    Ok(Expr::Sequence(exprs))
+                    Ok((Expr::Sequence(exprs), spans))
                 }
             },
             Err(e) => {
@@ -789,8 +809,7 @@ impl PestParser {
                 // Remove the quotes from the string
                 let text = pair.as_str();
                 let content = &text[1..text.len() - 1];
-                // In a real parser, you'd also handle escape sequences here
-                Ok(Expr::String(content.to_string()))
+                Ok(Expr::String(unescape(content)))
             },
             Rule::symbol => {
                 let name = pair.as_str();
@@ -840,15 +859,8 @@ impl PestParser {
                     }
                 }
                 
-                // Apply named parameter translation if we have parameters
-                if !params.is_empty() {
-                    match translate_quotation(&params, &body) {
-                        Ok(translated_body) => Ok(Expr::Quotation(Vec::new(), translated_body)),
-                        Err(e) => Err(e)
-                    }
-                } else {
-                    Ok(Expr::Quotation(params, body))
-                }
+                // Named parameters are kept; `expand` shows their stack translation
+                Ok(Expr::Quotation(params, body))
             },
             Rule::assignment => {
                 // Parse an assignment
@@ -954,6 +966,16 @@ impl PestParser {
                 let effect = parse_stack_effect(pair.as_str())?;
                 Ok(Expr::StackEffect(effect))
             },
+            Rule::effect_annotation => {
+                // The grammar only admits well-formed annotations
+                let effect = parse_effect(pair.as_str()).map_err(|e| BorfError::ParseError {
+                    message: e.to_string(),
+                    src: Some(self.source.clone()),
+                    span: Some((pair.as_span().start(), pair.as_str().len()).into()),
+                    help: "Effect annotations are !creates[T], !consumes[T], !uses[T], !transfers[T] or !pure".to_string(),
+                })?;
+                Ok(Expr::EffectAnnotation(effect))
+            },
             _ => {
                 Err(BorfError::ParseError {
                     message: format!("Unexpected expression rule: {:?}", pair.as_rule()),
@@ -970,10 +992,13 @@ impl PestParser {
         
         for param_pair in pair.into_inner() {
             if param_pair.as_rule() == Rule::param {
-                let param_name = param_pair.as_str().to_string();
+                // name, optionally followed by `<: Type`
+                let mut inner = param_pair.into_inner();
+                let param_name = inner.next().unwrap().as_str().to_string();
+                let type_annotation = inner.next().map(|typ| parse_item_type(typ.as_str()));
                 params.push(Param {
                     name: param_name,
-                    type_annotation: None,
+                    type_annotation,
                 });
             }
         }
@@ -991,8 +1016,7 @@ impl PestParser {
                 // Remove the quotes from the string
                 let text = pair.as_str();
                 let content = &text[1..text.len() - 1];
-                // In a real parser, you'd also handle escape sequences here
-                Ok(Pattern::Literal(Expr::String(content.to_string())))
+                Ok(Pattern::Literal(Expr::String(unescape(content))))
             },
             Rule::number => {
                 let text = pair.as_str();
@@ -1060,4 +1084,52 @@ impl PestParser {
 pub fn parse(input: &str) -> Result<Expr> {
     let parser = PestParser::new(input);
     parser.parse()
+}
+
+fn span(pair: &Pair<Rule>) -> Span {
+    let (line, column) = pair.line_col();
+    Span { line, column }
+}
+
+// Where each expression in the body of a definition `[...] : name` starts
+fn definition_spans(pair: Pair<Rule>) -> Option<Vec<Span>> {
+    if pair.as_rule() != Rule::assignment {
+        return None;
+    }
+    let mut value = pair.into_inner().next()?;
+    while matches!(value.as_rule(), Rule::expr | Rule::atom) {
+        let mut inner = value.into_inner();
+        value = inner.next()?;
+        if inner.next().is_some() {
+            return None;
+        }
+    }
+    if value.as_rule() != Rule::quotation {
+        return None;
+    }
+    let body = value.into_inner()
+        .filter(|pair| pair.as_rule() == Rule::expr)
+        .map(|pair| span(&pair))
+        .collect();
+    Some(body)
+}
+
+// Replace the escape sequences the grammar allows in string literals
+fn unescape(content: &str) -> String {
+    let mut result = String::with_capacity(content.len());
+    let mut chars = content.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('r') => result.push('\r'),
+            Some('t') => result.push('\t'),
+            Some(other) => result.push(other),
+            None => result.push('\\'),
+        }
+    }
+    result
 }
\ No newline at end of file
@@ -0,0 +1,217 @@
+// src/repl/interpreter/process.rs
+// Subprocesses started by Borf programs. A spawned process is a linear resource
+// of type Process that must be waited for or killed exactly once; the pipes to
+// its standard streams are linear resources of type Pipe, taken from the process.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::process::{Child, ChildStderr, ChildStdin, ChildStdout, Command, Stdio};
+use std::rc::Rc;
+use std::thread::{self, JoinHandle};
+use crate::repl::interpreter::effects::Finalizer;
+use crate::repl::interpreter::handles::{handle, read_line};
+use crate::repl::interpreter::types::{EvaluatorError, Result, Value};
+
+/// The resource type of spawned processes
+pub const PROCESS_RESOURCE: &str = "Process";
+
+/// The resource type of pipes to a process's standard streams
+pub const PIPE_RESOURCE: &str = "Pipe";
+
+/// One end of a pipe to a child process
+#[derive(Debug)]
+pub enum Pipe {
+    Stdin(ChildStdin),
+    Stdout(BufReader<ChildStdout>),
+    Stderr(BufReader<ChildStderr>),
+}
+
+impl Pipe {
+    fn reader(&mut self) -> std::io::Result<&mut dyn BufRead> {
+        match self {
+            Pipe::Stdout(reader) => Ok(reader),
+            Pipe::Stderr(reader) => Ok(reader),
+            Pipe::Stdin(_) => Err(std::io::Error::new(ErrorKind::Other, "cannot read from a process's standard input")),
+        }
+    }
+}
+
+/// Running processes and open pipes by resource ID. Clones share the same tables,
+/// so the finalizer registered for Pipe resources can close what the evaluator opened.
+#[derive(Debug, Clone, Default)]
+pub struct ProcessTable {
+    processes: Rc<RefCell<HashMap<usize, Child>>>,
+    pipes: Rc<RefCell<HashMap<usize, Pipe>>>,
+}
+
+fn command(program: &str, args: &[String]) -> Command {
+    let mut command = Command::new(program);
+    command.args(args);
+    command
+}
+
+fn start_error(program: &str, err: std::io::Error) -> EvaluatorError {
+    EvaluatorError::EvalError(format!("Cannot run '{}': {}", program, err))
+}
+
+impl ProcessTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run a program to completion: `{ stdout: String, stderr: String, status: Int }`
+    pub fn run(program: &str, args: &[String]) -> Result<Value> {
+        let output = command(program, args)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|err| start_error(program, err))?;
+        let mut fields = HashMap::new();
+        fields.insert("stdout".to_string(), Value::String(String::from_utf8_lossy(&output.stdout).into_owned()));
+        fields.insert("stderr".to_string(), Value::String(String::from_utf8_lossy(&output.stderr).into_owned()));
+        fields.insert("status".to_string(), Value::Number(exit_status(output.status)));
+        Ok(Value::Map(fields))
+    }
+
+    /// Start a program with piped standard streams
+    pub fn spawn(program: &str, args: &[String]) -> Result<Child> {
+        command(program, args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|err| start_error(program, err))
+    }
+
+    pub fn insert(&self, id: usize, child: Child) {
+        self.processes.borrow_mut().insert(id, child);
+    }
+
+    /// Take one of a process's pipes ("stdin", "stdout" or "stderr"); each can be taken once
+    pub fn take_pipe(&self, process: usize, stream: &str) -> Result<Pipe> {
+        let mut processes = self.processes.borrow_mut();
+        let child = processes.get_mut(&process)
+            .ok_or_else(|| EvaluatorError::EvalError(format!("Process {} is not running", process)))?;
+        let pipe = match stream {
+            "stdin" => child.stdin.take().map(Pipe::Stdin),
+            "stdout" => child.stdout.take().map(|out| Pipe::Stdout(BufReader::new(out))),
+            "stderr" => child.stderr.take().map(|err| Pipe::Stderr(BufReader::new(err))),
+            _ => return Err(EvaluatorError::EvalError(format!("Unknown stream '{}'", stream))),
+        };
+        pipe.ok_or_else(|| EvaluatorError::EvalError(format!(
+            "The {} pipe of process {} has already been taken", stream, process
+        )))
+    }
+
+    pub fn insert_pipe(&self, id: usize, pipe: Pipe) {
+        self.pipes.borrow_mut().insert(id, pipe);
+    }
+
+    /// Wait for a process to exit and return its exit status. Its standard input is
+    /// closed first, unless the program holds the pipe, so the process sees the end of its input;
+    /// output pipes nobody took are drained so the process cannot block on a full pipe.
+    pub fn wait(&self, id: usize) -> Result<i32> {
+        let mut child = self.remove(id)?;
+        drop(child.stdin.take());
+        let drains = [drain(child.stdout.take()), drain(child.stderr.take())];
+        let status = child.wait()
+            .map_err(|err| EvaluatorError::EvalError(format!("Cannot wait for process {}: {}", id, err)));
+        for drain in drains.into_iter().flatten() {
+            let _ = drain.join();
+        }
+        Ok(exit_status(status?))
+    }
+
+    /// Kill a process and reap it
+    pub fn kill(&self, id: usize) -> Result<()> {
+        let mut child = self.remove(id)?;
+        // A process that has already exited cannot be killed, but is reaped all the same
+        let _ = child.kill();
+        child.wait()
+            .map(|_| ())
+            .map_err(|err| EvaluatorError::EvalError(format!("Cannot kill process {}: {}", id, err)))
+    }
+
+    fn remove(&self, id: usize) -> Result<Child> {
+        self.processes.borrow_mut().remove(&id)
+            .ok_or_else(|| EvaluatorError::EvalError(format!("Process {} is not running", id)))
+    }
+
+    fn with_pipe<T>(&self, id: usize, action: impl FnOnce(&mut Pipe) -> std::io::Result<T>) -> Result<T> {
+        let mut pipes = self.pipes.borrow_mut();
+        let pipe = pipes.get_mut(&id)
+            .ok_or_else(|| EvaluatorError::EvalError(format!("Pipe {} is not open", id)))?;
+        action(pipe).map_err(|err| EvaluatorError::EvalError(format!("I/O error on pipe {}: {}", id, err)))
+    }
+
+    /// Everything left in an output pipe, up to the end of the process's output
+    pub fn read_all(&self, id: usize) -> Result<String> {
+        self.with_pipe(id, |pipe| {
+            let mut text = String::new();
+            pipe.reader()?.read_to_string(&mut text)?;
+            Ok(text)
+        })
+    }
+
+    /// The next line of an output pipe without its line ending, or None at the end
+    pub fn read_line(&self, id: usize) -> Result<Option<String>> {
+        self.with_pipe(id, |pipe| read_line(pipe.reader()?))
+    }
+
+    pub fn write(&self, id: usize, text: &str) -> Result<()> {
+        self.with_pipe(id, |pipe| match pipe {
+            Pipe::Stdin(stdin) => stdin.write_all(text.as_bytes()),
+            _ => Err(std::io::Error::new(ErrorKind::Other, "cannot write to a process's output")),
+        })
+    }
+
+    /// Close a pipe; closing one that is not open does nothing
+    pub fn close_pipe(&self, id: usize) -> Result<()> {
+        let pipe = self.pipes.borrow_mut().remove(&id);
+        match pipe {
+            Some(Pipe::Stdin(mut stdin)) => stdin.flush()
+                .map_err(|err| EvaluatorError::EvalError(format!("I/O error closing pipe {}: {}", id, err))),
+            _ => Ok(()),
+        }
+    }
+
+    /// The finalizer for Pipe resources: closes the pipe named by the resource's inner value
+    pub fn pipe_finalizer(&self) -> Finalizer {
+        let table = self.clone();
+        Finalizer::Native(Rc::new(move |value: &Value| match handle(value) {
+            Some(id) => table.close_pipe(id),
+            None => Err(EvaluatorError::EvalError(format!("Not a pipe handle: {}", value))),
+        }))
+    }
+}
+
+// Discard what a process writes to an output pipe, on a thread of its own so one
+// stream filling up cannot block the reading of the other
+fn drain<R: Read + Send + 'static>(stream: Option<R>) -> Option<JoinHandle<()>> {
+    stream.map(|mut stream| thread::spawn(move || {
+        let _ = std::io::copy(&mut stream, &mut std::io::sink());
+    }))
+}
+
+// The exit code of a process, or -1 if it was ended by a signal
+fn exit_status(status: std::process::ExitStatus) -> i32 {
+    status.code().unwrap_or(-1)
+}
+
+/// The inner value of a Process resource: `{ program: String, pid: Int, handle: Int }`
+pub fn process_value(program: &str, pid: u32, id: usize) -> Value {
+    let mut fields = HashMap::new();
+    fields.insert("program".to_string(), Value::String(program.to_string()));
+    fields.insert("pid".to_string(), Value::Number(pid as i32));
+    fields.insert("handle".to_string(), Value::Number(id as i32));
+    Value::Map(fields)
+}
+
+/// The inner value of a Pipe resource: `{ stream: String, process: Int, handle: Int }`
+pub fn pipe_value(stream: &str, process: usize, id: usize) -> Value {
+    let mut fields = HashMap::new();
+    fields.insert("stream".to_string(), Value::String(stream.to_string()));
+    fields.insert("process".to_string(), Value::Number(process as i32));
+    fields.insert("handle".to_string(), Value::Number(id as i32));
+    Value::Map(fields)
+}
@@ -0,0 +1,268 @@
+// src/repl/interpreter/query.rs
+// Homomorphisms between ACSets and conjunctive queries over them. A homomorphism
+// sends each part of a pattern to a part of the same object in the target so that
+// homs are preserved and the attributes the pattern sets are matched. The search
+// backtracks over the pattern's parts, connected parts first, and takes each
+// part's candidates from the target's incidence indices where it can.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+use crate::repl::interpreter::acset::ACSet;
+use crate::repl::interpreter::types::{EvaluatorError, Result, Value};
+
+/// A homomorphism's components: for each object, the target part of each pattern part
+pub type Homomorphism = HashMap<String, Vec<usize>>;
+
+/// Every homomorphism from the pattern to the target, optionally only the monic (injective) ones
+pub fn homomorphisms(pattern: &ACSet, target: &ACSet, monic: bool) -> Result<Vec<Homomorphism>> {
+    if pattern.schema != target.schema {
+        return Err(EvaluatorError::TypeError(
+            "Homomorphisms need ACSets with the same schema".to_string()
+        ));
+    }
+    let mut search = Search::new(pattern, target, monic)?;
+    search.run(0)?;
+    Ok(search.results)
+}
+
+struct Search<'a> {
+    pattern: &'a ACSet,
+    target: &'a ACSet,
+    monic: bool,
+    // The pattern's parts in the order they are assigned
+    order: Vec<(String, usize)>,
+    assignment: HashMap<String, Vec<Option<usize>>>,
+    used: HashMap<String, Vec<bool>>,
+    results: Vec<Homomorphism>,
+}
+
+impl<'a> Search<'a> {
+    fn new(pattern: &'a ACSet, target: &'a ACSet, monic: bool) -> Result<Self> {
+        let schema = &pattern.schema;
+        let mut assignment = HashMap::new();
+        let mut used = HashMap::new();
+        let mut unordered = Vec::new();
+        for object in &schema.objects {
+            let count = pattern.nparts(object)?;
+            assignment.insert(object.clone(), vec![None; count]);
+            used.insert(object.clone(), vec![false; target.nparts(object)?]);
+            unordered.extend((0..count).map(|part| (object.clone(), part)));
+        }
+
+        // Assign parts connected to those already assigned first, so their candidates come from indices
+        let mut order: Vec<(String, usize)> = Vec::new();
+        while !unordered.is_empty() {
+            let mut best = 0;
+            let mut best_links = 0;
+            for (i, (object, part)) in unordered.iter().enumerate() {
+                let links = neighbours(pattern, object, *part)?.iter()
+                    .filter(|neighbour| order.contains(neighbour))
+                    .count();
+                if links > best_links {
+                    best = i;
+                    best_links = links;
+                }
+            }
+            order.push(unordered.remove(best));
+        }
+        Ok(Search { pattern, target, monic, order, assignment, used, results: Vec::new() })
+    }
+
+    fn run(&mut self, depth: usize) -> Result<()> {
+        if depth == self.order.len() {
+            let found = self.assignment.iter()
+                .map(|(object, parts)| (object.clone(), parts.iter().map(|part| part.unwrap()).collect()))
+                .collect();
+            self.results.push(found);
+            return Ok(());
+        }
+        let (object, part) = self.order[depth].clone();
+        for candidate in self.candidates(&object, part)? {
+            if self.monic && self.used[&object][candidate] {
+                continue;
+            }
+            self.assign(&object, part, Some(candidate));
+            if self.consistent(&object, part, candidate)? {
+                self.run(depth + 1)?;
+            }
+            self.assign(&object, part, None);
+        }
+        Ok(())
+    }
+
+    fn assign(&mut self, object: &str, part: usize, image: Option<usize>) {
+        let slot = &mut self.assignment.get_mut(object).unwrap()[part];
+        if let Some(previous) = slot.take() {
+            self.used.get_mut(object).unwrap()[previous] = false;
+        }
+        if let Some(image) = image {
+            *slot = Some(image);
+            self.used.get_mut(object).unwrap()[image] = true;
+        }
+    }
+
+    fn image(&self, object: &str, part: usize) -> Option<usize> {
+        self.assignment[object][part]
+    }
+
+    // The target parts a pattern part could go to, narrowed by its assigned neighbours
+    fn candidates(&self, object: &str, part: usize) -> Result<Vec<usize>> {
+        let schema = Rc::clone(&self.pattern.schema);
+        let mut best: Option<Vec<usize>> = None;
+        for hom in schema.homs_into(object) {
+            // An assigned source fixes the part's image
+            for &source in self.pattern.incident(part, &hom.name)? {
+                if let Some(image) = self.image(&hom.dom, source) {
+                    return Ok(self.target.subpart(image, &hom.name)?.into_iter().collect());
+                }
+            }
+        }
+        for hom in schema.homs_from(object) {
+            // An assigned subpart limits it to the parts incident to the subpart's image
+            let subpart = match self.pattern.subpart(part, &hom.name)? {
+                Some(subpart) => subpart,
+                None => continue,
+            };
+            if let Some(image) = self.image(&hom.codom, subpart) {
+                let incident = self.target.incident(image, &hom.name)?;
+                let fewer = match &best {
+                    Some(best) => incident.len() < best.len(),
+                    None => true,
+                };
+                if fewer {
+                    best = Some(incident.to_vec());
+                }
+            }
+        }
+        match best {
+            Some(candidates) => Ok(candidates),
+            None => Ok((0..self.target.nparts(object)?).collect()),
+        }
+    }
+
+    // Whether the part's image agrees with its homs and attributes, as far as they are assigned
+    fn consistent(&self, object: &str, part: usize, image: usize) -> Result<bool> {
+        let schema = Rc::clone(&self.pattern.schema);
+        for hom in schema.homs_from(object) {
+            let subpart = match self.pattern.subpart(part, &hom.name)? {
+                Some(subpart) => subpart,
+                None => continue,
+            };
+            match (self.target.subpart(image, &hom.name)?, self.image(&hom.codom, subpart)) {
+                (None, _) => return Ok(false),
+                (Some(found), Some(expected)) if found != expected => return Ok(false),
+                _ => {}
+            }
+        }
+        for hom in schema.homs_into(object) {
+            for &source in self.pattern.incident(part, &hom.name)? {
+                if let Some(source_image) = self.image(&hom.dom, source) {
+                    if self.target.subpart(source_image, &hom.name)? != Some(image) {
+                        return Ok(false);
+                    }
+                }
+            }
+        }
+        for attr in schema.attrs_from(object) {
+            if let Some(value) = self.pattern.get_attr(part, &attr.name)? {
+                if self.target.get_attr(image, &attr.name)? != Some(value) {
+                    return Ok(false);
+                }
+            }
+        }
+        Ok(true)
+    }
+}
+
+// The parts a part is linked to by a hom, either way
+fn neighbours(acset: &ACSet, object: &str, part: usize) -> Result<Vec<(String, usize)>> {
+    let mut linked = Vec::new();
+    for hom in acset.schema.homs_from(object) {
+        if let Some(subpart) = acset.subpart(part, &hom.name)? {
+            linked.push((hom.codom.clone(), subpart));
+        }
+    }
+    for hom in acset.schema.homs_into(object) {
+        linked.extend(acset.incident(part, &hom.name)?.iter().map(|&source| (hom.dom.clone(), source)));
+    }
+    Ok(linked)
+}
+
+/// A homomorphism as a Borf map from each object to the list of its parts' images
+pub fn homomorphism_value(homomorphism: &Homomorphism) -> Value {
+    Value::Map(homomorphism.iter()
+        .map(|(object, parts)| {
+            let images = parts.iter().map(|&part| Value::Number(part as i32)).collect();
+            (object.clone(), Value::List(images))
+        })
+        .collect())
+}
+
+/// Answer a conjunctive query. The variables are `(name ob)` pairs; each atom is
+/// `(hom x y)`, saying hom sends x to y, or `(attr x value)`. Each answer is a map
+/// from variable names to parts of the target, in the order the search finds them.
+pub fn query(target: &ACSet, variables: Value, atoms: Value) -> Result<Value> {
+    let mut pattern = ACSet::new(Rc::clone(&target.schema));
+    let mut parts: HashMap<String, (String, usize)> = HashMap::new();
+    for variable in list(variables, "variables")? {
+        match list(variable.clone(), "variables")?.as_slice() {
+            [Value::String(name), Value::String(object)] => {
+                let part = pattern.add_part(object)?;
+                parts.insert(name.clone(), (object.clone(), part));
+            },
+            _ => return Err(EvaluatorError::TypeError(format!("Query variables must be (name ob) pairs, got {}", variable))),
+        }
+    }
+    let lookup = |name: &str, object: &str| match parts.get(name) {
+        Some((found, part)) if found == object => Ok(*part),
+        Some((found, _)) => Err(EvaluatorError::TypeError(format!(
+            "Query variable {} is a {}, not a {}", name, found, object
+        ))),
+        None => Err(EvaluatorError::EvalError(format!("Unknown query variable {}", name))),
+    };
+
+    for atom in list(atoms, "atoms")? {
+        let items = list(atom.clone(), "atoms")?;
+        let schema = Rc::clone(&target.schema);
+        match items.as_slice() {
+            [Value::String(name), Value::String(source), Value::String(subpart)] if schema.hom(name).is_some() => {
+                let hom = schema.hom(name).unwrap();
+                let part = lookup(source, &hom.dom)?;
+                let image = lookup(subpart, &hom.codom)?;
+                if let Some(set) = pattern.subpart(part, name)? {
+                    if set != image {
+                        return Err(EvaluatorError::EvalError(format!(
+                            "Query atoms give {} two different {} subparts", source, name
+                        )));
+                    }
+                }
+                pattern.set_subpart(part, name, image)?;
+            },
+            [Value::String(name), Value::String(source), value] if schema.attr(name).is_some() => {
+                let attr = schema.attr(name).unwrap();
+                let part = lookup(source, &attr.dom)?;
+                pattern.set_attr(part, name, value.clone())?;
+            },
+            _ => return Err(EvaluatorError::TypeError(format!(
+                "Query atoms must be (hom x y) or (attr x value), got {}", atom
+            ))),
+        }
+    }
+
+    let answers = homomorphisms(&pattern, target, false)?.iter()
+        .map(|homomorphism| {
+            let bindings = parts.iter()
+                .map(|(name, (object, part))| (name.clone(), Value::Number(homomorphism[object][*part] as i32)))
+                .collect();
+            Value::Map(bindings)
+        })
+        .collect();
+    Ok(Value::List(answers))
+}
+
+fn list(value: Value, what: &str) -> Result<Vec<Value>> {
+    match value {
+        Value::List(items) => Ok(items),
+        other => Err(EvaluatorError::TypeError(format!("Query {} must be a list, got {}", what, other))),
+    }
+}
@@ -0,0 +1,495 @@
+// src/repl/interpreter/stack_check.rs
+// Static stack-effect inference and verification for user-defined words
+
+use std::collections::HashMap;
+use crate::repl::interpreter::errors::{BorfError, Result};
+use crate::repl::interpreter::stack_effects::{StackEffect, StackItem, get_word_effect};
+use crate::repl::interpreter::types::{Expr, Param};
+
+/// An item on the abstract stack used while simulating a word body
+#[derive(Debug, Clone)]
+enum Slot {
+    // A value pulled from below the body's own stack, numbered in the order it was needed
+    Input(usize),
+    // A value produced by the body, named after the effect that produced it
+    Value(String),
+    // An integer literal, kept so that `n pick` and `n roll` can be simulated
+    Literal(i32),
+    // A quotation whose effect is known if it could be inferred
+    Quotation(Option<StackEffect>),
+}
+
+/// Why a simulation stopped early
+enum Stop {
+    // The body uses something whose effect cannot be known statically
+    Unknown,
+    // The body is definitely wrong
+    Error(BorfError),
+}
+
+impl From<BorfError> for Stop {
+    fn from(error: BorfError) -> Self {
+        Stop::Error(error)
+    }
+}
+
+type Step<T> = std::result::Result<T, Stop>;
+
+/// Format a stack effect the way it is written in source
+pub fn format_effect(effect: &StackEffect) -> String {
+    effect.to_string()
+}
+
+/// Simulates a word body on an abstract stack to infer or verify its stack effect
+struct StackChecker<'a> {
+    // Effects of words defined earlier in the program
+    known: &'a HashMap<String, StackEffect>,
+    // Parameters in scope, bound to the slot they were popped into
+    params: HashMap<String, Slot>,
+    // The abstract stack (top is last)
+    stack: Vec<Slot>,
+    // Number of items pulled from below the body's own stack
+    pulled: usize,
+    // Declared number of inputs; pulling beyond this is an underflow
+    limit: Option<usize>,
+    // Slots for declared inputs, top first, so declared quotation effects are known
+    seeds: Vec<Slot>,
+}
+
+impl<'a> StackChecker<'a> {
+    fn new(known: &'a HashMap<String, StackEffect>, limit: Option<usize>) -> Self {
+        StackChecker {
+            known,
+            params: HashMap::new(),
+            stack: Vec::new(),
+            pulled: 0,
+            limit,
+            seeds: Vec::new(),
+        }
+    }
+    
+    // Start from a declared effect: its inputs are all the body may take
+    fn declared(known: &'a HashMap<String, StackEffect>, declared: &StackEffect) -> Self {
+        // A row variable means the body may reach arbitrarily deep
+        let limit = if declared.input_row.is_some() { None } else { Some(declared.inputs.len()) };
+        let mut checker = StackChecker::new(known, limit);
+        checker.seeds = declared.inputs.iter().rev().enumerate()
+            .map(|(index, item)| match &item.effect {
+                Some(effect) => Slot::Quotation(Some(effect.clone())),
+                None => Slot::Input(index),
+            })
+            .collect();
+        checker
+    }
+
+    // Pop the top item, pulling a fresh input when the body's own stack is empty
+    fn pop(&mut self) -> Step<Slot> {
+        if let Some(slot) = self.stack.pop() {
+            return Ok(slot);
+        }
+        if let Some(available) = self.limit {
+            if self.pulled >= available {
+                return Err(Stop::Error(BorfError::stack_underflow(self.pulled + 1, available, None, None)));
+            }
+        }
+        let slot = self.seeds.get(self.pulled).cloned().unwrap_or(Slot::Input(self.pulled));
+        self.pulled += 1;
+        Ok(slot)
+    }
+
+    // Pop n items, returned deepest first
+    fn pop_n(&mut self, n: usize) -> Step<Vec<Slot>> {
+        let mut items = Vec::with_capacity(n);
+        for _ in 0..n {
+            items.push(self.pop()?);
+        }
+        items.reverse();
+        Ok(items)
+    }
+
+    // Apply a stack effect; outputs named like an input are copies of that input
+    fn apply(&mut self, effect: &StackEffect) -> Step<()> {
+        // Effects that reach below their items depend on their quotation arguments
+        if effect.is_row_polymorphic() {
+            return Err(Stop::Unknown);
+        }
+        let taken = self.pop_n(effect.inputs.len())?;
+        for output in &effect.outputs {
+            match effect.inputs.iter().rposition(|input| input.name == output.name) {
+                Some(index) => self.stack.push(taken[index].clone()),
+                None => match &output.effect {
+                    Some(quotation) => self.stack.push(Slot::Quotation(Some(quotation.clone()))),
+                    None => self.stack.push(Slot::Value(output.name.clone())),
+                },
+            }
+        }
+        Ok(())
+    }
+
+    // Pop a quotation and run its effect
+    fn call(&mut self) -> Step<()> {
+        match self.pop()? {
+            Slot::Quotation(Some(effect)) => self.apply(&effect),
+            _ => Err(Stop::Unknown),
+        }
+    }
+
+    fn word(&mut self, name: &str) -> Step<()> {
+        if let Some(slot) = self.params.get(name) {
+            let slot = slot.clone();
+            self.stack.push(slot);
+            return Ok(());
+        }
+
+        match name {
+            "call" => self.call(),
+            "dip" => {
+                // ( x quot -- quot(x) x )
+                let quot = self.pop()?;
+                let x = self.pop()?;
+                self.stack.push(quot);
+                self.call()?;
+                self.stack.push(x);
+                Ok(())
+            },
+            "keep" => {
+                // ( x quot -- quot(x) x )
+                let quot = self.pop()?;
+                let x = self.pop()?;
+                self.stack.push(x.clone());
+                self.stack.push(quot);
+                self.call()?;
+                self.stack.push(x);
+                Ok(())
+            },
+            "bi" | "tri" => {
+                // ( x p q -- p(x) q(x) ) and ( x p q r -- p(x) q(x) r(x) )
+                let count = if name == "bi" { 2 } else { 3 };
+                let quots = self.pop_n(count)?;
+                let x = self.pop()?;
+                for quot in quots {
+                    self.stack.push(x.clone());
+                    self.stack.push(quot);
+                    self.call()?;
+                }
+                Ok(())
+            },
+            "bi*" => {
+                // ( x y p q -- p(x) q(y) )
+                let quots = self.pop_n(2)?;
+                let values = self.pop_n(2)?;
+                for (value, quot) in values.into_iter().zip(quots) {
+                    self.stack.push(value);
+                    self.stack.push(quot);
+                    self.call()?;
+                }
+                Ok(())
+            },
+            "bi@" => {
+                // ( x y p -- p(x) p(y) )
+                let quot = self.pop()?;
+                let values = self.pop_n(2)?;
+                for value in values {
+                    self.stack.push(value);
+                    self.stack.push(quot.clone());
+                    self.call()?;
+                }
+                Ok(())
+            },
+            "curry" => {
+                // ( x quot -- quot' ): x fills the quotation's topmost input
+                let quot = self.pop()?;
+                self.pop()?;
+                let effect = match quot {
+                    Slot::Quotation(Some(mut effect)) if !effect.inputs.is_empty() => {
+                        effect.inputs.pop();
+                        Some(effect)
+                    },
+                    _ => None,
+                };
+                self.stack.push(Slot::Quotation(effect));
+                Ok(())
+            },
+            "pick" | "roll" => {
+                // Only literal depths can be followed statically
+                let depth = match self.pop()? {
+                    Slot::Literal(n) if n >= 0 => n as usize,
+                    _ => return Err(Stop::Unknown),
+                };
+                let mut items = self.pop_n(depth + 1)?;
+                if name == "pick" {
+                    let copy = items[0].clone();
+                    self.stack.extend(items);
+                    self.stack.push(copy);
+                } else {
+                    let moved = items.remove(0);
+                    self.stack.extend(items);
+                    self.stack.push(moved);
+                }
+                Ok(())
+            },
+            _ => {
+                if let Some(effect) = self.known.get(name) {
+                    let effect = effect.clone();
+                    self.apply(&effect)
+                } else if let Some(effect) = get_word_effect(name) {
+                    self.apply(&effect)
+                } else {
+                    Err(Stop::Unknown)
+                }
+            },
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Step<()> {
+        match expr {
+            Expr::Number(n) => self.stack.push(Slot::Literal(*n)),
+            Expr::String(_) => self.stack.push(Slot::Value("str".to_string())),
+            Expr::Boolean(_) => self.stack.push(Slot::Value("bool".to_string())),
+            Expr::Nil => self.stack.push(Slot::Value("nil".to_string())),
+            Expr::Literal(_) => self.stack.push(Slot::Value("value".to_string())),
+            Expr::Symbol(name) => self.word(name)?,
+            Expr::Sequence(exprs) => {
+                for expr in exprs {
+                    self.expr(expr)?;
+                }
+            },
+            Expr::Quotation(params, body) | Expr::TypedQuotation(params, body, _) => {
+                let effect = self.nested(params, body)?;
+                self.stack.push(Slot::Quotation(effect));
+            },
+            Expr::Pipeline(left, right) => {
+                // A quotation on the right of |> is applied to the value on the left
+                self.expr(left)?;
+                self.expr(right)?;
+                if matches!(right.as_ref(), Expr::Quotation(..) | Expr::TypedQuotation(..)) {
+                    self.call()?;
+                }
+            },
+            Expr::If(condition, then_branch, else_branch) => {
+                // A Nil condition means the condition is already on the stack
+                if !matches!(condition.as_ref(), Expr::Nil) {
+                    self.expr(condition)?;
+                }
+                self.pop()?;
+                self.branches(then_branch, else_branch)?;
+            },
+            Expr::Dip(quot) => {
+                self.expr(quot)?;
+                self.word("dip")?;
+            },
+            Expr::Bi(x, p, q) => {
+                for expr in [x, p, q] {
+                    self.expr(expr)?;
+                }
+                self.word("bi")?;
+            },
+            Expr::Tri(x, p, q, r) => {
+                for expr in [x, p, q, r] {
+                    self.expr(expr)?;
+                }
+                self.word("tri")?;
+            },
+            Expr::StackEffect(_) | Expr::EffectAnnotation(_) => {},
+            _ => return Err(Stop::Unknown),
+        }
+        Ok(())
+    }
+
+    // Infer the effect of a quotation literal found inside the body
+    fn nested(&self, params: &[Param], body: &[Expr]) -> Step<Option<StackEffect>> {
+        let mut inner = StackChecker::new(self.known, None);
+        // Outer parameters are captured by the quotation rather than taken from the stack,
+        // so they are values of its own and not inputs
+        inner.params = self.params.iter()
+            .map(|(name, slot)| match slot {
+                Slot::Input(_) => (name.clone(), Slot::Value(name.clone())),
+                other => (name.clone(), other.clone()),
+            })
+            .collect();
+        match inner.body(params, body) {
+            Ok(()) => Ok(Some(inner.effect())),
+            Err(Stop::Unknown) => Ok(None),
+            Err(Stop::Error(e)) => Err(Stop::Error(e)),
+        }
+    }
+
+    // Both branches of an if must leave the stack at the same depth
+    fn branches(&mut self, then_branch: &Expr, else_branch: &Expr) -> Step<()> {
+        let start = self.stack.len() as isize - self.pulled as isize;
+
+        let mut then_state = self.fork();
+        then_state.expr(then_branch)?;
+        let mut else_state = self.fork();
+        else_state.expr(else_branch)?;
+
+        let then_change = then_state.stack.len() as isize - then_state.pulled as isize - start;
+        let else_change = else_state.stack.len() as isize - else_state.pulled as isize - start;
+        if then_change != else_change {
+            return Err(Stop::Error(BorfError::invalid_stack_effect(
+                &format!(
+                    "Branches of if change the stack depth differently ({:+} vs {:+})",
+                    then_change, else_change
+                ),
+                None,
+                None,
+            )));
+        }
+
+        // Continue with whichever branch reaches deeper into the stack
+        let chosen = if else_state.pulled > then_state.pulled { else_state } else { then_state };
+        self.stack = chosen.stack;
+        self.pulled = chosen.pulled;
+        Ok(())
+    }
+
+    fn fork(&self) -> StackChecker<'a> {
+        StackChecker {
+            known: self.known,
+            params: self.params.clone(),
+            stack: self.stack.clone(),
+            pulled: self.pulled,
+            limit: self.limit,
+            seeds: self.seeds.clone(),
+        }
+    }
+
+    // Bind parameters (rightmost on top) and simulate the body
+    fn body(&mut self, params: &[Param], body: &[Expr]) -> Step<()> {
+        let bound = self.pop_n(params.len())?;
+        for (param, slot) in params.iter().zip(bound) {
+            self.params.insert(param.name.clone(), slot);
+        }
+        for expr in body {
+            self.expr(expr)?;
+        }
+        Ok(())
+    }
+
+    // The effect simulated so far, with inputs named a, b, c, ... from the deepest
+    fn effect(&self) -> StackEffect {
+        let name_of = |index: usize| input_name(self.pulled - 1 - index);
+        let inputs = (0..self.pulled).map(|position| StackItem::named(&input_name(position))).collect();
+        let outputs = self.stack.iter()
+            .map(|slot| match slot {
+                Slot::Input(index) => StackItem::named(&name_of(*index)),
+                Slot::Value(name) => StackItem::named(name),
+                Slot::Literal(_) => StackItem::named("n"),
+                Slot::Quotation(Some(effect)) => StackItem::quotation("quot", effect.clone()),
+                Slot::Quotation(None) => StackItem::named("quot"),
+            })
+            .collect();
+        StackEffect { input_row: None, inputs, output_row: None, outputs }
+    }
+}
+
+fn input_name(position: usize) -> String {
+    if position < 26 {
+        ((b'a' + position as u8) as char).to_string()
+    } else {
+        format!("x{}", position)
+    }
+}
+
+/// Infer the stack effect of a quotation body from the effects of its words.
+/// Returns `None` when the body uses words whose effect cannot be known statically.
+pub fn infer_effect(
+    params: &[Param],
+    body: &[Expr],
+    known: &HashMap<String, StackEffect>,
+) -> Result<Option<StackEffect>> {
+    let mut checker = StackChecker::new(known, None);
+    match checker.body(params, body) {
+        Ok(()) => Ok(Some(checker.effect())),
+        Err(Stop::Unknown) => Ok(None),
+        Err(Stop::Error(e)) => Err(e),
+    }
+}
+
+/// Verify a quotation body against its declared stack effect
+pub fn check_effect(
+    name: &str,
+    declared: &StackEffect,
+    params: &[Param],
+    body: &[Expr],
+    known: &HashMap<String, StackEffect>,
+) -> Result<()> {
+    let mut checker = StackChecker::declared(known, declared);
+    match checker.body(params, body) {
+        Ok(()) => {},
+        Err(Stop::Unknown) => return Ok(()),
+        Err(Stop::Error(e)) => return Err(e),
+    }
+
+    // Without a fixed number of inputs the depth cannot be compared
+    if declared.is_row_polymorphic() || checker.pulled > declared.inputs.len() {
+        return Ok(());
+    }
+
+    let inferred = checker.effect();
+    let consumed = checker.pulled;
+    let produced = checker.stack.len() + declared.inputs.len() - consumed;
+    if produced != declared.outputs.len() {
+        return Err(BorfError::invalid_stack_effect(
+            &format!(
+                "Word '{}' is declared {} but its body has effect {}",
+                name,
+                format_effect(declared),
+                format_effect(&inferred)
+            ),
+            None,
+            None,
+        ));
+    }
+    Ok(())
+}
+
+/// Infer the effects of every definition in a program and check them against declarations.
+///
+/// A declaration is a stack effect written directly after the word's name
+/// (`square ( n -- n )`) or directly before its definition (`( n -- n ) [dup *] : square`).
+/// Returns the effect of each definition whose effect is known.
+pub fn check_program(program: &Expr) -> Result<HashMap<String, StackEffect>> {
+    let exprs = match program {
+        Expr::Sequence(exprs) => exprs.as_slice(),
+        other => std::slice::from_ref(other),
+    };
+
+    // Collect the declarations first so that words can be used before they are declared
+    let mut declared: HashMap<String, StackEffect> = HashMap::new();
+    for (index, expr) in exprs.iter().enumerate() {
+        if let Expr::StackEffect(effect) = expr {
+            if let Some(Expr::Symbol(name)) = index.checked_sub(1).map(|i| &exprs[i]) {
+                declared.insert(name.clone(), effect.clone());
+            } else if let Some(Expr::Assignment(_, name)) = exprs[index + 1..].iter()
+                .find(|expr| !matches!(expr, Expr::EffectAnnotation(_)))
+            {
+                // Effect annotations may come between the declaration and the definition
+                declared.insert(name.clone(), effect.clone());
+            }
+        }
+    }
+
+    let mut known = declared.clone();
+    for expr in exprs {
+        let (value, name) = match expr {
+            Expr::Assignment(value, name) => (value, name),
+            _ => continue,
+        };
+        let (params, body) = match value.as_ref() {
+            Expr::Quotation(params, body) | Expr::TypedQuotation(params, body, _) => (params, body),
+            _ => continue,
+        };
+
+        match declared.get(name) {
+            Some(effect) => check_effect(name, effect, params, body, &known)?,
+            None => {
+                if let Some(effect) = infer_effect(params, body, &known)? {
+                    known.insert(name.clone(), effect);
+                }
+            },
+        }
+    }
+
+    Ok(known)
+}
@@ -2,70 +2,250 @@
 // Implementation of the STACKER algorithm for translating named parameters to stack operations
 
 use std::collections::HashMap;
+use std::fmt;
 use crate::repl::interpreter::errors::{BorfError, Result};
-use crate::repl::interpreter::types::{Expr, Param};
+use crate::repl::interpreter::types::{Expr, Param, Type};
+use crate::repl::interpreter::stack_types::format_type;
+use crate::repl::interpreter::stack_check::infer_effect;
+use crate::repl::interpreter::optimizer::Optimizer;
+
+/// An item in a stack effect: a name, optionally with a type (`a:Int`)
+/// or, for quotations, with the quotation's own effect (`quot: ( x -- y )`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackItem {
+    pub name: String,
+    pub item_type: Option<Type>,
+    pub effect: Option<StackEffect>,
+}
+
+impl StackItem {
+    pub fn named(name: &str) -> Self {
+        StackItem { name: name.to_string(), item_type: None, effect: None }
+    }
+
+    pub fn typed(name: &str, item_type: Type) -> Self {
+        StackItem { name: name.to_string(), item_type: Some(item_type), effect: None }
+    }
+
+    pub fn quotation(name: &str, effect: StackEffect) -> Self {
+        StackItem { name: name.to_string(), item_type: None, effect: Some(effect) }
+    }
+}
+
+impl fmt::Display for StackItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.effect, &self.item_type) {
+            (Some(effect), _) => write!(f, "{}: {}", self.name, effect),
+            (None, Some(item_type)) => write!(f, "{}:{}", self.name, format_type(item_type)),
+            (None, None) => write!(f, "{}", self.name),
+        }
+    }
+}
 
 /// Represents the stack effect of a word
+///
+/// Row variables (`..a`) stand for the rest of the stack below the named items,
+/// as in Factor: `dip` is `( ..a x quot: ( ..a -- ..b ) -- ..b x )`.
 #[derive(Debug, Clone, PartialEq)]
 pub struct StackEffect {
-    pub inputs: Vec<String>,   // Names of input items
-    pub outputs: Vec<String>,  // Names of output items
+    pub input_row: Option<String>,  // Row variable below the inputs
+    pub inputs: Vec<StackItem>,     // Input items, deepest first
+    pub output_row: Option<String>, // Row variable below the outputs
+    pub outputs: Vec<StackItem>,    // Output items, deepest first
 }
 
 impl StackEffect {
     pub fn new(inputs: Vec<String>, outputs: Vec<String>) -> Self {
-        Self { inputs, outputs }
+        Self {
+            input_row: None,
+            inputs: inputs.iter().map(|name| StackItem::named(name)).collect(),
+            output_row: None,
+            outputs: outputs.iter().map(|name| StackItem::named(name)).collect(),
+        }
     }
     
     // Calculate the net change in stack depth
+    // (only explicit items are counted; row variables are assumed to be unchanged)
     pub fn stack_depth_change(&self) -> isize {
         self.outputs.len() as isize - self.inputs.len() as isize
     }
+    
+    // Whether the effect changes the stack below its items (e.g. call, dip)
+    pub fn is_row_polymorphic(&self) -> bool {
+        self.input_row != self.output_row
+    }
+    
+    pub fn input_names(&self) -> Vec<String> {
+        self.inputs.iter().map(|item| item.name.clone()).collect()
+    }
+    
+    pub fn output_names(&self) -> Vec<String> {
+        self.outputs.iter().map(|item| item.name.clone()).collect()
+    }
+}
+
+impl fmt::Display for StackEffect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = vec!["(".to_string()];
+        parts.extend(self.input_row.iter().cloned());
+        parts.extend(self.inputs.iter().map(|item| item.to_string()));
+        parts.push("--".to_string());
+        parts.extend(self.output_row.iter().cloned());
+        parts.extend(self.outputs.iter().map(|item| item.to_string()));
+        parts.push(")".to_string());
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+fn stack_effect_error(message: String) -> BorfError {
+    BorfError::StackEffectError {
+        message,
+        src: None,
+        span: None,
+        help: "Stack effect declarations should have the form '( input1 input2 -- output1 output2 )', \
+               with optional types ('a:Int'), quotation effects ('quot: ( x -- y )') and row variables ('..a')".to_string(),
+    }
+}
+
+/// Split a stack effect into tokens: parentheses, ':', '--' and words
+fn tokenize_stack_effect(effect_str: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    for c in effect_str.chars() {
+        match c {
+            '(' | ')' | ':' => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+                tokens.push(c.to_string());
+            },
+            c if c.is_whitespace() || c == ',' => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            },
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+/// Parse the type of a stack item: `Int`, `!File`, `?Int`, `List[Int]`
+pub fn parse_item_type(type_str: &str) -> Type {
+    if let Some(inner) = type_str.strip_prefix('!') {
+        return Type::Linear(Box::new(parse_item_type(inner)));
+    }
+    if let Some(inner) = type_str.strip_prefix('?') {
+        return Type::Optional(Box::new(parse_item_type(inner)));
+    }
+    if let (Some(open), true) = (type_str.find('['), type_str.ends_with(']')) {
+        let params = type_str[open + 1..type_str.len() - 1]
+            .split(',')
+            .map(|param| parse_item_type(param.trim()))
+            .collect();
+        return Type::Generic(type_str[..open].to_string(), params);
+    }
+    Type::Simple(type_str.to_string())
+}
+
+// Recursive descent over the tokens of a (possibly nested) stack effect
+struct StackEffectParser {
+    tokens: Vec<String>,
+    pos: usize,
+}
+
+impl StackEffectParser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|t| t.as_str())
+    }
+
+    fn expect(&mut self, token: &str) -> Result<()> {
+        match self.peek() {
+            Some(t) if t == token => {
+                self.pos += 1;
+                Ok(())
+            },
+            Some(t) => Err(stack_effect_error(format!("Expected '{}' in stack effect but found '{}'", token, t))),
+            None => Err(stack_effect_error(format!("Expected '{}' but the stack effect ended", token))),
+        }
+    }
+
+    fn effect(&mut self) -> Result<StackEffect> {
+        self.expect("(")?;
+        let (input_row, inputs) = self.items("--")?;
+        self.expect("--")?;
+        let (output_row, outputs) = self.items(")")?;
+        self.expect(")")?;
+        Ok(StackEffect { input_row, inputs, output_row, outputs })
+    }
+
+    // Parse items up to the terminator; a row variable may only come first
+    fn items(&mut self, terminator: &str) -> Result<(Option<String>, Vec<StackItem>)> {
+        let mut row = None;
+        let mut items = Vec::new();
+        while let Some(token) = self.peek() {
+            if token == terminator {
+                break;
+            }
+            let token = token.to_string();
+            self.pos += 1;
+            
+            if token.starts_with("..") {
+                if row.is_some() || !items.is_empty() {
+                    return Err(stack_effect_error(format!(
+                        "Row variable '{}' must come before the other stack items", token
+                    )));
+                }
+                row = Some(token);
+                continue;
+            }
+            if matches!(token.as_str(), "(" | ")" | ":" | "--") {
+                return Err(stack_effect_error(format!("Unexpected '{}' in stack effect", token)));
+            }
+            
+            if self.peek() == Some(":") {
+                self.pos += 1;
+                match self.peek() {
+                    Some("(") => {
+                        let effect = self.effect()?;
+                        items.push(StackItem::quotation(&token, effect));
+                    },
+                    Some(type_str) if !matches!(type_str, ")" | ":" | "--") => {
+                        let item_type = parse_item_type(type_str);
+                        self.pos += 1;
+                        items.push(StackItem::typed(&token, item_type));
+                    },
+                    _ => return Err(stack_effect_error(format!("Expected a type or stack effect after '{}:'", token))),
+                }
+            } else {
+                items.push(StackItem::named(&token));
+            }
+        }
+        Ok((row, items))
+    }
 }
 
 /// Parse a stack effect declaration string
 pub fn parse_stack_effect(effect_str: &str) -> Result<StackEffect> {
-    // Stack effect format: ( inputs -- outputs )
+    // Stack effect format: ( ..row name:Type quot: ( inputs -- outputs ) -- ..row outputs )
     let effect_str = effect_str.trim();
     
     // Check for basic format
     if !effect_str.starts_with('(') || !effect_str.ends_with(')') {
-        return Err(BorfError::StackEffectError {
-            message: format!("Invalid stack effect format: {}", effect_str),
-            src: None,
-            span: None,
-            help: "Stack effect declarations should have the form '( input1 input2 -- output1 output2 )'".to_string(),
-        });
+        return Err(stack_effect_error(format!("Invalid stack effect format: {}", effect_str)));
     }
     
-    // Remove the outer parentheses
-    let inner = &effect_str[1..effect_str.len()-1].trim();
-    
-    // Split by the "--" separator
-    let parts: Vec<&str> = inner.split("--").collect();
-    if parts.len() != 2 {
-        return Err(BorfError::StackEffectError {
-            message: format!("Stack effect must contain exactly one '--' separator: {}", effect_str),
-            src: None,
-            span: None,
-            help: "Stack effect declarations should have the form '( input1 input2 -- output1 output2 )'".to_string(),
-        });
+    let mut parser = StackEffectParser { tokens: tokenize_stack_effect(effect_str), pos: 0 };
+    let effect = parser.effect()?;
+    if parser.pos != parser.tokens.len() {
+        return Err(stack_effect_error(format!(
+            "Unexpected text after the end of the stack effect: {}", effect_str
+        )));
     }
-    
-    // Parse inputs and outputs
-    let inputs = parts[0]
-        .trim()
-        .split_whitespace()
-        .map(|s| s.to_string())
-        .collect();
-    
-    let outputs = parts[1]
-        .trim()
-        .split_whitespace()
-        .map(|s| s.to_string())
-        .collect();
-    
-    Ok(StackEffect::new(inputs, outputs))
+    Ok(effect)
 }
 
 /// Get the stack effect for a built-in word
@@ -96,6 +276,10 @@ pub fn get_word_effect(word: &str) -> Option<StackEffect> {
             vec!["a".to_string(), "b".to_string()], 
             vec!["b".to_string(), "a".to_string(), "b".to_string()]
         )),
+        "2dup" => Some(StackEffect::new(
+            vec!["a".to_string(), "b".to_string()], 
+            vec!["a".to_string(), "b".to_string(), "a".to_string(), "b".to_string()]
+        )),
         "nip" => Some(StackEffect::new(
             vec!["a".to_string(), "b".to_string()], 
             vec!["b".to_string()]
@@ -169,31 +353,107 @@ pub fn get_word_effect(word: &str) -> Option<StackEffect> {
             vec!["result".to_string()]
         )),
         
-        // Joy-inspired combinators
-        "dip" => Some(StackEffect::new(
-            vec!["x".to_string(), "quot".to_string()], 
-            vec!["quot(x)".to_string()]
-        )),
-        "bi" => Some(StackEffect::new(
-            vec!["x".to_string(), "p".to_string(), "q".to_string()], 
-            vec!["p(x)".to_string(), "q(x)".to_string()]
-        )),
-        "tri" => Some(StackEffect::new(
-            vec!["x".to_string(), "p".to_string(), "q".to_string(), "r".to_string()], 
-            vec!["p(x)".to_string(), "q(x)".to_string(), "r(x)".to_string()]
-        )),
-        "keep" => Some(StackEffect::new(
-            vec!["x".to_string(), "quot".to_string()], 
-            vec!["x".to_string(), "quot(x)".to_string()]
-        )),
-        "bi*" => Some(StackEffect::new(
-            vec!["x".to_string(), "y".to_string(), "p".to_string(), "q".to_string()], 
-            vec!["p(x)".to_string(), "q(y)".to_string()]
-        )),
-        "bi@" => Some(StackEffect::new(
-            vec!["x".to_string(), "y".to_string(), "p".to_string()], 
-            vec!["p(x)".to_string(), "p(y)".to_string()]
-        )),
+        // Joy-inspired combinators, written with row variables and quotation effects
+        "call" => parse_stack_effect("( ..a quot: ( ..a -- ..b ) -- ..b )").ok(),
+        "dip" => parse_stack_effect("( ..a x quot: ( ..a -- ..b ) -- ..b x )").ok(),
+        "keep" => parse_stack_effect("( ..a x quot: ( ..a x -- ..b ) -- ..b x )").ok(),
+        "bi" => parse_stack_effect("( ..a x p: ( ..a x -- ..b ) q: ( ..b x -- ..c ) -- ..c )").ok(),
+        "tri" => parse_stack_effect(
+            "( ..a x p: ( ..a x -- ..b ) q: ( ..b x -- ..c ) r: ( ..c x -- ..d ) -- ..d )"
+        ).ok(),
+        "bi*" => parse_stack_effect("( ..a x y p: ( ..a x -- ..b ) q: ( ..b y -- ..c ) -- ..c )").ok(),
+        // The quotation runs on x, then on y over what it left, so it must keep the row below
+        "bi@" => parse_stack_effect("( ..a x y quot: ( ..a x -- ..a z ) -- ..a z z )").ok(),
+        
+        "try" => parse_stack_effect("( ..a quot: ( ..a -- ..b ) handler: ( ..a error -- ..b ) -- ..b )").ok(),
+        
+        // Optional values
+        "unwrap" => parse_stack_effect("( optional -- value )").ok(),
+        
+        // File operations; a File handle is linear
+        "open_file" => parse_stack_effect("( path:String mode:String -- file:?!File )").ok(),
+        "close_file" => parse_stack_effect("( file:!File -- )").ok(),
+        "read_all" => parse_stack_effect("( file:!File -- file:!File text:String )").ok(),
+        "read_line" => parse_stack_effect("( file:!File -- file:!File line:?String )").ok(),
+        "write" => parse_stack_effect("( file:!File text:String -- file:!File )").ok(),
+        "seek" => parse_stack_effect("( file:!File offset:Int -- file:!File )").ok(),
+        
+        // Process operations; a Process and its Pipes are linear
+        "run" => parse_stack_effect("( program:String args:List -- result:Map )").ok(),
+        "spawn" => parse_stack_effect("( program:String args:List -- process:!Process )").ok(),
+        "wait" => parse_stack_effect("( process:!Process -- status:Int )").ok(),
+        "kill" => parse_stack_effect("( process:!Process -- )").ok(),
+        "stdin_pipe" | "stdout_pipe" | "stderr_pipe" => {
+            parse_stack_effect("( process:!Process -- process:!Process pipe:!Pipe )").ok()
+        },
+        "close_pipe" => parse_stack_effect("( pipe:!Pipe -- )").ok(),
+        
+        // Network operations; a Listener and a Socket are linear
+        "tcp_listen" => parse_stack_effect("( host:String port:Int -- listener:!Listener )").ok(),
+        "tcp_connect" => parse_stack_effect("( host:String port:Int -- socket:!Socket )").ok(),
+        "unix_listen" => parse_stack_effect("( path:String -- listener:!Listener )").ok(),
+        "unix_connect" => parse_stack_effect("( path:String -- socket:!Socket )").ok(),
+        "accept" => parse_stack_effect("( listener:!Listener -- listener:!Listener socket:!Socket )").ok(),
+        "local_port" => parse_stack_effect("( listener:!Listener -- listener:!Listener port:Int )").ok(),
+        "send" => parse_stack_effect("( socket:!Socket text:String -- socket:!Socket )").ok(),
+        "recv" => parse_stack_effect("( socket:!Socket -- socket:!Socket text:?String )").ok(),
+        "close" => parse_stack_effect("( handle:!Handle -- )").ok(),
+        
+        // ACSet operations; an ACSet is given back by each word that uses it
+        "schema" => parse_stack_effect("( objects:List homs:List attr_types:List attrs:List -- schema:Schema )").ok(),
+        "empty_acset" => parse_stack_effect("( schema:Schema -- acset:ACSet )").ok(),
+        "add_part" => parse_stack_effect("( acset:ACSet ob:String -- acset:ACSet part:Int )").ok(),
+        "add_parts" => parse_stack_effect("( acset:ACSet ob:String n:Int -- acset:ACSet parts:List )").ok(),
+        "nparts" => parse_stack_effect("( acset:ACSet ob:String -- acset:ACSet n:Int )").ok(),
+        "parts" => parse_stack_effect("( acset:ACSet ob:String -- acset:ACSet parts:List )").ok(),
+        "set_subpart" => parse_stack_effect("( acset:ACSet part:Int hom:String target:Int -- acset:ACSet )").ok(),
+        "subpart" => parse_stack_effect("( acset:ACSet part:Int hom:String -- acset:ACSet target:?Int )").ok(),
+        "incident" => parse_stack_effect("( acset:ACSet part:Int hom:String -- acset:ACSet sources:List )").ok(),
+        "set_prop" => parse_stack_effect("( acset:ACSet part:Int attr:String value -- acset:ACSet )").ok(),
+        "get_prop" => parse_stack_effect("( acset:ACSet part:Int attr:String -- acset:ACSet value:?Any )").ok(),
+        "rem_part" | "cascading_rem_part" => parse_stack_effect("( acset:ACSet ob:String part:Int -- acset:ACSet )").ok(),
+        "homomorphisms" | "monic_homomorphisms" => {
+            parse_stack_effect("( pattern:ACSet acset:ACSet -- acset:ACSet homs:List )").ok()
+        },
+        "schema_functor" => parse_stack_effect(
+            "( dom:Schema codom:Schema objects:List homs:List attrs:List -- functor:SchemaFunctor )"
+        ).ok(),
+        "delta_migrate" | "sigma_migrate" => parse_stack_effect("( acset:ACSet functor:SchemaFunctor -- acset:ACSet )").ok(),
+        
+        // Wiring diagram operations
+        "empty_diagram" => parse_stack_effect("( inputs:List outputs:List -- diagram:WiringDiagram )").ok(),
+        "add_box" => parse_stack_effect(
+            "( diagram:WiringDiagram name:String inputs:List outputs:List -- diagram:WiringDiagram box:Int )"
+        ).ok(),
+        "add_wire" => parse_stack_effect(
+            "( diagram:WiringDiagram src_box:Int src_port:Int tgt_box:Int tgt_port:Int -- diagram:WiringDiagram )"
+        ).ok(),
+        "compose" | "tensor" => parse_stack_effect("( d1:WiringDiagram d2:WiringDiagram -- d:WiringDiagram )").ok(),
+        "substitute" => parse_stack_effect(
+            "( diagram:WiringDiagram box:Int inner:WiringDiagram -- diagram:WiringDiagram )"
+        ).ok(),
+        "to_dot" => parse_stack_effect("( diagram:WiringDiagram -- dot:String )").ok(),
+        "quotation_to_diagram" => parse_stack_effect("( quot:Quotation -- diagram:WiringDiagram )").ok(),
+        
+        // Interaction net operations
+        "create_cell" => parse_stack_effect("( agent:String arity:Int -- cell:Cell )").ok(),
+        "empty_net" => parse_stack_effect("( free:Int -- net:Net )").ok(),
+        "add_cell" => parse_stack_effect("( net:Net cell:Cell -- net:Net id:Int )").ok(),
+        "connect" => parse_stack_effect("( net:Net cell1:Int port1:Int cell2:Int port2:Int -- net:Net )").ok(),
+        "active_pairs" => parse_stack_effect("( net:Net -- net:Net pairs:List )").ok(),
+        "create_rewrite_system" => parse_stack_effect("( rules:List -- system:RewriteSystem )").ok(),
+        "normalize" => parse_stack_effect("( net:Net system:RewriteSystem -- net:Net stats:Map )").ok(),
+        "normalize_parallel" => parse_stack_effect("( net:Net system:RewriteSystem threads:Number -- net:Net stats:Map )").ok(),
+        "query" => parse_stack_effect("( acset:ACSet variables:List atoms:List -- acset:ACSet answers:List )").ok(),
+        
+        // Script operations
+        "args" => parse_stack_effect("( -- args:List )").ok(),
+        "getenv" => parse_stack_effect("( name:String -- value:?String )").ok(),
+        "read_stdin_line" => parse_stack_effect("( -- line:?String )").ok(),
+        "read_stdin" => parse_stack_effect("( -- text:String )").ok(),
+        "exit" => parse_stack_effect("( status:Int -- )").ok(),
+        "with_file" => parse_stack_effect("( ..a path:String mode:String quot: ( ..a file:!File -- ..b file:!File ) -- ..b )").ok(),
+        "curry" => parse_stack_effect("( x quot: ( ..a x -- ..b ) -- curried: ( ..a -- ..b ) )").ok(),
         
         // Special cases for literals
         _ if word.parse::<i32>().is_ok() => Some(StackEffect::new(
@@ -210,268 +470,199 @@ pub fn get_word_effect(word: &str) -> Option<StackEffect> {
     }
 }
 
+/// A stack slot as seen by the translator
+#[derive(Debug, Clone, PartialEq)]
+enum Slot {
+    // A parameter that is still waiting for its last use
+    Param(String),
+    // A quotation, with its effect when it could be inferred
+    Quotation(Option<StackEffect>),
+    Value,
+}
+
 /// STACKER Algorithm Implementation
-/// Enhanced with both Strategy 1 (Peephole Optimization) and Strategy 2 (Usage Tracking)
+///
+/// Parameters start out on the stack in declaration order. Every use of a parameter copies
+/// it to the top (`dup`, `over`, `n pick`) except the last, which moves it there (`swap`,
+/// `rot`, `n roll`); parameters that are never used are dropped before the body runs.
+///
+/// Quotations in the body are closure-converted: the outer parameters they refer to become
+/// extra trailing parameters of the inner quotation, and are pushed before it and bound with
+/// `curry`, so `[x -> [y -> x y -]]` translates to `[[swap -] curry]`.
 pub struct StackerTranslator {
-    // Map from parameter name to its initial stack depth before the body starts
-    param_depths: HashMap<String, usize>,
-    // Current stack depth increase caused by operations within the body
-    current_stack_depth_increase: isize,
-    // The output list of stack operations
+    // Effects of user-defined words, consulted before the built-in ones
+    known: HashMap<String, StackEffect>,
+    params: Vec<String>,
+    // Uses of each parameter left in the body being translated
+    uses: HashMap<String, usize>,
+    stack: Vec<Slot>,
     output: Vec<Expr>,
-    // Parameter usage tracking for optimization
-    param_usage_count: HashMap<String, usize>,
-    param_last_use: HashMap<String, usize>,
-    // Track which parameters have been consumed already
-    consumed_params: Vec<String>,
-    // Map of adjusted parameter depths after consumption
-    adjusted_param_depths: HashMap<String, isize>,
 }
 
 impl StackerTranslator {
     pub fn new() -> Self {
+        Self::with_effects(HashMap::new())
+    }
+
+    /// A translator that can also follow calls to user-defined words
+    pub fn with_effects(known: HashMap<String, StackEffect>) -> Self {
         StackerTranslator {
-            param_depths: HashMap::new(),
-            current_stack_depth_increase: 0,
+            known,
+            params: Vec::new(),
+            uses: HashMap::new(),
+            stack: Vec::new(),
             output: Vec::new(),
-            param_usage_count: HashMap::new(),
-            param_last_use: HashMap::new(),
-            consumed_params: Vec::new(),
-            adjusted_param_depths: HashMap::new(),
         }
     }
 
     // Translate a quotation with named parameters to explicit stack operations
     pub fn translate(&mut self, params: &[Param], body: &[Expr]) -> Result<Vec<Expr>> {
-        // Reset state
-        self.param_depths.clear();
-        self.current_stack_depth_increase = 0;
+        self.params = params.iter().map(|param| param.name.clone()).collect();
+        self.uses = self.params.iter().map(|name| (name.clone(), 0)).collect();
+        self.count_uses(body);
+        self.stack = self.params.iter().map(|name| Slot::Param(name.clone())).collect();
         self.output.clear();
-        self.param_usage_count.clear();
-        self.param_last_use.clear();
-        self.consumed_params.clear();
-        self.adjusted_param_depths.clear();
-        
-        // Step 1: Map parameters to initial stack depths
-        // Last parameter (rightmost) is at depth 0, second-to-last at depth 1, etc.
-        for (i, param) in params.iter().enumerate().rev() {
-            self.param_depths.insert(param.name.clone(), i);
-            self.adjusted_param_depths.insert(param.name.clone(), i as isize);
+
+        // Parameters the body never refers to are dropped up front
+        for name in self.params.clone() {
+            if self.uses[&name] > 0 {
+                continue;
+            }
+            if let Some((position, depth)) = self.locate(&name) {
+                self.stack.remove(position);
+                match depth {
+                    0 => self.emit("drop"),
+                    1 => self.emit("nip"),
+                    _ => {
+                        self.output.push(Expr::Number(depth as i32));
+                        self.emit("roll");
+                        self.emit("drop");
+                    },
+                }
+            }
         }
-        
-        // Step 1.5: Scan the body to count parameter usage and track last use
-        self.analyze_parameter_usage(body);
-        
-        // Step 2: Translate the body expressions with enhanced strategy
-        for (index, expr) in body.iter().enumerate() {
-            self.translate_expr_enhanced(expr, index)?;
+
+        for expr in body {
+            self.translate_expr(expr)?;
         }
-        
-        // Step 3: Apply peephole optimizations to the output
-        let optimized = self.apply_peephole_optimizations();
-        
-        Ok(optimized)
+
+        Ok(self.apply_peephole_optimizations())
     }
-    
-    // Analyze parameter usage in the body to track usage patterns
-    fn analyze_parameter_usage(&mut self, body: &[Expr]) {
-        // First pass - count occurrences
-        for (index, expr) in body.iter().enumerate() {
+
+    // Count the uses of each parameter; a quotation capturing a parameter uses it once
+    fn count_uses(&mut self, body: &[Expr]) {
+        for expr in body {
             match expr {
-                Expr::Symbol(s) => {
-                    if self.param_depths.contains_key(s) {
-                        // Increment usage count
-                        *self.param_usage_count.entry(s.clone()).or_insert(0) += 1;
-                        // Update last use index
-                        self.param_last_use.insert(s.clone(), index);
+                Expr::Symbol(name) => {
+                    if let Some(count) = self.uses.get_mut(name) {
+                        *count += 1;
                     }
                 },
-                // Recursively analyze nested expressions
                 Expr::Pipeline(left, right) => {
-                    self.analyze_parameter_usage(&[*left.clone(), *right.clone()]);
+                    self.count_uses(std::slice::from_ref(left.as_ref()));
+                    self.count_uses(std::slice::from_ref(right.as_ref()));
                 },
-                Expr::Quotation(_, inner_body) => {
-                    // For simplicity, we don't track parameter usage across quotation boundaries
-                    // A more sophisticated implementation would handle this
+                Expr::Quotation(params, inner) | Expr::TypedQuotation(params, inner, _) => {
+                    for name in self.captures(params, inner) {
+                        *self.uses.entry(name).or_insert(0) += 1;
+                    }
                 },
-                _ => {}
+                _ => {},
             }
         }
     }
-    
-    // Translate a single expression with enhanced strategy 2
-    fn translate_expr_enhanced(&mut self, expr: &Expr, index: usize) -> Result<()> {
-        match expr {
-            Expr::Number(n) => {
-                // Push the number onto the stack
-                self.output.push(Expr::Number(*n));
-                self.current_stack_depth_increase += 1;
-            },
-            Expr::String(s) => {
-                // Push the string onto the stack
-                self.output.push(Expr::String(s.clone()));
-                self.current_stack_depth_increase += 1;
-            },
-            Expr::Boolean(b) => {
-                // Push the boolean onto the stack
-                self.output.push(Expr::Boolean(*b));
-                self.current_stack_depth_increase += 1;
-            },
-            Expr::Symbol(s) => {
-                // Check if it's a parameter name
-                if let Some(&initial_depth) = self.param_depths.get(s) {
-                    // Skip if this parameter has already been consumed
-                    if self.consumed_params.contains(&s.clone()) {
-                        return Err(BorfError::StackEffectError {
-                            message: format!("Parameter '{}' has already been consumed and cannot be used again", s),
-                            src: None,
-                            span: None,
-                            help: format!("This parameter was marked as consumed in a previous operation. Parameters can only be consumed once with Strategy 2."),
-                        });
-                    }
-                    
-                    // Check if this is the last use of this parameter
-                    let is_last_use = self.param_last_use.get(s) == Some(&index);
-                    
-                    // Get the adjusted depth considering consumed parameters
-                    let actual_depth = self.adjusted_param_depths[s] + self.current_stack_depth_increase;
-                    
-                    if actual_depth < 0 {
-                        return Err(BorfError::StackEffectError {
-                            message: format!("Invalid stack depth for parameter '{}': {}", s, actual_depth),
-                            src: None,
-                            span: None,
-                            help: format!("This usually happens when stack operations have consumed too many items before the parameter is used. Check the stack effect of operations before this point."),
-                        });
-                    }
-                    
-                    // Strategy 2: Consume parameter if it's the last use
-                    if is_last_use {
-                        // If parameter is at the top of the stack, just consume it (no operation needed)
-                        if actual_depth == 0 {
-                            // No operation needed - it's already on top
-                            // Mark as consumed
-                            self.consumed_params.push(s.clone());
-                        }
-                        // If parameter is just below the top, use swap and then consume
-                        else if actual_depth == 1 {
-                            self.output.push(Expr::Symbol("swap".to_string()));
-                            // swap doesn't change net stack depth
-                            // Mark as consumed
-                            self.consumed_params.push(s.clone());
-                        }
-                        // If parameter is deeper, use roll to bring to top and consume
-                        else if actual_depth > 1 {
-                            if actual_depth <= 3 {
-                                // For depths <= 3, use rot or specific roll combinations
-                                if actual_depth == 2 {
-                                    self.output.push(Expr::Symbol("rot".to_string()));
-                                } else { // depth == 3
-                                    // rot works on top 3 items, so we'd need 2 rots for depth 3
-                                    // For simplicity use roll with depth marker
-                                    self.output.push(Expr::Number(actual_depth as i32));
-                                    self.output.push(Expr::Symbol("roll".to_string()));
-                                }
-                            } else {
-                                // Use roll for deeper items
-                                self.output.push(Expr::Number(actual_depth as i32));
-                                self.output.push(Expr::Symbol("roll".to_string()));
-                            }
-                            // Mark as consumed
-                            self.consumed_params.push(s.clone());
-                        }
-                        
-                        // Update adjusted depths for all remaining parameters
-                        // When we consume a parameter, all deeper parameters move up by 1
-                        for (param, depth) in self.adjusted_param_depths.iter_mut() {
-                            if *depth > actual_depth {
-                                *depth -= 1;
-                            }
-                        }
-                    }
-                    // Not the last use, so use pick to copy the parameter
-                    else {
-                        // Generate "N pick" operation
-                        self.output.push(Expr::Number(actual_depth as i32));
-                        self.output.push(Expr::Symbol("pick".to_string()));
-                        // pick adds an item to the stack
-                        self.current_stack_depth_increase += 1;
-                    }
-                } else {
-                    // Regular word - look up its stack effect
-                    let stack_effect = get_word_effect(s).ok_or_else(|| BorfError::StackEffectError {
-                        message: format!("Unknown word '{}' with no stack effect declaration", s),
-                        src: None,
-                        span: None,
-                        help: format!("Make sure '{}' is a valid Borf word or declare its stack effect.", s),
-                    })?;
-                    
-                    // Special case for pick and roll
-                    if s == "pick" || s == "roll" {
-                        // The actual depth difference depends on the value on top of the stack
-                        if let Some(Expr::Number(_)) = self.output.last() {
-                            // pick consumes the depth but produces a copy of the item at that depth
-                            // roll consumes the depth and moves the item
-                            self.current_stack_depth_increase += 0; // -1 (for the depth) + 1 (for the result)
-                        } else {
-                            // If we can't determine the depth, use a default
-                            self.current_stack_depth_increase += 0; // Assume no net change
-                        }
-                    } else {
-                        // Update stack depth based on the word's effect
-                        self.current_stack_depth_increase += stack_effect.stack_depth_change();
+
+    // The parameters of this translator a nested quotation refers to, in order of first use
+    fn captures(&self, params: &[Param], body: &[Expr]) -> Vec<String> {
+        let bound: Vec<&str> = params.iter().map(|param| param.name.as_str()).collect();
+        let mut found = Vec::new();
+        self.collect_captures(body, &bound, &mut found);
+        found
+    }
+
+    fn collect_captures<'e>(&self, body: &'e [Expr], bound: &[&'e str], found: &mut Vec<String>) {
+        for expr in body {
+            match expr {
+                Expr::Symbol(name) => {
+                    if self.params.contains(name) && !bound.contains(&name.as_str()) && !found.contains(name) {
+                        found.push(name.clone());
                     }
-                    
-                    // Add the word to the output
-                    self.output.push(Expr::Symbol(s.clone()));
-                }
+                },
+                Expr::Pipeline(left, right) => {
+                    self.collect_captures(std::slice::from_ref(left.as_ref()), bound, found);
+                    self.collect_captures(std::slice::from_ref(right.as_ref()), bound, found);
+                },
+                Expr::Quotation(params, inner) | Expr::TypedQuotation(params, inner, _) => {
+                    let mut shadowed = bound.to_vec();
+                    shadowed.extend(params.iter().map(|param| param.name.as_str()));
+                    self.collect_captures(inner, &shadowed, found);
+                },
+                _ => {},
+            }
+        }
+    }
+
+    // Position of a parameter in the modelled stack, and its depth from the top
+    fn locate(&self, name: &str) -> Option<(usize, usize)> {
+        let position = self.stack.iter().rposition(|slot| matches!(slot, Slot::Param(p) if p == name))?;
+        Some((position, self.stack.len() - 1 - position))
+    }
+
+    fn emit(&mut self, word: &str) {
+        self.output.push(Expr::Symbol(word.to_string()));
+    }
+
+    // Bring a parameter to the top: copy it, or move it on its last use
+    fn use_param(&mut self, name: &str) -> Result<()> {
+        let (position, depth) = self.locate(name).ok_or_else(|| stack_effect_error(
+            format!("Parameter '{}' is no longer on the stack", name)
+        ))?;
+        let remaining = self.uses.get_mut(name).map(|count| {
+            *count = count.saturating_sub(1);
+            *count
+        });
+
+        if remaining == Some(0) {
+            self.stack.remove(position);
+            match depth {
+                0 => {},
+                1 => self.emit("swap"),
+                2 => self.emit("rot"),
+                _ => {
+                    self.output.push(Expr::Number(depth as i32));
+                    self.emit("roll");
+                },
+            }
+        } else {
+            match depth {
+                0 => self.emit("dup"),
+                1 => self.emit("over"),
+                _ => {
+                    self.output.push(Expr::Number(depth as i32));
+                    self.emit("pick");
+                },
+            }
+        }
+        self.stack.push(Slot::Value);
+        Ok(())
+    }
+
+    fn translate_expr(&mut self, expr: &Expr) -> Result<()> {
+        match expr {
+            Expr::Number(_) | Expr::String(_) | Expr::Boolean(_) | Expr::Nil | Expr::Literal(_) => {
+                self.output.push(expr.clone());
+                self.stack.push(Slot::Value);
             },
+            Expr::Symbol(name) if self.uses.contains_key(name) => self.use_param(name)?,
+            Expr::Symbol(name) => self.word(name)?,
             Expr::Pipeline(left, right) => {
-                // Handle pipeline by translating the left side, then the right
                 // The |> operator is just syntactic sugar and doesn't translate to any operation
-                self.translate_expr_enhanced(left, index)?;
-                self.translate_expr_enhanced(right, index)?;
+                self.translate_expr(left)?;
+                self.translate_expr(right)?;
             },
-            Expr::Quotation(inner_params, inner_body) => {
-                // For nested quotations, we need to store the current state
-                let saved_params = self.param_depths.clone();
-                let saved_adjusted_depths = self.adjusted_param_depths.clone();
-                let saved_consumed = self.consumed_params.clone();
-                let saved_depth = self.current_stack_depth_increase;
-                
-                // Start a quotation
-                self.output.push(Expr::Symbol("[".to_string()));
-                
-                // Translate the inner quotation if it has parameters
-                if !inner_params.is_empty() {
-                    let mut inner_translator = StackerTranslator::new();
-                    match inner_translator.translate(inner_params, inner_body) {
-                        Ok(translated_body) => {
-                            // Add the translated body to our output
-                            self.output.extend(translated_body);
-                        },
-                        Err(e) => return Err(e)
-                    }
-                } else {
-                    // No parameters, just add the body as is
-                    for (inner_index, expr) in inner_body.iter().enumerate() {
-                        self.translate_expr_enhanced(expr, inner_index)?;
-                    }
-                }
-                
-                // End the quotation
-                self.output.push(Expr::Symbol("]".to_string()));
-                
-                // A quotation is a single item on the stack
-                self.current_stack_depth_increase += 1;
-                
-                // Restore the outer quotation state
-                self.param_depths = saved_params;
-                self.adjusted_param_depths = saved_adjusted_depths;
-                self.consumed_params = saved_consumed;
-                // Note: we keep the updated stack depth increase
+            Expr::Quotation(params, body) | Expr::TypedQuotation(params, body, _) => {
+                self.quotation(params, body)?;
             },
-            // Handle other expression types as needed
             _ => {
                 return Err(BorfError::StackEffectError {
                     message: format!("Unsupported expression in translation: {:?}", expr),
@@ -481,149 +672,232 @@ impl StackerTranslator {
                 });
             }
         }
-        
         Ok(())
     }
-    
-    // Apply peephole optimizations to the translated output
-    fn apply_peephole_optimizations(&self) -> Vec<Expr> {
-        if self.output.is_empty() {
-            return Vec::new();
+
+    // Translate a nested quotation, passing the parameters it captures in with curry
+    fn quotation(&mut self, params: &[Param], body: &[Expr]) -> Result<()> {
+        let captured = self.captures(params, body);
+        for name in &captured {
+            self.use_param(name)?;
         }
-        
-        let mut optimized = Vec::new();
-        let mut i = 0;
-        
-        while i < self.output.len() {
-            // Pattern: 1 pick 1 pick + -> +
-            // (If we have two items x and y on top of the stack, just apply the operator)
-            if i + 4 <= self.output.len() && 
-                is_expr_number(&self.output[i], 1) && 
-                is_expr_symbol(&self.output[i+1], "pick") && 
-                is_expr_number(&self.output[i+2], 1) && 
-                is_expr_symbol(&self.output[i+3], "pick") && 
-                i + 4 < self.output.len() && 
-                is_binary_op(&self.output[i+4]) {
-                    // Skip the picks and just add the binary operator
-                    optimized.push(self.output[i+4].clone());
-                    i += 5;
-            }
-            // Pattern: 0 pick 1 pick + -> swap +
-            // (If we need to swap the order of the top two items)
-            else if i + 4 <= self.output.len() && 
-                is_expr_number(&self.output[i], 0) && 
-                is_expr_symbol(&self.output[i+1], "pick") && 
-                is_expr_number(&self.output[i+2], 1) && 
-                is_expr_symbol(&self.output[i+3], "pick") && 
-                i + 4 < self.output.len() && 
-                is_binary_op(&self.output[i+4]) {
-                    // Replace with swap + binary operator
-                    optimized.push(Expr::Symbol("swap".to_string()));
-                    optimized.push(self.output[i+4].clone());
-                    i += 5;
-            }
-            // Pattern: 0 pick <op> -> <op>
-            // (If we're performing an operation on the top item)
-            else if i + 2 <= self.output.len() && 
-                is_expr_number(&self.output[i], 0) && 
-                is_expr_symbol(&self.output[i+1], "pick") && 
-                i + 2 < self.output.len() && 
-                is_unary_op(&self.output[i+2]) {
-                    // Skip the pick and just add the unary operator
-                    optimized.push(self.output[i+2].clone());
-                    i += 3;
-            }
-            // Pattern: 1 pick drop -> nip
-            // (Copy second item then drop it? Just use nip)
-            else if i + 2 <= self.output.len() && 
-                is_expr_number(&self.output[i], 1) && 
-                is_expr_symbol(&self.output[i+1], "pick") && 
-                i + 2 < self.output.len() && 
-                is_expr_symbol(&self.output[i+2], "drop") {
-                    // Replace with nip
-                    optimized.push(Expr::Symbol("nip".to_string()));
-                    i += 3;
-            }
-            // Pattern: 0 pick drop -> drop
-            // (Copy top item then drop it? Just drop it)
-            else if i + 2 <= self.output.len() && 
-                is_expr_number(&self.output[i], 0) && 
-                is_expr_symbol(&self.output[i+1], "pick") && 
-                i + 2 < self.output.len() && 
-                is_expr_symbol(&self.output[i+2], "drop") {
-                    // Replace with drop
-                    optimized.push(Expr::Symbol("drop".to_string()));
-                    i += 3;
-            }
-            // Pattern: 1 pick (as last word) -> drop
-            // (If the last thing we do is copy the second item to the top)
-            else if i + 2 <= self.output.len() && 
-                is_expr_number(&self.output[i], 1) && 
-                is_expr_symbol(&self.output[i+1], "pick") && 
-                i + 2 == self.output.len() {
-                    // Replace with drop (to discard the top item, leaving the second one)
-                    optimized.push(Expr::Symbol("swap".to_string()));
-                    optimized.push(Expr::Symbol("drop".to_string()));
-                    i += 2;
-            }
-            // Pattern: 0 pick (as last word) -> no-op
-            // (If the last thing we do is copy the top item to the top - redundant)
-            else if i + 2 <= self.output.len() && 
-                is_expr_number(&self.output[i], 0) && 
-                is_expr_symbol(&self.output[i+1], "pick") && 
-                i + 2 == self.output.len() {
-                    // Skip it entirely - the item is already on top
-                    i += 2;
-            }
-            // Pattern: roll roll -> roll2 (hypothetical combined operation)
-            // Could create more optimizations like this if needed
-            
-            // No optimization applies, copy as is
-            else {
-                optimized.push(self.output[i].clone());
-                i += 1;
-            }
+
+        let mut inner_params = params.to_vec();
+        inner_params.extend(captured.iter().map(|name| Param {
+            name: name.clone(),
+            type_annotation: None,
+        }));
+        let mut inner = StackerTranslator::with_effects(self.known.clone());
+        let translated = if inner_params.is_empty() {
+            // Nothing to bind; only quotations nested further in may need translating
+            inner.translate(&[], body).unwrap_or_else(|_| body.to_vec())
+        } else {
+            inner.translate(&inner_params, body)?
+        };
+
+        let effect = infer_effect(&[], &translated, &self.known).ok().flatten();
+        self.output.push(Expr::Quotation(Vec::new(), translated));
+        self.stack.push(Slot::Quotation(effect));
+        for _ in &captured {
+            self.word("curry")?;
         }
-        
-        optimized
+        Ok(())
     }
-}
 
-// Helper functions for pattern matching in peephole optimization
-fn is_expr_number(expr: &Expr, value: i32) -> bool {
-    match expr {
-        Expr::Number(n) => *n == value,
-        _ => false,
+    // Take the top slot for a word; parameters may only leave the stack on their last use
+    fn pop(&mut self, word: &str) -> Result<Slot> {
+        match self.stack.pop() {
+            Some(Slot::Param(name)) => Err(stack_effect_error(format!(
+                "'{}' would consume parameter '{}' before its last use", word, name
+            ))),
+            Some(slot) => Ok(slot),
+            // Below the parameters: values the quotation takes from its caller
+            None => Ok(Slot::Value),
+        }
     }
-}
 
-fn is_expr_symbol(expr: &Expr, name: &str) -> bool {
-    match expr {
-        Expr::Symbol(s) => s == name,
-        _ => false,
+    fn pop_quotation(&mut self, word: &str) -> Result<StackEffect> {
+        match self.pop(word)? {
+            Slot::Quotation(Some(effect)) => Ok(effect),
+            _ => Err(stack_effect_error(format!(
+                "Cannot translate '{}' without knowing the stack effect of its quotation", word
+            ))),
+        }
     }
-}
 
-fn is_binary_op(expr: &Expr) -> bool {
-    match expr {
-        Expr::Symbol(s) => {
-            matches!(s.as_str(), "+" | "-" | "*" | "/" | "mod" | "==" | "!=" | "<" | ">" | "<=" | ">=" | "and" | "or")
-        },
-        _ => false,
+    // Apply a stack effect; outputs named like an input are copies of that input
+    fn apply(&mut self, effect: &StackEffect, word: &str) -> Result<()> {
+        if effect.is_row_polymorphic() {
+            return Err(stack_effect_error(format!(
+                "Cannot translate '{}': its effect {} depends on its quotation arguments", word, effect
+            )));
+        }
+        let mut taken = Vec::with_capacity(effect.inputs.len());
+        for _ in &effect.inputs {
+            taken.push(self.pop(word)?);
+        }
+        taken.reverse();
+        for output in &effect.outputs {
+            let slot = match effect.inputs.iter().rposition(|input| input.name == output.name) {
+                Some(index) => taken[index].clone(),
+                None => match &output.effect {
+                    Some(quotation) => Slot::Quotation(Some(quotation.clone())),
+                    None => Slot::Value,
+                },
+            };
+            self.stack.push(slot);
+        }
+        Ok(())
     }
-}
 
-fn is_unary_op(expr: &Expr) -> bool {
-    match expr {
-        Expr::Symbol(s) => {
-            matches!(s.as_str(), "not" | "sqrt" | "sin" | "cos" | "tan" | "abs" | "neg")
-        },
-        _ => false,
+    fn word(&mut self, name: &str) -> Result<()> {
+        match name {
+            "call" => {
+                let quot = self.pop_quotation(name)?;
+                self.apply(&quot, name)?;
+            },
+            "dip" | "keep" => {
+                // ( x quot -- quot(x) x ), keep also leaves x for the quotation
+                let quot = self.pop_quotation(name)?;
+                let x = self.pop(name)?;
+                if name == "keep" {
+                    self.stack.push(x.clone());
+                }
+                self.apply(&quot, name)?;
+                self.stack.push(x);
+            },
+            "bi" | "tri" => {
+                // ( x p q -- p(x) q(x) ) and ( x p q r -- p(x) q(x) r(x) )
+                let count = if name == "bi" { 2 } else { 3 };
+                let mut quots = Vec::with_capacity(count);
+                for _ in 0..count {
+                    quots.push(self.pop_quotation(name)?);
+                }
+                let x = self.pop(name)?;
+                for quot in quots.iter().rev() {
+                    self.stack.push(x.clone());
+                    self.apply(quot, name)?;
+                }
+            },
+            "bi*" => {
+                // ( x y p q -- p(x) q(y) )
+                let q = self.pop_quotation(name)?;
+                let p = self.pop_quotation(name)?;
+                let y = self.pop(name)?;
+                let x = self.pop(name)?;
+                self.stack.push(x);
+                self.apply(&p, name)?;
+                self.stack.push(y);
+                self.apply(&q, name)?;
+            },
+            "bi@" => {
+                // ( x y p -- p(x) p(y) )
+                let quot = self.pop_quotation(name)?;
+                let y = self.pop(name)?;
+                let x = self.pop(name)?;
+                self.stack.push(x);
+                self.apply(&quot, name)?;
+                self.stack.push(y);
+                self.apply(&quot, name)?;
+            },
+            "curry" => {
+                // ( x quot -- quot' ): x fills the quotation's topmost input
+                let effect = match self.pop(name)? {
+                    Slot::Quotation(effect) => effect,
+                    _ => None,
+                };
+                self.pop(name)?;
+                self.stack.push(Slot::Quotation(effect.and_then(curried)));
+            },
+            "pick" | "roll" => {
+                // Only literal depths can be followed
+                let depth = match (self.output.last(), self.stack.last()) {
+                    (Some(Expr::Number(n)), Some(Slot::Value)) if *n >= 0 => *n as usize,
+                    _ => return Err(stack_effect_error(format!(
+                        "Cannot translate '{}' without a literal depth", name
+                    ))),
+                };
+                self.stack.pop();
+                let slot = match self.stack.len().checked_sub(depth + 1) {
+                    Some(position) if name == "pick" => match &self.stack[position] {
+                        Slot::Param(_) => Slot::Value,
+                        slot => slot.clone(),
+                    },
+                    Some(position) => match self.stack.remove(position) {
+                        Slot::Param(param) => return Err(stack_effect_error(format!(
+                            "'roll' would consume parameter '{}' before its last use", param
+                        ))),
+                        slot => slot,
+                    },
+                    None => Slot::Value,
+                };
+                self.stack.push(slot);
+            },
+            _ => {
+                let effect = self.known.get(name).cloned()
+                    .or_else(|| get_word_effect(name))
+                    .ok_or_else(|| BorfError::StackEffectError {
+                        message: format!("Unknown word '{}' with no stack effect declaration", name),
+                        src: None,
+                        span: None,
+                        help: format!("Make sure '{}' is a valid Borf word or declare its stack effect.", name),
+                    })?;
+                self.apply(&effect, name)?;
+            },
+        }
+        self.emit(name);
+        Ok(())
+    }
+
+    // Remove shuffles made redundant by moving parameters
+    fn apply_peephole_optimizations(&self) -> Vec<Expr> {
+        Optimizer::new().optimize(&self.output)
     }
 }
 
+// The effect of a quotation once its topmost input has been supplied by curry
+fn curried(mut effect: StackEffect) -> Option<StackEffect> {
+    effect.inputs.pop()?;
+    Some(effect)
+}
+
 /// Translate a named parameter quotation to explicit stack operations
 pub fn translate_quotation(params: &[Param], body: &[Expr]) -> Result<Vec<Expr>> {
     let mut translator = StackerTranslator::new();
     translator.translate(params, body)
-}
\ No newline at end of file
+}
+
+/// Translate a named parameter quotation that may call user-defined words with known effects
+pub fn translate_quotation_with(
+    params: &[Param],
+    body: &[Expr],
+    known: &HashMap<String, StackEffect>,
+) -> Result<Vec<Expr>> {
+    let mut translator = StackerTranslator::with_effects(known.clone());
+    translator.translate(params, body)
+}
+
+/// Translate every definition in a program to its point-free form.
+/// Definitions without named parameters are already point-free and are kept as written;
+/// a definition that cannot be translated carries its error and does not stop the others.
+pub fn expand_definitions(program: &Expr) -> Vec<(String, Result<Expr>)> {
+    let exprs = match program {
+        Expr::Sequence(exprs) => exprs.as_slice(),
+        other => std::slice::from_ref(other),
+    };
+    
+    let mut definitions = Vec::new();
+    for expr in exprs {
+        if let Expr::Assignment(value, name) = expr {
+            if let Expr::Quotation(params, body) | Expr::TypedQuotation(params, body, _) = value.as_ref() {
+                let body = if params.is_empty() {
+                    Ok(body.clone())
+                } else {
+                    translate_quotation(params, body)
+                };
+                definitions.push((name.clone(), body.map(|body| Expr::Quotation(Vec::new(), body))));
+            }
+        }
+    }
+    definitions
+}
@@ -0,0 +1,591 @@
+// src/repl/interpreter/stack_types.rs
+// Row-polymorphic stack type inference for quotations (in the style of Cat and Kitten)
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use crate::repl::interpreter::types::{EvaluatorError, Expr, Param, Result, Type, Value};
+
+/// A value type in the inference engine
+#[derive(Debug, Clone, PartialEq)]
+pub enum StackTy {
+    Con(String),            // Concrete types like Num, String, Bool
+    Var(usize),             // Type variable
+    Quot(Box<Signature>),   // Quotation with its own stack signature
+}
+
+/// A stack type: a row variable (the rest of the stack) followed by known items.
+/// Items are ordered from deepest to topmost.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+    pub var: usize,
+    pub items: Vec<StackTy>,
+}
+
+/// The stack signature of a word or quotation: ( ..a inputs -- ..b outputs )
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    pub inputs: Row,
+    pub outputs: Row,
+}
+
+impl Signature {
+    // Convert to the evaluator's Type representation, naming variables a, b, c...
+    pub fn to_type(&self) -> Type {
+        let mut names = VarNames::new();
+        self.to_type_with(&mut names)
+    }
+
+    fn to_type_with(&self, names: &mut VarNames) -> Type {
+        let mut inputs = Vec::new();
+        let mut outputs = Vec::new();
+
+        // Row variables are only shown when the quotation changes the rest of the stack
+        if self.inputs.var != self.outputs.var {
+            inputs.push(Type::Simple(names.row(self.inputs.var)));
+            outputs.push(Type::Simple(names.row(self.outputs.var)));
+        }
+
+        for item in &self.inputs.items {
+            inputs.push(item.to_type_with(names));
+        }
+        for item in &self.outputs.items {
+            outputs.push(item.to_type_with(names));
+        }
+
+        Type::StackEffect(inputs, outputs)
+    }
+}
+
+impl StackTy {
+    fn to_type_with(&self, names: &mut VarNames) -> Type {
+        match self {
+            StackTy::Con(name) => Type::Simple(name.clone()),
+            StackTy::Var(id) => Type::Simple(names.var(*id)),
+            StackTy::Quot(sig) => sig.to_type_with(names),
+        }
+    }
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", format_type(&self.to_type()))
+    }
+}
+
+// Assigns readable names to type and row variables in order of appearance
+struct VarNames {
+    vars: HashMap<usize, String>,
+    rows: HashMap<usize, String>,
+}
+
+impl VarNames {
+    fn new() -> Self {
+        VarNames {
+            vars: HashMap::new(),
+            rows: HashMap::new(),
+        }
+    }
+
+    fn var(&mut self, id: usize) -> String {
+        let next = self.vars.len();
+        self.vars.entry(id).or_insert_with(|| letter_name(next)).clone()
+    }
+
+    fn row(&mut self, id: usize) -> String {
+        let next = self.rows.len();
+        self.rows.entry(id).or_insert_with(|| format!("..{}", letter_name(next))).clone()
+    }
+}
+
+fn letter_name(index: usize) -> String {
+    let letter = (b'a' + (index % 26) as u8) as char;
+    if index < 26 {
+        letter.to_string()
+    } else {
+        format!("{}{}", letter, index / 26)
+    }
+}
+
+/// Render a type in stack effect notation where applicable
+pub fn format_type(typ: &Type) -> String {
+    match typ {
+        Type::StackEffect(inputs, outputs) => {
+            let mut parts = vec!["(".to_string()];
+            parts.extend(inputs.iter().map(format_type));
+            parts.push("--".to_string());
+            parts.extend(outputs.iter().map(format_type));
+            parts.push(")".to_string());
+            parts.join(" ")
+        },
+        Type::Simple(name) => name.clone(),
+        Type::Linear(inner) => format!("!{}", format_type(inner)),
+        Type::Optional(inner) => format!("?{}", format_type(inner)),
+        Type::Generic(name, params) => {
+            let params: Vec<String> = params.iter().map(format_type).collect();
+            format!("{}[{}]", name, params.join(", "))
+        },
+        other => format!("{:?}", other),
+    }
+}
+
+/// Lookup used to resolve user-defined words while inferring
+pub type WordLookup<'a> = &'a dyn Fn(&str) -> Option<Value>;
+
+/// Infer the stack signature of a quotation without evaluating it
+pub fn infer_quotation(params: &[Param], body: &[Expr], lookup: WordLookup) -> Result<Signature> {
+    let mut inferencer = Inferencer::new(lookup);
+    let sig = inferencer.infer_body(params, body)?;
+    Ok(inferencer.resolve_sig(&sig))
+}
+
+// Inference state: substitutions for type variables and row variables
+struct Inferencer<'a> {
+    next_var: usize,
+    types: HashMap<usize, StackTy>,
+    rows: HashMap<usize, Row>,
+    lookup: WordLookup<'a>,
+    // Words currently being inferred, to reject recursive definitions
+    visiting: HashSet<String>,
+    // Parameter scopes, innermost last
+    scopes: Vec<HashMap<String, StackTy>>,
+}
+
+impl<'a> Inferencer<'a> {
+    fn new(lookup: WordLookup<'a>) -> Self {
+        Inferencer {
+            next_var: 0,
+            types: HashMap::new(),
+            rows: HashMap::new(),
+            lookup,
+            visiting: HashSet::new(),
+            scopes: Vec::new(),
+        }
+    }
+
+    fn fresh(&mut self) -> usize {
+        let id = self.next_var;
+        self.next_var += 1;
+        id
+    }
+
+    fn fresh_ty(&mut self) -> StackTy {
+        StackTy::Var(self.fresh())
+    }
+
+    fn fresh_row(&mut self) -> Row {
+        Row { var: self.fresh(), items: Vec::new() }
+    }
+
+    // Infer a quotation body, binding named parameters to the topmost inputs
+    fn infer_body(&mut self, params: &[Param], body: &[Expr]) -> Result<Signature> {
+        let start = self.fresh_row();
+        let mut inputs = start.clone();
+        let mut scope = HashMap::new();
+
+        for param in params {
+            let ty = match &param.type_annotation {
+                Some(annotation) => type_to_stack_ty(annotation),
+                None => self.fresh_ty(),
+            };
+            inputs.items.push(ty.clone());
+            scope.insert(param.name.clone(), ty);
+        }
+
+        self.scopes.push(scope);
+        let result = self.infer_sequence(body, start);
+        self.scopes.pop();
+
+        Ok(Signature { inputs, outputs: result? })
+    }
+
+    fn infer_sequence(&mut self, body: &[Expr], mut stack: Row) -> Result<Row> {
+        let mut i = 0;
+        while i < body.len() {
+            // Literal depths make pick and roll statically typeable
+            if let (Expr::Number(n), Some(Expr::Symbol(word))) = (&body[i], body.get(i + 1)) {
+                if (word == "pick" || word == "roll") && *n >= 0 {
+                    let sig = self.depth_word_sig(word, *n as usize);
+                    stack = self.apply(stack, sig)?;
+                    i += 2;
+                    continue;
+                }
+            }
+            stack = self.infer_expr(&body[i], stack)?;
+            i += 1;
+        }
+        Ok(stack)
+    }
+
+    fn infer_expr(&mut self, expr: &Expr, stack: Row) -> Result<Row> {
+        match expr {
+            Expr::Number(_) => Ok(self.push(stack, StackTy::Con("Num".to_string()))),
+            Expr::String(_) => Ok(self.push(stack, StackTy::Con("String".to_string()))),
+            Expr::Boolean(_) => Ok(self.push(stack, StackTy::Con("Bool".to_string()))),
+            Expr::Nil => Ok(self.push(stack, StackTy::Con("Nil".to_string()))),
+            Expr::Quotation(params, body) | Expr::TypedQuotation(params, body, _) => {
+                let sig = self.infer_body(params, body)?;
+                Ok(self.push(stack, StackTy::Quot(Box::new(sig))))
+            },
+            Expr::Symbol(name) => self.infer_word(name, stack),
+            Expr::Sequence(items) => self.infer_sequence(items, stack),
+            Expr::Pipeline(left, right) => {
+                let stack = self.infer_expr(left, stack)?;
+                let stack = self.infer_expr(right, stack)?;
+                // A quotation on the right of a pipeline is applied to the left value
+                if let Expr::Quotation(_, _) = right.as_ref() {
+                    self.infer_word("call", stack)
+                } else {
+                    Ok(stack)
+                }
+            },
+            Expr::If(cond, then_branch, else_branch) => {
+                let stack = self.infer_expr(cond, stack)?;
+                let condition = self.sig_from(&["Bool"], &[]);
+                let stack = self.apply(stack, condition)?;
+                let then_stack = self.infer_expr(then_branch, stack.clone())?;
+                let else_stack = self.infer_expr(else_branch, stack)?;
+                self.unify_rows(&then_stack, &else_stack).map_err(|e| EvaluatorError::TypeError(format!(
+                    "Branches of if have incompatible stack types: {}", e
+                )))?;
+                Ok(then_stack)
+            },
+            Expr::Dip(quotation) => {
+                let stack = self.infer_expr(quotation, stack)?;
+                self.infer_word("dip", stack)
+            },
+            Expr::Keep(quotation) => {
+                let stack = self.infer_expr(quotation, stack)?;
+                self.infer_word("keep", stack)
+            },
+            Expr::StackEffect(_) | Expr::EffectAnnotation(_) => Ok(stack),
+            _ => Err(EvaluatorError::TypeError(format!(
+                "Cannot infer stack type of expression: {:?}", expr
+            ))),
+        }
+    }
+
+    fn infer_word(&mut self, name: &str, stack: Row) -> Result<Row> {
+        // Named parameters push their bound type
+        for scope in self.scopes.iter().rev() {
+            if let Some(ty) = scope.get(name) {
+                let ty = ty.clone();
+                return Ok(self.push(stack, ty));
+            }
+        }
+
+        if let Some(sig) = self.builtin_sig(name) {
+            return self.apply(stack, sig);
+        }
+
+        // User-defined words are inferred from their definitions
+        match (self.lookup)(name) {
+            Some(Value::Quotation(params, body, _)) | Some(Value::TypedQuotation(params, body, _, _)) => {
+                if !self.visiting.insert(name.to_string()) {
+                    return Err(EvaluatorError::TypeError(format!(
+                        "Cannot infer stack type of recursive word '{}'", name
+                    )));
+                }
+                let saved_scopes = std::mem::take(&mut self.scopes);
+                let sig = self.infer_body(&params, &body);
+                self.scopes = saved_scopes;
+                self.visiting.remove(name);
+                self.apply(stack, sig?)
+            },
+            Some(Value::Symbol(op)) if op != name => self.infer_word(&op, stack),
+            Some(value) => {
+                let ty = value_to_stack_ty(&value);
+                Ok(self.push(stack, ty))
+            },
+            None => Err(EvaluatorError::TypeError(format!(
+                "Cannot infer stack type of unknown word '{}'", name
+            ))),
+        }
+    }
+
+    fn push(&mut self, mut stack: Row, ty: StackTy) -> Row {
+        stack.items.push(ty);
+        stack
+    }
+
+    // Compose the current stack with a word's signature
+    fn apply(&mut self, stack: Row, sig: Signature) -> Result<Row> {
+        self.unify_rows(&stack, &sig.inputs)?;
+        Ok(sig.outputs)
+    }
+
+    // Build a signature over a shared fresh row from simple item names.
+    // Capitalised names are concrete types, lowercase names are type variables.
+    fn sig_from(&mut self, inputs: &[&str], outputs: &[&str]) -> Signature {
+        let row = self.fresh_row();
+        let mut vars: HashMap<String, StackTy> = HashMap::new();
+        let mut item = |this: &mut Self, name: &str| -> StackTy {
+            if name.starts_with(|c: char| c.is_ascii_uppercase()) {
+                StackTy::Con(name.to_string())
+            } else {
+                vars.entry(name.to_string()).or_insert_with(|| this.fresh_ty()).clone()
+            }
+        };
+        let inputs: Vec<StackTy> = inputs.iter().map(|n| item(self, n)).collect();
+        let outputs: Vec<StackTy> = outputs.iter().map(|n| item(self, n)).collect();
+        Signature {
+            inputs: Row { var: row.var, items: inputs },
+            outputs: Row { var: row.var, items: outputs },
+        }
+    }
+
+    // Signature of a quotation ( ..from items -- ..to outputs )
+    fn quot(&self, from: usize, inputs: Vec<StackTy>, to: usize, outputs: Vec<StackTy>) -> StackTy {
+        StackTy::Quot(Box::new(Signature {
+            inputs: Row { var: from, items: inputs },
+            outputs: Row { var: to, items: outputs },
+        }))
+    }
+
+    fn builtin_sig(&mut self, name: &str) -> Option<Signature> {
+        let sig = match name {
+            // Core stack operations
+            "dup" => self.sig_from(&["a"], &["a", "a"]),
+            "drop" => self.sig_from(&["a"], &[]),
+            "swap" => self.sig_from(&["a", "b"], &["b", "a"]),
+            "rot" => self.sig_from(&["a", "b", "c"], &["b", "c", "a"]),
+            "over" => self.sig_from(&["a", "b"], &["a", "b", "a"]),
+            "tuck" => self.sig_from(&["a", "b"], &["b", "a", "b"]),
+            "nip" => self.sig_from(&["a", "b"], &["b"]),
+
+            // Arithmetic operations
+            "+" | "-" | "*" | "/" | "mod" | "add" | "sub" | "mul" | "div" => {
+                self.sig_from(&["Num", "Num"], &["Num"])
+            },
+            "sqrt" | "neg" | "abs" => self.sig_from(&["Num"], &["Num"]),
+
+            // Comparison and logical operations
+            "==" | "!=" | "eq" => self.sig_from(&["a", "a"], &["Bool"]),
+            "<" | ">" | "<=" | ">=" => self.sig_from(&["Num", "Num"], &["Bool"]),
+            "and" | "or" => self.sig_from(&["Bool", "Bool"], &["Bool"]),
+            "not" => self.sig_from(&["Bool"], &["Bool"]),
+
+            // Strings and output
+            "++" | "concat" => self.sig_from(&["String", "String"], &["String"]),
+            "print" => self.sig_from(&["a"], &[]),
+            "depth" => self.sig_from(&[], &["Num"]),
+
+            // Combinators are typed with explicit row variables
+            "call" | "apply" => {
+                // ( ..a ( ..a -- ..b ) -- ..b )
+                let (a, b) = (self.fresh(), self.fresh());
+                let q = self.quot(a, vec![], b, vec![]);
+                Signature {
+                    inputs: Row { var: a, items: vec![q] },
+                    outputs: Row { var: b, items: vec![] },
+                }
+            },
+            "dip" => {
+                // ( ..a x ( ..a -- ..b ) -- ..b x )
+                let (a, b) = (self.fresh(), self.fresh());
+                let x = self.fresh_ty();
+                let q = self.quot(a, vec![], b, vec![]);
+                Signature {
+                    inputs: Row { var: a, items: vec![x.clone(), q] },
+                    outputs: Row { var: b, items: vec![x] },
+                }
+            },
+            "keep" => {
+                // ( ..a x ( ..a x -- ..b ) -- ..b x )
+                let (a, b) = (self.fresh(), self.fresh());
+                let x = self.fresh_ty();
+                let q = self.quot(a, vec![x.clone()], b, vec![]);
+                Signature {
+                    inputs: Row { var: a, items: vec![x.clone(), q] },
+                    outputs: Row { var: b, items: vec![x] },
+                }
+            },
+            "bi" => {
+                // ( ..a x ( ..a x -- ..b ) ( ..b x -- ..c ) -- ..c )
+                let (a, b, c) = (self.fresh(), self.fresh(), self.fresh());
+                let x = self.fresh_ty();
+                let p = self.quot(a, vec![x.clone()], b, vec![]);
+                let q = self.quot(b, vec![x.clone()], c, vec![]);
+                Signature {
+                    inputs: Row { var: a, items: vec![x, p, q] },
+                    outputs: Row { var: c, items: vec![] },
+                }
+            },
+            "if" => {
+                // ( ..a Bool ( ..a -- ..b ) ( ..a -- ..b ) -- ..b )
+                let (a, b) = (self.fresh(), self.fresh());
+                let t = self.quot(a, vec![], b, vec![]);
+                let f = self.quot(a, vec![], b, vec![]);
+                Signature {
+                    inputs: Row { var: a, items: vec![StackTy::Con("Bool".to_string()), t, f] },
+                    outputs: Row { var: b, items: vec![] },
+                }
+            },
+            _ => return None,
+        };
+        Some(sig)
+    }
+
+    // n pick copies the item n deep; n roll moves it to the top
+    fn depth_word_sig(&mut self, word: &str, depth: usize) -> Signature {
+        let row = self.fresh_row();
+        let items: Vec<StackTy> = (0..=depth).map(|_| self.fresh_ty()).collect();
+        let target = items[0].clone();
+        let outputs = if word == "pick" {
+            let mut outputs = items.clone();
+            outputs.push(target);
+            outputs
+        } else {
+            let mut outputs = items[1..].to_vec();
+            outputs.push(target);
+            outputs
+        };
+        Signature {
+            inputs: Row { var: row.var, items },
+            outputs: Row { var: row.var, items: outputs },
+        }
+    }
+
+    // Follow type variable substitutions
+    fn resolve_ty(&self, ty: &StackTy) -> StackTy {
+        match ty {
+            StackTy::Var(id) => match self.types.get(id) {
+                Some(bound) => self.resolve_ty(bound),
+                None => ty.clone(),
+            },
+            StackTy::Quot(sig) => StackTy::Quot(Box::new(self.resolve_sig(sig))),
+            StackTy::Con(_) => ty.clone(),
+        }
+    }
+
+    // Expand row variable substitutions so the row variable is unbound
+    fn resolve_row(&self, row: &Row) -> Row {
+        match self.rows.get(&row.var) {
+            Some(bound) => {
+                let mut expanded = self.resolve_row(bound);
+                expanded.items.extend(row.items.iter().cloned());
+                expanded
+            },
+            None => row.clone(),
+        }
+    }
+
+    fn resolve_sig(&self, sig: &Signature) -> Signature {
+        let resolve = |row: &Row| {
+            let row = self.resolve_row(row);
+            Row {
+                var: row.var,
+                items: row.items.iter().map(|t| self.resolve_ty(t)).collect(),
+            }
+        };
+        Signature {
+            inputs: resolve(&sig.inputs),
+            outputs: resolve(&sig.outputs),
+        }
+    }
+
+    fn unify(&mut self, a: &StackTy, b: &StackTy) -> Result<()> {
+        let a = self.resolve_ty(a);
+        let b = self.resolve_ty(b);
+        match (&a, &b) {
+            _ if a == b => Ok(()),
+            (StackTy::Var(id), other) | (other, StackTy::Var(id)) => {
+                if self.ty_mentions(other, *id) {
+                    return Err(EvaluatorError::TypeError(
+                        "Cannot construct an infinite type".to_string()
+                    ));
+                }
+                self.types.insert(*id, other.clone());
+                Ok(())
+            },
+            (StackTy::Quot(sa), StackTy::Quot(sb)) => {
+                self.unify_rows(&sa.inputs, &sb.inputs)?;
+                self.unify_rows(&sa.outputs, &sb.outputs)
+            },
+            _ => Err(EvaluatorError::TypeError(format!(
+                "Type mismatch: expected {} but found {}",
+                self.describe(&b), self.describe(&a)
+            ))),
+        }
+    }
+
+    fn unify_rows(&mut self, a: &Row, b: &Row) -> Result<()> {
+        let mut a = self.resolve_row(a);
+        let mut b = self.resolve_row(b);
+
+        // Match items from the top of the stack downwards
+        while let (Some(ta), Some(tb)) = (a.items.last().cloned(), b.items.last().cloned()) {
+            self.unify(&ta, &tb)?;
+            a.items.pop();
+            b.items.pop();
+        }
+
+        match (a.items.is_empty(), b.items.is_empty()) {
+            (true, true) => {
+                if a.var != b.var {
+                    self.rows.insert(a.var, Row { var: b.var, items: Vec::new() });
+                }
+                Ok(())
+            },
+            (true, false) => self.bind_row(a.var, b),
+            (false, true) => self.bind_row(b.var, a),
+            (false, false) => unreachable!("unify_rows consumed all common items"),
+        }
+    }
+
+    fn bind_row(&mut self, var: usize, row: Row) -> Result<()> {
+        if row.var == var {
+            return Err(EvaluatorError::TypeError(
+                "Stack imbalance: a stack cannot equal itself with extra items".to_string()
+            ));
+        }
+        self.rows.insert(var, row);
+        Ok(())
+    }
+
+    fn ty_mentions(&self, ty: &StackTy, id: usize) -> bool {
+        match self.resolve_ty(ty) {
+            StackTy::Var(other) => other == id,
+            StackTy::Quot(sig) => sig.inputs.items.iter()
+                .chain(sig.outputs.items.iter())
+                .any(|t| self.ty_mentions(t, id)),
+            StackTy::Con(_) => false,
+        }
+    }
+
+    fn describe(&self, ty: &StackTy) -> String {
+        match ty {
+            StackTy::Quot(sig) => format!("{}", self.resolve_sig(sig)),
+            other => format_type(&other.to_type_with(&mut VarNames::new())),
+        }
+    }
+}
+
+// Map an annotated parameter type into the inference engine
+fn type_to_stack_ty(typ: &Type) -> StackTy {
+    match typ {
+        Type::Simple(name) => StackTy::Con(name.clone()),
+        other => StackTy::Con(format_type(other)),
+    }
+}
+
+// Type of a non-quotation value bound in the environment
+fn value_to_stack_ty(value: &Value) -> StackTy {
+    let name = match value {
+        Value::Number(_) => "Num",
+        Value::String(_) => "String",
+        Value::Symbol(_) => "Symbol",
+        Value::List(_) => "List",
+        Value::Map(_) => "Map",
+        Value::Nil => "Nil",
+        Value::Nothing => "Nothing",
+        Value::Schema(_) => "Schema",
+        Value::ACSet(_) => "ACSet",
+        Value::SchemaFunctor(_) => "SchemaFunctor",
+        Value::WiringDiagram(_) => "WiringDiagram",
+        Value::Cell(_) => "Cell",
+        Value::Net(_) => "Net",
+        Value::RewriteSystem(_) => "RewriteSystem",
+        _ => "Any",
+    };
+    StackTy::Con(name.to_string())
+}
@@ -19,6 +19,28 @@ pub enum EvaluatorError {
 
     #[error("Type error: {0}")]
     TypeError(String),
+
+    #[error("Permission denied: {0}")]
+    PermissionDenied(String),
+
+    // Raised by the `exit` word; unwinds to the embedder, which decides what exiting means
+    #[error("Exit with status {0}")]
+    Exit(i32),
+}
+
+impl EvaluatorError {
+    /// The error as a Borf value, e.g. PermissionDenied("read access to ..."), for `try` handlers
+    pub fn to_value(&self) -> Value {
+        let (kind, message) = match self {
+            EvaluatorError::FileError(err) => ("FileError", err.to_string()),
+            EvaluatorError::ParseError(message) => ("ParseError", message.clone()),
+            EvaluatorError::EvalError(message) => ("EvalError", message.clone()),
+            EvaluatorError::TypeError(message) => ("TypeError", message.clone()),
+            EvaluatorError::PermissionDenied(message) => ("PermissionDenied", message.clone()),
+            EvaluatorError::Exit(status) => ("Exit", status.to_string()),
+        };
+        Value::Variant(kind.to_string(), vec![Value::String(message)])
+    }
 }
 
 pub type Result<T> = std::result::Result<T, EvaluatorError>;
@@ -53,6 +75,8 @@ pub enum Expr {
     Tuple(Vec<Expr>),                     // Tuple literal
     If(Box<Expr>, Box<Expr>, Box<Expr>),  // Condition, true branch, false branch
     StackEffect(crate::repl::interpreter::stack_effects::StackEffect), // Stack effect declaration
+    EffectAnnotation(crate::repl::interpreter::effects::EffectType), // Resource effect annotation !creates[File]
+    Literal(Box<Value>),                  // Value embedded in code, e.g. by curry
     
     // Loop constructs borrowed from Factor, Forth, and Joy
     Times(Box<Expr>, Box<Expr>),          // Repeat n times: 5 [code] times
@@ -107,6 +131,7 @@ pub enum Type {
     Record(HashMap<String, Type>),       // Record types like { x: Num, y: String }
     Variant(HashMap<String, Vec<Type>>), // Variant types like { tag: val }
     Function(Vec<Type>, Box<Type>),      // Function types (a,b) => c
+    StackEffect(Vec<Type>, Vec<Type>),   // Stack types ( a b -- c ) inferred for quotations
 }
 
 // Pattern for match expressions
@@ -142,10 +167,39 @@ pub enum Value {
     BorrowedResource(usize, Box<Value>),    // Borrowed resource that can't be consumed
     Optional(Option<Box<Value>>),           // Optional value ?value (value or Nothing)
     Variant(String, Vec<Value>),            // Variant like tag(val)
+    Schema(std::rc::Rc<crate::repl::interpreter::acset::Schema>), // ACSet schema, shared by its ACSets
+    ACSet(Box<crate::repl::interpreter::acset::ACSet>), // Attributed C-set
+    SchemaFunctor(std::rc::Rc<crate::repl::interpreter::migration::SchemaFunctor>), // Functor between schemas
+    WiringDiagram(Box<crate::repl::interpreter::wiring::WiringDiagram>), // Boxes and typed wires
+    Cell(crate::repl::interpreter::inet::Cell),              // Interaction net agent and arity
+    Net(Box<crate::repl::interpreter::inet::Net>),           // Interaction net
+    RewriteSystem(std::rc::Rc<crate::repl::interpreter::inet::RewriteSystem>), // Interaction rules
     Nothing,                                // Represents "Nothing" value
     Nil,                                    // For internal use
 }
 
+// Where an expression starts in its source
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+// Where the expressions of a parsed program start
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SourceMap {
+    // One span per top-level expression
+    pub program: Vec<Span>,
+    // One span per expression in the body of each word the program defines
+    pub words: HashMap<String, Vec<Span>>,
+}
+
 // Environment to store bound values
 #[derive(Debug, Clone, PartialEq)]
 pub struct Env {
@@ -160,8 +214,11 @@ impl fmt::Display for Value {
             Value::Number(n) => write!(f, "{}", n),
             Value::String(s) => write!(f, "\"{}\"", s),
             Value::Symbol(s) => write!(f, "{}", s),
-            Value::Quotation(_, _, _) => write!(f, "[...]"),
-            Value::TypedQuotation(_, _, _, _) => write!(f, "[...] : Type"),
+            Value::Quotation(params, body, _) => write_quotation(f, params, body),
+            Value::TypedQuotation(params, body, _, _) => {
+                write_quotation(f, params, body)?;
+                write!(f, " : Type")
+            },
             Value::Pipeline(_, _) => write!(f, "pipeline"),
             Value::List(items) => {
                 write!(f, "[")?;
@@ -198,11 +255,86 @@ impl fmt::Display for Value {
                 }
                 Ok(())
             }
+            Value::Schema(schema) => write!(f, "{}", schema),
+            Value::ACSet(acset) => write!(f, "{}", acset),
+            Value::SchemaFunctor(functor) => write!(f, "{}", functor),
+            Value::WiringDiagram(diagram) => write!(f, "{}", diagram),
+            Value::Cell(cell) => write!(f, "{}", cell),
+            Value::Net(net) => write!(f, "{}", net),
+            Value::RewriteSystem(system) => write!(f, "{}", system),
             Value::Nil => write!(f, "nil"),
         }
     }
 }
 
+// Render a string literal with the escapes the parser reads back
+fn write_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+// Render a quotation as source code: [x y -> x y +]
+fn write_quotation(f: &mut fmt::Formatter<'_>, params: &[Param], body: &[Expr]) -> fmt::Result {
+    write!(f, "[")?;
+    if !params.is_empty() {
+        let names: Vec<String> = params.iter().map(|p| p.to_string()).collect();
+        write!(f, "{} -> ", names.join(" "))?;
+    }
+    let words: Vec<String> = body.iter().map(|e| e.to_string()).collect();
+    write!(f, "{}]", words.join(" "))
+}
+
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.type_annotation {
+            Some(typ) => write!(f, "{} <: {}", self.name, crate::repl::interpreter::stack_types::format_type(typ)),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+// Expressions are displayed as Borf source code where there is a direct syntax for them
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Number(n) => write!(f, "{}", n),
+            Expr::String(s) => write_string(f, s),
+            Expr::Boolean(b) => write!(f, "{}", b),
+            Expr::Nil => write!(f, "nil"),
+            Expr::Symbol(s) => write!(f, "{}", s),
+            Expr::Quotation(params, body) => write_quotation(f, params, body),
+            Expr::TypedQuotation(params, body, _) => write_quotation(f, params, body),
+            Expr::Pipeline(left, right) => write!(f, "{} |> {}", left, right),
+            Expr::Assignment(value, name) => write!(f, "{} : {}", value, name),
+            Expr::EffectAnnotation(effect) => write!(f, "{}", effect),
+            Expr::Sequence(exprs) => {
+                let words: Vec<String> = exprs.iter().map(|e| e.to_string()).collect();
+                write!(f, "{}", words.join(" "))
+            },
+            Expr::Tuple(items) => {
+                let items: Vec<String> = items.iter().map(|e| e.to_string()).collect();
+                write!(f, "({})", items.join(", "))
+            },
+            Expr::Quote(inner) => write!(f, "'{}", inner),
+            Expr::Unquote(inner) => write!(f, "${}", inner),
+            Expr::Quasiquote(inner) => write!(f, "`{}", inner),
+            Expr::StackEffect(effect) => write!(f, "{}", effect),
+            Expr::Literal(value) => write!(f, "{}", value),
+            other => write!(f, "{:?}", other),
+        }
+    }
+}
+
 // Extension methods for Value
 impl Value {
     // Check if the value is empty (for backward compatibility)
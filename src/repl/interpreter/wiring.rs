@@ -0,0 +1,277 @@
+// src/repl/interpreter/wiring.rs
+// Wiring diagrams, after Catlab.jl and the sketch in catlab.borf. A diagram has typed
+// input and output ports on its outer box and contains boxes with typed ports of their
+// own; wires run from a diagram input or a box output to a box input or a diagram
+// output. Boxes are numbered from 1, and box 0 stands for the outer box, so a wire
+// from (0, p) starts at the diagram's input p and one to (0, p) ends at its output p.
+
+use std::fmt;
+use crate::repl::interpreter::types::{EvaluatorError, Result, Value};
+
+/// The box number of the diagram's own boundary
+pub const OUTER_BOX: usize = 0;
+
+/// A box with typed ports
+#[derive(Debug, Clone, PartialEq)]
+pub struct WiringBox {
+    pub name: String,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+}
+
+/// A wire from a (box, output port) to a (box, input port)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Wire {
+    pub source: (usize, usize),
+    pub target: (usize, usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct WiringDiagram {
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    pub boxes: Vec<WiringBox>,
+    pub wires: Vec<Wire>,
+}
+
+fn wiring_error(message: String) -> EvaluatorError {
+    EvaluatorError::TypeError(message)
+}
+
+/// Port types match when they are the same or either is Any
+pub fn types_match(a: &str, b: &str) -> bool {
+    a == b || a == "Any" || b == "Any"
+}
+
+fn check_boundary(left: &[String], right: &[String], what: &str) -> Result<()> {
+    let matches = left.len() == right.len() && left.iter().zip(right).all(|(a, b)| types_match(a, b));
+    if matches {
+        Ok(())
+    } else {
+        Err(wiring_error(format!("{}: ({}) does not match ({})", what, left.join(", "), right.join(", "))))
+    }
+}
+
+impl WiringDiagram {
+    /// A diagram with the given interface and nothing inside
+    pub fn new(inputs: Vec<String>, outputs: Vec<String>) -> Self {
+        WiringDiagram { inputs, outputs, boxes: Vec::new(), wires: Vec::new() }
+    }
+
+    /// Add a box and return its number
+    pub fn add_box(&mut self, name: &str, inputs: Vec<String>, outputs: Vec<String>) -> usize {
+        self.boxes.push(WiringBox { name: name.to_string(), inputs, outputs });
+        self.boxes.len()
+    }
+
+    pub fn get_box(&self, number: usize) -> Result<&WiringBox> {
+        match number {
+            OUTER_BOX => Err(wiring_error("Box 0 is the outer box".to_string())),
+            _ => self.boxes.get(number - 1).ok_or_else(|| wiring_error(format!("The diagram has no box {}", number))),
+        }
+    }
+
+    // The type of the port a wire can start at: a diagram input or a box output
+    fn source_type(&self, (number, port): (usize, usize)) -> Result<&str> {
+        let ports = if number == OUTER_BOX { &self.inputs } else { &self.get_box(number)?.outputs };
+        ports.get(port)
+            .map(|port_type| port_type.as_str())
+            .ok_or_else(|| wiring_error(format!("Box {} has no output port {}", number, port)))
+    }
+
+    // The type of the port a wire can end at: a box input or a diagram output
+    fn target_type(&self, (number, port): (usize, usize)) -> Result<&str> {
+        let ports = if number == OUTER_BOX { &self.outputs } else { &self.get_box(number)?.inputs };
+        ports.get(port)
+            .map(|port_type| port_type.as_str())
+            .ok_or_else(|| wiring_error(format!("Box {} has no input port {}", number, port)))
+    }
+
+    /// Add a wire, checking that its ports exist and have matching types
+    pub fn add_wire(&mut self, source: (usize, usize), target: (usize, usize)) -> Result<()> {
+        let (source_type, target_type) = (self.source_type(source)?, self.target_type(target)?);
+        if !types_match(source_type, target_type) {
+            return Err(wiring_error(format!(
+                "Cannot wire a {} port {:?} to a {} port {:?}", source_type, source, target_type, target
+            )));
+        }
+        self.wires.push(Wire { source, target });
+        Ok(())
+    }
+
+    /// Check every wire against the ports it connects
+    pub fn validate(&self) -> Result<()> {
+        let mut checked = WiringDiagram { wires: Vec::new(), ..self.clone() };
+        for wire in &self.wires {
+            checked.add_wire(wire.source, wire.target)?;
+        }
+        Ok(())
+    }
+
+    /// Sequential composition: self's outputs feed other's inputs
+    pub fn compose(&self, other: &WiringDiagram) -> Result<WiringDiagram> {
+        check_boundary(&self.outputs, &other.inputs, "Cannot compose diagrams")?;
+        let offset = self.boxes.len();
+        let shift = |(number, port): (usize, usize)| (number + offset, port);
+        let mut composed = WiringDiagram::new(self.inputs.clone(), other.outputs.clone());
+        composed.boxes = self.boxes.iter().chain(&other.boxes).cloned().collect();
+        for wire in &self.wires {
+            if wire.target.0 != OUTER_BOX {
+                composed.wires.push(*wire);
+            }
+        }
+        for wire in &other.wires {
+            if wire.source.0 != OUTER_BOX {
+                let target = if wire.target.0 == OUTER_BOX { wire.target } else { shift(wire.target) };
+                composed.wires.push(Wire { source: shift(wire.source), target });
+            }
+        }
+        // A wire to one of self's outputs joins each wire from the matching input of other
+        for first in self.wires.iter().filter(|wire| wire.target.0 == OUTER_BOX) {
+            for second in other.wires.iter().filter(|wire| wire.source == first.target) {
+                let target = if second.target.0 == OUTER_BOX { second.target } else { shift(second.target) };
+                composed.wires.push(Wire { source: first.source, target });
+            }
+        }
+        Ok(composed)
+    }
+
+    /// Parallel composition: self above other, with other's ports after self's
+    pub fn tensor(&self, other: &WiringDiagram) -> WiringDiagram {
+        let (boxes, inputs, outputs) = (self.boxes.len(), self.inputs.len(), self.outputs.len());
+        let mut tensored = WiringDiagram::new(
+            self.inputs.iter().chain(&other.inputs).cloned().collect(),
+            self.outputs.iter().chain(&other.outputs).cloned().collect(),
+        );
+        tensored.boxes = self.boxes.iter().chain(&other.boxes).cloned().collect();
+        tensored.wires = self.wires.clone();
+        for wire in &other.wires {
+            let (source, target) = (wire.source, wire.target);
+            let source = if source.0 == OUTER_BOX { (OUTER_BOX, source.1 + inputs) } else { (source.0 + boxes, source.1) };
+            let target = if target.0 == OUTER_BOX { (OUTER_BOX, target.1 + outputs) } else { (target.0 + boxes, target.1) };
+            tensored.wires.push(Wire { source, target });
+        }
+        tensored
+    }
+
+    /// Replace a box by a diagram with the same interface. The boxes after it move
+    /// down by one and the diagram's boxes follow the rest.
+    pub fn substitute(&self, number: usize, inner: &WiringDiagram) -> Result<WiringDiagram> {
+        let replaced = self.get_box(number)?;
+        check_boundary(&replaced.inputs, &inner.inputs, &format!("Cannot substitute into box {} (inputs)", number))?;
+        check_boundary(&replaced.outputs, &inner.outputs, &format!("Cannot substitute into box {} (outputs)", number))?;
+        if self.wires.iter().any(|wire| wire.source.0 == number && wire.target.0 == number) {
+            return Err(wiring_error(format!("Cannot substitute into box {}, which is wired to itself", number)));
+        }
+
+        let offset = self.boxes.len() - 1;
+        let renumber = |(box_number, port): (usize, usize)| match box_number {
+            OUTER_BOX => (OUTER_BOX, port),
+            n if n > number => (n - 1, port),
+            n => (n, port),
+        };
+        let mut substituted = WiringDiagram::new(self.inputs.clone(), self.outputs.clone());
+        substituted.boxes = self.boxes.iter()
+            .enumerate()
+            .filter(|(i, _)| i + 1 != number)
+            .map(|(_, wiring_box)| wiring_box.clone())
+            .chain(inner.boxes.iter().cloned())
+            .collect();
+        for wire in &self.wires {
+            if wire.source.0 != number && wire.target.0 != number {
+                substituted.wires.push(Wire { source: renumber(wire.source), target: renumber(wire.target) });
+            }
+        }
+
+        // An inner wire from the inner diagram's input p starts where each wire into the
+        // box's input p does, and one to its output q ends where each wire from output q does
+        for wire in &inner.wires {
+            let sources: Vec<(usize, usize)> = if wire.source.0 == OUTER_BOX {
+                self.wires.iter().filter(|outer| outer.target == (number, wire.source.1)).map(|outer| renumber(outer.source)).collect()
+            } else {
+                vec![(wire.source.0 + offset, wire.source.1)]
+            };
+            let targets: Vec<(usize, usize)> = if wire.target.0 == OUTER_BOX {
+                self.wires.iter().filter(|outer| outer.source == (number, wire.target.1)).map(|outer| renumber(outer.target)).collect()
+            } else {
+                vec![(wire.target.0 + offset, wire.target.1)]
+            };
+            for &source in &sources {
+                for &target in &targets {
+                    substituted.wires.push(Wire { source, target });
+                }
+            }
+        }
+        Ok(substituted)
+    }
+
+    /// The diagram in Graphviz DOT: boxes are records with a field per port
+    pub fn to_dot(&self) -> String {
+        let ports = |prefix: &str, types: &[String]| types.iter()
+            .enumerate()
+            .map(|(i, port_type)| format!("<{}{}> {}", prefix, i, escape(port_type)))
+            .collect::<Vec<_>>()
+            .join("|");
+        let mut dot = String::from("digraph {\n  rankdir=LR;\n  node [shape=record];\n");
+        for (i, port_type) in self.inputs.iter().enumerate() {
+            dot.push_str(&format!("  in{} [shape=plaintext, label=\"{}\"];\n", i, escape(port_type)));
+        }
+        for (i, port_type) in self.outputs.iter().enumerate() {
+            dot.push_str(&format!("  out{} [shape=plaintext, label=\"{}\"];\n", i, escape(port_type)));
+        }
+        for (i, wiring_box) in self.boxes.iter().enumerate() {
+            dot.push_str(&format!(
+                "  box{} [label=\"{{{{{}}}|{}|{{{}}}}}\"];\n",
+                i + 1, ports("i", &wiring_box.inputs), escape(&wiring_box.name), ports("o", &wiring_box.outputs)
+            ));
+        }
+        for wire in &self.wires {
+            let source = match wire.source {
+                (OUTER_BOX, port) => format!("in{}", port),
+                (number, port) => format!("box{}:o{}", number, port),
+            };
+            let target = match wire.target {
+                (OUTER_BOX, port) => format!("out{}", port),
+                (number, port) => format!("box{}:i{}", number, port),
+            };
+            dot.push_str(&format!("  {} -> {};\n", source, target));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+// Escape what DOT record labels treat specially
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if "{}|<>\"\\".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// The interface and size: WiringDiagram(Int, Int -> Int; 2 boxes, 3 wires)
+impl fmt::Display for WiringDiagram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f, "WiringDiagram({} -> {}; {} boxes, {} wires)",
+            self.inputs.join(", "), self.outputs.join(", "), self.boxes.len(), self.wires.len()
+        )
+    }
+}
+
+/// Port types from a Borf list of type names
+pub fn port_types(value: Value) -> Result<Vec<String>> {
+    match value {
+        Value::List(items) => items.into_iter()
+            .map(|item| match item {
+                Value::String(name) | Value::Symbol(name) => Ok(name),
+                other => Err(wiring_error(format!("Port types must be type names, got {}", other))),
+            })
+            .collect(),
+        other => Err(wiring_error(format!("Ports must be a list of type names, got {}", other))),
+    }
+}
@@ -17,7 +17,7 @@ use rustyline::{CompletionType, Config, EditMode, Editor};
 use rustyline::history::{DefaultHistory, History};
 use rustyline_derive::Helper;
 
-use crate::repl::interpreter::{Evaluator, Result, EvaluatorError};
+use crate::repl::interpreter::{Capabilities, Evaluator, Result, EvaluatorError, Strategy};
 
 // Add FromError implementation for ReadlineError
 impl From<ReadlineError> for EvaluatorError {
@@ -144,8 +144,10 @@ impl Repl {
         }
 
         // Create evaluator and initialize it
+        // The REPL is run by the person at the keyboard, so it may do anything they can
         let mut evaluator = Evaluator::new();
         evaluator.initialize()?;
+        evaluator.capabilities = Capabilities::all();
 
         Ok(Repl {
             editor,
@@ -160,6 +162,8 @@ impl Repl {
         println!("{}", "Borf REPL v0.1.0".bold().blue());
         println!("Type {} to exit, {} for help", ":quit".yellow(), ":help".yellow());
 
+        // The status given to `exit`, which ends the REPL like it ends a script
+        let mut exit_status = None;
         loop {
             let prompt = if self.in_multiline {
                 "...> ".green()
@@ -173,6 +177,7 @@ impl Repl {
                     if !self.in_multiline && line.trim().starts_with(':') {
                         match line.trim() {
                             ":quit" | ":q" => {
+                                self.report_leaks();
                                 println!("Goodbye!");
                                 break;
                             }
@@ -190,12 +195,76 @@ impl Repl {
                             }
                             cmd if cmd.starts_with(":load ") => {
                                 if let Some(filename) = cmd.split_whitespace().nth(1) {
-                                    self.load_file(filename)?;
+                                    match self.load_file(filename) {
+                                        Err(EvaluatorError::Exit(status)) => {
+                                            exit_status = Some(status);
+                                            break;
+                                        }
+                                        result => result?,
+                                    }
                                 } else {
                                     println!("{}", "Error: Expected filename after :load".red());
                                 }
                                 continue;
                             }
+                            ":checked" => {
+                                let checked = !self.evaluator.checked;
+                                self.set_checked(checked);
+                                println!("Checked mode {}", if checked { "on" } else { "off" });
+                                continue;
+                            }
+                            ":stacker" => {
+                                let strategy = match self.evaluator.strategy {
+                                    Strategy::Named => Strategy::Stacker,
+                                    Strategy::Stacker => Strategy::Named,
+                                };
+                                self.set_strategy(strategy);
+                                println!("STACKER translation {}", if strategy == Strategy::Stacker { "on" } else { "off" });
+                                continue;
+                            }
+                            cmd if cmd.starts_with(":optimize ") => {
+                                let expr = cmd[":optimize ".len()..].trim();
+                                match self.evaluator.optimize(expr) {
+                                    Ok(code) => println!("{}", code.cyan()),
+                                    Err(err) => println!("{}", format!("Error: {}", err).red()),
+                                }
+                                continue;
+                            }
+                            cmd if cmd.starts_with(":dot ") => {
+                                let expr = cmd[":dot ".len()..].trim();
+                                match self.evaluator.dot(expr) {
+                                    Ok(dot) => print!("{}", dot),
+                                    Err(err) => println!("{}", format!("Error: {}", err).red()),
+                                }
+                                continue;
+                            }
+                            ":effects" => {
+                                self.show_effects();
+                                continue;
+                            }
+                            ":rules" => {
+                                for rule in self.evaluator.optimizer.rules() {
+                                    println!("{}", rule);
+                                }
+                                continue;
+                            }
+                            cmd if cmd.starts_with(":expand ") => {
+                                let expr = cmd[":expand ".len()..].trim();
+                                match self.evaluator.expand(expr) {
+                                    Ok(code) => println!("{}", code.cyan()),
+                                    Err(err) => println!("{}", format!("Error: {}", err).red()),
+                                }
+                                continue;
+                            }
+                            cmd if cmd.starts_with(":type ") => {
+                                let expr = cmd[":type ".len()..].trim();
+                                if expr.is_empty() {
+                                    println!("{}", "Error: Expected expression after :type".red());
+                                } else {
+                                    self.show_type(expr);
+                                }
+                                continue;
+                            }
                             cmd if cmd.starts_with(":save ") => {
                                 if let Some(filename) = cmd.split_whitespace().nth(1) {
                                     self.save_history(filename)?;
@@ -241,10 +310,13 @@ impl Repl {
                         self.editor.add_history_entry(&input_to_eval)?;
                         
                         // Evaluate the multiline input
-                        self.evaluate_and_print(&input_to_eval);
+                        exit_status = self.evaluate_and_print(&input_to_eval);
                         
                         // Clear the multiline buffer for next time
                         self.multiline_input.clear();
+                        if exit_status.is_some() {
+                            break;
+                        }
                         continue;
                     }
 
@@ -255,7 +327,10 @@ impl Repl {
 
                     // Add to history and evaluate normal input
                     self.editor.add_history_entry(&line)?;
-                    self.evaluate_and_print(&line);
+                    exit_status = self.evaluate_and_print(&line);
+                    if exit_status.is_some() {
+                        break;
+                    }
                 }
                 Err(ReadlineError::Interrupted) => {
                     // Ctrl-C pressed, cancel current input
@@ -269,6 +344,7 @@ impl Repl {
                 }
                 Err(ReadlineError::Eof) => {
                     // Ctrl-D pressed, exit REPL
+                    self.report_leaks();
                     println!("Goodbye!");
                     break;
                 }
@@ -284,10 +360,14 @@ impl Repl {
             eprintln!("Error saving history: {}", err);
         }
 
+        if let Some(status) = exit_status {
+            std::process::exit(status);
+        }
         Ok(())
     }
 
-    fn evaluate_and_print(&mut self, input: &str) {
+    // Evaluate input and print the result or error; the status if it called `exit`
+    fn evaluate_and_print(&mut self, input: &str) -> Option<i32> {
         // Measure evaluation time
         let start = std::time::Instant::now();
 
@@ -302,10 +382,34 @@ impl Repl {
                     println!("{}", format!("Executed in {:.2?}", duration).bright_black());
                 }
             }
+            Err(EvaluatorError::Exit(status)) => return Some(status),
             Err(err) => {
                 println!("{}", format!("Error: {}", err).red());
             }
         }
+        None
+    }
+
+    // Check word calls against their declared stack effects
+    pub fn set_checked(&mut self, checked: bool) {
+        self.evaluator.checked = checked;
+    }
+    
+    // Restrict what I/O words may access
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        self.evaluator.capabilities = capabilities;
+    }
+
+    // Choose how quotations with named parameters are applied
+    pub fn set_strategy(&mut self, strategy: Strategy) {
+        self.evaluator.strategy = strategy;
+    }
+
+    fn show_type(&self, input: &str) {
+        match self.evaluator.type_of(input) {
+            Ok(typ) => println!("{} : {}", input, typ.cyan()),
+            Err(err) => println!("{}", format!("Error: {}", err).red()),
+        }
     }
 
     fn show_help(&self) {
@@ -317,6 +421,14 @@ impl Repl {
         println!("  {:15} - Show command history", ":history".yellow());
         println!("  {:15} - Load and execute a file", ":load <filename>".yellow());
         println!("  {:15} - Save command history to file", ":save <filename>".yellow());
+        println!("  {:15} - Show the inferred stack type", ":type <expr>".yellow());
+        println!("  {:15} - Toggle runtime stack effect checks", ":checked".yellow());
+        println!("  {:15} - Show the stack translation", ":expand <quot>".yellow());
+        println!("  {:15} - Toggle running quotations as stack code", ":stacker".yellow());
+        println!("  {:15} - Show stack code after peephole rules", ":optimize <quot>".yellow());
+        println!("  {:15} - List the peephole rules", ":rules".yellow());
+        println!("  {:15} - Show the dataflow as Graphviz DOT", ":dot <quot>".yellow());
+        println!("  {:15} - Show declared and observed resource effects", ":effects".yellow());
         println!("\nMultiline Input:");
         println!("  End a line with {} or type {} alone to start multiline mode", "\\".yellow(), "\\".yellow());
         println!("  Press {} to submit multiline input", "Enter".yellow());
@@ -344,6 +456,10 @@ impl Repl {
         println!("  #Type               => Quote a type");
     }
 
+    fn show_effects(&self) {
+        println!("{}", self.evaluator.format_effects());
+    }
+
     fn show_history(&self) {
         let history = self.editor.history();
         if history.len() == 0 {
@@ -357,9 +473,18 @@ impl Repl {
         }
     }
 
+    // Warn about resources created during the session that were never consumed
+    fn report_leaks(&self) {
+        if let Err(err) = self.evaluator.check_for_resource_leaks() {
+            println!("{}", err.to_string().yellow());
+        }
+    }
+
     fn load_file(&mut self, filename: &str) -> Result<()> {
         println!("Loading file: {}", filename);
         match self.evaluator.eval_file(filename) {
+            // `exit` ends the REPL rather than failing the load
+            Err(err @ EvaluatorError::Exit(_)) => Err(err),
             Ok(result) => {
                 if !result.is_empty() {
                     println!("{}", result.green());
@@ -369,6 +494,8 @@ impl Repl {
             }
             Err(err) => {
                 println!("{}", format!("Error loading file: {}", err).red());
+                // Whatever the file created before failing is left behind
+                self.report_leaks();
                 Err(err)
             }
         }
@@ -0,0 +1,109 @@
+// Tests for ACSets: schemas, incidence indices and part removal
+
+mod common;
+
+use borf_lib::repl::interpreter::{get_word_effect, Evaluator, Schema, Value};
+use common::{graph, hom};
+
+const GRAPH_SCHEMA: &str = "(\"V\" \"E\") ((\"src\" \"E\" \"V\") (\"tgt\" \"E\" \"V\")) (\"Name\") ((\"name\" \"V\" \"Name\")) schema";
+
+fn evaluator() -> Evaluator {
+    let mut evaluator = Evaluator::new();
+    evaluator.initialize().unwrap();
+    evaluator
+}
+
+fn numbers(values: &[i32]) -> Value {
+    Value::List(values.iter().map(|&n| Value::Number(n)).collect())
+}
+
+#[test]
+fn test_schema_rejects_homs_outside_it() {
+    let error = Schema::new(vec!["V".to_string()], vec![hom("src", "E", "V")], vec![], vec![]).unwrap_err();
+    assert!(error.to_string().contains("src: E -> V"), "{}", error);
+    let error = Schema::new(vec!["V".to_string(), "V".to_string()], vec![], vec![], vec![]).unwrap_err();
+    assert!(error.to_string().contains("twice"), "{}", error);
+}
+
+#[test]
+fn test_incident_follows_set_subpart() {
+    let mut graph = graph(3, &[(0, 1), (0, 2), (2, 1)]);
+    assert_eq!(graph.incident(0, "src").unwrap(), &[0, 1]);
+    assert_eq!(graph.incident(1, "tgt").unwrap(), &[0, 2]);
+
+    // Moving an edge moves it between index entries
+    graph.set_subpart(1, "src", 2).unwrap();
+    assert_eq!(graph.incident(0, "src").unwrap(), &[0]);
+    assert_eq!(graph.incident(2, "src").unwrap(), &[1, 2]);
+    assert_eq!(graph.subpart(1, "src").unwrap(), Some(2));
+}
+
+#[test]
+fn test_rem_part_renumbers_the_last_part() {
+    let mut graph = graph(3, &[(0, 2), (2, 2), (1, 0)]);
+    graph.rem_part("V", 0).unwrap();
+
+    // Vertex 2 is now vertex 0; edges into the removed vertex lose their subpart
+    assert_eq!(graph.nparts("V").unwrap(), 2);
+    assert_eq!(graph.nparts("E").unwrap(), 3);
+    assert_eq!(graph.subpart(0, "src").unwrap(), None);
+    assert_eq!(graph.subpart(0, "tgt").unwrap(), Some(0));
+    assert_eq!(graph.subpart(1, "src").unwrap(), Some(0));
+    assert_eq!(graph.subpart(2, "tgt").unwrap(), None);
+    assert_eq!(graph.incident(0, "tgt").unwrap(), &[0, 1]);
+    assert_eq!(graph.incident(1, "src").unwrap(), &[2]);
+}
+
+#[test]
+fn test_cascading_rem_part_removes_referring_parts() {
+    let mut graph = graph(3, &[(0, 1), (1, 2), (2, 0), (2, 2)]);
+    graph.set_attr(2, "name", Value::String("c".to_string())).unwrap();
+    graph.cascading_rem_part("V", 0).unwrap();
+
+    // Edges 0 and 2 touched vertex 0; vertex 2 became vertex 0
+    assert_eq!(graph.nparts("V").unwrap(), 2);
+    assert_eq!(graph.nparts("E").unwrap(), 2);
+    assert_eq!(graph.get_attr(0, "name").unwrap(), Some(&Value::String("c".to_string())));
+    let mut edges: Vec<_> = (0..2).map(|e| (graph.subpart(e, "src").unwrap(), graph.subpart(e, "tgt").unwrap())).collect();
+    edges.sort();
+    assert_eq!(edges, vec![(Some(0), Some(0)), (Some(1), Some(0))]);
+    assert_eq!(graph.incident(0, "tgt").unwrap().len(), 2);
+}
+
+#[test]
+fn test_graph_words() {
+    let mut evaluator = evaluator();
+    evaluator.eval(&format!("{} empty_acset \"V\" 2 add_parts", GRAPH_SCHEMA)).unwrap();
+    assert_eq!(evaluator.stack.pop(), Some(numbers(&[0, 1])));
+    evaluator.eval("\"E\" add_part \"src\" 0 set_subpart 0 \"tgt\" 1 set_subpart").unwrap();
+    evaluator.eval("1 \"tgt\" incident").unwrap();
+    assert_eq!(evaluator.stack.pop(), Some(numbers(&[0])));
+    evaluator.eval("0 \"name\" \"a\" set_prop 0 \"name\" get_prop").unwrap();
+    assert_eq!(evaluator.stack.pop(), Some(Value::Optional(Some(Box::new(Value::String("a".to_string()))))));
+    evaluator.eval("1 \"name\" get_prop").unwrap();
+    assert_eq!(evaluator.stack.pop(), Some(Value::Optional(None)));
+
+    evaluator.eval("\"V\" 1 cascading_rem_part \"E\" nparts").unwrap();
+    assert_eq!(evaluator.stack.pop(), Some(Value::Number(0)));
+    assert_eq!(evaluator.stack.last().unwrap().to_string(), "ACSet(V: 1, E: 0)");
+}
+
+#[test]
+fn test_attribute_types_are_checked() {
+    let mut evaluator = evaluator();
+    let error = evaluator.eval(
+        "(\"V\") () (\"String\") ((\"label\" \"V\" \"String\")) schema empty_acset \"V\" add_part \"label\" 7 set_prop"
+    ).unwrap_err();
+    assert!(error.to_string().contains("Attribute label takes a String"), "{}", error);
+}
+
+#[test]
+fn test_acset_words_have_effects() {
+    assert_eq!(
+        get_word_effect("incident").unwrap().to_string(),
+        "( acset:ACSet part:Int hom:String -- acset:ACSet sources:List )"
+    );
+    let mut evaluator = evaluator();
+    let error = evaluator.eval(&format!("{} empty_acset 0 \"src\" incident", GRAPH_SCHEMA)).unwrap_err();
+    assert!(error.to_string().contains("V has no part 0"), "{}", error);
+}
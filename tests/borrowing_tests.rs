@@ -0,0 +1,115 @@
+// Tests for scoped borrowing of resources with `with_borrowed`
+
+use borf_lib::repl::interpreter::{Capabilities, Evaluator, Expr, ResourceManager, ResourceValue, Type, Value};
+
+fn evaluator() -> Evaluator {
+    let mut evaluator = Evaluator::new();
+    evaluator.initialize().unwrap();
+    evaluator
+}
+
+#[test]
+fn test_quotation_runs_on_borrowed_resource() {
+    let mut evaluator = evaluator();
+    evaluator.eval("42 \"file\" create_resource [f -> f resource_type] with_borrowed").unwrap();
+    assert_eq!(evaluator.stack.len(), 2);
+    assert_eq!(evaluator.stack[0], Value::String("file".to_string()));
+    // The owner gets the resource back and can still consume it
+    assert!(evaluator.stack[1].is_resource());
+    evaluator.eval("consume_resource").unwrap();
+    assert_eq!(evaluator.stack[1], Value::Number(42));
+}
+
+#[test]
+fn test_borrowed_resource_cannot_be_consumed() {
+    let mut evaluator = evaluator();
+    let error = evaluator.eval("42 \"file\" create_resource [f -> f consume_resource] with_borrowed")
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("Cannot consume borrowed resource"), "{}", error);
+}
+
+#[test]
+fn test_borrow_escaping_on_stack_is_rejected() {
+    let mut evaluator = evaluator();
+    let error = evaluator.eval("42 \"file\" create_resource [f -> f] with_borrowed").unwrap_err().to_string();
+    assert!(error.contains("Borrowed resource 0 (type file) escapes its region on the stack"), "{}", error);
+}
+
+#[test]
+fn test_borrow_escaping_in_closure_is_rejected() {
+    let mut evaluator = evaluator();
+    let error = evaluator.eval("42 \"file\" create_resource [f -> [f]] with_borrowed").unwrap_err().to_string();
+    assert!(error.contains("escapes its region on the stack inside a quotation's closure"), "{}", error);
+}
+
+#[test]
+fn test_region_checks_bindings_and_containers() {
+    let mut manager = ResourceManager::new();
+    let id = manager.create_resource("socket");
+    manager.start_region();
+    manager.borrow_resource(id).unwrap();
+    let borrowed = Value::BorrowedResource(id, Box::new(Value::Nil));
+    let live = vec![("in binding 'conns'".to_string(), Value::List(vec![Value::Number(1), borrowed]))];
+    let error = manager.end_region(&live).unwrap_err().to_string();
+    assert!(error.contains("escapes its region in binding 'conns' inside a list"), "{}", error);
+
+    // Borrows from a region that has ended are no longer tracked
+    assert!(!manager.is_borrowed(id));
+    manager.consume_resource(id).unwrap();
+}
+
+#[test]
+fn test_borrow_of_enclosing_region_may_survive_inner_region() {
+    let mut manager = ResourceManager::new();
+    let id = manager.create_resource("file");
+    manager.start_region();
+    manager.borrow_resource(id).unwrap();
+    manager.start_region();
+    manager.borrow_resource(id).unwrap();
+    let live = vec![("on the stack".to_string(), Value::BorrowedResource(id, Box::new(Value::Nil)))];
+    manager.end_region(&live).unwrap();
+    assert!(manager.end_region(&live).is_err());
+}
+
+#[test]
+fn test_resource_is_returned_when_quotation_fails() {
+    let mut evaluator = evaluator();
+    let error = evaluator.eval("42 \"file\" create_resource [f -> f consume_resource] with_borrowed").unwrap_err();
+    assert!(error.to_string().contains("Cannot consume borrowed resource"), "{}", error);
+    assert!(evaluator.stack.last().is_some_and(Value::is_resource), "{:?}", evaluator.stack);
+    evaluator.eval("consume_resource").unwrap();
+    assert_eq!(evaluator.stack.last(), Some(&Value::Number(42)));
+}
+
+#[test]
+fn test_typed_quotation_runs_on_borrowed_resource() {
+    let mut evaluator = evaluator();
+    evaluator.eval("42 \"file\" create_resource").unwrap();
+    let body = vec![Expr::Symbol("resource_type".to_string())];
+    evaluator.stack.push(Value::TypedQuotation(Vec::new(), body, Type::Simple("String".to_string()), None));
+    evaluator.eval("with_borrowed").unwrap();
+    assert_eq!(evaluator.stack[0], Value::String("file".to_string()));
+    assert!(evaluator.stack[1].is_resource());
+}
+
+#[test]
+fn test_spec_example_reads_a_line_from_a_borrowed_file() {
+    let path = std::env::temp_dir().join(format!("borf_borrowing_{}.txt", std::process::id()));
+    std::fs::write(&path, "first\nsecond\n").unwrap();
+
+    let mut evaluator = evaluator();
+    evaluator.capabilities = Capabilities::all();
+    let result = evaluator.eval(&format!(
+        "\"{}\" \"r\" open_file unwrap [borrowed_file -> borrowed_file read_line swap drop] with_borrowed",
+        path.display()
+    ));
+    std::fs::remove_file(&path).unwrap();
+    result.unwrap();
+
+    // The line read through the borrow, with the file back on top for its owner
+    assert_eq!(evaluator.stack.len(), 2);
+    assert_eq!(evaluator.stack[0], Value::Optional(Some(Box::new(Value::String("first".to_string())))));
+    evaluator.eval("close_file").unwrap();
+    assert!(evaluator.check_for_resource_leaks().is_ok());
+}
@@ -0,0 +1,100 @@
+// Tests for the capabilities that gate file, environment, process and network access
+
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use borf_lib::repl::interpreter::{Capabilities, Evaluator, EvaluatorError, Value};
+
+fn evaluator(capabilities: Capabilities) -> Evaluator {
+    let mut evaluator = Evaluator::new();
+    evaluator.initialize().unwrap();
+    evaluator.capabilities = capabilities;
+    evaluator
+}
+
+// A directory with one file in it, unique to this test run and removed when the test is done
+struct DataDir(PathBuf);
+
+impl Deref for DataDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for DataDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn data_dir(name: &str) -> DataDir {
+    let dir = std::env::temp_dir().join(format!("borf_capabilities_{}_{}", std::process::id(), name));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("input.txt"), "data").unwrap();
+    DataDir(dir)
+}
+
+#[test]
+fn test_evaluator_grants_nothing_by_default() {
+    let dir = data_dir("default");
+    let mut evaluator = evaluator(Capabilities::default());
+    let error = evaluator.eval(&format!("\"{}\" \"r\" open_file", dir.join("input.txt").display())).unwrap_err();
+    assert!(matches!(error, EvaluatorError::PermissionDenied(_)), "{}", error);
+    assert!(error.to_string().contains("read access to"), "{}", error);
+}
+
+#[test]
+fn test_read_is_limited_to_granted_paths() {
+    let dir = data_dir("read");
+    let mut capabilities = Capabilities::none();
+    capabilities.allow_read(&*dir);
+    let mut evaluator = evaluator(capabilities);
+    evaluator.eval(&format!("\"{}\" \"r\" [read_all swap] with_file", dir.join("input.txt").display())).unwrap();
+    assert_eq!(evaluator.stack, vec![Value::String("data".to_string())]);
+
+    // `..` cannot lead out of the granted directory, and reading does not grant writing
+    let outside = dir.join("..").join("elsewhere.txt");
+    assert!(evaluator.eval(&format!("\"{}\" \"r\" open_file", outside.display())).is_err());
+    let error = evaluator.eval(&format!("\"{}\" \"w\" open_file", dir.join("out.txt").display())).unwrap_err();
+    assert!(error.to_string().contains("write access to"), "{}", error);
+}
+
+#[test]
+fn test_denial_can_be_caught() {
+    let dir = data_dir("catch");
+    let mut evaluator = evaluator(Capabilities::none());
+    let code = format!("1 [\"{}\" \"r\" open_file] [] try", dir.join("input.txt").display());
+    evaluator.eval(&code).unwrap();
+    // The stack is restored to where it was before the failing quotation, and the handler gets the error
+    assert_eq!(evaluator.stack.len(), 2);
+    assert_eq!(evaluator.stack[0], Value::Number(1));
+    match &evaluator.stack[1] {
+        Value::Variant(kind, message) if kind == "PermissionDenied" => {
+            assert!(matches!(&message[..], [Value::String(text)] if text.contains("is not granted")));
+        },
+        other => panic!("expected a PermissionDenied error, got {}", other),
+    }
+}
+
+#[test]
+fn test_try_without_error_skips_handler() {
+    let mut evaluator = evaluator(Capabilities::none());
+    evaluator.eval("[1 2 add] [drop 0] try").unwrap();
+    assert_eq!(evaluator.stack, vec![Value::Number(3)]);
+}
+
+#[test]
+fn test_other_capabilities() {
+    let mut capabilities = Capabilities::none();
+    capabilities.allow_env("HOME");
+    capabilities.allow_subprocess("ls");
+    capabilities.allow_net("localhost:8080");
+    assert!(capabilities.check_env("HOME").is_ok());
+    assert!(capabilities.check_env("PATH").is_err());
+    assert!(capabilities.check_subprocess("ls").is_ok());
+    assert!(capabilities.check_subprocess("rm").is_err());
+    assert!(capabilities.check_net("localhost", 8080).is_ok());
+    assert!(capabilities.check_net("localhost", 22).is_err());
+    assert!(Capabilities::all().check_net("example.com", 443).is_ok());
+}
@@ -0,0 +1,68 @@
+// Tests for runtime enforcement of declared stack effects
+
+use borf_lib::repl::interpreter::{Evaluator, Value};
+
+fn evaluator(checked: bool) -> Evaluator {
+    let mut evaluator = Evaluator::new();
+    evaluator.initialize().unwrap();
+    evaluator.checked = checked;
+    evaluator
+}
+
+#[test]
+fn test_matching_effect_passes() {
+    let mut evaluator = evaluator(true);
+    let result = evaluator.eval("( a b -- c ) [add] : plus 1 2 plus").unwrap();
+    assert_eq!(result, Value::Number(3));
+}
+
+#[test]
+fn test_mismatched_effect_is_reported() {
+    let mut evaluator = evaluator(true);
+    let error = evaluator.eval("( a b -- c ) [dup] : bad 1 2 bad").unwrap_err().to_string();
+    assert!(
+        error.contains("word bad declared ( a b -- c ) but consumed 1 / produced 2"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn test_declaration_after_name() {
+    let mut evaluator = evaluator(true);
+    let error = evaluator.eval("bad ( a -- ) [dup] : bad 1 bad").unwrap_err().to_string();
+    assert!(error.contains("consumed 1 / produced 2"), "{}", error);
+}
+
+#[test]
+fn test_unchecked_mode_ignores_declarations() {
+    let mut evaluator = evaluator(false);
+    assert!(evaluator.eval("( a b -- c ) [dup] : bad 1 2 bad").is_ok());
+    assert_eq!(evaluator.stack.len(), 3);
+}
+
+#[test]
+fn test_nested_words_count_towards_caller() {
+    let mut evaluator = evaluator(true);
+    let program = "( a -- a a ) [dup] : twin  ( a -- b ) [twin mul] : square  3 square";
+    assert_eq!(evaluator.eval(program).unwrap(), Value::Number(9));
+}
+
+#[test]
+fn test_defined_words_are_called() {
+    let mut evaluator = evaluator(false);
+    evaluator.eval("[dup mul] : square 3 square").unwrap();
+    assert_eq!(evaluator.stack, vec![Value::Number(9)]);
+}
+
+#[test]
+fn test_quotations_bound_to_parameters_are_pushed() {
+    let mut evaluator = evaluator(false);
+    evaluator.eval("[f -> 2 f] : keep 1 [1 add] keep").unwrap();
+    assert_eq!(evaluator.stack.len(), 3);
+    assert!(matches!(evaluator.stack[2], Value::Quotation(..)));
+    evaluator.stack.clear();
+
+    evaluator.eval("[f -> 2 f call] : apply 1 [1 add] apply").unwrap();
+    assert_eq!(evaluator.stack, vec![Value::Number(1), Value::Number(3)]);
+}
@@ -0,0 +1,81 @@
+// Tests for STACKER closure conversion, running named and translated forms side by side
+
+use borf_lib::repl::interpreter::{Evaluator, Strategy, Value};
+
+// Quotations with parameters bind them in the environment when called
+fn run(program: &str) -> Vec<Value> {
+    let mut evaluator = Evaluator::new();
+    evaluator.initialize().unwrap();
+    evaluator.strategy = Strategy::Named;
+    evaluator.eval(program).unwrap();
+    evaluator.stack
+}
+
+fn expand(quotation: &str) -> String {
+    let mut evaluator = Evaluator::new();
+    evaluator.initialize().unwrap();
+    evaluator.expand(quotation).unwrap()
+}
+
+// `{}` is replaced by the quotation, which binds its parameters, then by its point-free
+// translation, and both runs must agree
+fn assert_same_result(program: &str, quotation: &str) {
+    let named = run(&program.replace("{}", quotation));
+    let expanded = expand(quotation);
+    assert!(!expanded.contains("->"), "{} still binds parameters", expanded);
+    let translated = run(&program.replace("{}", &expanded));
+    assert_eq!(named, translated, "{} with {} and {}", program, quotation, expanded);
+}
+
+#[test]
+fn test_parameters_are_copied_moved_and_dropped() {
+    assert_same_result("10 3 {} call", "[x y -> y x sub]");
+    assert_same_result("7 {} call", "[x -> x x mul]");
+    assert_same_result("3 4 {} call", "[x y -> x x mul y add]");
+    assert_same_result("1 2 3 {} call", "[a b c -> c b a]");
+    assert_same_result("1 2 3 {} call", "[x y z -> x z add]");
+    assert_same_result("1 2 3 4 5 {} call", "[v w x y z -> v z sub w mul]");
+
+    assert_eq!(expand("[x y z -> x z add]"), "[nip add]");
+    assert_eq!(expand("[x -> x x mul]"), "[dup mul]");
+}
+
+#[test]
+fn test_captured_parameter_is_curried() {
+    let quotation = "[x -> [y -> x y sub]]";
+    assert_eq!(expand(quotation), "[[swap sub] curry]");
+    assert_same_result("10 {} call 3 swap call", quotation);
+    assert_eq!(run("10 [x -> [y -> x y sub]] call 3 swap call"), vec![Value::Number(7)]);
+}
+
+#[test]
+fn test_several_captures() {
+    assert_same_result("1 2 {} call 3 swap call", "[x y -> [z -> x y z sub sub]]");
+    assert_same_result("2 5 {} call 3 swap call", "[x y -> [z -> y z mul x sub]]");
+}
+
+#[test]
+fn test_parameter_used_and_captured() {
+    assert_same_result("5 {} call", "[x -> x [y -> x y mul] call]");
+    assert_same_result("5 {} call", "[x -> x [y -> x y mul] call x add]");
+}
+
+#[test]
+fn test_captures_through_several_levels() {
+    assert_same_result("20 {} call 5 swap call 1 swap call", "[x -> [y -> [z -> x y z sub sub]]]");
+    assert_same_result("4 {} call 3 swap call", "[x -> [y -> [x y mul] call]]");
+}
+
+#[test]
+fn test_shadowed_parameters_are_not_captured() {
+    assert_same_result("1 {} call 2 swap call", "[x -> [x -> x x add]]");
+    assert_eq!(expand("[x -> [x -> x x add]]"), "[drop [dup add]]");
+}
+
+#[test]
+fn test_consuming_a_parameter_implicitly_is_rejected() {
+    let mut evaluator = Evaluator::new();
+    evaluator.initialize().unwrap();
+    let error = evaluator.expand("[x y -> add y]").unwrap_err().to_string();
+    assert!(error.contains("would consume parameter 'y'"), "{}", error);
+}
@@ -0,0 +1,32 @@
+// tests/common/mod.rs
+// Fixtures shared by the ACSet, query and migration tests
+
+use std::rc::Rc;
+use borf_lib::repl::interpreter::{ACSet, Hom, Schema};
+
+pub fn hom(name: &str, dom: &str, codom: &str) -> Hom {
+    Hom { name: name.to_string(), dom: dom.to_string(), codom: codom.to_string() }
+}
+
+// Vertices and edges with a source and a target, and a name on each vertex
+pub fn graph_schema() -> Rc<Schema> {
+    let schema = Schema::new(
+        vec!["V".to_string(), "E".to_string()],
+        vec![hom("src", "E", "V"), hom("tgt", "E", "V")],
+        vec!["Name".to_string()],
+        vec![hom("name", "V", "Name")],
+    );
+    Rc::new(schema.unwrap())
+}
+
+// A graph with the given number of vertices and edges between them
+pub fn graph(vertices: usize, edges: &[(usize, usize)]) -> ACSet {
+    let mut graph = ACSet::new(graph_schema());
+    graph.add_parts("V", vertices).unwrap();
+    for &(src, tgt) in edges {
+        let edge = graph.add_part("E").unwrap();
+        graph.set_subpart(edge, "src", src).unwrap();
+        graph.set_subpart(edge, "tgt", tgt).unwrap();
+    }
+    graph
+}
@@ -0,0 +1,87 @@
+// Tests for drawing the dataflow of stack code as a wiring diagram
+
+use std::collections::HashMap;
+use borf_lib::repl::interpreter::{quotation_to_diagram, Evaluator, Expr, Value, Wire, WiringDiagram};
+
+fn symbols(words: &str) -> Vec<Expr> {
+    words.split_whitespace()
+        .map(|word| match word.parse() {
+            Ok(n) => Expr::Number(n),
+            Err(_) => Expr::Symbol(word.to_string()),
+        })
+        .collect()
+}
+
+fn diagram(words: &str) -> WiringDiagram {
+    quotation_to_diagram(&symbols(words), &HashMap::new()).unwrap()
+}
+
+fn wires(diagram: &WiringDiagram) -> Vec<((usize, usize), (usize, usize))> {
+    let mut wires: Vec<_> = diagram.wires.iter().map(|wire| (wire.source, wire.target)).collect();
+    wires.sort();
+    wires
+}
+
+#[test]
+fn test_words_become_boxes_fed_by_stack_positions() {
+    let drawn = diagram("- 2 *");
+    let names: Vec<_> = drawn.boxes.iter().map(|wiring_box| wiring_box.name.as_str()).collect();
+    assert_eq!(names, vec!["-", "2", "*"]);
+    assert_eq!(drawn.inputs.len(), 2);
+    assert_eq!(drawn.outputs.len(), 1);
+    assert_eq!(wires(&drawn), vec![
+        ((0, 0), (1, 0)), ((0, 1), (1, 1)),
+        ((1, 0), (3, 0)), ((2, 0), (3, 1)),
+        ((3, 0), (0, 0)),
+    ]);
+}
+
+#[test]
+fn test_shuffles_are_wires() {
+    // dup fans one value out to both inputs of *
+    let square = diagram("dup *");
+    assert_eq!(square.boxes.len(), 1);
+    assert_eq!(wires(&square), vec![((0, 0), (1, 0)), ((0, 0), (1, 1)), ((1, 0), (0, 0))]);
+
+    // swap crosses the wires, and drop leaves a port unconnected
+    let crossed = diagram("swap drop");
+    assert!(crossed.boxes.is_empty());
+    assert_eq!(crossed.wires, vec![Wire { source: (0, 1), target: (0, 0) }]);
+    assert_eq!(diagram("2 pick").wires, vec![
+        Wire { source: (0, 0), target: (0, 0) },
+        Wire { source: (0, 1), target: (0, 1) },
+        Wire { source: (0, 2), target: (0, 2) },
+        Wire { source: (0, 0), target: (0, 3) },
+    ]);
+}
+
+#[test]
+fn test_port_types_come_from_stack_effects() {
+    let drawn = diagram("add_part");
+    assert_eq!(drawn.inputs, vec!["ACSet".to_string(), "String".to_string()]);
+    assert_eq!(drawn.outputs, vec!["ACSet".to_string(), "Int".to_string()]);
+}
+
+#[test]
+fn test_undrawable_words_are_reported() {
+    let error = quotation_to_diagram(&symbols("frobnicate"), &HashMap::new()).unwrap_err();
+    assert!(error.to_string().contains("'frobnicate': it has no known stack effect"), "{}", error);
+    let error = quotation_to_diagram(&symbols("dip"), &HashMap::new()).unwrap_err();
+    assert!(error.to_string().contains("depends on its quotation arguments"), "{}", error);
+}
+
+#[test]
+fn test_quotation_to_diagram_word() {
+    let mut evaluator = Evaluator::new();
+    evaluator.initialize().unwrap();
+    evaluator.eval("[x y -> x x mul y add] quotation_to_diagram").unwrap();
+    assert_eq!(evaluator.stack.last().unwrap().to_string(), "WiringDiagram(Any, Any -> Any; 2 boxes, 5 wires)");
+    evaluator.eval("to_dot").unwrap();
+    match evaluator.stack.pop() {
+        Some(Value::String(dot)) => {
+            assert!(dot.contains("in0 -> box1:i0;") && dot.contains("in0 -> box1:i1;"), "{}", dot);
+            assert!(dot.contains("in1 -> box2:i0;") && dot.contains("box1:o0 -> box2:i1;"), "{}", dot);
+        },
+        other => panic!("Expected DOT source, got {:?}", other),
+    }
+}
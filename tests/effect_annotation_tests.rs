@@ -0,0 +1,110 @@
+// Tests for resource effect annotations verified in checked mode
+
+use borf_lib::repl::interpreter::{EffectType, Evaluator};
+
+const PROGRAM: &str = r#"
+open_log ( path -- log ) !creates[File]
+[path -> path "file" create_resource] : open_log
+close_log ( log -- ) !consumes[File]
+[log -> log consume_resource drop] : close_log
+"#;
+
+fn checked(program: &str) -> Evaluator {
+    let mut evaluator = Evaluator::new();
+    evaluator.initialize().unwrap();
+    evaluator.checked = true;
+    evaluator.eval(PROGRAM).unwrap();
+    evaluator.eval(program).unwrap();
+    evaluator
+}
+
+fn checked_error(program: &str) -> String {
+    let mut evaluator = Evaluator::new();
+    evaluator.initialize().unwrap();
+    evaluator.checked = true;
+    evaluator.eval(PROGRAM).unwrap();
+    evaluator.eval(program).unwrap_err().to_string()
+}
+
+#[test]
+fn test_annotations_are_attached_to_words() {
+    let evaluator = checked("");
+    assert_eq!(evaluator.annotated_effects["open_log"], vec![EffectType::Creates("File".to_string())]);
+    assert_eq!(evaluator.annotated_effects["close_log"], vec![EffectType::Consumes("File".to_string())]);
+}
+
+#[test]
+fn test_matching_effects_pass() {
+    let evaluator = checked("\"a.log\" open_log close_log");
+    assert_eq!(evaluator.observed_effects["open_log"], vec![EffectType::Creates("file".to_string())]);
+    assert_eq!(evaluator.observed_effects["close_log"], vec![EffectType::Consumes("file".to_string())]);
+}
+
+#[test]
+fn test_pure_word_creating_a_resource_fails() {
+    let error = checked_error("leaky ( -- r ) !pure [-> 1 \"file\" create_resource] : leaky leaky");
+    assert!(error.contains("word leaky declared !pure but created a file resource"), "{}", error);
+}
+
+#[test]
+fn test_consuming_word_that_does_not_consume_fails() {
+    let error = checked_error("keep ( log -- log ) !consumes[File] [log -> log dup resource_type drop] : keep \"a.log\" open_log keep");
+    assert!(error.contains("word keep declared !consumes[File] but did not consume a File resource"), "{}", error);
+}
+
+#[test]
+fn test_annotations_are_not_checked_outside_checked_mode() {
+    let mut evaluator = Evaluator::new();
+    evaluator.initialize().unwrap();
+    evaluator.eval("leaky ( -- r ) !pure [-> 1 \"file\" create_resource] : leaky leaky").unwrap();
+    // The effects are still observed, for :effects
+    let report = evaluator.effects_report();
+    assert_eq!(report, vec![(
+        "leaky".to_string(),
+        vec![EffectType::Pure],
+        vec![EffectType::Creates("file".to_string())],
+    )]);
+}
+
+#[test]
+fn test_effects_table() {
+    let evaluator = checked("\"a.log\" open_log close_log");
+    let table = evaluator.format_effects();
+    assert!(table.contains("open_log        !creates[File] / !creates[file]"), "{}", table);
+    assert!(table.contains("close_log       !consumes[File] / !consumes[file]"), "{}", table);
+}
+
+#[test]
+fn test_using_and_transferring_words_pass() {
+    let evaluator = checked(concat!(
+        "peek ( log -- log ) !uses[File] [log -> log [resource_type drop] with_borrowed] : peek ",
+        "wrap ( log -- quot ) !transfers[File] [log -> [log]] : wrap ",
+        "\"a.log\" open_log peek wrap call close_log",
+    ));
+    assert_eq!(evaluator.observed_effects["peek"], vec![EffectType::Uses("file".to_string())]);
+    assert_eq!(evaluator.observed_effects["wrap"], vec![EffectType::Transfers("file".to_string())]);
+}
+
+#[test]
+fn test_pure_word_using_a_resource_fails() {
+    let error = checked_error("peek ( log -- log ) !pure [log -> log [resource_type drop] with_borrowed] : peek \"a.log\" open_log peek");
+    assert!(error.contains("word peek declared !pure but used a file resource"), "{}", error);
+}
+
+#[test]
+fn test_using_word_that_does_not_use_fails() {
+    let error = checked_error("idle ( log -- n log ) !uses[File] [log -> 0 log] : idle \"a.log\" open_log idle");
+    assert!(error.contains("word idle declared !uses[File] but did not use a File resource"), "{}", error);
+}
+
+#[test]
+fn test_word_transferring_what_it_declares_using_fails() {
+    let error = checked_error("wrap ( log -- quot ) !uses[File] [log -> [log]] : wrap \"a.log\" open_log wrap");
+    assert!(error.contains("word wrap declared !uses[File] but transferred a file resource"), "{}", error);
+}
+
+#[test]
+fn test_transferring_word_that_does_not_transfer_fails() {
+    let error = checked_error("hold ( log -- n log ) !transfers[File] [log -> 0 log] : hold \"a.log\" open_log hold");
+    assert!(error.contains("word hold declared !transfers[File] but did not transfer a File resource"), "{}", error);
+}
@@ -0,0 +1,98 @@
+// Tests for expanding named-parameter quotations to stack code
+
+use borf_lib::repl::interpreter::{expand_definitions, Evaluator, Expr, Param, Value};
+
+fn sym(name: &str) -> Expr {
+    Expr::Symbol(name.to_string())
+}
+
+fn param(name: &str) -> Param {
+    Param { name: name.to_string(), type_annotation: None }
+}
+
+#[test]
+fn test_quotations_display_as_source() {
+    let quotation = Expr::Quotation(vec![param("x"), param("y")], vec![sym("x"), sym("y"), sym("+")]);
+    assert_eq!(quotation.to_string(), "[x y -> x y +]");
+
+    let definition = Expr::Assignment(
+        Box::new(Expr::Quotation(vec![], vec![sym("dup"), sym("*")])),
+        "square".to_string(),
+    );
+    assert_eq!(definition.to_string(), "[dup *] : square");
+}
+
+#[test]
+fn test_strings_display_as_literals_that_read_back() {
+    let text = "say \"hi\"\\n\tC:\\dir\n";
+    let string = Expr::String(text.to_string());
+    assert_eq!(string.to_string(), r#""say \"hi\"\\n\tC:\\dir\n""#);
+
+    let mut evaluator = Evaluator::new();
+    evaluator.initialize().unwrap();
+    assert_eq!(evaluator.eval(&string.to_string()).unwrap(), Value::String(text.to_string()));
+    let quotation = Expr::Quotation(vec![], vec![string.clone(), sym("print")]);
+    match evaluator.eval(&quotation.to_string()).unwrap() {
+        Value::Quotation(_, body, _) => assert_eq!(body, vec![string, sym("print")]),
+        other => panic!("Expected a quotation, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_expand_definitions() {
+    let program = Expr::Sequence(vec![
+        Expr::Assignment(
+            Box::new(Expr::Quotation(vec![param("x"), param("y")], vec![sym("y"), sym("x"), sym("-")])),
+            "rsub".to_string(),
+        ),
+        Expr::Assignment(
+            Box::new(Expr::Quotation(vec![], vec![sym("dup"), sym("*")])),
+            "square".to_string(),
+        ),
+        Expr::Number(42),
+    ]);
+
+    let expanded: Vec<String> = expand_definitions(&program)
+        .into_iter()
+        .map(|(name, quotation)| format!("{} : {}", quotation.unwrap(), name))
+        .collect();
+    assert_eq!(expanded, vec!["[swap -] : rsub", "[dup *] : square"]);
+}
+
+#[test]
+fn test_expand_definitions_goes_on_past_untranslatable() {
+    let program = Expr::Sequence(vec![
+        Expr::Assignment(
+            Box::new(Expr::Quotation(vec![param("q")], vec![sym("q"), sym("call")])),
+            "run".to_string(),
+        ),
+        Expr::Assignment(
+            Box::new(Expr::Quotation(vec![param("x")], vec![sym("x"), sym("x"), sym("*")])),
+            "square".to_string(),
+        ),
+    ]);
+
+    let expanded = expand_definitions(&program);
+    assert_eq!(expanded.len(), 2);
+    assert_eq!(expanded[0].0, "run");
+    assert!(expanded[0].1.is_err());
+    assert_eq!(expanded[1].0, "square");
+    assert_eq!(expanded[1].1.as_ref().unwrap().to_string(), "[dup *]");
+}
+
+#[test]
+fn test_expand_word() {
+    let mut evaluator = Evaluator::new();
+    evaluator.initialize().unwrap();
+    let expanded = evaluator.eval("[x y -> y x -] expand").unwrap();
+    assert_eq!(expanded.to_string(), "[swap -]");
+}
+
+#[test]
+fn test_expand_named_word() {
+    let mut evaluator = Evaluator::new();
+    evaluator.initialize().unwrap();
+    evaluator.eval("[x y -> x y -] : minus").unwrap();
+    assert_eq!(evaluator.expand("minus").unwrap(), "[-]");
+    assert_eq!(evaluator.expand("[x y -> y x -]").unwrap(), "[swap -]");
+}
@@ -0,0 +1,128 @@
+// Tests for file I/O with linear File resources
+
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use borf_lib::repl::interpreter::{get_word_effect, Capabilities, Evaluator, Value};
+
+fn evaluator() -> Evaluator {
+    let mut evaluator = Evaluator::new();
+    evaluator.initialize().unwrap();
+    evaluator.capabilities = Capabilities::all();
+    evaluator
+}
+
+// A file in the temporary directory, removed when the test is done with it
+struct TempFile(PathBuf);
+
+impl Deref for TempFile {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+fn temp_file(name: &str, contents: &str) -> TempFile {
+    let path = std::env::temp_dir().join(format!("borf_file_io_{}_{}", std::process::id(), name));
+    std::fs::write(&path, contents).unwrap();
+    TempFile(path)
+}
+
+fn string(value: &str) -> Value {
+    Value::String(value.to_string())
+}
+
+#[test]
+fn test_write_then_read_all() {
+    let path = temp_file("write.txt", "");
+    let path = path.display();
+    let mut evaluator = evaluator();
+    evaluator.eval(&format!("\"{}\" \"w\" open_file unwrap \"hello \" write \"world\" write close_file", path)).unwrap();
+    evaluator.eval(&format!("\"{}\" \"r\" open_file unwrap read_all swap close_file", path)).unwrap();
+    assert_eq!(evaluator.stack, vec![string("hello world")]);
+    assert!(evaluator.check_for_resource_leaks().is_ok());
+}
+
+#[test]
+fn test_append_keeps_contents() {
+    let path = temp_file("append.txt", "one\n");
+    let mut evaluator = evaluator();
+    evaluator.eval(&format!("\"{}\" \"a\" open_file unwrap \"two\" write close_file", path.display())).unwrap();
+    assert_eq!(std::fs::read_to_string(&*path).unwrap(), "one\ntwo");
+}
+
+#[test]
+fn test_read_line_until_end_of_file() {
+    let path = temp_file("lines.txt", "first\r\nsecond\n");
+    let mut evaluator = evaluator();
+    evaluator.eval(&format!("\"{}\" \"r\" open_file unwrap read_line swap read_line swap read_line swap close_file", path.display())).unwrap();
+    assert_eq!(evaluator.stack, vec![
+        Value::Optional(Some(Box::new(string("first")))),
+        Value::Optional(Some(Box::new(string("second")))),
+        Value::Optional(None),
+    ]);
+}
+
+#[test]
+fn test_seek_rereads_from_offset() {
+    let path = temp_file("seek.txt", "abcdef");
+    let mut evaluator = evaluator();
+    evaluator.eval(&format!("\"{}\" \"r\" open_file unwrap read_all drop 3 seek read_all swap close_file", path.display())).unwrap();
+    assert_eq!(evaluator.stack, vec![string("def")]);
+}
+
+#[test]
+fn test_missing_file_gives_nothing() {
+    let path = std::env::temp_dir().join("borf_file_io_missing_dir").join("none.txt");
+    let mut evaluator = evaluator();
+    evaluator.eval(&format!("\"{}\" \"r\" open_file", path.display())).unwrap();
+    assert_eq!(evaluator.stack, vec![Value::Optional(None)]);
+    let error = evaluator.eval("unwrap").unwrap_err().to_string();
+    assert!(error.contains("unwrap of Nothing"), "{}", error);
+}
+
+#[test]
+fn test_unknown_mode_is_an_error() {
+    let path = temp_file("mode.txt", "text");
+    let mut evaluator = evaluator();
+    let error = evaluator.eval(&format!("\"{}\" \"rw\" open_file", path.display())).unwrap_err().to_string();
+    assert!(error.contains("Unknown file mode 'rw'"), "{}", error);
+    assert!(evaluator.stack.is_empty());
+}
+
+#[test]
+fn test_closed_file_cannot_be_used() {
+    let path = temp_file("closed.txt", "text");
+    let mut evaluator = evaluator();
+    evaluator.eval(&format!("\"{}\" \"r\" open_file unwrap dup close_file", path.display())).unwrap();
+    let error = evaluator.eval("read_all").unwrap_err().to_string();
+    assert!(error.contains("has been consumed"), "{}", error);
+}
+
+#[test]
+fn test_with_file_closes_on_success_and_error() {
+    let path = temp_file("with.txt", "contents");
+    let mut evaluator = evaluator();
+    evaluator.eval(&format!("\"{}\" \"r\" [read_all swap] with_file", path.display())).unwrap();
+    assert_eq!(evaluator.stack, vec![string("contents")]);
+    assert!(evaluator.check_for_resource_leaks().is_ok());
+
+    let error = evaluator.eval(&format!("\"{}\" \"r\" [read_all no_such_word] with_file", path.display()))
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("no_such_word"), "{}", error);
+    assert!(evaluator.check_for_resource_leaks().is_ok());
+}
+
+#[test]
+fn test_file_words_have_linear_effects() {
+    let effect = get_word_effect("close_file").unwrap();
+    assert_eq!(effect.to_string(), "( file:!File -- )");
+    assert!(get_word_effect("open_file").unwrap().to_string().contains("file:?!File"));
+}
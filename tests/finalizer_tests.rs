@@ -0,0 +1,82 @@
+// Tests for resource finalizers and leak reports
+
+use std::cell::RefCell;
+use std::fs;
+use std::rc::Rc;
+use borf_lib::repl::interpreter::{Evaluator, EvaluatorError, Finalizer, Value};
+
+fn evaluator() -> Evaluator {
+    let mut evaluator = Evaluator::new();
+    evaluator.initialize().unwrap();
+    evaluator
+}
+
+#[test]
+fn test_native_finalizer_runs_on_consume() {
+    let released = Rc::new(RefCell::new(Vec::new()));
+    let log = released.clone();
+    let mut evaluator = evaluator();
+    evaluator.register_finalizer("file", Finalizer::Native(Rc::new(move |value: &Value| {
+        log.borrow_mut().push(value.clone());
+        Ok(())
+    })));
+
+    evaluator.eval("7 \"file\" create_resource").unwrap();
+    assert!(released.borrow().is_empty());
+    evaluator.eval("consume_resource").unwrap();
+    assert_eq!(*released.borrow(), vec![Value::Number(7)]);
+}
+
+#[test]
+fn test_quotation_finalizer_gets_inner_value() {
+    let mut evaluator = evaluator();
+    // This finalizer leaves a result behind so that the test can see it ran
+    evaluator.eval("[v -> v v add] \"counter\" register_finalizer").unwrap();
+    evaluator.eval("5 \"counter\" create_resource consume_resource").unwrap();
+    assert_eq!(evaluator.stack, vec![Value::Number(10), Value::Number(5)]);
+}
+
+#[test]
+fn test_failing_finalizer_fails_consume() {
+    let mut evaluator = evaluator();
+    evaluator.register_finalizer("lock", Finalizer::Native(Rc::new(|_: &Value| {
+        Err(EvaluatorError::EvalError("unlock failed".to_string()))
+    })));
+    let error = evaluator.eval("0 \"lock\" create_resource consume_resource").unwrap_err();
+    assert!(error.to_string().contains("unlock failed"), "{}", error);
+}
+
+#[test]
+fn test_leak_report_shows_creation_site_and_call_stack() {
+    let path = std::env::temp_dir().join("borf_leak_report_test.borf");
+    fs::write(&path, "[-> 1 \"file\" create_resource] : open_log\n[-> open_log] : start\nstart\n").unwrap();
+
+    let mut evaluator = evaluator();
+    let error = evaluator.eval_file(&path).unwrap_err().to_string();
+    fs::remove_file(&path).unwrap();
+
+    assert!(error.contains("Resource leak detected: 1 resources not consumed"), "{}", error);
+    assert!(error.contains("0 (type file) created at line 1, column 14 in open_log"), "{}", error);
+    assert!(error.contains("open_log called at line 2, column 5 in start"), "{}", error);
+    assert!(error.contains(&format!("start called at line 3, column 1 in {}", path.display())), "{}", error);
+}
+
+#[test]
+fn test_leak_report_places_input_by_line_and_column() {
+    let mut evaluator = evaluator();
+    evaluator.eval("1 \"file\" create_resource").unwrap();
+    let error = evaluator.check_for_resource_leaks().unwrap_err().to_string();
+    assert!(error.contains("0 (type file) created at line 1, column 10 in <input>"), "{}", error);
+}
+
+#[test]
+fn test_consumed_resources_are_not_leaks() {
+    let path = std::env::temp_dir().join("borf_no_leak_test.borf");
+    fs::write(&path, "1 \"file\" create_resource consume_resource\n").unwrap();
+
+    let mut evaluator = evaluator();
+    let result = evaluator.eval_file(&path);
+    fs::remove_file(&path).unwrap();
+    assert!(result.is_ok(), "{:?}", result);
+    assert!(evaluator.check_for_resource_leaks().is_ok());
+}
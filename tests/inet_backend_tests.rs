@@ -0,0 +1,132 @@
+// Tests for running Borf on interaction combinators, checked against the tree walker
+
+use borf_lib::repl::interpreter::{combinator_rules, eval_inet, Evaluator, Value};
+
+// Programs in the pure fragment, each run by both evaluators
+const CORPUS: &[&str] = &[
+    "2 3 add",
+    "4 5 mul",
+    "0 7 add 0 mul",
+    "3 dup mul",
+    "1 2 swap",
+    "1 2 3 rot",
+    "1 2 over",
+    "1 2 tuck",
+    "1 2 nip",
+    "1 2 2dup",
+    "1 2 drop",
+    "1 2 3 2 pick",
+    "1 2 3 2 roll",
+    "12 12 mul 10 mul",
+    "3 [dup mul] call",
+    "[2 3] call add",
+    "[1 add] dup 2 swap call swap call",
+    "5 [x -> x x mul x add] call",
+    "2 3 [x y -> y x] call",
+    "[x y -> x y mul y add] 3 4 rot call",
+    "0 [1] [2] if",
+    "4 [1] [2] if",
+    "3 4 1 [add] [mul] if",
+    "3 4 0 [add] [mul] if",
+    "5 1 [dup] [0] if",
+    "5 0 [dup] [0] if",
+    "1 [2 [3] [4] if] [5] if",
+    "6 [0 [1] [2] if add] call",
+    "2 3 [add] call 0 [10] [20] if",
+    "[dup mul] : square 3 square square",
+    "[x -> x 1 add] : inc 2 inc inc 3 mul",
+    "[f -> 3 f call] : three 2 [x -> x x mul] three",
+    "[n -> n [n 1 add] [10] if] : bump 0 bump 4 bump",
+];
+
+fn tree_walk(source: &str) -> Vec<Value> {
+    let mut evaluator = Evaluator::new();
+    evaluator.initialize().unwrap();
+    evaluator.eval_source(source).unwrap_or_else(|e| panic!("{}: {}", source, e));
+    evaluator.stack
+}
+
+fn inet_error(source: &str) -> String {
+    eval_inet(source).unwrap_err().to_string()
+}
+
+#[test]
+fn test_corpus_matches_the_tree_walker() {
+    for source in CORPUS {
+        let (stack, stats) = eval_inet(source).unwrap_or_else(|e| panic!("{}: {}", source, e));
+        assert_eq!(stack, tree_walk(source), "{}", source);
+        assert_eq!(stats.stuck, 0, "{}", source);
+    }
+}
+
+#[test]
+fn test_reduction_counts_interactions() {
+    let (stack, stats) = eval_inet("2 3 mul").unwrap();
+    assert_eq!(stack, vec![Value::Number(6)]);
+    assert!(stats.interactions > 0);
+    assert!(stats.rules.contains_key("γ >< γ"), "{:?}", stats.rules);
+}
+
+#[test]
+fn test_if_in_the_tree_walker() {
+    assert_eq!(tree_walk("0 [1] [2] if"), vec![Value::Number(2)]);
+    assert_eq!(tree_walk("4 3 [x -> x x mul] [drop 0] if"), vec![Value::Number(16)]);
+}
+
+#[test]
+fn test_code_outside_the_fragment_is_refused() {
+    assert!(inet_error("5 2 sub").contains("Cannot compile 'sub'"));
+    assert!(inet_error("-1").contains("never negative"));
+    assert!(inet_error("[x -> x loop] : loop 1 loop").contains("recursive"));
+    assert!(inet_error("1 [1] [drop] if").contains("change the stack depth differently"));
+    assert!(inet_error("add").contains("needs more values"));
+}
+
+#[test]
+fn test_quotations_do_not_read_back_as_numbers() {
+    assert!(inet_error("[dup]").contains("Value 0 on the stack is a quotation"));
+    // Branches leaving different quotations are only found out when reading back
+    let error = inet_error("1 [[dup]] [[drop]] if");
+    assert!(error.contains("reads back as λa.λb.((b a) a), which is not a number"), "{}", error);
+}
+
+#[test]
+fn test_rules_cover_every_pair_of_agents() {
+    // γ, δ0 and δ1: three annihilations, three commutations and four erasures
+    assert_eq!(combinator_rules(2).unwrap().rules.len(), 10);
+}
+
+#[test]
+fn test_copies_match_the_tree_walker() {
+    for source in [
+        // Copies of numbers, copied again
+        "2 dup dup mul mul",
+        "2 dup 2dup mul mul mul",
+        "3 dup dup add mul",
+        "2 3 over over mul mul mul",
+        // Quotations made of copies, which are not copies themselves
+        "2 dup mul dup mul",
+        "[dup mul] dup 2 swap call swap call",
+        "[f -> 2 f call f call] : twice [dup mul] twice",
+    ] {
+        let (stack, _) = eval_inet(source).unwrap_or_else(|e| panic!("{}: {}", source, e));
+        assert_eq!(stack, tree_walk(source), "{}", source);
+    }
+}
+
+#[test]
+fn test_copying_a_copied_quotation_is_refused() {
+    for (source, word) in [
+        ("[1 add] dup dup", "dup"),
+        ("[1 add] dup 2dup", "2dup"),
+        ("[1 add] 2 1 pick 0 pick", "pick"),
+        ("[1 add] dup [dup] call", "call"),
+        ("[dup] : twin [1 add] dup twin", "twin"),
+        ("[swap] : flip 2 [1 add] flip drop dup", "dup"),
+    ] {
+        // The tree walker runs them; the net would share duplicator labels between copies
+        assert!(!tree_walk(source).is_empty());
+        let error = inet_error(source);
+        assert!(error.contains(&format!("Cannot compile '{}' copying a quotation that is already a copy", word)), "{}: {}", source, error);
+    }
+}
@@ -0,0 +1,188 @@
+// Tests for reducing interaction nets on several threads, checked against normalize
+
+use borf_lib::repl::interpreter::{
+    compile_source, max_threads, read_back, Cell, Evaluator, InteractionRule, Net, RewriteSystem, Value, INTERFACE, PARALLEL_ROUND, PRINCIPAL,
+};
+
+fn cell(agent: &str, arity: usize) -> Cell {
+    Cell { agent: agent.to_string(), arity }
+}
+
+// Unary numbers: Z, and S whose auxiliary port holds the predecessor
+fn number(net: &mut Net, n: usize) -> usize {
+    let mut top = net.add_cell(&cell("Z", 0));
+    for _ in 0..n {
+        let succ = net.add_cell(&cell("S", 1));
+        net.connect((succ, 1), (top, PRINCIPAL)).unwrap();
+        top = succ;
+    }
+    top
+}
+
+fn read_number(net: &Net, port: (usize, usize)) -> usize {
+    let (mut at, _) = net.target(port).unwrap();
+    let mut n = 0;
+    while net.agent(at) == Some("S") {
+        n += 1;
+        at = net.target((at, 1)).unwrap().0;
+    }
+    assert_eq!(net.agent(at), Some("Z"));
+    n
+}
+
+// Add's principal port takes the first number; its auxiliary ports the second and the sum
+fn addition() -> RewriteSystem {
+    let mut zero = Net::new(2);
+    zero.connect((INTERFACE, 0), (INTERFACE, 1)).unwrap();
+    let mut succ = Net::new(3);
+    let add = succ.add_cell(&cell("Add", 2));
+    let s = succ.add_cell(&cell("S", 1));
+    succ.connect((add, PRINCIPAL), (INTERFACE, 2)).unwrap();
+    succ.connect((add, 1), (INTERFACE, 0)).unwrap();
+    succ.connect((add, 2), (s, 1)).unwrap();
+    succ.connect((s, PRINCIPAL), (INTERFACE, 1)).unwrap();
+    RewriteSystem::new(vec![
+        InteractionRule { left: cell("Add", 2), right: cell("Z", 0), rhs: zero },
+        InteractionRule { left: cell("Add", 2), right: cell("S", 1), rhs: succ },
+    ]).unwrap()
+}
+
+// Sums k + 2 for k below count, side by side, the sum of k on free port k
+fn sums(count: usize) -> Net {
+    let mut net = Net::new(count);
+    for k in 0..count {
+        let add = net.add_cell(&cell("Add", 2));
+        let a = number(&mut net, k % 7);
+        let b = number(&mut net, 2);
+        net.connect((add, PRINCIPAL), (a, PRINCIPAL)).unwrap();
+        net.connect((add, 1), (b, PRINCIPAL)).unwrap();
+        net.connect((add, 2), (INTERFACE, k)).unwrap();
+    }
+    net
+}
+
+#[test]
+fn test_parallel_reduction_matches_normalize() {
+    let count = 3 * PARALLEL_ROUND;
+    let mut sequential = sums(count);
+    let mut parallel = sequential.clone();
+    let expected = sequential.normalize(&addition()).unwrap();
+    let stats = parallel.normalize_parallel(&addition(), 4).unwrap();
+    for k in 0..count {
+        assert_eq!(read_number(&parallel, (INTERFACE, k)), k % 7 + 2);
+    }
+    assert_eq!(parallel.canonical(), sequential.canonical());
+    assert_eq!(stats.interactions, expected.interactions);
+    assert_eq!(stats.rules, expected.rules);
+    assert_eq!(stats.cells, expected.cells);
+    assert!(parallel.active_pairs().is_empty());
+}
+
+#[test]
+fn test_the_net_does_not_depend_on_the_number_of_threads() {
+    let (net, system) = compile_source("12 12 mul 10 mul [x -> x x add] call").unwrap();
+    let nets: Vec<Net> = [1, 2, 3, 8].iter()
+        .map(|&threads| {
+            let mut net = net.clone();
+            net.normalize_parallel(&system, threads).unwrap();
+            net
+        })
+        .collect();
+    assert!(nets.iter().all(|other| *other == nets[0]));
+    assert_eq!(read_back(&nets[0]).unwrap(), vec![Value::Number(2880)]);
+}
+
+#[test]
+fn test_combinator_programs_read_back_the_same() {
+    for source in ["2 3 add", "3 [dup mul] call", "1 2 3 rot", "4 3 [x -> x x mul] [drop 0] if", "[dup mul] : square 3 square square"] {
+        let (mut sequential, system) = compile_source(source).unwrap();
+        let mut parallel = sequential.clone();
+        let expected = sequential.normalize(&system).unwrap();
+        let stats = parallel.normalize_parallel(&system, 4).unwrap();
+        assert_eq!(read_back(&parallel).unwrap(), read_back(&sequential).unwrap(), "{}", source);
+        assert_eq!(parallel.canonical(), sequential.canonical(), "{}", source);
+        assert_eq!(stats.interactions, expected.interactions, "{}", source);
+    }
+}
+
+#[test]
+fn test_threads_beyond_the_cores_are_not_started() {
+    let count = 2 * PARALLEL_ROUND;
+    let mut sequential = sums(count);
+    let mut parallel = sequential.clone();
+    sequential.normalize(&addition()).unwrap();
+    parallel.normalize_parallel(&addition(), 100 * max_threads()).unwrap();
+    assert_eq!(parallel.canonical(), sequential.canonical());
+    assert!(max_threads() >= 1);
+}
+
+#[test]
+fn test_wires_pass_through_pairs_rewritten_together() {
+    // A >< B joins the wires on their auxiliary ports
+    let mut join = Net::new(2);
+    join.connect((INTERFACE, 0), (INTERFACE, 1)).unwrap();
+    let system = RewriteSystem::new(vec![InteractionRule { left: cell("A", 1), right: cell("B", 1), rhs: join }]).unwrap();
+
+    // A long chain of pairs between the free ports, all rewritten in one round, leaves a single wire
+    let mut chain = Net::new(2);
+    let mut last = (INTERFACE, 0);
+    for _ in 0..2 * PARALLEL_ROUND {
+        let a = chain.add_cell(&cell("A", 1));
+        let b = chain.add_cell(&cell("B", 1));
+        chain.connect(last, (a, 1)).unwrap();
+        chain.connect((a, PRINCIPAL), (b, PRINCIPAL)).unwrap();
+        last = (b, 1);
+    }
+    chain.connect(last, (INTERFACE, 1)).unwrap();
+    assert_eq!(chain.normalize_parallel(&system, 3).unwrap().interactions, 2 * PARALLEL_ROUND);
+    assert_eq!(chain.target((INTERFACE, 0)), Some((INTERFACE, 1)));
+    assert!(chain.cell_numbers().is_empty());
+}
+
+#[test]
+fn test_pairs_without_rules_are_left() {
+    let mut net = sums(2);
+    let stats = net.normalize_parallel(&RewriteSystem::default(), 2).unwrap();
+    assert_eq!(stats.interactions, 0);
+    assert_eq!(stats.stuck, 2);
+    assert_eq!(net.active_pairs().len(), 2);
+}
+
+#[test]
+fn test_canonical_ignores_numbering() {
+    let mut first = Net::new(1);
+    let s = first.add_cell(&cell("S", 1));
+    let z = first.add_cell(&cell("Z", 0));
+    first.connect((s, 1), (z, PRINCIPAL)).unwrap();
+    first.connect((s, PRINCIPAL), (INTERFACE, 0)).unwrap();
+    let mut second = Net::new(1);
+    let top = number(&mut second, 1);
+    second.connect((top, PRINCIPAL), (INTERFACE, 0)).unwrap();
+    assert_ne!(first, second);
+    assert_eq!(first.canonical(), second.canonical());
+}
+
+#[test]
+fn test_normalize_parallel_word() {
+    let mut evaluator = Evaluator::new();
+    evaluator.initialize().unwrap();
+    let era = evaluator.eval("\"Era\" 0 create_cell").unwrap();
+    evaluator.env.set("era", era);
+    let z = evaluator.eval("\"Z\" 0 create_cell").unwrap();
+    evaluator.env.set("z", z);
+    evaluator.stack.clear();
+    let system = evaluator.eval("((era z 0 empty_net)) create_rewrite_system").unwrap();
+    evaluator.env.set("system", system);
+    evaluator.stack.clear();
+
+    evaluator.eval("0 empty_net era add_cell drop z add_cell drop 1 0 2 0 connect system 2 normalize_parallel").unwrap();
+    match evaluator.stack.pop() {
+        Some(Value::Map(stats)) => assert_eq!(stats["interactions"], Value::Number(1)),
+        other => panic!("Expected statistics, got {:?}", other),
+    }
+    assert_eq!(evaluator.stack.pop().unwrap().to_string(), "Net(0 free ports)");
+
+    evaluator.stack.clear();
+    let error = evaluator.eval("0 empty_net system 0 normalize_parallel").unwrap_err();
+    assert!(error.to_string().contains("positive number of threads"), "{}", error);
+}
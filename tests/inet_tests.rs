@@ -0,0 +1,175 @@
+// Tests for interaction nets and their reduction
+
+use borf_lib::repl::interpreter::{Cell, Evaluator, InteractionRule, Net, RewriteSystem, Value, INTERFACE, PRINCIPAL};
+
+fn cell(agent: &str, arity: usize) -> Cell {
+    Cell { agent: agent.to_string(), arity }
+}
+
+// Unary numbers: Z, and S whose auxiliary port holds the predecessor
+fn number(net: &mut Net, n: usize) -> usize {
+    let mut top = net.add_cell(&cell("Z", 0));
+    for _ in 0..n {
+        let succ = net.add_cell(&cell("S", 1));
+        net.connect((succ, 1), (top, PRINCIPAL)).unwrap();
+        top = succ;
+    }
+    top
+}
+
+// The number a port leads to, following S cells down to Z
+fn read_number(net: &Net, port: (usize, usize)) -> usize {
+    let (mut at, _) = net.target(port).unwrap();
+    let mut n = 0;
+    while net.agent(at) == Some("S") {
+        n += 1;
+        at = net.target((at, 1)).unwrap().0;
+    }
+    assert_eq!(net.agent(at), Some("Z"));
+    n
+}
+
+// Add's principal port takes the first number; its auxiliary ports the second and the sum
+fn addition() -> RewriteSystem {
+    // Add >< Z: the second number is the sum
+    let mut zero = Net::new(2);
+    zero.connect((INTERFACE, 0), (INTERFACE, 1)).unwrap();
+
+    // Add >< S: S(x) + y = S(x + y)
+    let mut succ = Net::new(3);
+    let add = succ.add_cell(&cell("Add", 2));
+    let s = succ.add_cell(&cell("S", 1));
+    succ.connect((add, PRINCIPAL), (INTERFACE, 2)).unwrap();
+    succ.connect((add, 1), (INTERFACE, 0)).unwrap();
+    succ.connect((add, 2), (s, 1)).unwrap();
+    succ.connect((s, PRINCIPAL), (INTERFACE, 1)).unwrap();
+
+    RewriteSystem::new(vec![
+        InteractionRule { left: cell("Add", 2), right: cell("Z", 0), rhs: zero },
+        InteractionRule { left: cell("Add", 2), right: cell("S", 1), rhs: succ },
+    ]).unwrap()
+}
+
+fn sum(a: usize, b: usize) -> Net {
+    let mut net = Net::new(1);
+    let add = net.add_cell(&cell("Add", 2));
+    let a = number(&mut net, a);
+    let b = number(&mut net, b);
+    net.connect((add, PRINCIPAL), (a, PRINCIPAL)).unwrap();
+    net.connect((add, 1), (b, PRINCIPAL)).unwrap();
+    net.connect((add, 2), (INTERFACE, 0)).unwrap();
+    net
+}
+
+#[test]
+fn test_addition_reduces_to_normal_form() {
+    let mut net = sum(2, 3);
+    assert_eq!(net.active_pairs().len(), 1);
+    let stats = net.normalize(&addition()).unwrap();
+    assert_eq!(read_number(&net, (INTERFACE, 0)), 5);
+    assert_eq!(stats.interactions, 3);
+    assert_eq!(stats.rules["Add >< S"], 2);
+    assert_eq!(stats.rules["Add >< Z"], 1);
+    assert_eq!(stats.stuck, 0);
+    assert_eq!(stats.cells, 6);
+    assert!(net.active_pairs().is_empty());
+    assert_eq!(net.to_string(), "Net(Z: 1, S: 5; 1 free port)");
+}
+
+#[test]
+fn test_rules_apply_either_way_round() {
+    // Written as S >< Add, with the free ports in that order
+    let mut succ = Net::new(3);
+    let add = succ.add_cell(&cell("Add", 2));
+    let s = succ.add_cell(&cell("S", 1));
+    succ.connect((add, PRINCIPAL), (INTERFACE, 0)).unwrap();
+    succ.connect((add, 1), (INTERFACE, 1)).unwrap();
+    succ.connect((add, 2), (s, 1)).unwrap();
+    succ.connect((s, PRINCIPAL), (INTERFACE, 2)).unwrap();
+    let mut zero = Net::new(2);
+    zero.connect((INTERFACE, 0), (INTERFACE, 1)).unwrap();
+    let system = RewriteSystem::new(vec![
+        InteractionRule { left: cell("Z", 0), right: cell("Add", 2), rhs: zero },
+        InteractionRule { left: cell("S", 1), right: cell("Add", 2), rhs: succ },
+    ]).unwrap();
+
+    let mut net = sum(4, 1);
+    net.normalize(&system).unwrap();
+    assert_eq!(read_number(&net, (INTERFACE, 0)), 5);
+}
+
+#[test]
+fn test_wires_pass_through_vanishing_pairs() {
+    // A >< B joins the wires on their auxiliary ports
+    let mut join = Net::new(2);
+    join.connect((INTERFACE, 0), (INTERFACE, 1)).unwrap();
+    let system = RewriteSystem::new(vec![InteractionRule { left: cell("A", 1), right: cell("B", 1), rhs: join }]).unwrap();
+
+    // Two pairs in a row between the free ports leave a single wire
+    let mut chain = Net::new(2);
+    let cells: Vec<usize> = ["A", "B", "A", "B"].iter().map(|agent| chain.add_cell(&cell(agent, 1))).collect();
+    chain.connect((INTERFACE, 0), (cells[0], 1)).unwrap();
+    chain.connect((cells[0], PRINCIPAL), (cells[1], PRINCIPAL)).unwrap();
+    chain.connect((cells[1], 1), (cells[2], 1)).unwrap();
+    chain.connect((cells[2], PRINCIPAL), (cells[3], PRINCIPAL)).unwrap();
+    chain.connect((cells[3], 1), (INTERFACE, 1)).unwrap();
+    assert_eq!(chain.normalize(&system).unwrap().interactions, 2);
+    assert_eq!(chain.target((INTERFACE, 0)), Some((INTERFACE, 1)));
+
+    // A pair wired to itself closes into a loop, which disappears
+    let mut circle = Net::new(0);
+    let a = circle.add_cell(&cell("A", 1));
+    let b = circle.add_cell(&cell("B", 1));
+    circle.connect((a, PRINCIPAL), (b, PRINCIPAL)).unwrap();
+    circle.connect((a, 1), (b, 1)).unwrap();
+    circle.normalize(&system).unwrap();
+    assert!(circle.cell_numbers().is_empty());
+}
+
+#[test]
+fn test_pairs_without_rules_are_left() {
+    let mut net = sum(1, 0);
+    let stats = net.normalize(&RewriteSystem::default()).unwrap();
+    assert_eq!(stats.interactions, 0);
+    assert_eq!(stats.stuck, 1);
+    assert_eq!(net.active_pairs().len(), 1);
+}
+
+#[test]
+fn test_wiring_is_checked() {
+    let mut net = Net::new(1);
+    let z = net.add_cell(&cell("Z", 0));
+    net.connect((z, PRINCIPAL), (INTERFACE, 0)).unwrap();
+    let error = net.connect((INTERFACE, 0), (z, PRINCIPAL)).unwrap_err();
+    assert!(error.to_string().contains("already wired"), "{}", error);
+    assert!(net.connect((z, 1), (INTERFACE, 0)).is_err());
+
+    let error = RewriteSystem::new(vec![
+        InteractionRule { left: cell("Add", 2), right: cell("Z", 0), rhs: Net::new(1) },
+    ]).unwrap_err();
+    assert!(error.to_string().contains("needs a net with 2 free ports, not 1"), "{}", error);
+}
+
+#[test]
+fn test_interaction_net_words() {
+    let mut evaluator = Evaluator::new();
+    evaluator.initialize().unwrap();
+    let era = evaluator.eval("\"Era\" 0 create_cell").unwrap();
+    evaluator.env.set("era", era);
+    let z = evaluator.eval("\"Z\" 0 create_cell").unwrap();
+    evaluator.env.set("z", z);
+    evaluator.stack.clear();
+    let system = evaluator.eval("((era z 0 empty_net)) create_rewrite_system").unwrap();
+    evaluator.env.set("system", system);
+    evaluator.stack.clear();
+
+    evaluator.eval("0 empty_net era add_cell drop z add_cell drop 1 0 2 0 connect active_pairs").unwrap();
+    let pair = Value::List(vec![Value::Number(1), Value::Number(2)]);
+    assert_eq!(evaluator.stack.pop(), Some(Value::List(vec![pair])));
+    evaluator.eval("system normalize").unwrap();
+    match evaluator.stack.pop() {
+        Some(Value::Map(stats)) => assert_eq!(stats["interactions"], Value::Number(1)),
+        other => panic!("Expected statistics, got {:?}", other),
+    }
+    assert_eq!(evaluator.stack.pop().unwrap().to_string(), "Net(0 free ports)");
+}
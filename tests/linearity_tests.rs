@@ -0,0 +1,166 @@
+// Tests for the static linearity check of values with `!` types
+
+use std::collections::HashMap;
+use borf_lib::repl::interpreter::{check_linearity, parse_stack_effect, Evaluator, Expr, Param, Parser, Pattern, StackEffect, Type};
+
+fn sym(name: &str) -> Expr {
+    Expr::Symbol(name.to_string())
+}
+
+fn file(name: &str) -> Param {
+    Param {
+        name: name.to_string(),
+        type_annotation: Some(Type::Linear(Box::new(Type::Simple("File".to_string())))),
+    }
+}
+
+fn define(name: &str, params: Vec<Param>, body: Vec<Expr>) -> Expr {
+    Expr::Assignment(Box::new(Expr::Quotation(params, body)), name.to_string())
+}
+
+fn known() -> HashMap<String, StackEffect> {
+    let mut known = HashMap::new();
+    known.insert("close_file".to_string(), parse_stack_effect("( f:!File -- )").unwrap());
+    known.insert("open_file".to_string(), parse_stack_effect("( path:String -- f:!File )").unwrap());
+    known.insert("write".to_string(), parse_stack_effect("( f:!File s:String -- f:!File )").unwrap());
+    known
+}
+
+fn errors(program: Vec<Expr>) -> Vec<String> {
+    check_linearity(&Expr::Sequence(program), &known())
+        .into_iter()
+        .map(|e| e.to_string())
+        .collect()
+}
+
+#[test]
+fn test_used_exactly_once_is_accepted() {
+    let program = vec![
+        define("close", vec![file("f")], vec![sym("f"), sym("close_file")]),
+        define("greet", vec![file("f")], vec![sym("f"), Expr::String("hi".to_string()), sym("write"), sym("close_file")]),
+    ];
+    assert_eq!(errors(program), Vec::<String>::new());
+}
+
+#[test]
+fn test_second_use_is_reported() {
+    let program = vec![define("close_twice", vec![file("f")], vec![
+        sym("f"), sym("close_file"), sym("f"), sym("close_file"),
+    ])];
+    let errors = errors(program);
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert!(errors[0].starts_with("close_twice, word 3 `f`"), "{}", errors[0]);
+    assert!(errors[0].contains("first used at close_twice, word 1 `f`"), "{}", errors[0]);
+}
+
+#[test]
+fn test_missing_use_is_reported() {
+    let program = vec![define("forget", vec![file("f")], vec![Expr::Number(1)])];
+    assert_eq!(errors(program), vec!["forget, end of body: linear parameter 'f' is never used"]);
+}
+
+#[test]
+fn test_dup_and_drop_are_rejected() {
+    let program = vec![
+        define("copy", vec![file("f")], vec![sym("f"), sym("dup"), sym("close_file"), sym("close_file")]),
+        define("discard", vec![], vec![Expr::String("log".to_string()), sym("open_file"), sym("drop")]),
+    ];
+    let errors = errors(program);
+    assert_eq!(errors, vec![
+        "copy, word 2 `dup`: `dup` would duplicate linear value 'f'",
+        "discard, word 3 `drop`: `drop` would discard linear value 'f' from `open_file`",
+    ]);
+}
+
+#[test]
+fn test_declared_linear_input_cannot_be_dropped() {
+    let mut effects = known();
+    effects.insert("leak".to_string(), parse_stack_effect("( f:!File -- )").unwrap());
+    let program = Expr::Sequence(vec![define("leak", vec![], vec![sym("drop")])]);
+    let errors = check_linearity(&program, &effects);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].message, "`drop` would discard linear value input 'f'");
+}
+
+#[test]
+fn test_if_branches_must_agree() {
+    let both = Expr::If(
+        Box::new(Expr::Boolean(true)),
+        Box::new(Expr::Sequence(vec![sym("f"), sym("close_file")])),
+        Box::new(Expr::Sequence(vec![sym("f"), sym("close_file")])),
+    );
+    assert!(errors(vec![define("ok", vec![file("f")], vec![both])]).is_empty());
+
+    let one = Expr::If(
+        Box::new(Expr::Boolean(true)),
+        Box::new(Expr::Sequence(vec![sym("f"), sym("close_file")])),
+        Box::new(Expr::Nil),
+    );
+    let errors = errors(vec![define("maybe", vec![file("f")], vec![one.clone()])]);
+    assert_eq!(errors, vec![format!(
+        "maybe, word 1 `{}`, else branch: linear value 'f' is not used here but is used in the then branch", one
+    )]);
+}
+
+#[test]
+fn test_match_arms_must_agree() {
+    let cases = vec![
+        (Pattern::Literal(Expr::Number(0)), Expr::Sequence(vec![sym("f"), sym("close_file")])),
+        (Pattern::Wildcard, Expr::Number(1)),
+    ];
+    let program = vec![define("check", vec![file("f")], vec![
+        Expr::Match(Box::new(Expr::Number(3)), cases),
+    ])];
+    let errors = errors(program);
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert!(errors[0].contains("arm 2 `_`: linear value 'f' is not used here but is used in the arm 1 `0`"), "{}", errors[0]);
+}
+
+#[test]
+fn test_wildcard_on_linear_subject_discards_it() {
+    let cases = vec![(Pattern::Wildcard, Expr::Number(0))];
+    let program = vec![define("ignore", vec![file("f")], vec![Expr::Match(Box::new(sym("f")), cases)])];
+    let errors = errors(program);
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert!(errors[0].ends_with("`_` would discard the linear value being matched"), "{}", errors[0]);
+}
+
+#[test]
+fn test_captured_linear_value_is_moved_into_the_quotation() {
+    let closer = Expr::Quotation(vec![], vec![sym("f"), sym("close_file")]);
+    let ok = define("later", vec![file("f")], vec![closer.clone(), sym("call")]);
+    assert!(errors(vec![ok]).is_empty());
+
+    // Capturing the value and then using it again is a second use
+    let twice = define("both", vec![file("f")], vec![closer, sym("drop"), sym("f"), sym("close_file")]);
+    let errors = errors(vec![twice]);
+    assert!(errors.iter().any(|e| e.contains("`drop` would discard linear value quotation capturing 'f'")), "{:?}", errors);
+    assert!(errors.iter().any(|e| e.contains("linear value 'f' is used again")), "{:?}", errors);
+}
+
+#[test]
+fn test_spec_examples_are_checked_after_parsing() {
+    let source = "[f <: !File -> f close_file] : close\n\
+                  [f <: !File -> f close_file f close_file] : twice\n\
+                  [f <: !File -> 1] : forget";
+    let program = Parser::new(source).parse().unwrap();
+    let errors: Vec<String> = check_linearity(&program, &known()).into_iter().map(|e| e.to_string()).collect();
+    assert_eq!(errors, vec![
+        "twice, word 3 `f`: linear value 'f' is used again (first used at twice, word 1 `f`)",
+        "forget, end of body: linear parameter 'f' is never used",
+    ]);
+}
+
+#[test]
+fn test_eval_source_rejects_nonlinear_uses() {
+    let mut evaluator = Evaluator::new();
+    evaluator.initialize().unwrap();
+
+    let error = evaluator.eval_source("[f <: !File -> f close_file f close_file] : twice").unwrap_err().to_string();
+    assert!(error.contains("twice, word 3 `f`: linear value 'f' is used again"), "{}", error);
+
+    let error = evaluator.eval_source("[f <: !File -> 1] : forget").unwrap_err().to_string();
+    assert!(error.contains("forget, end of body: linear parameter 'f' is never used"), "{}", error);
+
+    assert!(evaluator.eval_source("[f <: !File -> f close_file] : close").is_ok());
+}
@@ -0,0 +1,127 @@
+// Tests for functorial data migration between ACSet schemas
+
+mod common;
+
+use std::collections::HashMap;
+use std::rc::Rc;
+use borf_lib::repl::interpreter::{delta_migrate, sigma_migrate, ACSet, Evaluator, Hom, Schema, SchemaFunctor, Value};
+use common::{graph, graph_schema, hom};
+
+fn strings(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| name.to_string()).collect()
+}
+
+fn schema(objects: &[&str], homs: Vec<Hom>, attr_types: &[&str], attrs: Vec<Hom>) -> Rc<Schema> {
+    Rc::new(Schema::new(strings(objects), homs, strings(attr_types), attrs).unwrap())
+}
+
+fn functor(dom: Rc<Schema>, codom: Rc<Schema>, objects: &[(&str, &str)], homs: &[(&str, &[&str])]) -> SchemaFunctor {
+    let objects = objects.iter().map(|(object, image)| (object.to_string(), image.to_string())).collect();
+    let homs = homs.iter().map(|(name, path)| (name.to_string(), strings(path))).collect();
+    SchemaFunctor::new(dom, codom, objects, homs, HashMap::new()).unwrap()
+}
+
+#[test]
+fn test_delta_along_swap_reverses_a_graph() {
+    let reverse = functor(graph_schema(), graph_schema(), &[], &[("src", &["tgt"]), ("tgt", &["src"])]);
+    let mut original = graph(3, &[(0, 1), (1, 2)]);
+    original.set_attr(2, "name", Value::String("end".to_string())).unwrap();
+    let reversed = delta_migrate(&reverse, &original).unwrap();
+    assert_eq!(reversed.subpart(0, "src").unwrap(), Some(1));
+    assert_eq!(reversed.subpart(1, "tgt").unwrap(), Some(1));
+    assert_eq!(reversed.incident(2, "src").unwrap(), &[1]);
+    assert_eq!(reversed.get_attr(2, "name").unwrap(), Some(&Value::String("end".to_string())));
+}
+
+#[test]
+fn test_delta_composes_paths() {
+    let chain = schema(&["A", "B", "C"], vec![hom("f", "A", "B"), hom("g", "B", "C")], &[], vec![]);
+    let shortcut = schema(&["A", "C"], vec![hom("h", "A", "C")], &[], vec![]);
+    let compose = functor(shortcut, Rc::clone(&chain), &[], &[("h", &["f", "g"])]);
+
+    let mut data = ACSet::new(chain);
+    data.add_parts("A", 2).unwrap();
+    data.add_parts("B", 2).unwrap();
+    data.add_parts("C", 2).unwrap();
+    data.set_subpart(0, "f", 1).unwrap();
+    data.set_subpart(1, "f", 0).unwrap();
+    data.set_subpart(0, "g", 0).unwrap();
+    data.set_subpart(1, "g", 1).unwrap();
+    let migrated = delta_migrate(&compose, &data).unwrap();
+    assert_eq!(migrated.nparts("A").unwrap(), 2);
+    assert_eq!(migrated.subpart(0, "h").unwrap(), Some(1));
+    assert_eq!(migrated.subpart(1, "h").unwrap(), Some(0));
+}
+
+#[test]
+fn test_sigma_collapsing_a_graph_gives_its_components() {
+    let point = schema(&["P"], vec![], &["Name"], vec![hom("name", "P", "Name")]);
+    let collapse = functor(graph_schema(), point, &[("V", "P"), ("E", "P")], &[("src", &[]), ("tgt", &[])]);
+    let components = sigma_migrate(&collapse, &graph(5, &[(0, 1), (1, 2), (4, 3)])).unwrap();
+    assert_eq!(components.nparts("P").unwrap(), 2);
+}
+
+#[test]
+fn test_sigma_adds_missing_parts_freely() {
+    let set = schema(&["A"], vec![], &[], vec![]);
+    let arrow = schema(&["A", "B"], vec![hom("f", "A", "B")], &[], vec![]);
+    let include = functor(set.clone(), arrow, &[], &[]);
+    let mut data = ACSet::new(set);
+    data.add_parts("A", 3).unwrap();
+    let migrated = sigma_migrate(&include, &data).unwrap();
+    assert_eq!(migrated.nparts("B").unwrap(), 3);
+    let images: Vec<_> = (0..3).map(|a| migrated.subpart(a, "f").unwrap()).collect();
+    assert_eq!(images, vec![Some(0), Some(1), Some(2)]);
+}
+
+#[test]
+fn test_sigma_rejects_conflicting_attributes_and_cycles() {
+    let labelled = schema(&["P"], vec![], &["Name"], vec![hom("name", "P", "Name")]);
+    let mut objects = HashMap::new();
+    objects.insert("V".to_string(), "P".to_string());
+    objects.insert("E".to_string(), "P".to_string());
+    let mut homs = HashMap::new();
+    homs.insert("src".to_string(), vec![]);
+    homs.insert("tgt".to_string(), vec![]);
+    let collapse = SchemaFunctor::new(graph_schema(), labelled, objects, homs, HashMap::new()).unwrap();
+    let mut named = graph(2, &[(0, 1)]);
+    named.set_attr(0, "name", Value::String("a".to_string())).unwrap();
+    named.set_attr(1, "name", Value::String("b".to_string())).unwrap();
+    let error = sigma_migrate(&collapse, &named).unwrap_err();
+    assert!(error.to_string().contains("whose name differ"), "{}", error);
+
+    let set = schema(&["A"], vec![], &[], vec![]);
+    let cycle = schema(&["A"], vec![hom("next", "A", "A")], &[], vec![]);
+    let mut data = ACSet::new(set.clone());
+    data.add_part("A").unwrap();
+    let error = sigma_migrate(&functor(set, cycle, &[], &[]), &data).unwrap_err();
+    assert!(error.to_string().contains("may form a cycle"), "{}", error);
+}
+
+#[test]
+fn test_functor_paths_are_checked() {
+    let point = schema(&["P"], vec![], &[], vec![]);
+    let error = SchemaFunctor::new(graph_schema(), point, HashMap::new(), HashMap::new(), HashMap::new()).unwrap_err();
+    assert!(error.to_string().contains("Object V goes to V"), "{}", error);
+
+    let mut homs = HashMap::new();
+    homs.insert("src".to_string(), strings(&["src", "src"]));
+    let error = SchemaFunctor::new(graph_schema(), graph_schema(), HashMap::new(), homs, HashMap::new()).unwrap_err();
+    assert!(error.to_string().contains("cannot follow src"), "{}", error);
+}
+
+#[test]
+fn test_migration_words() {
+    let mut evaluator = Evaluator::new();
+    evaluator.initialize().unwrap();
+    let graph = evaluator.eval("(\"V\" \"E\") ((\"src\" \"E\" \"V\") (\"tgt\" \"E\" \"V\")) () () schema").unwrap();
+    evaluator.env.set("graph", graph);
+    evaluator.stack.clear();
+    let reverse = evaluator.eval("graph graph () ((\"src\" \"tgt\") (\"tgt\" \"src\")) () schema_functor").unwrap();
+    evaluator.env.set("reverse", reverse);
+    evaluator.stack.clear();
+    evaluator.eval("graph empty_acset \"V\" 2 add_parts drop \"E\" add_part \"src\" 0 set_subpart 0 \"tgt\" 1 set_subpart").unwrap();
+    evaluator.eval("reverse delta_migrate 0 \"src\" subpart").unwrap();
+    assert_eq!(evaluator.stack.pop(), Some(Value::Optional(Some(Box::new(Value::Number(1))))));
+    assert_eq!(evaluator.stack.last().unwrap().to_string(), "ACSet(V: 2, E: 1)");
+}
@@ -0,0 +1,117 @@
+// Tests for TCP and Unix domain sockets as linear resources, over loopback
+
+use std::io::Write;
+use std::net::TcpListener;
+use borf_lib::repl::interpreter::{Capabilities, Evaluator, EvaluatorError, Value};
+
+fn evaluator() -> Evaluator {
+    let mut evaluator = Evaluator::new();
+    evaluator.initialize().unwrap();
+    evaluator.capabilities = Capabilities::all();
+    evaluator
+}
+
+fn received(text: &str) -> Value {
+    Value::Optional(Some(Box::new(Value::String(text.to_string()))))
+}
+
+#[test]
+fn test_tcp_round_trip() {
+    let mut evaluator = evaluator();
+    // Connections wait in the listen backlog, so one thread can be both ends
+    evaluator.eval("\"127.0.0.1\" 0 tcp_listen local_port \"127.0.0.1\" swap tcp_connect").unwrap();
+    evaluator.eval("\"ping\" send swap accept recv").unwrap();
+    assert_eq!(evaluator.stack.last(), Some(&received("ping")));
+
+    // The server answers and hangs up; the client sees the answer, then the end
+    evaluator.eval("drop \"pong\" send close close recv").unwrap();
+    assert_eq!(evaluator.stack.last(), Some(&received("pong")));
+    evaluator.eval("drop recv").unwrap();
+    assert_eq!(evaluator.stack.last(), Some(&Value::Optional(None)));
+    evaluator.eval("drop close").unwrap();
+    assert!(evaluator.stack.is_empty());
+    assert!(evaluator.check_for_resource_leaks().is_ok());
+}
+
+#[test]
+fn test_characters_split_across_reads_arrive_whole() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let mut evaluator = evaluator();
+    evaluator.eval(&format!("\"127.0.0.1\" {} tcp_connect", port)).unwrap();
+    let (mut peer, _) = listener.accept().unwrap();
+
+    // "é" is 0xC3 0xA9; recv holds back the first byte until the second arrives
+    peer.write_all(b"a\xC3").unwrap();
+    evaluator.eval("recv").unwrap();
+    assert_eq!(evaluator.stack.last(), Some(&received("a")));
+    peer.write_all(b"\xA9b").unwrap();
+    evaluator.eval("drop recv").unwrap();
+    assert_eq!(evaluator.stack.last(), Some(&received("éb")));
+
+    // A line read after recv starts with what recv held back
+    peer.write_all(b"c\xC3").unwrap();
+    evaluator.eval("drop recv").unwrap();
+    assert_eq!(evaluator.stack.last(), Some(&received("c")));
+    peer.write_all(b"\xA9 d\n").unwrap();
+    evaluator.eval("drop read_line").unwrap();
+    assert_eq!(evaluator.stack.last(), Some(&received("é d")));
+    evaluator.eval("drop close").unwrap();
+    assert!(evaluator.check_for_resource_leaks().is_ok());
+}
+
+#[test]
+fn test_lines_over_tcp() {
+    let mut evaluator = evaluator();
+    evaluator.eval("\"127.0.0.1\" 0 tcp_listen local_port \"127.0.0.1\" swap tcp_connect").unwrap();
+    evaluator.eval("\"first\" send \"\n\" send \"second\" write swap accept").unwrap();
+    evaluator.eval("read_line").unwrap();
+    assert_eq!(evaluator.stack.last(), Some(&received("first")));
+    evaluator.eval("drop rot close read_all").unwrap();
+    assert_eq!(evaluator.stack.last(), Some(&Value::String("second".to_string())));
+    evaluator.eval("drop close close").unwrap();
+    assert!(evaluator.check_for_resource_leaks().is_ok());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_unix_socket_round_trip() {
+    let path = std::env::temp_dir().join(format!("borf_net_{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let path = path.display().to_string();
+    let mut evaluator = evaluator();
+    evaluator.eval(&format!("\"{}\" unix_listen \"{}\" unix_connect \"hello\" send swap accept recv", path, path)).unwrap();
+    assert_eq!(evaluator.stack.last(), Some(&received("hello")));
+
+    // Closing the listener removes its socket file
+    evaluator.eval("drop close close close").unwrap();
+    assert!(evaluator.stack.is_empty());
+    assert!(!std::path::Path::new(&path).exists());
+}
+
+#[test]
+fn test_closed_socket_cannot_be_used() {
+    let mut evaluator = evaluator();
+    evaluator.eval("\"127.0.0.1\" 0 tcp_listen local_port \"127.0.0.1\" swap tcp_connect dup close").unwrap();
+    let error = evaluator.eval("\"late\" send").unwrap_err().to_string();
+    assert!(error.contains("has been consumed"), "{}", error);
+}
+
+#[test]
+fn test_unclosed_listener_is_a_leak() {
+    let mut evaluator = evaluator();
+    evaluator.eval("\"127.0.0.1\" 0 tcp_listen").unwrap();
+    let error = evaluator.check_for_resource_leaks().unwrap_err().to_string();
+    assert!(error.contains("type Listener"), "{}", error);
+    evaluator.eval("close").unwrap();
+}
+
+#[test]
+fn test_network_needs_capability() {
+    let mut evaluator = evaluator();
+    evaluator.capabilities = Capabilities::none();
+    evaluator.capabilities.allow_net("127.0.0.1");
+    evaluator.eval("\"127.0.0.1\" 0 tcp_listen close").unwrap();
+    let error = evaluator.eval("\"localhost\" 0 tcp_listen").unwrap_err();
+    assert!(matches!(error, EvaluatorError::PermissionDenied(_)), "{}", error);
+}
@@ -0,0 +1,111 @@
+// Tests for the peephole optimizer over stack code
+
+use std::collections::HashMap;
+use borf_lib::repl::interpreter::{equivalent_code, Evaluator, Expr, Optimizer, Value};
+
+fn code(words: &str) -> Vec<Expr> {
+    words.split_whitespace()
+        .map(|word| match word.parse::<i32>() {
+            Ok(n) => Expr::Number(n),
+            Err(_) => Expr::Symbol(word.to_string()),
+        })
+        .collect()
+}
+
+fn optimized(words: &str) -> String {
+    let optimized = Optimizer::new().optimize(&code(words));
+    optimized.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(" ")
+}
+
+#[test]
+fn test_builtin_rules_preserve_the_stack() {
+    let known = HashMap::new();
+    for rule in Optimizer::new().rules() {
+        assert!(equivalent_code(&rule.pattern, &rule.replacement, &known), "{}", rule);
+    }
+}
+
+#[test]
+fn test_builtin_rewrites() {
+    assert_eq!(optimized("swap swap"), "");
+    assert_eq!(optimized("dup drop 1 add"), "1 add");
+    assert_eq!(optimized("over over"), "2dup");
+    assert_eq!(optimized("0 pick mul"), "dup mul");
+    assert_eq!(optimized("swap add"), "add");
+    assert_eq!(optimized("swap sub"), "swap sub");
+}
+
+#[test]
+fn test_rewrites_repeat_until_nothing_applies() {
+    assert_eq!(optimized("swap dup drop swap"), "");
+    assert_eq!(optimized("dup swap swap drop 5"), "5");
+}
+
+#[test]
+fn test_nested_quotations_are_optimized() {
+    let program = vec![Expr::Quotation(vec![], code("swap swap dup")), Expr::Symbol("call".to_string())];
+    let optimized = Optimizer::new().optimize(&program);
+    assert_eq!(optimized[0].to_string(), "[dup]");
+}
+
+#[test]
+fn test_rules_must_leave_the_same_stack() {
+    let known = HashMap::new();
+    let mut optimizer = Optimizer::new();
+    assert!(optimizer.add_rule(code("dup"), code(""), &known).is_err());
+    assert!(optimizer.add_rule(code("swap"), code("swap"), &known).is_err());
+    assert!(optimizer.add_rule(code("dup add"), code("2 mul"), &known).is_err());
+    assert!(optimizer.add_rule(code("dup add"), code("2"), &known).is_err());
+    assert!(optimizer.add_rule(code("dup swap"), code("dup"), &known).is_ok());
+    assert_eq!(
+        optimizer.optimize(&code("dup swap dup swap")),
+        code("dup dup")
+    );
+}
+
+#[test]
+fn test_rules_that_only_keep_the_depth_are_rejected() {
+    let known = HashMap::new();
+    let mut optimizer = Optimizer::new();
+    for (pattern, replacement) in [("1 add 1 add", "2 add"), ("2 mul 2 mul", "4 mul"), ("swap sub", "sub"), ("over swap", "dup")] {
+        let error = optimizer.add_rule(code(pattern), code(replacement), &known).unwrap_err().to_string();
+        assert!(error.contains("cannot be shown to leave the same stack"), "{}", error);
+    }
+    assert_eq!(optimizer.rules().len(), Optimizer::new().rules().len());
+}
+
+#[test]
+fn test_rules_with_impure_words_are_rejected() {
+    let known = HashMap::new();
+    let mut optimizer = Optimizer::new();
+    for (pattern, replacement) in [("read_stdin_line drop", ""), ("send drop", "drop drop"), ("dup print", "print")] {
+        let error = optimizer.add_rule(code(pattern), code(replacement), &known).unwrap_err().to_string();
+        assert!(error.contains("which is not known to be pure"), "{}", error);
+    }
+    assert_eq!(optimizer.rules().len(), Optimizer::new().rules().len());
+}
+
+#[test]
+fn test_user_rules_written_in_borf() {
+    let mut evaluator = Evaluator::new();
+    evaluator.initialize().unwrap();
+    evaluator.eval("[dup swap] [dup] rule").unwrap();
+    assert_eq!(evaluator.optimize("[3 dup swap mul]").unwrap(), "[3 dup mul]");
+
+    // The optimize word rewrites user-written stack code, which still computes the same result
+    let quotation = evaluator.eval("[swap swap dup swap mul] optimize").unwrap();
+    assert_eq!(quotation.to_string(), "[dup mul]");
+    assert_eq!(evaluator.eval("5 swap call").unwrap(), Value::Number(25));
+}
+
+#[test]
+fn test_invalid_user_rule_is_rejected() {
+    let mut evaluator = Evaluator::new();
+    evaluator.initialize().unwrap();
+    let error = evaluator.eval("[dup dup] [dup] rule").unwrap_err().to_string();
+    assert!(error.contains("changes the stack depth"), "{}", error);
+    let error = evaluator.eval("[2 mul 2 mul] [4 mul] rule").unwrap_err().to_string();
+    assert!(error.contains("cannot be shown to leave the same stack"), "{}", error);
+    let error = evaluator.eval("[read_stdin_line drop] [] rule").unwrap_err().to_string();
+    assert!(error.contains("'read_stdin_line', which is not known to be pure"), "{}", error);
+}
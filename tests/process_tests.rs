@@ -0,0 +1,98 @@
+// Tests for subprocesses as linear Process and Pipe resources
+
+use std::collections::HashMap;
+use borf_lib::repl::interpreter::{Capabilities, Evaluator, EvaluatorError, Value};
+
+fn evaluator() -> Evaluator {
+    let mut evaluator = Evaluator::new();
+    evaluator.initialize().unwrap();
+    evaluator.capabilities = Capabilities::all();
+    evaluator
+}
+
+fn string(value: &str) -> Value {
+    Value::String(value.to_string())
+}
+
+#[test]
+fn test_run_captures_output_and_status() {
+    let mut evaluator = evaluator();
+    evaluator.eval("\"sh\" (\"-c\" \"echo out; echo err >&2; exit 3\") run").unwrap();
+    let mut expected = HashMap::new();
+    expected.insert("stdout".to_string(), string("out\n"));
+    expected.insert("stderr".to_string(), string("err\n"));
+    expected.insert("status".to_string(), Value::Number(3));
+    assert_eq!(evaluator.stack, vec![Value::Map(expected)]);
+}
+
+#[test]
+fn test_spawn_with_pipes() {
+    let mut evaluator = evaluator();
+    evaluator.eval("\"cat\" () spawn stdin_pipe \"hello\" write close_pipe").unwrap();
+    evaluator.eval("stdout_pipe read_all swap close_pipe swap wait").unwrap();
+    assert_eq!(evaluator.stack, vec![string("hello"), Value::Number(0)]);
+    assert!(evaluator.check_for_resource_leaks().is_ok());
+}
+
+#[test]
+fn test_read_lines_from_pipe() {
+    let mut evaluator = evaluator();
+    evaluator.eval("\"printf\" (\"a\\nb\\n\") spawn stdout_pipe read_line swap read_line swap read_line swap close_pipe").unwrap();
+    let line = |text: &str| Value::Optional(Some(Box::new(string(text))));
+    let lines: Vec<Value> = evaluator.stack.drain(1..).collect();
+    assert_eq!(lines, vec![line("a"), line("b"), Value::Optional(None)]);
+    evaluator.eval("wait").unwrap();
+    assert_eq!(evaluator.stack, vec![Value::Number(0)]);
+}
+
+#[test]
+fn test_wait_drains_output_nobody_took() {
+    // Far more than a pipe buffer holds on either stream
+    let mut evaluator = evaluator();
+    evaluator.eval("\"sh\" (\"-c\" \"head -c 300000 /dev/zero; head -c 300000 /dev/zero >&2; exit 5\") spawn wait").unwrap();
+    assert_eq!(evaluator.stack, vec![Value::Number(5)]);
+    assert!(evaluator.check_for_resource_leaks().is_ok());
+}
+
+#[test]
+fn test_process_is_waited_for_exactly_once() {
+    let mut evaluator = evaluator();
+    evaluator.eval("\"true\" () spawn dup wait").unwrap();
+    let error = evaluator.eval("drop wait").unwrap_err().to_string();
+    assert!(error.contains("has been consumed"), "{}", error);
+}
+
+#[test]
+fn test_kill_consumes_process() {
+    let mut evaluator = evaluator();
+    evaluator.eval("\"sleep\" (\"10\") spawn kill").unwrap();
+    assert!(evaluator.stack.is_empty());
+    assert!(evaluator.check_for_resource_leaks().is_ok());
+}
+
+#[test]
+fn test_unwaited_process_is_a_leak() {
+    let mut evaluator = evaluator();
+    evaluator.eval("\"true\" () spawn stdout_pipe close_pipe").unwrap();
+    let error = evaluator.check_for_resource_leaks().unwrap_err().to_string();
+    assert!(error.contains("type Process"), "{}", error);
+    evaluator.eval("wait").unwrap();
+}
+
+#[test]
+fn test_pipe_can_only_be_taken_once() {
+    let mut evaluator = evaluator();
+    evaluator.eval("\"true\" () spawn stdout_pipe close_pipe").unwrap();
+    let error = evaluator.eval("stdout_pipe").unwrap_err().to_string();
+    assert!(error.contains("has already been taken"), "{}", error);
+}
+
+#[test]
+fn test_subprocess_needs_capability() {
+    let mut evaluator = evaluator();
+    evaluator.capabilities = Capabilities::none();
+    evaluator.capabilities.allow_subprocess("echo");
+    evaluator.eval("\"echo\" (\"ok\") run").unwrap();
+    let error = evaluator.eval("\"sh\" () spawn").unwrap_err();
+    assert!(matches!(error, EvaluatorError::PermissionDenied(_)), "{}", error);
+}
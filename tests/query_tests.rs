@@ -0,0 +1,93 @@
+// Tests for homomorphism search and conjunctive queries over ACSets
+
+mod common;
+
+use std::collections::HashMap;
+use borf_lib::repl::interpreter::{homomorphisms, ACSet, Evaluator, Value};
+use common::graph;
+
+fn path(length: usize) -> ACSet {
+    let edges: Vec<_> = (0..length).map(|i| (i, i + 1)).collect();
+    graph(length + 1, &edges)
+}
+
+fn cycle(length: usize) -> ACSet {
+    let edges: Vec<_> = (0..length).map(|i| (i, (i + 1) % length)).collect();
+    graph(length, &edges)
+}
+
+#[test]
+fn test_paths_into_a_cycle() {
+    // A path of length 2 can start anywhere on a triangle
+    let found = homomorphisms(&path(2), &cycle(3), false).unwrap();
+    assert_eq!(found.len(), 3);
+    for homomorphism in &found {
+        let v = &homomorphism["V"];
+        assert_eq!((v[0] + 1) % 3, v[1]);
+        assert_eq!((v[1] + 1) % 3, v[2]);
+    }
+}
+
+#[test]
+fn test_monic_homomorphisms_are_injective() {
+    // A path of length 3 wraps around a triangle, but not injectively
+    assert_eq!(homomorphisms(&path(3), &cycle(3), false).unwrap().len(), 3);
+    assert!(homomorphisms(&path(3), &cycle(3), true).unwrap().is_empty());
+
+    // Two isolated vertices go anywhere in a triangle, but injectively only to distinct ones
+    assert_eq!(homomorphisms(&graph(2, &[]), &cycle(3), false).unwrap().len(), 9);
+    assert_eq!(homomorphisms(&graph(2, &[]), &cycle(3), true).unwrap().len(), 6);
+}
+
+#[test]
+fn test_loops_only_map_to_loops() {
+    let target = graph(3, &[(0, 1), (2, 2)]);
+    let found = homomorphisms(&graph(1, &[(0, 0)]), &target, false).unwrap();
+    let expected: HashMap<String, Vec<usize>> = [("V".to_string(), vec![2]), ("E".to_string(), vec![1])].into_iter().collect();
+    assert_eq!(found, vec![expected]);
+}
+
+#[test]
+fn test_pattern_attributes_constrain_matches() {
+    let mut target = cycle(3);
+    for (vertex, name) in ["a", "b", "c"].iter().enumerate() {
+        target.set_attr(vertex, "name", Value::String(name.to_string())).unwrap();
+    }
+    let mut pattern = path(1);
+    pattern.set_attr(1, "name", Value::String("a".to_string())).unwrap();
+    let found = homomorphisms(&pattern, &target, false).unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0]["V"], vec![2, 0]);
+}
+
+#[test]
+fn test_query_words() {
+    let mut evaluator = Evaluator::new();
+    evaluator.initialize().unwrap();
+    evaluator.eval("(\"V\" \"E\") ((\"src\" \"E\" \"V\") (\"tgt\" \"E\" \"V\")) (\"Name\") ((\"name\" \"V\" \"Name\")) schema empty_acset").unwrap();
+    evaluator.eval("\"V\" 3 add_parts drop 0 \"name\" \"a\" set_prop 1 \"name\" \"b\" set_prop 2 \"name\" \"c\" set_prop").unwrap();
+    evaluator.eval("\"E\" add_part \"src\" 0 set_subpart 0 \"tgt\" 1 set_subpart").unwrap();
+    evaluator.eval("\"E\" add_part \"src\" 1 set_subpart 1 \"tgt\" 2 set_subpart").unwrap();
+
+    evaluator.eval("((\"e\" \"E\") (\"f\" \"E\") (\"v\" \"V\")) ((\"tgt\" \"e\" \"v\") (\"src\" \"f\" \"v\") (\"name\" \"v\" \"b\")) query").unwrap();
+    let answer: HashMap<String, Value> = [("e", 0), ("f", 1), ("v", 1)].iter()
+        .map(|(name, part)| (name.to_string(), Value::Number(*part)))
+        .collect();
+    assert_eq!(evaluator.stack.pop(), Some(Value::List(vec![Value::Map(answer)])));
+
+    // With every vertex named, the graph's only automorphism is the identity
+    evaluator.eval("dup monic_homomorphisms").unwrap();
+    let identity: HashMap<String, Value> = [("V", vec![0, 1, 2]), ("E", vec![0, 1])].iter()
+        .map(|(object, parts)| (object.to_string(), Value::List(parts.iter().map(|&part| Value::Number(part)).collect())))
+        .collect();
+    assert_eq!(evaluator.stack.pop(), Some(Value::List(vec![Value::Map(identity)])));
+}
+
+#[test]
+fn test_query_checks_variables() {
+    let mut evaluator = Evaluator::new();
+    evaluator.initialize().unwrap();
+    evaluator.eval("(\"V\" \"E\") ((\"src\" \"E\" \"V\")) () () schema empty_acset").unwrap();
+    let error = evaluator.eval("((\"x\" \"V\")) ((\"src\" \"x\" \"x\")) query").unwrap_err();
+    assert!(error.to_string().contains("x is a V, not a E"), "{}", error);
+}
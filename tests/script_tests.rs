@@ -0,0 +1,81 @@
+// Tests for script mode: arguments, environment, standard input and exit
+
+use std::io::Cursor;
+use borf_lib::repl::interpreter::{Capabilities, Evaluator, EvaluatorError, Value};
+
+fn evaluator() -> Evaluator {
+    let mut evaluator = Evaluator::new();
+    evaluator.initialize().unwrap();
+    evaluator
+}
+
+fn string(value: &str) -> Value {
+    Value::String(value.to_string())
+}
+
+fn line(value: &str) -> Value {
+    Value::Optional(Some(Box::new(string(value))))
+}
+
+#[test]
+fn test_args() {
+    let mut evaluator = evaluator();
+    evaluator.args = vec!["a".to_string(), "--flag".to_string()];
+    evaluator.eval("args").unwrap();
+    assert_eq!(evaluator.stack, vec![Value::List(vec![string("a"), string("--flag")])]);
+}
+
+#[test]
+fn test_getenv_needs_env_capability() {
+    std::env::set_var("BORF_SCRIPT_TEST", "value");
+    let mut evaluator = evaluator();
+    let error = evaluator.eval("\"BORF_SCRIPT_TEST\" getenv").unwrap_err();
+    assert!(matches!(error, EvaluatorError::PermissionDenied(_)), "{}", error);
+
+    evaluator.stack.clear();
+    evaluator.capabilities.allow_env("BORF_SCRIPT_TEST");
+    evaluator.capabilities.allow_env("BORF_SCRIPT_UNSET");
+    evaluator.eval("\"BORF_SCRIPT_TEST\" getenv \"BORF_SCRIPT_UNSET\" getenv").unwrap();
+    assert_eq!(evaluator.stack, vec![line("value"), Value::Optional(None)]);
+}
+
+#[test]
+fn test_read_stdin_line_and_read_stdin() {
+    let mut evaluator = evaluator();
+    evaluator.input = Box::new(Cursor::new("first\nsecond\nrest\nof input"));
+    evaluator.eval("read_stdin_line read_stdin_line read_stdin read_stdin_line").unwrap();
+    assert_eq!(evaluator.stack, vec![
+        line("first"),
+        line("second"),
+        string("rest\nof input"),
+        Value::Optional(None),
+    ]);
+}
+
+#[test]
+fn test_read_line_does_not_read_standard_input() {
+    let mut evaluator = evaluator();
+    evaluator.input = Box::new(Cursor::new("first\n"));
+    assert!(evaluator.eval("1 read_line").is_err());
+    evaluator.stack.clear();
+    evaluator.eval("read_stdin_line").unwrap();
+    assert_eq!(evaluator.stack, vec![line("first")]);
+}
+
+#[test]
+fn test_exit_is_not_caught() {
+    let mut evaluator = evaluator();
+    evaluator.capabilities = Capabilities::all();
+    let error = evaluator.eval("[3 exit] [drop 0] try").unwrap_err();
+    assert!(matches!(error, EvaluatorError::Exit(3)), "{}", error);
+}
+
+#[test]
+fn test_script_with_shebang() {
+    let path = std::env::temp_dir().join(format!("borf_script_{}.borf", std::process::id()));
+    std::fs::write(&path, "#!/usr/bin/env borf\nargs\n").unwrap();
+    let mut evaluator = evaluator();
+    evaluator.args = vec!["x".to_string()];
+    evaluator.eval_file(&path).unwrap();
+    assert_eq!(evaluator.stack, vec![Value::List(vec![string("x")])]);
+}
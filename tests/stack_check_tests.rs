@@ -0,0 +1,135 @@
+// Tests for static stack-effect inference and verification of user words
+
+use std::collections::HashMap;
+use borf_lib::repl::interpreter::{check_program, format_effect, infer_effect, parse_stack_effect, Expr, Param, StackEffect};
+
+fn sym(name: &str) -> Expr {
+    Expr::Symbol(name.to_string())
+}
+
+fn quot(body: Vec<Expr>) -> Expr {
+    Expr::Quotation(vec![], body)
+}
+
+fn effect(inputs: &[&str], outputs: &[&str]) -> StackEffect {
+    StackEffect::new(
+        inputs.iter().map(|s| s.to_string()).collect(),
+        outputs.iter().map(|s| s.to_string()).collect(),
+    )
+}
+
+fn define(name: &str, body: Vec<Expr>) -> Expr {
+    Expr::Assignment(Box::new(quot(body)), name.to_string())
+}
+
+fn infer(body: Vec<Expr>) -> Option<String> {
+    infer_effect(&[], &body, &HashMap::new()).unwrap().map(|e| format_effect(&e))
+}
+
+#[test]
+fn test_infer_shuffle_words() {
+    assert_eq!(infer(vec![sym("swap")]), Some("( a b -- b a )".to_string()));
+    assert_eq!(infer(vec![sym("over"), sym("over")]), Some("( a b -- a b a b )".to_string()));
+    assert_eq!(infer(vec![Expr::Number(2), sym("pick")]), Some("( a b c -- a b c a )".to_string()));
+}
+
+#[test]
+fn test_infer_through_combinators() {
+    // [1 +] dip leaves the top item alone and increments the one below it
+    let body = vec![quot(vec![Expr::Number(1), sym("+")]), sym("dip")];
+    assert_eq!(infer(body), Some("( a b -- sum b )".to_string()));
+
+    // [dup] [drop] bi
+    let body = vec![quot(vec![sym("dup")]), quot(vec![sym("drop")]), sym("bi")];
+    assert_eq!(infer(body), Some("( a -- a a )".to_string()));
+}
+
+#[test]
+fn test_unknown_words_are_not_inferred() {
+    assert_eq!(infer(vec![sym("frobnicate")]), None);
+    assert_eq!(infer(vec![sym("call")]), None);
+}
+
+#[test]
+fn test_quotations_capturing_parameters_are_inferred() {
+    // [x] captures x, an input of the word, but takes nothing from the stack itself
+    let params = vec![Param { name: "x".to_string(), type_annotation: None }];
+    let effect = infer_effect(&params, &[quot(vec![sym("x")])], &HashMap::new()).unwrap();
+    assert_eq!(effect.map(|e| format_effect(&e)), Some("( a -- quot: ( -- x ) )".to_string()));
+}
+
+#[test]
+fn test_definitions_compose() {
+    let program = Expr::Sequence(vec![
+        define("square", vec![sym("dup"), sym("*")]),
+        define("sum_of_squares", vec![sym("square"), sym("swap"), sym("square"), sym("+")]),
+    ]);
+    let effects = check_program(&program).unwrap();
+    assert_eq!(effects["square"].stack_depth_change(), 0);
+    assert_eq!(format_effect(&effects["sum_of_squares"]), "( a b -- sum )");
+}
+
+#[test]
+fn test_declared_effect_is_verified() {
+    let program = Expr::Sequence(vec![
+        Expr::StackEffect(effect(&["n"], &["n"])),
+        define("square", vec![sym("dup"), sym("*")]),
+    ]);
+    assert!(check_program(&program).is_ok());
+}
+
+#[test]
+fn test_declared_effect_after_name() {
+    let program = Expr::Sequence(vec![
+        sym("square"),
+        Expr::StackEffect(effect(&["n"], &["n"])),
+        define("square", vec![sym("dup"), sym("*")]),
+    ]);
+    assert!(check_program(&program).is_ok());
+}
+
+#[test]
+fn test_underflow_is_reported() {
+    let program = Expr::Sequence(vec![
+        Expr::StackEffect(effect(&["a"], &["b"])),
+        define("add_one_wrong", vec![sym("+")]),
+    ]);
+    let error = check_program(&program).unwrap_err().to_string();
+    assert!(error.contains("Stack underflow"), "{}", error);
+}
+
+#[test]
+fn test_imbalance_is_reported() {
+    let program = Expr::Sequence(vec![
+        Expr::StackEffect(effect(&["a", "b"], &["c"])),
+        define("keeps_both", vec![sym("dup"), sym("+")]),
+    ]);
+    let error = check_program(&program).unwrap_err().to_string();
+    assert!(error.contains("declared ( a b -- c )"), "{}", error);
+}
+
+#[test]
+fn test_if_branches_must_balance() {
+    let branches = Expr::If(
+        Box::new(Expr::Nil),
+        Box::new(Expr::Sequence(vec![sym("drop")])),
+        Box::new(Expr::Sequence(vec![])),
+    );
+    let program = define("unbalanced", vec![branches]);
+    assert!(check_program(&program).is_err());
+}
+
+#[test]
+fn test_declared_quotation_effect_is_used() {
+    let program = Expr::Sequence(vec![
+        Expr::StackEffect(parse_stack_effect("( x quot: ( a -- b b ) -- y z )").unwrap()),
+        define("apply", vec![sym("call")]),
+    ]);
+    assert!(check_program(&program).is_ok());
+
+    let program = Expr::Sequence(vec![
+        Expr::StackEffect(parse_stack_effect("( x quot: ( a -- b ) -- y z )").unwrap()),
+        define("apply", vec![sym("call")]),
+    ]);
+    assert!(check_program(&program).is_err());
+}
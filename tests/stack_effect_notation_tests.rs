@@ -0,0 +1,78 @@
+// Tests for typed, higher-order and row-polymorphic stack effect notation
+
+use borf_lib::repl::interpreter::{get_word_effect, parse_stack_effect, StackItem, Type};
+
+#[test]
+fn test_plain_effect() {
+    let effect = parse_stack_effect("( a b -- c )").unwrap();
+    assert_eq!(effect.input_names(), vec!["a", "b"]);
+    assert_eq!(effect.output_names(), vec!["c"]);
+    assert_eq!(effect.stack_depth_change(), -1);
+    assert!(!effect.is_row_polymorphic());
+}
+
+#[test]
+fn test_typed_items() {
+    let effect = parse_stack_effect("( a:Int f: !File -- ok:?Bool xs:List[Int] )").unwrap();
+    assert_eq!(effect.inputs[0], StackItem::typed("a", Type::Simple("Int".to_string())));
+    assert_eq!(
+        effect.inputs[1].item_type,
+        Some(Type::Linear(Box::new(Type::Simple("File".to_string()))))
+    );
+    assert_eq!(
+        effect.outputs[0].item_type,
+        Some(Type::Optional(Box::new(Type::Simple("Bool".to_string()))))
+    );
+    assert_eq!(
+        effect.outputs[1].item_type,
+        Some(Type::Generic("List".to_string(), vec![Type::Simple("Int".to_string())]))
+    );
+}
+
+#[test]
+fn test_quotation_items_and_rows() {
+    let effect = parse_stack_effect("( ..a x quot: ( ..a -- ..b ) -- ..b x )").unwrap();
+    assert_eq!(effect.input_row.as_deref(), Some("..a"));
+    assert_eq!(effect.output_row.as_deref(), Some("..b"));
+    assert!(effect.is_row_polymorphic());
+
+    let quot = effect.inputs[1].effect.as_ref().unwrap();
+    assert_eq!(quot.input_row.as_deref(), Some("..a"));
+    assert!(quot.inputs.is_empty());
+    assert_eq!(effect.to_string(), "( ..a x quot: ( ..a -- ..b ) -- ..b x )");
+}
+
+#[test]
+fn test_invalid_effects() {
+    assert!(parse_stack_effect("( a b c )").is_err());
+    assert!(parse_stack_effect("( a ..b -- )").is_err());
+    assert!(parse_stack_effect("( quot: -- )").is_err());
+    assert!(parse_stack_effect("( a -- b ) c").is_err());
+}
+
+#[test]
+fn test_combinator_effects_use_quotation_notation() {
+    let dip = get_word_effect("dip").unwrap();
+    assert_eq!(dip.to_string(), "( ..a x quot: ( ..a -- ..b ) -- ..b x )");
+
+    let bi = get_word_effect("bi").unwrap();
+    let p = bi.inputs[1].effect.as_ref().unwrap();
+    let q = bi.inputs[2].effect.as_ref().unwrap();
+    assert_eq!(p.output_row, q.input_row);
+
+    // bi@ runs its quotation twice, so the quotation leaves the row below alone
+    let bi_at = get_word_effect("bi@").unwrap();
+    assert_eq!(bi_at.to_string(), "( ..a x y quot: ( ..a x -- ..a z ) -- ..a z z )");
+
+    let curry = get_word_effect("curry").unwrap();
+    assert_eq!(curry.to_string(), "( x quot: ( ..a x -- ..b ) -- curried: ( ..a -- ..b ) )");
+    assert!(curry.outputs[0].effect.is_some());
+
+    for word in ["call", "dip", "keep", "bi", "tri", "bi*", "bi@", "curry"] {
+        let effect = get_word_effect(word).unwrap();
+        assert!(effect.inputs.iter().any(|item| item.effect.is_some()), "{}", word);
+    }
+    for word in ["call", "dip", "keep", "bi", "tri", "bi*"] {
+        assert!(get_word_effect(word).unwrap().is_row_polymorphic(), "{}", word);
+    }
+}
@@ -0,0 +1,63 @@
+// Tests for row-polymorphic stack type inference of quotations
+
+use borf_lib::repl::interpreter::{format_type, infer_quotation, Expr, Param, Value};
+
+fn sym(name: &str) -> Expr {
+    Expr::Symbol(name.to_string())
+}
+
+fn param(name: &str) -> Param {
+    Param { name: name.to_string(), type_annotation: None }
+}
+
+fn no_words(_: &str) -> Option<Value> {
+    None
+}
+
+fn infer(params: &[Param], body: &[Expr]) -> String {
+    match infer_quotation(params, body, &no_words) {
+        Ok(signature) => format_type(&signature.to_type()),
+        Err(e) => format!("error: {}", e),
+    }
+}
+
+#[test]
+fn test_named_parameters_addition() {
+    let body = vec![sym("x"), sym("y"), sym("+")];
+    assert_eq!(infer(&[param("x"), param("y")], &body), "( Num Num -- Num )");
+}
+
+#[test]
+fn test_point_free_square() {
+    assert_eq!(infer(&[], &[sym("dup"), sym("*")]), "( Num -- Num )");
+}
+
+#[test]
+fn test_polymorphic_swap() {
+    assert_eq!(infer(&[], &[sym("swap")]), "( a b -- b a )");
+}
+
+#[test]
+fn test_nested_quotation_is_pushed() {
+    let body = vec![Expr::Quotation(vec![], vec![sym("dup")])];
+    assert_eq!(infer(&[], &body), "( -- ( a -- a a ) )");
+}
+
+#[test]
+fn test_call_is_row_polymorphic() {
+    assert_eq!(infer(&[], &[sym("call")]), "( ..a ( ..a -- ..b ) -- ..b )");
+}
+
+#[test]
+fn test_type_mismatch_is_reported() {
+    let body = vec![Expr::String("a".to_string()), Expr::Number(1), sym("+")];
+    assert!(infer(&[], &body).starts_with("error:"));
+}
+
+#[test]
+fn test_user_words_are_looked_up() {
+    let square = Value::Quotation(vec![], vec![sym("dup"), sym("*")], None);
+    let lookup = |name: &str| if name == "square" { Some(square.clone()) } else { None };
+    let signature = infer_quotation(&[], &[sym("square"), sym("square")], &lookup).unwrap();
+    assert_eq!(signature.to_string(), "( Num -- Num )");
+}
@@ -0,0 +1,87 @@
+// Tests for running quotations through their STACKER translation
+
+use borf_lib::repl::interpreter::{Capabilities, Env, Evaluator, Expr, Param, Strategy, Value};
+
+fn evaluator(strategy: Strategy) -> Evaluator {
+    let mut evaluator = Evaluator::new();
+    evaluator.initialize().unwrap();
+    evaluator.strategy = strategy;
+    evaluator
+}
+
+#[test]
+fn test_stacker_strategy_runs_translated_code() {
+    let mut evaluator = evaluator(Strategy::Stacker);
+    let program = "[x y -> x x mul y add] : f  3 4 f";
+    assert_eq!(evaluator.eval(program).unwrap(), Value::Number(13));
+    // The translation binds nothing, so the parameter names never reach the environment
+    assert_eq!(evaluator.eval("5 6 f").unwrap(), Value::Number(31));
+    assert!(evaluator.env.get("x").is_none());
+}
+
+#[test]
+fn test_stacker_strategy_falls_back_for_untranslatable_bodies() {
+    let mut evaluator = evaluator(Strategy::Stacker);
+    assert_eq!(evaluator.eval("7 [x -> x print x] call").unwrap(), Value::Number(7));
+}
+
+#[test]
+fn test_stacker_strategy_keeps_values_captured_in_closures() {
+    // `dup` inside the quotation is the captured 10, not the word
+    let mut evaluator = evaluator(Strategy::Stacker);
+    let mut closure = Env::with_parent(&evaluator.env);
+    closure.set("dup", Value::Number(10));
+    let params = vec![Param { name: "x".to_string(), type_annotation: None }];
+    let body = vec![Expr::Symbol("x".to_string()), Expr::Symbol("dup".to_string())];
+    evaluator.stack.push(Value::Number(7));
+    evaluator.stack.push(Value::Quotation(params, body, Some(Box::new(closure))));
+    evaluator.eval("call").unwrap();
+    assert_eq!(evaluator.stack, vec![Value::Number(7), Value::Number(10)]);
+}
+
+#[test]
+fn test_named_strategy_binds_parameters() {
+    let mut evaluator = evaluator(Strategy::Named);
+    // The translator cannot follow a quotation parameter through `call`
+    assert_eq!(evaluator.eval("[q -> 5 q call] : run  [1 add] run").unwrap(), Value::Number(6));
+    // The inner quotation finds x in the environment its closure captured
+    assert_eq!(evaluator.eval("[x -> [x 1 add]] : later  7 later call").unwrap(), Value::Number(8));
+    assert!(evaluator.env.get("x").is_none());
+}
+
+#[test]
+fn test_differential_agrees() {
+    let source = "[x y -> y x sub] : rsub  [x -> [y -> x y sub]] : minus_from  10 3 rsub 20 minus_from 5 swap call";
+    let report = Evaluator::differential(source, &Capabilities::none());
+    assert!(!report.diverged(), "{}", report);
+    assert_eq!(report.named, Ok(vec![Value::Number(-7), Value::Number(15)]));
+    assert!(report.to_string().starts_with("Strategies agree"));
+}
+
+#[test]
+fn test_differential_reports_errors_from_both_runs() {
+    let report = Evaluator::differential("[x y -> x y add] call", &Capabilities::none());
+    assert!(report.named.is_err());
+    assert!(report.stacker.is_err());
+}
+
+#[test]
+fn test_differential_reports_fallbacks() {
+    let report = Evaluator::differential("[x -> x 0 eq [1] [x] if] : f  5 f 0 f", &Capabilities::none());
+    assert!(!report.diverged(), "{}", report);
+    assert_eq!(report.fallbacks, vec!["[x -> x 0 eq [1] [x] if]".to_string()]);
+    assert!(report.to_string().contains("stacker bound the parameters of [x -> x 0 eq [1] [x] if]"), "{}", report);
+    assert!(Evaluator::differential("[x -> x x mul] : sq  3 sq", &Capabilities::none()).fallbacks.is_empty());
+}
+
+#[test]
+fn test_differential_uses_given_capabilities() {
+    let source = "\"PATH\" getenv";
+    assert!(Evaluator::differential(source, &Capabilities::none()).named.is_err());
+
+    let mut capabilities = Capabilities::none();
+    capabilities.allow_env("PATH");
+    let report = Evaluator::differential(source, &capabilities);
+    assert!(report.named.is_ok(), "{}", report);
+    assert!(!report.diverged(), "{}", report);
+}
@@ -0,0 +1,113 @@
+// Tests for wiring diagrams with typed ports
+
+use borf_lib::repl::interpreter::{Evaluator, Value, Wire, WiringDiagram, OUTER_BOX};
+
+fn types(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| name.to_string()).collect()
+}
+
+fn wire(source: (usize, usize), target: (usize, usize)) -> Wire {
+    Wire { source, target }
+}
+
+// A diagram with one box of the given interface, wired straight through
+fn single(name: &str, inputs: &[&str], outputs: &[&str]) -> WiringDiagram {
+    let mut diagram = WiringDiagram::new(types(inputs), types(outputs));
+    let number = diagram.add_box(name, types(inputs), types(outputs));
+    for port in 0..inputs.len() {
+        diagram.add_wire((OUTER_BOX, port), (number, port)).unwrap();
+    }
+    for port in 0..outputs.len() {
+        diagram.add_wire((number, port), (OUTER_BOX, port)).unwrap();
+    }
+    diagram
+}
+
+#[test]
+fn test_wires_are_type_checked() {
+    let mut diagram = WiringDiagram::new(types(&["Int"]), types(&["String"]));
+    let show = diagram.add_box("show", types(&["Int"]), types(&["String"]));
+    assert_eq!(show, 1);
+    diagram.add_wire((OUTER_BOX, 0), (show, 0)).unwrap();
+    let error = diagram.add_wire((OUTER_BOX, 0), (OUTER_BOX, 0)).unwrap_err();
+    assert!(error.to_string().contains("Cannot wire a Int port"), "{}", error);
+    let error = diagram.add_wire((show, 1), (OUTER_BOX, 0)).unwrap_err();
+    assert!(error.to_string().contains("Box 1 has no output port 1"), "{}", error);
+    assert!(diagram.add_wire((2, 0), (OUTER_BOX, 0)).is_err());
+
+    let any = diagram.add_box("log", types(&["Any"]), types(&[]));
+    diagram.add_wire((show, 0), (any, 0)).unwrap();
+    diagram.validate().unwrap();
+}
+
+#[test]
+fn test_compose_joins_wires_through_the_boundary() {
+    let parse = single("parse", &["String"], &["Int"]);
+    let double = single("double", &["Int"], &["Int"]);
+    let composed = parse.compose(&double).unwrap();
+    assert_eq!(composed.inputs, types(&["String"]));
+    assert_eq!(composed.outputs, types(&["Int"]));
+    assert_eq!(composed.boxes.len(), 2);
+    let mut wires = composed.wires.clone();
+    wires.sort();
+    assert_eq!(wires, vec![wire((0, 0), (1, 0)), wire((1, 0), (2, 0)), wire((2, 0), (0, 0))]);
+    composed.validate().unwrap();
+
+    let error = double.compose(&parse).unwrap_err();
+    assert!(error.to_string().contains("(Int) does not match (String)"), "{}", error);
+}
+
+#[test]
+fn test_tensor_renumbers_the_second_diagram() {
+    let parse = single("parse", &["String"], &["Int"]);
+    let add = single("add", &["Int", "Int"], &["Int"]);
+    let tensored = parse.tensor(&add);
+    assert_eq!(tensored.inputs, types(&["String", "Int", "Int"]));
+    assert_eq!(tensored.outputs, types(&["Int", "Int"]));
+    assert!(tensored.wires.contains(&wire((0, 2), (2, 1))));
+    assert!(tensored.wires.contains(&wire((2, 0), (0, 1))));
+    tensored.validate().unwrap();
+}
+
+#[test]
+fn test_substitute_replaces_a_box() {
+    let outer = single("parse", &["String"], &["Int"]).compose(&single("f", &["Int"], &["Int"])).unwrap();
+    let inner = single("inc", &["Int"], &["Int"]).compose(&single("double", &["Int"], &["Int"])).unwrap();
+    let substituted = outer.substitute(2, &inner).unwrap();
+    let names: Vec<_> = substituted.boxes.iter().map(|wiring_box| wiring_box.name.as_str()).collect();
+    assert_eq!(names, vec!["parse", "inc", "double"]);
+    let mut wires = substituted.wires.clone();
+    wires.sort();
+    assert_eq!(wires, vec![wire((0, 0), (1, 0)), wire((1, 0), (2, 0)), wire((2, 0), (3, 0)), wire((3, 0), (0, 0))]);
+
+    let error = outer.substitute(1, &inner).unwrap_err();
+    assert!(error.to_string().contains("Cannot substitute into box 1"), "{}", error);
+}
+
+#[test]
+fn test_to_dot() {
+    let dot = single("add", &["Int", "Int"], &["Int"]).to_dot();
+    assert!(dot.starts_with("digraph {"), "{}", dot);
+    assert!(dot.contains("box1 [label=\"{{<i0> Int|<i1> Int}|add|{<o0> Int}}\"];"), "{}", dot);
+    assert!(dot.contains("in1 -> box1:i1;"), "{}", dot);
+    assert!(dot.contains("box1:o0 -> out0;"), "{}", dot);
+}
+
+#[test]
+fn test_wiring_diagram_words() {
+    let mut evaluator = Evaluator::new();
+    evaluator.initialize().unwrap();
+    evaluator.eval("(\"Int\") (\"Int\") empty_diagram \"inc\" (\"Int\") (\"Int\") add_box").unwrap();
+    assert_eq!(evaluator.stack.pop(), Some(Value::Number(1)));
+    evaluator.eval("0 0 1 0 add_wire 1 0 0 0 add_wire dup compose").unwrap();
+    assert_eq!(evaluator.stack.last().unwrap().to_string(), "WiringDiagram(Int -> Int; 2 boxes, 3 wires)");
+    evaluator.eval("to_dot").unwrap();
+    match evaluator.stack.pop() {
+        Some(Value::String(dot)) => assert!(dot.contains("box1:o0 -> box2:i0;"), "{}", dot),
+        other => panic!("Expected DOT source, got {:?}", other),
+    }
+
+    evaluator.eval("(\"Int\") (\"Int\") empty_diagram \"show\" (\"Int\") (\"String\") add_box drop").unwrap();
+    let error = evaluator.eval("1 0 0 0 add_wire").unwrap_err();
+    assert!(error.to_string().contains("Cannot wire a String port"), "{}", error);
+}